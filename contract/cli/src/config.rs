@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use solana_keypair::{read_keypair_file, Keypair};
+
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+
+// The fields we read from the Solana CLI config (~/.config/solana/cli/config.yml)
+#[derive(Debug, Default, Deserialize)]
+struct SolanaCliConfig {
+    json_rpc_url: Option<String>,
+    keypair_path: Option<String>,
+}
+
+pub struct Config {
+    pub rpc_url: String,
+    pub keypair_path: PathBuf,
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".config/solana/cli/config.yml"))
+}
+
+fn default_keypair_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_default()
+        .join(".config/solana/id.json")
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+impl Config {
+    /// Flags win over the config file, the config file wins over localnet defaults
+    pub fn load(
+        config_path: Option<&Path>,
+        url: Option<String>,
+        keypair: Option<PathBuf>,
+    ) -> Result<Self> {
+        let path = config_path
+            .map(Path::to_path_buf)
+            .or_else(default_config_path);
+
+        let file_config = match path {
+            Some(path) if path.exists() => {
+                let raw = fs::read_to_string(&path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+                serde_yaml::from_str(&raw).with_context(|| format!("parsing {}", path.display()))?
+            }
+            Some(path) if config_path.is_some() => {
+                return Err(anyhow!("config file {} not found", path.display()))
+            }
+            _ => SolanaCliConfig::default(),
+        };
+
+        Ok(Self {
+            rpc_url: url
+                .or(file_config.json_rpc_url)
+                .unwrap_or_else(|| DEFAULT_RPC_URL.to_string()),
+            keypair_path: keypair
+                .or_else(|| file_config.keypair_path.as_deref().map(expand_home))
+                .unwrap_or_else(default_keypair_path),
+        })
+    }
+
+    pub fn keypair(&self) -> Result<Keypair> {
+        read_keypair_file(&self.keypair_path)
+            .map_err(|e| anyhow!("reading keypair {}: {e}", self.keypair_path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pm-cli-{}-{name}.yml", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn flags_win_over_the_config_file() {
+        let path = write_config(
+            "flags",
+            "json_rpc_url: http://example.com:8899\nkeypair_path: /keys/id.json\n",
+        );
+
+        let config = Config::load(Some(&path), None, None).unwrap();
+        assert_eq!(config.rpc_url, "http://example.com:8899");
+        assert_eq!(config.keypair_path, PathBuf::from("/keys/id.json"));
+
+        let config = Config::load(
+            Some(&path),
+            Some("http://localhost:1234".to_string()),
+            Some(PathBuf::from("/other.json")),
+        )
+        .unwrap();
+        assert_eq!(config.rpc_url, "http://localhost:1234");
+        assert_eq!(config.keypair_path, PathBuf::from("/other.json"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_fields_fall_back_to_localnet() {
+        let path = write_config("empty", "{}\n");
+        let config = Config::load(Some(&path), None, None).unwrap();
+        assert_eq!(config.rpc_url, DEFAULT_RPC_URL);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn an_explicit_config_file_has_to_exist() {
+        let path = std::env::temp_dir().join("pm-cli-does-not-exist.yml");
+        assert!(Config::load(Some(&path), None, None).is_err());
+    }
+}
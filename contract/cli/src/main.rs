@@ -0,0 +1,1382 @@
+mod config;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::associated_token::spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use anchor_spl::{token, token_2022};
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use prediction_market::constants::DEFAULT_VOLUME_WINDOW;
+use prediction_market::state::{
+    FeeTier, LiquidityRewardsParams, LmsrMaker, Market, MarketMetadata, MarketOrderParams,
+    MarketStatus, OrderBook, OrderSide, SplitMergeParams, TokenType, WinningOutcome,
+};
+use prediction_market_client::accounts::{
+    decode_liquidity_rewards, decode_lmsr_maker, decode_market, decode_market_counter,
+    decode_order_book, decode_user_stats,
+};
+use prediction_market_client::instructions::{
+    self as ix, Collateral, OrderRequest, UserTokenAccounts,
+};
+use prediction_market_client::pda::{self, MarketAddresses};
+use prediction_market_client::rpc::RpcClient;
+use solana_keypair::Keypair;
+use solana_sha256_hasher::hash;
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+
+use crate::config::Config;
+
+// Levels printed per side by `book`
+const LADDER_DEPTH: usize = 10;
+
+#[derive(Parser)]
+#[command(name = "pm-cli", about = "Operate prediction markets end to end")]
+struct Cli {
+    /// Solana CLI config file, defaults to ~/.config/solana/cli/config.yml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// RPC URL, overrides the config file
+    #[arg(long, short = 'u', global = true)]
+    url: Option<String>,
+
+    /// Signer keypair file, overrides the config file
+    #[arg(long, short = 'k', global = true)]
+    keypair: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a market, its outcome mints, vault, escrows and order book
+    CreateMarket {
+        #[arg(long)]
+        collateral_mint: String,
+        /// Settlement deadline as a unix timestamp
+        #[arg(long, conflicts_with = "duration")]
+        deadline: Option<i64>,
+        /// Settlement deadline as seconds from now
+        #[arg(long)]
+        duration: Option<i64>,
+        #[command(flatten)]
+        metadata: MetadataArgs,
+    },
+    /// Deposit collateral and receive the same amount of YES and NO
+    Split {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// Burn matching YES and NO back into collateral
+    Merge {
+        #[arg(long)]
+        market_id: u32,
+    },
+    /// Place a limit order, matched makers are picked from the current book
+    PlaceOrder {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, value_enum)]
+        side: Side,
+        #[arg(long, value_enum)]
+        token: Token,
+        #[arg(long)]
+        quantity: u64,
+        #[arg(long)]
+        price: u64,
+        #[arg(long, default_value_t = 10)]
+        max_iteration: u64,
+        /// Front-end credited with a share of your fees, only the first one sticks per market
+        #[arg(long)]
+        referrer: Option<String>,
+    },
+    /// Fill an order across the book and the market's AMM at the best price, nothing rests
+    MarketOrder {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, value_enum)]
+        side: Side,
+        #[arg(long, value_enum)]
+        token: Token,
+        #[arg(long)]
+        quantity: u64,
+        /// Worst price per token on either venue, in book price units
+        #[arg(long)]
+        limit_price: u64,
+        /// Most a buy pays, fees included, or least a sell receives. Unbounded by default
+        #[arg(long)]
+        slippage_limit: Option<u64>,
+        #[arg(long, default_value_t = 10)]
+        max_iteration: u64,
+        #[arg(long)]
+        referrer: Option<String>,
+    },
+    /// Short an outcome: split collateral and sell that outcome to the bids, keeping the other
+    SplitAndSell {
+        #[arg(long)]
+        market_id: u32,
+        /// Outcome to sell
+        #[arg(long, value_enum)]
+        token: Token,
+        #[arg(long)]
+        quantity: u64,
+        /// Lowest bid to sell into, in book price units
+        #[arg(long)]
+        limit_price: u64,
+        #[arg(long, default_value_t = 10)]
+        max_iteration: u64,
+    },
+    /// Close a short: buy an outcome from the asks and merge it with the other outcome you hold
+    BuyAndMerge {
+        #[arg(long)]
+        market_id: u32,
+        /// Outcome to buy
+        #[arg(long, value_enum)]
+        token: Token,
+        #[arg(long)]
+        quantity: u64,
+        /// Highest ask to buy from, in book price units
+        #[arg(long)]
+        limit_price: u64,
+        #[arg(long, default_value_t = 10)]
+        max_iteration: u64,
+    },
+    /// Cancel one of your resting orders
+    CancelOrder {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long)]
+        order_id: u64,
+    },
+    /// Print the order book ladder for both outcomes
+    Book {
+        #[arg(long)]
+        market_id: u32,
+    },
+    /// Show a user's UserStats for a market, defaults to the signer
+    UserStats {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long)]
+        user: Option<String>,
+    },
+    /// Set the winning outcome (market authority only)
+    Settle {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, value_enum)]
+        outcome: Outcome,
+    },
+    /// Pause, close-only, reactivate or void a market (market authority only)
+    MarketStatus {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, value_enum)]
+        status: Status,
+    },
+    /// Replace the market description, only before the first split (market authority only)
+    UpdateMetadata {
+        #[arg(long)]
+        market_id: u32,
+        #[command(flatten)]
+        metadata: MetadataArgs,
+    },
+    /// Redeem winning tokens for collateral
+    Claim {
+        #[arg(long)]
+        market_id: u32,
+    },
+    /// Open the market's AMM pool with an LP fee on every trade (market authority only)
+    AmmInit {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, default_value_t = 0)]
+        fee_bps: u16,
+    },
+    /// Deposit collateral into the AMM pool for LP shares
+    AddLiquidity {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long)]
+        amount: u64,
+    },
+    /// Burn LP shares for your part of the pool's YES and NO
+    RemoveLiquidity {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long)]
+        shares: u64,
+    },
+    /// Buy outcome tokens from the AMM pool for `amount` collateral
+    AmmBuy {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, value_enum)]
+        token: Token,
+        #[arg(long)]
+        amount: u64,
+        #[arg(long, default_value_t = 0)]
+        min_out: u64,
+    },
+    /// Sell outcome tokens to the AMM pool for exactly `return_amount` collateral
+    AmmSell {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, value_enum)]
+        token: Token,
+        #[arg(long)]
+        return_amount: u64,
+        #[arg(long, default_value_t = u64::MAX)]
+        max_in: u64,
+    },
+    /// Fund an LMSR maker with liquidity b, costs b * ln(2) collateral (market authority only)
+    LmsrInit {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long)]
+        liquidity: u64,
+    },
+    /// Buy outcome tokens from the LMSR, the cost defaults to the current quote
+    LmsrBuy {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, value_enum)]
+        token: Token,
+        #[arg(long)]
+        shares: u64,
+        #[arg(long)]
+        max_cost: Option<u64>,
+    },
+    /// Sell outcome tokens to the LMSR, the proceeds default to the current quote
+    LmsrSell {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, value_enum)]
+        token: Token,
+        #[arg(long)]
+        shares: u64,
+        #[arg(long)]
+        min_proceeds: Option<u64>,
+    },
+    /// Take back what's left of the LMSR subsidy after settlement (LMSR creator only)
+    WithdrawSubsidy {
+        #[arg(long)]
+        market_id: u32,
+    },
+    /// Fund rewards for makers resting near the midpoint, rewards_per_epoch * epochs up front
+    RewardsCreate {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long)]
+        rewards_per_epoch: u64,
+        /// Seconds per epoch
+        #[arg(long)]
+        epoch_duration: i64,
+        #[arg(long)]
+        epochs: u32,
+        /// Orders this far from the midpoint or further earn nothing
+        #[arg(long, default_value_t = 5000)]
+        max_spread_bps: u16,
+        /// Least seconds between two samples of the book
+        #[arg(long, default_value_t = 60)]
+        sample_interval: i64,
+        /// Least unfilled quantity an order needs to earn anything
+        #[arg(long)]
+        min_size: u64,
+    },
+    /// Sample the book or close a finished epoch of a sponsor's rewards, anyone can run it
+    RewardsCrank {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long)]
+        sponsor: String,
+    },
+    /// Claim the liquidity rewards closed epochs credited to the signer
+    RewardsClaim {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long)]
+        sponsor: String,
+    },
+    /// Take back what a finished rewards program didn't pay out (sponsor only)
+    RewardsWithdraw {
+        #[arg(long)]
+        market_id: u32,
+    },
+    /// Show a sponsor's rewards program and the makers' scores
+    Rewards {
+        #[arg(long)]
+        market_id: u32,
+        /// Defaults to the signer
+        #[arg(long)]
+        sponsor: Option<String>,
+    },
+    /// Create the protocol config, the signer has to be the program upgrade authority
+    InitConfig {
+        /// Defaults to the signer
+        #[arg(long)]
+        fee_recipient: Option<String>,
+        #[arg(long, default_value_t = 0)]
+        taker_fee_bps: u16,
+        #[arg(long, default_value_t = 0)]
+        maker_fee_bps: u16,
+        /// Part of every market's fees paid to the market creator
+        #[arg(long, default_value_t = 0)]
+        creator_fee_share_bps: u16,
+    },
+    /// Replace the volume fee schedule (protocol admin only), no --tier clears it
+    SetFeeTiers {
+        /// MIN_VOLUME:TAKER_BPS:MAKER_BPS, a negative maker rate is a rebate. Repeat in volume order
+        #[arg(long = "tier", value_parser = parse_fee_tier, allow_hyphen_values = true)]
+        tiers: Vec<FeeTier>,
+        /// Seconds per volume window
+        #[arg(long, default_value_t = DEFAULT_VOLUME_WINDOW)]
+        volume_window: i64,
+    },
+    /// Override the default fee rates of one market (protocol admin only)
+    SetMarketFees {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long)]
+        taker_fee_bps: u16,
+        #[arg(long)]
+        maker_fee_bps: u16,
+    },
+    /// Withdraw the protocol's accrued fees of a market, with --creator the market authority's
+    /// or with --referral your earnings as a referrer
+    WithdrawFees {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, conflicts_with = "referral")]
+        creator: bool,
+        #[arg(long)]
+        referral: bool,
+    },
+    /// Set the share of referred traders' taker fees paid to referrers (protocol admin only)
+    SetReferralShare {
+        #[arg(long)]
+        bps: u16,
+    },
+    /// Allow a mint as collateral for new markets (protocol admin only)
+    AllowCollateral {
+        #[arg(long)]
+        mint: String,
+    },
+    /// Stop all trading (protocol admin only)
+    Pause,
+    /// Resume trading (protocol admin only)
+    Unpause,
+}
+
+// Market description, the question text is stored on chain as its sha256 hash
+#[derive(Args)]
+struct MetadataArgs {
+    /// Off chain metadata (image, title, rules), up to 200 bytes
+    #[arg(long, default_value = "")]
+    metadata_url: String,
+    #[arg(long, default_value = "")]
+    question: String,
+    /// Up to 32 bytes
+    #[arg(long, default_value = "")]
+    category: String,
+    /// Where the answer comes from, up to 200 bytes
+    #[arg(long, default_value = "")]
+    resolution_source: String,
+}
+
+impl From<MetadataArgs> for MarketMetadata {
+    fn from(args: MetadataArgs) -> Self {
+        MarketMetadata {
+            meta_data_url: args.metadata_url,
+            question_hash: hash(args.question.as_bytes()).to_bytes(),
+            category: args.category,
+            resolution_source: args.resolution_source,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Token {
+    Yes,
+    No,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Outcome {
+    A,
+    B,
+    Neither,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Status {
+    Active,
+    Paused,
+    CloseOnly,
+    Voided,
+}
+
+impl From<Side> for OrderSide {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Buy => OrderSide::Buy,
+            Side::Sell => OrderSide::Sell,
+        }
+    }
+}
+
+impl From<Token> for TokenType {
+    fn from(token: Token) -> Self {
+        match token {
+            Token::Yes => TokenType::Yes,
+            Token::No => TokenType::No,
+        }
+    }
+}
+
+impl From<Status> for MarketStatus {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::Active => MarketStatus::Active,
+            Status::Paused => MarketStatus::Paused,
+            Status::CloseOnly => MarketStatus::CloseOnly,
+            Status::Voided => MarketStatus::Voided,
+        }
+    }
+}
+
+impl From<Outcome> for WinningOutcome {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::A => WinningOutcome::OutcomeA,
+            Outcome::B => WinningOutcome::OutcomeB,
+            Outcome::Neither => WinningOutcome::Neither,
+        }
+    }
+}
+
+fn parse_pubkey(value: &str) -> Result<Pubkey> {
+    Pubkey::from_str(value).map_err(|e| anyhow!("invalid pubkey {value}: {e}"))
+}
+
+// "1000000:10:-2" => 10 bps taker and a 2 bps maker rebate from 1000000 volume on
+fn parse_fee_tier(value: &str) -> Result<FeeTier> {
+    let parts: Vec<&str> = value.split(':').collect();
+    let [min_volume, taker_fee_bps, maker_fee_bps] = parts[..] else {
+        return Err(anyhow!("fee tier {value} is not MIN_VOLUME:TAKER_BPS:MAKER_BPS"));
+    };
+    Ok(FeeTier {
+        min_volume: min_volume.parse()?,
+        taker_fee_bps: taker_fee_bps.parse()?,
+        maker_fee_bps: maker_fee_bps.parse()?,
+    })
+}
+
+fn now() -> Result<i64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64)
+}
+
+struct Context {
+    rpc: RpcClient,
+    config: Config,
+}
+
+impl Context {
+    // Loaded on demand so read-only commands work without a keypair
+    fn payer(&self) -> Result<Keypair> {
+        self.config.keypair()
+    }
+
+    fn send(&self, payer: &Keypair, instructions: &[Instruction]) -> Result<String> {
+        let blockhash = self.rpc.latest_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &[payer],
+            blockhash,
+        );
+        self.rpc.send_and_confirm(&tx)
+    }
+
+    fn market(&self, market_id: u32) -> Result<Market> {
+        let data = self
+            .rpc
+            .account_data(&pda::market(market_id))?
+            .ok_or_else(|| anyhow!("market {market_id} does not exist"))?;
+        Ok(decode_market(&data)?)
+    }
+
+    fn order_book(&self, market_id: u32) -> Result<OrderBook> {
+        let data = self
+            .rpc
+            .account_data(&pda::orderbook(market_id))?
+            .ok_or_else(|| anyhow!("order book for market {market_id} does not exist"))?;
+        Ok(decode_order_book(&data)?)
+    }
+
+    fn lmsr(&self, market_id: u32) -> Result<LmsrMaker> {
+        let data = self
+            .rpc
+            .account_data(&pda::lmsr(market_id))?
+            .ok_or_else(|| anyhow!("market {market_id} has no LMSR"))?;
+        Ok(decode_lmsr_maker(&data)?)
+    }
+
+    // Collateral can be an SPL Token or a Token-2022 mint, the owner tells which
+    fn collateral(&self, mint: &Pubkey) -> Result<Collateral> {
+        let token_program = self
+            .rpc
+            .account_owner(mint)?
+            .ok_or_else(|| anyhow!("collateral mint {mint} does not exist"))?;
+        if token_program != token::ID && token_program != token_2022::ID {
+            return Err(anyhow!("{mint} is not a token mint"));
+        }
+        Ok(Collateral::new(*mint, token_program))
+    }
+
+    // The signer's ATAs for the market, created first if they don't exist yet
+    fn user_accounts(
+        &self,
+        payer: &Keypair,
+        market: &Market,
+    ) -> Result<(UserTokenAccounts, Vec<Instruction>)> {
+        let user = payer.pubkey();
+        let collateral = self.collateral(&market.collateral_mint)?;
+        let accounts = UserTokenAccounts::associated(&user, &collateral, market.market_id);
+        // The outcome mints are always Token-2022, native SOL needs no collateral account
+        let collateral_ata =
+            (!collateral.is_native()).then_some((collateral.mint, collateral.token_program));
+        let create = collateral_ata
+            .into_iter()
+            .chain([
+                (market.outcome_yes_mint, token_2022::ID),
+                (market.outcome_no_mint, token_2022::ID),
+            ])
+            .map(|(mint, program)| {
+                create_associated_token_account_idempotent(&user, &user, &mint, &program)
+            })
+            .collect();
+
+        Ok((accounts, create))
+    }
+}
+
+// The user's collateral ATA for a payout, created first if it doesn't exist yet
+// Native SOL is paid out as lamports and needs none
+fn payout_account(user: &Pubkey, collateral: &Collateral) -> (Option<Pubkey>, Vec<Instruction>) {
+    if collateral.is_native() {
+        return (None, Vec::new());
+    }
+    let account =
+        get_associated_token_address_with_program_id(user, &collateral.mint, &collateral.token_program);
+    let create =
+        create_associated_token_account_idempotent(user, user, &collateral.mint, &collateral.token_program);
+    (Some(account), vec![create])
+}
+
+fn print_ladder(book: &prediction_market::state::OrderBook, token_type: TokenType, name: &str) {
+    let asks = book.depth(token_type, OrderSide::Sell, LADDER_DEPTH);
+    let bids = book.depth(token_type, OrderSide::Buy, LADDER_DEPTH);
+
+    println!("{name}");
+    println!("  {:>6} {:>12} {:>14} {:>6}", "", "price", "size", "orders");
+    for level in asks.iter().rev() {
+        println!(
+            "  {:>6} {:>12} {:>14} {:>6}",
+            "ask", level.price, level.total_size, level.order_count
+        );
+    }
+    println!("  {}", "-".repeat(41));
+    for level in bids.iter() {
+        println!(
+            "  {:>6} {:>12} {:>14} {:>6}",
+            "bid", level.price, level.total_size, level.order_count
+        );
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = Config::load(cli.config.as_deref(), cli.url, cli.keypair)?;
+    let ctx = Context {
+        rpc: RpcClient::new(config.rpc_url.clone()),
+        config,
+    };
+
+    match cli.command {
+        Command::CreateMarket {
+            collateral_mint,
+            deadline,
+            duration,
+            metadata,
+        } => {
+            let settlement_deadline = match (deadline, duration) {
+                (Some(deadline), _) => deadline,
+                (None, Some(duration)) => now()? + duration,
+                (None, None) => return Err(anyhow!("pass --deadline or --duration")),
+            };
+            let collateral = ctx.collateral(&parse_pubkey(&collateral_mint)?)?;
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+
+            // The program hands out the id, the accounts have to be derived from the same one
+            let counter_data = ctx
+                .rpc
+                .account_data(&pda::market_counter())?
+                .ok_or_else(|| anyhow!("market counter does not exist, run init-config first"))?;
+            let market_id = decode_market_counter(&counter_data)?.next_market_id;
+
+            let signature = ctx.send(
+                &payer,
+                &[ix::initialize_market(
+                    &signer,
+                    market_id,
+                    &collateral,
+                    settlement_deadline,
+                    metadata.into(),
+                )],
+            )?;
+
+            let addresses = MarketAddresses::new(market_id);
+            println!("Market {market_id} created: {}", addresses.market);
+            println!("  YES mint:  {}", addresses.outcome_yes_mint);
+            println!("  NO mint:   {}", addresses.outcome_no_mint);
+            println!("  orderbook: {}", addresses.orderbook);
+            println!("Signature: {signature}");
+        }
+        Command::Split { market_id, amount } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            instructions.push(ix::split_tokens(&signer, market_id, &accounts, amount));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::Merge { market_id } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            instructions.push(ix::merge_tokens(&signer, market_id, &accounts));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::PlaceOrder {
+            market_id,
+            side,
+            token,
+            quantity,
+            price,
+            max_iteration,
+            referrer,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let book_data = ctx
+                .rpc
+                .account_data(&pda::orderbook(market_id))?
+                .ok_or_else(|| anyhow!("order book for market {market_id} does not exist"))?;
+            let book = decode_order_book(&book_data)?;
+
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            let order = OrderRequest {
+                side: side.into(),
+                token_type: token.into(),
+                quantity,
+                price,
+                max_iteration,
+                referrer: referrer.as_deref().map(parse_pubkey).transpose()?,
+            };
+            instructions.push(ix::place_order_against(
+                &signer, market_id, &accounts, &order, &book,
+            ));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::MarketOrder {
+            market_id,
+            side,
+            token,
+            quantity,
+            limit_price,
+            slippage_limit,
+            max_iteration,
+            referrer,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let book_data = ctx
+                .rpc
+                .account_data(&pda::orderbook(market_id))?
+                .ok_or_else(|| anyhow!("order book for market {market_id} does not exist"))?;
+            let book = decode_order_book(&book_data)?;
+
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            let params = MarketOrderParams {
+                side: side.into(),
+                token_type: token.into(),
+                quantity,
+                limit_price,
+                slippage_limit: slippage_limit.unwrap_or(match side {
+                    Side::Buy => u64::MAX,
+                    Side::Sell => 0,
+                }),
+                max_iteration,
+            };
+            instructions.push(ix::market_order(
+                &signer,
+                market_id,
+                &accounts,
+                params,
+                referrer.as_deref().map(parse_pubkey).transpose()?,
+                market.amm,
+                &book,
+            ));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::SplitAndSell {
+            market_id,
+            token,
+            quantity,
+            limit_price,
+            max_iteration,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let book = ctx.order_book(market_id)?;
+
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            let params = SplitMergeParams {
+                token_type: token.into(),
+                quantity,
+                limit_price,
+                max_iteration,
+            };
+            instructions.push(ix::split_and_sell(&signer, market_id, &accounts, params, &book));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::BuyAndMerge {
+            market_id,
+            token,
+            quantity,
+            limit_price,
+            max_iteration,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let book = ctx.order_book(market_id)?;
+
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            let params = SplitMergeParams {
+                token_type: token.into(),
+                quantity,
+                limit_price,
+                max_iteration,
+            };
+            instructions.push(ix::buy_and_merge(&signer, market_id, &accounts, params, &book));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::CancelOrder {
+            market_id,
+            order_id,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            instructions.push(ix::cancel_order(&signer, market_id, &accounts, order_id));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::Book { market_id } => {
+            let data = ctx
+                .rpc
+                .account_data(&pda::orderbook(market_id))?
+                .ok_or_else(|| anyhow!("order book for market {market_id} does not exist"))?;
+            let book = decode_order_book(&data)?;
+
+            print_ladder(&book, TokenType::Yes, "YES");
+            println!();
+            print_ladder(&book, TokenType::No, "NO");
+        }
+        Command::UserStats { market_id, user } => {
+            let user = match user {
+                Some(user) => parse_pubkey(&user)?,
+                None => ctx.payer()?.pubkey(),
+            };
+            let address = pda::user_stats(&user, market_id);
+            let data = ctx
+                .rpc
+                .account_data(&address)?
+                .ok_or_else(|| anyhow!("no UserStats for {user} in market {market_id}"))?;
+            let stats = decode_user_stats(&data)?;
+
+            println!("UserStats {address}");
+            println!("  user:                 {}", stats.user);
+            println!("  market_id:            {}", stats.market_id);
+            println!("  claimable_yes:        {}", stats.claimable_yes);
+            println!("  locked_yes:           {}", stats.locked_yes);
+            println!("  claimable_no:         {}", stats.claimable_no);
+            println!("  locked_no:            {}", stats.locked_no);
+            println!("  claimable_collateral: {}", stats.claimable_collateral);
+            println!("  locked_collateral:    {}", stats.locked_collateral);
+            println!("  reward_claimed:       {}", stats.reward_claimed);
+        }
+        Command::Settle { market_id, outcome } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            println!(
+                "Signature: {}",
+                ctx.send(
+                    &payer,
+                    &[ix::set_winning_side(&signer, market_id, outcome.into())]
+                )?
+            );
+        }
+        Command::MarketStatus { market_id, status } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            println!(
+                "Signature: {}",
+                ctx.send(
+                    &payer,
+                    &[ix::set_market_status(&signer, market_id, status.into())]
+                )?
+            );
+        }
+        Command::UpdateMetadata {
+            market_id,
+            metadata,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            println!(
+                "Signature: {}",
+                ctx.send(
+                    &payer,
+                    &[ix::update_market_metadata(&signer, market_id, metadata.into())]
+                )?
+            );
+        }
+        Command::Claim { market_id } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            instructions.push(ix::claim_rewards(&signer, market_id, &accounts));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::AmmInit { market_id, fee_bps } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+
+            println!(
+                "Signature: {}",
+                ctx.send(&payer, &[ix::initialize_amm_pool(&signer, market_id, fee_bps)])?
+            );
+            println!("Pool: {}", pda::amm_pool(market_id));
+        }
+        Command::AddLiquidity { market_id, amount } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            instructions.push(create_associated_token_account_idempotent(
+                &signer,
+                &signer,
+                &pda::amm_lp_mint(market_id),
+                &token_2022::ID,
+            ));
+            instructions.push(ix::add_liquidity(
+                &signer,
+                market_id,
+                &accounts,
+                &ix::associated_lp_account(&signer, market_id),
+                amount,
+            ));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::RemoveLiquidity { market_id, shares } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            instructions.push(ix::remove_liquidity(
+                &signer,
+                market_id,
+                &accounts,
+                &ix::associated_lp_account(&signer, market_id),
+                shares,
+            ));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::AmmBuy {
+            market_id,
+            token,
+            amount,
+            min_out,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            instructions.push(ix::amm_buy(
+                &signer,
+                market_id,
+                &accounts,
+                token.into(),
+                amount,
+                min_out,
+            ));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::AmmSell {
+            market_id,
+            token,
+            return_amount,
+            max_in,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            instructions.push(ix::amm_sell(
+                &signer,
+                market_id,
+                &accounts,
+                token.into(),
+                return_amount,
+                max_in,
+            ));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::LmsrInit {
+            market_id,
+            liquidity,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let collateral = ctx.collateral(&market.collateral_mint)?;
+            let authority_collateral =
+                UserTokenAccounts::associated(&signer, &collateral, market_id).collateral;
+
+            println!(
+                "Signature: {}",
+                ctx.send(
+                    &payer,
+                    &[ix::initialize_lmsr(
+                        &signer,
+                        market_id,
+                        &collateral,
+                        authority_collateral,
+                        liquidity,
+                    )]
+                )?
+            );
+        }
+        Command::LmsrBuy {
+            market_id,
+            token,
+            shares,
+            max_cost,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let max_cost = match max_cost {
+                Some(max_cost) => max_cost,
+                None => ctx
+                    .lmsr(market_id)?
+                    .buy_cost(token.into(), shares)
+                    .ok_or_else(|| anyhow!("{shares} shares are out of range"))?,
+            };
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            instructions.push(ix::lmsr_buy(
+                &signer,
+                market_id,
+                &accounts,
+                token.into(),
+                shares,
+                max_cost,
+            ));
+
+            println!("Cost at most {max_cost}");
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::LmsrSell {
+            market_id,
+            token,
+            shares,
+            min_proceeds,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let min_proceeds = match min_proceeds {
+                Some(min_proceeds) => min_proceeds,
+                None => ctx
+                    .lmsr(market_id)?
+                    .sell_proceeds(token.into(), shares)
+                    .ok_or_else(|| anyhow!("{shares} shares are out of range"))?,
+            };
+            let (accounts, mut instructions) = ctx.user_accounts(&payer, &market)?;
+            instructions.push(ix::lmsr_sell(
+                &signer,
+                market_id,
+                &accounts,
+                token.into(),
+                shares,
+                min_proceeds,
+            ));
+
+            println!("Proceeds at least {min_proceeds}");
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::WithdrawSubsidy { market_id } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let collateral = ctx.collateral(&market.collateral_mint)?;
+            let (creator_collateral, mut instructions) = payout_account(&signer, &collateral);
+            instructions.push(ix::withdraw_lmsr_subsidy(
+                &signer,
+                market_id,
+                &collateral,
+                creator_collateral,
+            ));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::RewardsCreate {
+            market_id,
+            rewards_per_epoch,
+            epoch_duration,
+            epochs,
+            max_spread_bps,
+            sample_interval,
+            min_size,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let collateral = ctx.collateral(&market.collateral_mint)?;
+            let sponsor_collateral =
+                UserTokenAccounts::associated(&signer, &collateral, market_id).collateral;
+            let params = LiquidityRewardsParams {
+                rewards_per_epoch,
+                epoch_duration,
+                epochs,
+                max_spread_bps,
+                sample_interval,
+                min_size,
+            };
+
+            println!(
+                "Signature: {}",
+                ctx.send(
+                    &payer,
+                    &[ix::create_liquidity_rewards(
+                        &signer,
+                        market_id,
+                        &collateral,
+                        sponsor_collateral,
+                        params,
+                    )]
+                )?
+            );
+        }
+        Command::RewardsCrank { market_id, sponsor } => {
+            let payer = ctx.payer()?;
+            let sponsor = parse_pubkey(&sponsor)?;
+
+            println!(
+                "Signature: {}",
+                ctx.send(&payer, &[ix::crank_liquidity_rewards(market_id, &sponsor)])?
+            );
+        }
+        Command::RewardsClaim { market_id, sponsor } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let sponsor = parse_pubkey(&sponsor)?;
+            let market = ctx.market(market_id)?;
+            let collateral = ctx.collateral(&market.collateral_mint)?;
+            let (maker_collateral, mut instructions) = payout_account(&signer, &collateral);
+            instructions.push(ix::claim_liquidity_rewards(
+                &signer,
+                market_id,
+                &sponsor,
+                &collateral,
+                maker_collateral,
+            ));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::RewardsWithdraw { market_id } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let collateral = ctx.collateral(&market.collateral_mint)?;
+            let (sponsor_collateral, mut instructions) = payout_account(&signer, &collateral);
+            instructions.push(ix::withdraw_liquidity_rewards(
+                &signer,
+                market_id,
+                &collateral,
+                sponsor_collateral,
+            ));
+
+            println!("Signature: {}", ctx.send(&payer, &instructions)?);
+        }
+        Command::Rewards { market_id, sponsor } => {
+            let sponsor = match sponsor {
+                Some(sponsor) => parse_pubkey(&sponsor)?,
+                None => ctx.payer()?.pubkey(),
+            };
+            let address = pda::liquidity_rewards(market_id, &sponsor);
+            let data = ctx
+                .rpc
+                .account_data(&address)?
+                .ok_or_else(|| anyhow!("no rewards from {sponsor} in market {market_id}"))?;
+            let rewards = decode_liquidity_rewards(&data)?;
+
+            println!("LiquidityRewards {address}");
+            println!("  rewards_per_epoch: {}", rewards.rewards_per_epoch);
+            println!("  epoch:             {} of {}", rewards.epoch, rewards.epochs);
+            println!("  epoch_duration:    {}s", rewards.epoch_duration);
+            println!("  max_spread_bps:    {}", rewards.max_spread_bps);
+            println!("  sample_interval:   {}s", rewards.sample_interval);
+            println!("  min_size:          {}", rewards.min_size);
+            println!("  last_sample:       {}", rewards.last_sample);
+            println!("  total_claimable:   {}", rewards.total_claimable);
+            for entry in &rewards.makers {
+                println!(
+                    "  {}  score {}  claimable {}",
+                    entry.maker, entry.score, entry.claimable
+                );
+            }
+        }
+        Command::InitConfig {
+            fee_recipient,
+            taker_fee_bps,
+            maker_fee_bps,
+            creator_fee_share_bps,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let fee_recipient = match fee_recipient {
+                Some(fee_recipient) => parse_pubkey(&fee_recipient)?,
+                None => signer,
+            };
+
+            println!(
+                "Signature: {}",
+                ctx.send(
+                    &payer,
+                    &[ix::initialize_protocol_config(
+                        &signer,
+                        &fee_recipient,
+                        taker_fee_bps,
+                        maker_fee_bps,
+                        creator_fee_share_bps,
+                    )]
+                )?
+            );
+        }
+        Command::SetFeeTiers {
+            tiers,
+            volume_window,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+
+            println!(
+                "Signature: {}",
+                ctx.send(&payer, &[ix::set_fee_tiers(&signer, tiers, volume_window)])?
+            );
+        }
+        Command::SetReferralShare { bps } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+
+            println!(
+                "Signature: {}",
+                ctx.send(&payer, &[ix::set_referral_fee_share(&signer, bps)])?
+            );
+        }
+        Command::SetMarketFees {
+            market_id,
+            taker_fee_bps,
+            maker_fee_bps,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+
+            println!(
+                "Signature: {}",
+                ctx.send(
+                    &payer,
+                    &[ix::set_market_fees(&signer, market_id, taker_fee_bps, maker_fee_bps)]
+                )?
+            );
+        }
+        Command::WithdrawFees {
+            market_id,
+            creator,
+            referral,
+        } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let market = ctx.market(market_id)?;
+            let collateral = ctx.collateral(&market.collateral_mint)?;
+            // Fees are paid as tokens, wrapped SOL included
+            let recipient_collateral = get_associated_token_address_with_program_id(
+                &signer,
+                &collateral.mint,
+                &collateral.token_program,
+            );
+            let withdraw = if creator {
+                ix::withdraw_creator_fees
+            } else if referral {
+                ix::withdraw_referral_fees
+            } else {
+                ix::withdraw_protocol_fees
+            };
+
+            println!(
+                "Signature: {}",
+                ctx.send(
+                    &payer,
+                    &[
+                        create_associated_token_account_idempotent(
+                            &signer,
+                            &signer,
+                            &collateral.mint,
+                            &collateral.token_program,
+                        ),
+                        withdraw(&signer, market_id, &collateral, &recipient_collateral),
+                    ]
+                )?
+            );
+        }
+        Command::AllowCollateral { mint } => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let mint = parse_pubkey(&mint)?;
+
+            println!(
+                "Signature: {}",
+                ctx.send(&payer, &[ix::add_allowed_collateral(&signer, &mint)])?
+            );
+        }
+        Command::Pause | Command::Unpause => {
+            let payer = ctx.payer()?;
+            let signer = payer.pubkey();
+            let paused = matches!(cli.command, Command::Pause);
+
+            println!(
+                "Signature: {}",
+                ctx.send(&payer, &[ix::set_protocol_paused(&signer, paused)])?
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn command_line_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn rewards_create_needs_a_minimum_size() {
+        let args = [
+            "pm-cli",
+            "rewards-create",
+            "--market-id",
+            "7",
+            "--rewards-per-epoch",
+            "100",
+            "--epoch-duration",
+            "60",
+            "--epochs",
+            "2",
+        ];
+        assert!(Cli::try_parse_from(args).is_err());
+
+        let cli = Cli::try_parse_from(args.iter().chain(&["--min-size", "5"])).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::RewardsCreate {
+                min_size: 5,
+                sample_interval: 60,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parses_fee_tiers() {
+        let tier = parse_fee_tier("1000000:10:-2").unwrap();
+        assert_eq!(tier.min_volume, 1_000_000);
+        assert_eq!(tier.taker_fee_bps, 10);
+        assert_eq!(tier.maker_fee_bps, -2);
+        assert!(parse_fee_tier("1000000:10").is_err());
+    }
+
+    #[test]
+    fn payouts_create_the_collateral_ata() {
+        let user = Pubkey::new_unique();
+        let collateral = Collateral::new(Pubkey::new_unique(), token::ID);
+
+        let (account, create) = payout_account(&user, &collateral);
+        let expected =
+            get_associated_token_address_with_program_id(&user, &collateral.mint, &token::ID);
+        assert_eq!(account, Some(expected));
+        assert_eq!(create.len(), 1);
+        assert!(create[0].accounts.iter().any(|meta| meta.pubkey == expected));
+    }
+
+    #[test]
+    fn native_payouts_need_no_account() {
+        let user = Pubkey::new_unique();
+        let collateral = Collateral::new(token::spl_token::native_mint::ID, token::ID);
+
+        let (account, create) = payout_account(&user, &collateral);
+        assert_eq!(account, None);
+        assert!(create.is_empty());
+    }
+}
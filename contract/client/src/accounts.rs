@@ -0,0 +1,45 @@
+use anchor_lang::{AccountDeserialize, AnchorDeserialize, Result};
+use prediction_market::state::{
+    AmmPool, LiquidityRewards, LmsrMaker, Market, MarketCounter, OrderBook, PriceLevel, Quote,
+    UserStats,
+};
+
+// Raw account data (as returned by getAccountInfo) into program types, discriminator checked
+
+pub fn decode_market(mut data: &[u8]) -> Result<Market> {
+    Market::try_deserialize(&mut data)
+}
+
+pub fn decode_order_book(mut data: &[u8]) -> Result<OrderBook> {
+    OrderBook::try_deserialize(&mut data)
+}
+
+pub fn decode_user_stats(mut data: &[u8]) -> Result<UserStats> {
+    UserStats::try_deserialize(&mut data)
+}
+
+pub fn decode_amm_pool(mut data: &[u8]) -> Result<AmmPool> {
+    AmmPool::try_deserialize(&mut data)
+}
+
+pub fn decode_lmsr_maker(mut data: &[u8]) -> Result<LmsrMaker> {
+    LmsrMaker::try_deserialize(&mut data)
+}
+
+pub fn decode_liquidity_rewards(mut data: &[u8]) -> Result<LiquidityRewards> {
+    LiquidityRewards::try_deserialize(&mut data)
+}
+
+pub fn decode_market_counter(mut data: &[u8]) -> Result<MarketCounter> {
+    MarketCounter::try_deserialize(&mut data)
+}
+
+// Return data of the view instructions (simulateTransaction returnData, base64 decoded)
+
+pub fn decode_depth(mut return_data: &[u8]) -> Result<Vec<PriceLevel>> {
+    Ok(Vec::<PriceLevel>::deserialize(&mut return_data)?)
+}
+
+pub fn decode_quote(mut return_data: &[u8]) -> Result<Quote> {
+    Ok(Quote::deserialize(&mut return_data)?)
+}
@@ -0,0 +1,1238 @@
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{bpf_loader_upgradeable, sysvar};
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::{token, token_2022};
+use prediction_market::state::{
+    AmmKind, FeeTier, LiquidityRewardsParams, MarketMetadata, MarketOrderParams, MarketStatus,
+    OrderBook, OrderSide, SplitMergeParams, TokenType, WinningOutcome,
+};
+use prediction_market::{accounts, instruction};
+
+use crate::matching::maker_accounts;
+use crate::pda::{self, MarketAddresses};
+
+/// A collateral mint and the program that owns it, SPL Token or Token-2022
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collateral {
+    pub mint: Pubkey,
+    pub token_program: Pubkey,
+}
+
+impl Collateral {
+    pub fn new(mint: Pubkey, token_program: Pubkey) -> Self {
+        Self {
+            mint,
+            token_program,
+        }
+    }
+
+    /// A classic SPL Token mint
+    pub fn spl(mint: Pubkey) -> Self {
+        Self::new(mint, token::ID)
+    }
+
+    /// Wrapped SOL of either token program, markets on it take and pay out plain lamports
+    pub fn is_native(&self) -> bool {
+        self.mint == token::spl_token::native_mint::ID
+            || self.mint == token_2022::spl_token_2022::native_mint::ID
+    }
+}
+
+/// A user's collateral and outcome token accounts for one market,
+/// plus the collateral mint and token program the transfers go through
+/// `collateral` is None when the user pays and gets paid in lamports on a native SOL market
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserTokenAccounts {
+    pub collateral_mint: Pubkey,
+    pub collateral_token_program: Pubkey,
+    pub collateral: Option<Pubkey>,
+    pub outcome_yes: Pubkey,
+    pub outcome_no: Pubkey,
+}
+
+impl UserTokenAccounts {
+    /// Associated token accounts of `user`, the layout the tests and front end use
+    /// Outcome mints are Token-2022 mints, so their ATAs derive from the Token-2022 program
+    /// Native SOL collateral gets no wSOL account, lamports are wrapped by the program
+    pub fn associated(user: &Pubkey, collateral: &Collateral, market_id: u32) -> Self {
+        Self {
+            collateral_mint: collateral.mint,
+            collateral_token_program: collateral.token_program,
+            collateral: (!collateral.is_native()).then(|| {
+                get_associated_token_address_with_program_id(
+                    user,
+                    &collateral.mint,
+                    &collateral.token_program,
+                )
+            }),
+            outcome_yes: get_associated_token_address_with_program_id(
+                user,
+                &pda::outcome_yes_mint(market_id),
+                &token_2022::ID,
+            ),
+            outcome_no: get_associated_token_address_with_program_id(
+                user,
+                &pda::outcome_no_mint(market_id),
+                &token_2022::ID,
+            ),
+        }
+    }
+
+    // Payouts without a collateral account go through the user's temporary wSOL account
+    fn native_unwrap(&self, user: &Pubkey) -> Option<Pubkey> {
+        self.collateral.is_none().then(|| pda::native_unwrap(user))
+    }
+}
+
+/// Order parameters shared by place_order and remaining_accounts selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderRequest {
+    pub side: OrderSide,
+    pub token_type: TokenType,
+    pub quantity: u64,
+    pub price: u64,
+    pub max_iteration: u64,
+    /// Front-end to credit with a share of the fees. Only the first referrer is recorded per
+    /// market, pass that one again on later orders so it keeps getting its share
+    pub referrer: Option<Pubkey>,
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: prediction_market::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// `market_id` has to be the counter's current next_market_id (see decode_market_counter),
+/// the program assigns it and the derived addresses must match
+pub fn initialize_market(
+    authority: &Pubkey,
+    market_id: u32,
+    collateral: &Collateral,
+    settlement_deadline: i64,
+    metadata: MarketMetadata,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::InitializeMarket {
+            market_counter: pda::market_counter(),
+            market: addresses.market,
+            authority: *authority,
+            config: pda::protocol_config(),
+            collateral_mint: collateral.mint,
+            collateral_vault: addresses.collateral_vault,
+            fee_vault: addresses.fee_vault,
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            yes_escrow: addresses.yes_escrow,
+            no_escrow: addresses.no_escrow,
+            orderbook: addresses.orderbook,
+            system_program: system_program::ID,
+            token_program: collateral.token_program,
+            outcome_token_program: token_2022::ID,
+            rent: sysvar::rent::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::InitializeMarket {
+            settlement_deadline,
+            metadata,
+        },
+    )
+}
+
+/// Scalar market, the YES/NO mints act as LONG/SHORT. `market_id` as in initialize_market
+pub fn initialize_scalar_market(
+    authority: &Pubkey,
+    market_id: u32,
+    collateral: &Collateral,
+    settlement_deadline: i64,
+    scalar_low: i64,
+    scalar_high: i64,
+    metadata: MarketMetadata,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::InitializeMarket {
+            market_counter: pda::market_counter(),
+            market: addresses.market,
+            authority: *authority,
+            config: pda::protocol_config(),
+            collateral_mint: collateral.mint,
+            collateral_vault: addresses.collateral_vault,
+            fee_vault: addresses.fee_vault,
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            yes_escrow: addresses.yes_escrow,
+            no_escrow: addresses.no_escrow,
+            orderbook: addresses.orderbook,
+            system_program: system_program::ID,
+            token_program: collateral.token_program,
+            outcome_token_program: token_2022::ID,
+            rent: sysvar::rent::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::InitializeScalarMarket {
+            settlement_deadline,
+            scalar_low,
+            scalar_high,
+            metadata,
+        },
+    )
+}
+
+pub fn split_tokens(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    amount: u64,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::SplitToken {
+            market: addresses.market,
+            user: *user,
+            config: pda::protocol_config(),
+            user_collateral: user_accounts.collateral,
+            collateral_mint: user_accounts.collateral_mint,
+            collateral_vault: addresses.collateral_vault,
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            user_outcome_yes: user_accounts.outcome_yes,
+            user_outcome_no: user_accounts.outcome_no,
+            user_stats_account: pda::user_stats(user, market_id),
+            system_program: system_program::ID,
+            token_program: user_accounts.collateral_token_program,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::SplitTokens { market_id, amount },
+    )
+}
+
+pub fn merge_tokens(user: &Pubkey, market_id: u32, user_accounts: &UserTokenAccounts) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::MergeTokens {
+            market: addresses.market,
+            user: *user,
+            config: pda::protocol_config(),
+            user_collateral: user_accounts.collateral,
+            collateral_mint: user_accounts.collateral_mint,
+            collateral_vault: addresses.collateral_vault,
+            native_unwrap: user_accounts.native_unwrap(user),
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            user_outcome_yes: user_accounts.outcome_yes,
+            user_outcome_no: user_accounts.outcome_no,
+            system_program: system_program::ID,
+            token_program: user_accounts.collateral_token_program,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::MergeTokens {
+            _market_id: market_id,
+        },
+    )
+}
+
+pub fn set_winning_side(
+    authority: &Pubkey,
+    market_id: u32,
+    winning_outcome: WinningOutcome,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::SetWinner {
+            authority: *authority,
+            market: addresses.market,
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::SetWinningSide {
+            _market_id: market_id,
+            winning_outcome,
+        },
+    )
+}
+
+pub fn resolve_scalar_market(authority: &Pubkey, market_id: u32, value: i64) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::SetWinner {
+            authority: *authority,
+            market: addresses.market,
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::ResolveScalarMarket {
+            _market_id: market_id,
+            value,
+        },
+    )
+}
+
+pub fn set_market_status(authority: &Pubkey, market_id: u32, status: MarketStatus) -> Instruction {
+    build(
+        accounts::SetMarketStatus {
+            authority: *authority,
+            market: pda::market(market_id),
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::SetMarketStatus {
+            _market_id: market_id,
+            status,
+        },
+    )
+}
+
+/// Only allowed before the first split and while the market is open
+pub fn update_market_metadata(
+    authority: &Pubkey,
+    market_id: u32,
+    metadata: MarketMetadata,
+) -> Instruction {
+    build(
+        accounts::UpdateMarketMetadata {
+            authority: *authority,
+            market: pda::market(market_id),
+            outcome_yes_mint: pda::outcome_yes_mint(market_id),
+            outcome_no_mint: pda::outcome_no_mint(market_id),
+            system_program: system_program::ID,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::UpdateMarketMetadata {
+            _market_id: market_id,
+            metadata,
+        },
+    )
+}
+
+pub fn claim_rewards(user: &Pubkey, market_id: u32, user_accounts: &UserTokenAccounts) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::ClaimRewards {
+            user: *user,
+            market: addresses.market,
+            user_collateral: user_accounts.collateral,
+            collateral_mint: user_accounts.collateral_mint,
+            collateral_vault: addresses.collateral_vault,
+            native_unwrap: user_accounts.native_unwrap(user),
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            user_outcome_yes: user_accounts.outcome_yes,
+            user_outcome_no: user_accounts.outcome_no,
+            system_program: system_program::ID,
+            token_program: user_accounts.collateral_token_program,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::ClaimRewards {
+            _market_id: market_id,
+        },
+    )
+}
+
+/// place_order with the maker accounts passed explicitly: UserStats then TraderVolume of each maker, in match order
+pub fn place_order(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    order: &OrderRequest,
+    maker_accounts: &[Pubkey],
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    let mut ix = build(
+        accounts::PlaceOrder {
+            user: *user,
+            config: pda::protocol_config(),
+            market: addresses.market,
+            orderbook: addresses.orderbook,
+            collateral_mint: user_accounts.collateral_mint,
+            collateral_vault: addresses.collateral_vault,
+            fee_vault: addresses.fee_vault,
+            user_collateral: user_accounts.collateral,
+            user_stats_account: pda::user_stats(user, market_id),
+            trader_volume: pda::trader_volume(user),
+            referrer: order.referrer,
+            referrer_stats: order
+                .referrer
+                .map(|referrer| pda::user_stats(&referrer, market_id)),
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            user_outcome_yes: user_accounts.outcome_yes,
+            user_outcome_no: user_accounts.outcome_no,
+            yes_escrow: addresses.yes_escrow,
+            no_escrow: addresses.no_escrow,
+            system_program: system_program::ID,
+            token_program: user_accounts.collateral_token_program,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::PlaceOrder {
+            _market_id: market_id,
+            side: order.side,
+            token_type: order.token_type,
+            quantity: order.quantity,
+            price: order.price,
+            max_iteration: order.max_iteration,
+        },
+    );
+    ix.accounts
+        .extend(maker_accounts.iter().map(|key| AccountMeta::new(*key, false)));
+    ix
+}
+
+/// place_order with remaining_accounts picked from a fetched OrderBook
+pub fn place_order_against(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    order: &OrderRequest,
+    orderbook: &OrderBook,
+) -> Instruction {
+    let makers = maker_accounts(
+        orderbook,
+        order.token_type,
+        order.side,
+        order.quantity,
+        order.price,
+        order.max_iteration,
+    );
+    place_order(user, market_id, user_accounts, order, &makers)
+}
+
+/// market_order against a fetched OrderBook and the market's AMM (`Market.amm`). Makers are
+/// picked up to the limit price, the program takes them in order and ignores the ones it
+/// didn't need because the AMM filled that part
+pub fn market_order(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    params: MarketOrderParams,
+    referrer: Option<Pubkey>,
+    amm: Option<AmmKind>,
+    orderbook: &OrderBook,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+    let fpmm = amm == Some(AmmKind::Fpmm);
+
+    let mut ix = build(
+        accounts::MarketOrder {
+            user: *user,
+            config: pda::protocol_config(),
+            market: addresses.market,
+            orderbook: addresses.orderbook,
+            collateral_mint: user_accounts.collateral_mint,
+            collateral_vault: addresses.collateral_vault,
+            fee_vault: addresses.fee_vault,
+            user_collateral: user_accounts.collateral,
+            user_stats_account: pda::user_stats(user, market_id),
+            trader_volume: pda::trader_volume(user),
+            referrer,
+            referrer_stats: referrer.map(|referrer| pda::user_stats(&referrer, market_id)),
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            user_outcome_yes: user_accounts.outcome_yes,
+            user_outcome_no: user_accounts.outcome_no,
+            yes_escrow: addresses.yes_escrow,
+            no_escrow: addresses.no_escrow,
+            pool: fpmm.then_some(addresses.amm_pool),
+            yes_reserve: fpmm.then_some(addresses.yes_reserve),
+            no_reserve: fpmm.then_some(addresses.no_reserve),
+            lmsr: (amm == Some(AmmKind::Lmsr)).then_some(addresses.lmsr),
+            native_unwrap: match params.side {
+                OrderSide::Sell => user_accounts.native_unwrap(user),
+                OrderSide::Buy => None,
+            },
+            system_program: system_program::ID,
+            token_program: user_accounts.collateral_token_program,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::MarketOrder { market_id, params },
+    );
+    let makers = maker_accounts(
+        orderbook,
+        params.token_type,
+        params.side,
+        params.quantity,
+        params.limit_price,
+        params.max_iteration,
+    );
+    ix.accounts
+        .extend(makers.iter().map(|key| AccountMeta::new(*key, false)));
+    ix
+}
+
+/// split_and_sell against a fetched OrderBook: splits and sells `params.token_type` to the
+/// bids, the user keeps the other outcome
+pub fn split_and_sell(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    params: SplitMergeParams,
+    orderbook: &OrderBook,
+) -> Instruction {
+    let mut ix = build(
+        split_merge_accounts(user, market_id, user_accounts),
+        instruction::SplitAndSell { market_id, params },
+    );
+    ix.accounts.extend(
+        split_merge_makers(orderbook, OrderSide::Sell, &params)
+            .iter()
+            .map(|key| AccountMeta::new(*key, false)),
+    );
+    ix
+}
+
+/// buy_and_merge against a fetched OrderBook: buys `params.token_type` from the asks and
+/// merges it with the user's other outcome
+pub fn buy_and_merge(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    params: SplitMergeParams,
+    orderbook: &OrderBook,
+) -> Instruction {
+    let mut ix = build(
+        split_merge_accounts(user, market_id, user_accounts),
+        instruction::BuyAndMerge { market_id, params },
+    );
+    ix.accounts.extend(
+        split_merge_makers(orderbook, OrderSide::Buy, &params)
+            .iter()
+            .map(|key| AccountMeta::new(*key, false)),
+    );
+    ix
+}
+
+fn split_merge_accounts(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+) -> accounts::SplitMergeOrder {
+    let addresses = MarketAddresses::new(market_id);
+
+    accounts::SplitMergeOrder {
+        user: *user,
+        config: pda::protocol_config(),
+        market: addresses.market,
+        orderbook: addresses.orderbook,
+        collateral_mint: user_accounts.collateral_mint,
+        collateral_vault: addresses.collateral_vault,
+        fee_vault: addresses.fee_vault,
+        user_collateral: user_accounts.collateral,
+        trader_volume: pda::trader_volume(user),
+        outcome_yes_mint: addresses.outcome_yes_mint,
+        outcome_no_mint: addresses.outcome_no_mint,
+        user_outcome_yes: user_accounts.outcome_yes,
+        user_outcome_no: user_accounts.outcome_no,
+        yes_escrow: addresses.yes_escrow,
+        no_escrow: addresses.no_escrow,
+        native_unwrap: user_accounts.native_unwrap(user),
+        system_program: system_program::ID,
+        token_program: user_accounts.collateral_token_program,
+        outcome_token_program: token_2022::ID,
+        event_authority: pda::event_authority(),
+        program: prediction_market::ID,
+    }
+}
+
+fn split_merge_makers(
+    orderbook: &OrderBook,
+    side: OrderSide,
+    params: &SplitMergeParams,
+) -> Vec<Pubkey> {
+    maker_accounts(
+        orderbook,
+        params.token_type,
+        side,
+        params.quantity,
+        params.limit_price,
+        params.max_iteration,
+    )
+}
+
+pub fn cancel_order(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    order_id: u64,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::CancelOrder {
+            user: *user,
+            config: pda::protocol_config(),
+            market: addresses.market,
+            orderbook: addresses.orderbook,
+            collateral_mint: user_accounts.collateral_mint,
+            collateral_vault: addresses.collateral_vault,
+            native_unwrap: user_accounts.native_unwrap(user),
+            user_collateral: user_accounts.collateral,
+            user_stats_account: pda::user_stats(user, market_id),
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            user_outcome_yes: user_accounts.outcome_yes,
+            user_outcome_no: user_accounts.outcome_no,
+            yes_escrow: addresses.yes_escrow,
+            no_escrow: addresses.no_escrow,
+            system_program: system_program::ID,
+            token_program: user_accounts.collateral_token_program,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::CancelOrder {
+            _market_id: market_id,
+            order_id,
+        },
+    )
+}
+
+/// View instruction, run it with simulateTransaction and read the return data
+pub fn get_order_book_depth(
+    market_id: u32,
+    token_type: TokenType,
+    side: OrderSide,
+    max_levels: u16,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::ViewOrderBook {
+            market: addresses.market,
+            orderbook: addresses.orderbook,
+        },
+        instruction::GetOrderBookDepth {
+            _market_id: market_id,
+            token_type,
+            side,
+            max_levels,
+        },
+    )
+}
+
+/// View instruction, run it with simulateTransaction and read the return data
+pub fn quote(market_id: u32, token_type: TokenType, side: OrderSide, quantity: u64) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::ViewOrderBook {
+            market: addresses.market,
+            orderbook: addresses.orderbook,
+        },
+        instruction::Quote {
+            _market_id: market_id,
+            token_type,
+            side,
+            quantity,
+        },
+    )
+}
+
+/// Token-2022 associated account holding `user`'s LP shares of the market's AMM pool
+pub fn associated_lp_account(user: &Pubkey, market_id: u32) -> Pubkey {
+    get_associated_token_address_with_program_id(
+        user,
+        &pda::amm_lp_mint(market_id),
+        &token_2022::ID,
+    )
+}
+
+/// Signed by the market authority
+pub fn initialize_amm_pool(authority: &Pubkey, market_id: u32, fee_bps: u16) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::InitializeAmmPool {
+            authority: *authority,
+            market: addresses.market,
+            pool: addresses.amm_pool,
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            lp_mint: addresses.amm_lp_mint,
+            yes_reserve: addresses.yes_reserve,
+            no_reserve: addresses.no_reserve,
+            system_program: system_program::ID,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::InitializeAmmPool { market_id, fee_bps },
+    )
+}
+
+/// `user_lp` has to exist, see associated_lp_account
+pub fn add_liquidity(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    user_lp: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::AddLiquidity {
+            user: *user,
+            config: pda::protocol_config(),
+            market: addresses.market,
+            pool: addresses.amm_pool,
+            user_collateral: user_accounts.collateral,
+            collateral_mint: user_accounts.collateral_mint,
+            collateral_vault: addresses.collateral_vault,
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            yes_reserve: addresses.yes_reserve,
+            no_reserve: addresses.no_reserve,
+            user_outcome_yes: user_accounts.outcome_yes,
+            user_outcome_no: user_accounts.outcome_no,
+            lp_mint: addresses.amm_lp_mint,
+            user_lp: *user_lp,
+            system_program: system_program::ID,
+            token_program: user_accounts.collateral_token_program,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::AddLiquidity { market_id, amount },
+    )
+}
+
+pub fn remove_liquidity(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    user_lp: &Pubkey,
+    shares: u64,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::RemoveLiquidity {
+            user: *user,
+            market: addresses.market,
+            pool: addresses.amm_pool,
+            outcome_yes_mint: addresses.outcome_yes_mint,
+            outcome_no_mint: addresses.outcome_no_mint,
+            yes_reserve: addresses.yes_reserve,
+            no_reserve: addresses.no_reserve,
+            user_outcome_yes: user_accounts.outcome_yes,
+            user_outcome_no: user_accounts.outcome_no,
+            lp_mint: addresses.amm_lp_mint,
+            user_lp: *user_lp,
+            outcome_token_program: token_2022::ID,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::RemoveLiquidity { market_id, shares },
+    )
+}
+
+fn amm_trade_accounts(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+) -> accounts::AmmTrade {
+    let addresses = MarketAddresses::new(market_id);
+
+    accounts::AmmTrade {
+        user: *user,
+        config: pda::protocol_config(),
+        market: addresses.market,
+        pool: addresses.amm_pool,
+        user_collateral: user_accounts.collateral,
+        collateral_mint: user_accounts.collateral_mint,
+        collateral_vault: addresses.collateral_vault,
+        native_unwrap: None,
+        outcome_yes_mint: addresses.outcome_yes_mint,
+        outcome_no_mint: addresses.outcome_no_mint,
+        yes_reserve: addresses.yes_reserve,
+        no_reserve: addresses.no_reserve,
+        user_outcome_yes: user_accounts.outcome_yes,
+        user_outcome_no: user_accounts.outcome_no,
+        system_program: system_program::ID,
+        token_program: user_accounts.collateral_token_program,
+        outcome_token_program: token_2022::ID,
+        event_authority: pda::event_authority(),
+        program: prediction_market::ID,
+    }
+}
+
+/// Spends `amount` collateral, fails if fewer than `min_tokens_out` come back
+pub fn amm_buy(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    token_type: TokenType,
+    amount: u64,
+    min_tokens_out: u64,
+) -> Instruction {
+    build(
+        amm_trade_accounts(user, market_id, user_accounts),
+        instruction::AmmBuy {
+            market_id,
+            token_type,
+            amount,
+            min_tokens_out,
+        },
+    )
+}
+
+/// Gets exactly `return_amount` collateral back, fails if that takes more than `max_tokens_in`
+pub fn amm_sell(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    token_type: TokenType,
+    return_amount: u64,
+    max_tokens_in: u64,
+) -> Instruction {
+    build(
+        accounts::AmmTrade {
+            native_unwrap: user_accounts.native_unwrap(user),
+            ..amm_trade_accounts(user, market_id, user_accounts)
+        },
+        instruction::AmmSell {
+            market_id,
+            token_type,
+            return_amount,
+            max_tokens_in,
+        },
+    )
+}
+
+/// Signed by the market authority, which pays the b * ln(2) subsidy from `authority_collateral`
+/// (None on native SOL markets)
+pub fn initialize_lmsr(
+    authority: &Pubkey,
+    market_id: u32,
+    collateral: &Collateral,
+    authority_collateral: Option<Pubkey>,
+    liquidity: u64,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::InitializeLmsr {
+            authority: *authority,
+            market: addresses.market,
+            lmsr: addresses.lmsr,
+            authority_collateral,
+            collateral_mint: collateral.mint,
+            collateral_vault: addresses.collateral_vault,
+            system_program: system_program::ID,
+            token_program: collateral.token_program,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::InitializeLmsr {
+            market_id,
+            liquidity,
+        },
+    )
+}
+
+fn lmsr_trade_accounts(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+) -> accounts::LmsrTrade {
+    let addresses = MarketAddresses::new(market_id);
+
+    accounts::LmsrTrade {
+        user: *user,
+        config: pda::protocol_config(),
+        market: addresses.market,
+        lmsr: addresses.lmsr,
+        user_collateral: user_accounts.collateral,
+        collateral_mint: user_accounts.collateral_mint,
+        collateral_vault: addresses.collateral_vault,
+        native_unwrap: None,
+        outcome_yes_mint: addresses.outcome_yes_mint,
+        outcome_no_mint: addresses.outcome_no_mint,
+        user_outcome_yes: user_accounts.outcome_yes,
+        user_outcome_no: user_accounts.outcome_no,
+        system_program: system_program::ID,
+        token_program: user_accounts.collateral_token_program,
+        outcome_token_program: token_2022::ID,
+        event_authority: pda::event_authority(),
+        program: prediction_market::ID,
+    }
+}
+
+/// Buys exactly `shares`, fails if they cost more than `max_cost` (see LmsrMaker::buy_cost)
+pub fn lmsr_buy(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    token_type: TokenType,
+    shares: u64,
+    max_cost: u64,
+) -> Instruction {
+    build(
+        lmsr_trade_accounts(user, market_id, user_accounts),
+        instruction::LmsrBuy {
+            market_id,
+            token_type,
+            shares,
+            max_cost,
+        },
+    )
+}
+
+/// Sells exactly `shares`, fails if they pay less than `min_proceeds`
+pub fn lmsr_sell(
+    user: &Pubkey,
+    market_id: u32,
+    user_accounts: &UserTokenAccounts,
+    token_type: TokenType,
+    shares: u64,
+    min_proceeds: u64,
+) -> Instruction {
+    build(
+        accounts::LmsrTrade {
+            native_unwrap: user_accounts.native_unwrap(user),
+            ..lmsr_trade_accounts(user, market_id, user_accounts)
+        },
+        instruction::LmsrSell {
+            market_id,
+            token_type,
+            shares,
+            min_proceeds,
+        },
+    )
+}
+
+/// Signed by the LMSR creator once the market is settled. `creator_collateral` is None on
+/// native SOL markets, the leftover then arrives as lamports
+pub fn withdraw_lmsr_subsidy(
+    creator: &Pubkey,
+    market_id: u32,
+    collateral: &Collateral,
+    creator_collateral: Option<Pubkey>,
+) -> Instruction {
+    let addresses = MarketAddresses::new(market_id);
+
+    build(
+        accounts::WithdrawLmsrSubsidy {
+            creator: *creator,
+            market: addresses.market,
+            lmsr: addresses.lmsr,
+            creator_collateral,
+            collateral_mint: collateral.mint,
+            collateral_vault: addresses.collateral_vault,
+            native_unwrap: creator_collateral
+                .is_none()
+                .then(|| pda::native_unwrap(creator)),
+            system_program: system_program::ID,
+            token_program: collateral.token_program,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::WithdrawLmsrSubsidy { market_id },
+    )
+}
+
+pub fn create_liquidity_rewards(
+    sponsor: &Pubkey,
+    market_id: u32,
+    collateral: &Collateral,
+    sponsor_collateral: Option<Pubkey>,
+    params: LiquidityRewardsParams,
+) -> Instruction {
+    let rewards = pda::liquidity_rewards(market_id, sponsor);
+
+    build(
+        accounts::CreateLiquidityRewards {
+            sponsor: *sponsor,
+            market: pda::market(market_id),
+            rewards,
+            reward_vault: pda::reward_vault(&rewards),
+            sponsor_collateral,
+            collateral_mint: collateral.mint,
+            system_program: system_program::ID,
+            token_program: collateral.token_program,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::CreateLiquidityRewards { market_id, params },
+    )
+}
+
+/// Permissionless, any wallet can pay for it
+pub fn crank_liquidity_rewards(market_id: u32, sponsor: &Pubkey) -> Instruction {
+    build(
+        accounts::CrankLiquidityRewards {
+            market: pda::market(market_id),
+            orderbook: pda::orderbook(market_id),
+            rewards: pda::liquidity_rewards(market_id, sponsor),
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::CrankLiquidityRewards { market_id },
+    )
+}
+
+pub fn claim_liquidity_rewards(
+    maker: &Pubkey,
+    market_id: u32,
+    sponsor: &Pubkey,
+    collateral: &Collateral,
+    maker_collateral: Option<Pubkey>,
+) -> Instruction {
+    let rewards = pda::liquidity_rewards(market_id, sponsor);
+
+    build(
+        accounts::ClaimLiquidityRewards {
+            maker: *maker,
+            market: pda::market(market_id),
+            rewards,
+            reward_vault: pda::reward_vault(&rewards),
+            maker_collateral,
+            collateral_mint: collateral.mint,
+            native_unwrap: maker_collateral
+                .is_none()
+                .then(|| pda::native_unwrap(maker)),
+            system_program: system_program::ID,
+            token_program: collateral.token_program,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::ClaimLiquidityRewards { market_id },
+    )
+}
+
+pub fn withdraw_liquidity_rewards(
+    sponsor: &Pubkey,
+    market_id: u32,
+    collateral: &Collateral,
+    sponsor_collateral: Option<Pubkey>,
+) -> Instruction {
+    let rewards = pda::liquidity_rewards(market_id, sponsor);
+
+    build(
+        accounts::WithdrawLiquidityRewards {
+            sponsor: *sponsor,
+            market: pda::market(market_id),
+            rewards,
+            reward_vault: pda::reward_vault(&rewards),
+            sponsor_collateral,
+            collateral_mint: collateral.mint,
+            native_unwrap: sponsor_collateral
+                .is_none()
+                .then(|| pda::native_unwrap(sponsor)),
+            system_program: system_program::ID,
+            token_program: collateral.token_program,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::WithdrawLiquidityRewards { market_id },
+    )
+}
+
+/// Has to be signed by the program's upgrade authority, which becomes the protocol admin
+pub fn initialize_protocol_config(
+    admin: &Pubkey,
+    fee_recipient: &Pubkey,
+    taker_fee_bps: u16,
+    maker_fee_bps: u16,
+    creator_fee_share_bps: u16,
+) -> Instruction {
+    build(
+        accounts::InitializeProtocolConfig {
+            config: pda::protocol_config(),
+            market_counter: pda::market_counter(),
+            admin: *admin,
+            program: prediction_market::ID,
+            program_data: bpf_loader_upgradeable::get_program_data_address(&prediction_market::ID),
+            system_program: system_program::ID,
+        },
+        instruction::InitializeProtocolConfig {
+            fee_recipient: *fee_recipient,
+            taker_fee_bps,
+            maker_fee_bps,
+            creator_fee_share_bps,
+        },
+    )
+}
+
+/// Replaces the volume fee schedule, tiers sorted by min_volume (protocol admin only)
+pub fn set_fee_tiers(admin: &Pubkey, fee_tiers: Vec<FeeTier>, volume_window: i64) -> Instruction {
+    build(
+        update_protocol_config_accounts(admin),
+        instruction::SetFeeTiers {
+            fee_tiers,
+            volume_window,
+        },
+    )
+}
+
+/// Share of referred traders' taker fees paid to their referrer (protocol admin only)
+pub fn set_referral_fee_share(admin: &Pubkey, referral_fee_share_bps: u16) -> Instruction {
+    build(
+        update_protocol_config_accounts(admin),
+        instruction::SetReferralFeeShare {
+            referral_fee_share_bps,
+        },
+    )
+}
+
+/// Overrides the protocol default fee rates for one market (protocol admin only)
+pub fn set_market_fees(
+    admin: &Pubkey,
+    market_id: u32,
+    taker_fee_bps: u16,
+    maker_fee_bps: u16,
+) -> Instruction {
+    build(
+        accounts::SetMarketFees {
+            admin: *admin,
+            config: pda::protocol_config(),
+            market: pda::market(market_id),
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::SetMarketFees {
+            _market_id: market_id,
+            taker_fee_bps,
+            maker_fee_bps,
+        },
+    )
+}
+
+fn withdraw_fees_accounts(
+    recipient: &Pubkey,
+    market_id: u32,
+    collateral: &Collateral,
+    recipient_collateral: &Pubkey,
+) -> accounts::WithdrawFees {
+    accounts::WithdrawFees {
+        recipient: *recipient,
+        config: pda::protocol_config(),
+        market: pda::market(market_id),
+        collateral_mint: collateral.mint,
+        fee_vault: pda::fee_vault(market_id),
+        recipient_collateral: *recipient_collateral,
+        token_program: collateral.token_program,
+        event_authority: pda::event_authority(),
+        program: prediction_market::ID,
+    }
+}
+
+/// Signed by the protocol fee recipient
+pub fn withdraw_protocol_fees(
+    recipient: &Pubkey,
+    market_id: u32,
+    collateral: &Collateral,
+    recipient_collateral: &Pubkey,
+) -> Instruction {
+    build(
+        withdraw_fees_accounts(recipient, market_id, collateral, recipient_collateral),
+        instruction::WithdrawProtocolFees {
+            _market_id: market_id,
+        },
+    )
+}
+
+/// Signed by the referrer, pays out their referral earnings in one market
+pub fn withdraw_referral_fees(
+    referrer: &Pubkey,
+    market_id: u32,
+    collateral: &Collateral,
+    recipient_collateral: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::WithdrawReferralFees {
+            referrer: *referrer,
+            market: pda::market(market_id),
+            referrer_stats: pda::user_stats(referrer, market_id),
+            collateral_mint: collateral.mint,
+            fee_vault: pda::fee_vault(market_id),
+            recipient_collateral: *recipient_collateral,
+            token_program: collateral.token_program,
+            event_authority: pda::event_authority(),
+            program: prediction_market::ID,
+        },
+        instruction::WithdrawReferralFees {
+            _market_id: market_id,
+        },
+    )
+}
+
+/// Signed by the market authority
+pub fn withdraw_creator_fees(
+    recipient: &Pubkey,
+    market_id: u32,
+    collateral: &Collateral,
+    recipient_collateral: &Pubkey,
+) -> Instruction {
+    build(
+        withdraw_fees_accounts(recipient, market_id, collateral, recipient_collateral),
+        instruction::WithdrawCreatorFees {
+            _market_id: market_id,
+        },
+    )
+}
+
+fn update_protocol_config_accounts(admin: &Pubkey) -> accounts::UpdateProtocolConfig {
+    accounts::UpdateProtocolConfig {
+        admin: *admin,
+        config: pda::protocol_config(),
+    }
+}
+
+pub fn set_protocol_paused(admin: &Pubkey, paused: bool) -> Instruction {
+    build(
+        update_protocol_config_accounts(admin),
+        instruction::SetProtocolPaused { paused },
+    )
+}
+
+pub fn add_allowed_collateral(admin: &Pubkey, mint: &Pubkey) -> Instruction {
+    build(
+        accounts::AddAllowedCollateral {
+            admin: *admin,
+            config: pda::protocol_config(),
+            collateral_mint: *mint,
+        },
+        instruction::AddAllowedCollateral {},
+    )
+}
+
+pub fn remove_allowed_collateral(admin: &Pubkey, mint: &Pubkey) -> Instruction {
+    build(
+        update_protocol_config_accounts(admin),
+        instruction::RemoveAllowedCollateral { mint: *mint },
+    )
+}
@@ -0,0 +1,13 @@
+//! Client helpers for the prediction_market program: PDA derivation, instruction builders,
+//! remaining_accounts selection for place_order and account / return data decoding.
+//! The `rpc` feature adds the blocking JSON-RPC client the CLI and the indexer use.
+
+pub mod accounts;
+pub mod instructions;
+pub mod matching;
+pub mod pda;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+
+pub use prediction_market::state;
+pub use prediction_market::ID as PROGRAM_ID;
@@ -0,0 +1,182 @@
+use anchor_lang::prelude::Pubkey;
+use prediction_market::state::{OrderBook, OrderSide, TokenType};
+
+use crate::pda;
+
+/// Maker accounts place_order will consume from remaining_accounts, in match order:
+/// each maker's UserStats followed by their TraderVolume
+///
+/// Mirrors the matching loop in place_order: walks the opposite side best price first,
+/// stops at the first price that doesn't cross and allows at most `max_iteration + 1` matches.
+/// Only valid for the book state it was computed from, refetch the OrderBook if the tx fails.
+pub fn maker_accounts(
+    orderbook: &OrderBook,
+    token_type: TokenType,
+    side: OrderSide,
+    quantity: u64,
+    price: u64,
+    max_iteration: u64,
+) -> Vec<Pubkey> {
+    let book_side = match side {
+        OrderSide::Buy => OrderSide::Sell,
+        OrderSide::Sell => OrderSide::Buy,
+    };
+
+    let mut accounts = Vec::new();
+    let mut filled: u64 = 0;
+    let mut iteration: u64 = 0;
+
+    for book_order in orderbook.orders(token_type, book_side).iter() {
+        if iteration > max_iteration || filled >= quantity {
+            break;
+        }
+
+        let price_matches = match side {
+            OrderSide::Buy => price >= book_order.price,
+            OrderSide::Sell => price <= book_order.price,
+        };
+        if !price_matches {
+            break;
+        }
+
+        let book_left_qty = book_order.quantity.saturating_sub(book_order.filledquantity);
+        let min_qty = (quantity - filled).min(book_left_qty);
+        if min_qty == 0 {
+            continue;
+        }
+
+        accounts.push(book_order.user_stats);
+        accounts.push(pda::trader_volume(&book_order.user_key));
+        filled += min_qty;
+        iteration += 1;
+    }
+
+    accounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prediction_market::state::Order;
+
+    fn key(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    // Resting order of maker `maker`, its UserStats address is key(maker + 100)
+    fn order(maker: u8, side: OrderSide, price: u64, quantity: u64, filled: u64) -> Order {
+        Order {
+            id: maker as u64,
+            market_id: 1,
+            user_key: key(maker),
+            user_stats: key(maker + 100),
+            side,
+            token_type: TokenType::Yes,
+            price,
+            quantity,
+            filledquantity: filled,
+            timestamp: 0,
+            fee_reserved: 0,
+        }
+    }
+
+    // Sides are given best price first, the way place_order keeps them
+    fn book(bids: Vec<Order>, asks: Vec<Order>) -> OrderBook {
+        OrderBook {
+            market_id: 1,
+            next_order_id: 10,
+            yes_buy_orders: bids,
+            yes_sell_orders: asks,
+            no_buy_orders: Vec::new(),
+            no_sell_orders: Vec::new(),
+            bump: 255,
+        }
+    }
+
+    fn makers(makers: &[u8]) -> Vec<Pubkey> {
+        makers
+            .iter()
+            .flat_map(|&maker| [key(maker + 100), pda::trader_volume(&key(maker))])
+            .collect()
+    }
+
+    fn asks() -> OrderBook {
+        book(
+            Vec::new(),
+            vec![
+                order(1, OrderSide::Sell, 40, 10, 0),
+                order(2, OrderSide::Sell, 45, 10, 4),
+                order(3, OrderSide::Sell, 50, 10, 0),
+            ],
+        )
+    }
+
+    #[test]
+    fn empty_book_needs_no_accounts() {
+        let book = book(Vec::new(), Vec::new());
+        assert!(maker_accounts(&book, TokenType::Yes, OrderSide::Buy, 10, 99, 10).is_empty());
+    }
+
+    #[test]
+    fn stops_at_the_first_price_that_does_not_cross() {
+        let accounts = maker_accounts(&asks(), TokenType::Yes, OrderSide::Buy, 100, 45, 10);
+        assert_eq!(accounts, makers(&[1, 2]));
+    }
+
+    #[test]
+    fn stops_once_the_quantity_is_filled() {
+        // 10 from maker 1, the last 2 from what's left of maker 2's order
+        let accounts = maker_accounts(&asks(), TokenType::Yes, OrderSide::Buy, 12, 99, 10);
+        assert_eq!(accounts, makers(&[1, 2]));
+
+        let accounts = maker_accounts(&asks(), TokenType::Yes, OrderSide::Buy, 10, 99, 10);
+        assert_eq!(accounts, makers(&[1]));
+    }
+
+    #[test]
+    fn counts_only_what_is_left_of_partially_filled_orders() {
+        // Maker 2 has 6 left, so 10 + 6 don't cover 17 and maker 3 is needed too
+        let accounts = maker_accounts(&asks(), TokenType::Yes, OrderSide::Buy, 17, 99, 10);
+        assert_eq!(accounts, makers(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn allows_one_match_more_than_max_iteration() {
+        let accounts = maker_accounts(&asks(), TokenType::Yes, OrderSide::Buy, 100, 99, 0);
+        assert_eq!(accounts, makers(&[1]));
+
+        let accounts = maker_accounts(&asks(), TokenType::Yes, OrderSide::Buy, 100, 99, 1);
+        assert_eq!(accounts, makers(&[1, 2]));
+    }
+
+    #[test]
+    fn sells_walk_the_bids() {
+        let book = book(
+            vec![
+                order(4, OrderSide::Buy, 60, 5, 0),
+                order(5, OrderSide::Buy, 55, 5, 5), // Filled, takes no match
+                order(6, OrderSide::Buy, 55, 5, 0),
+                order(7, OrderSide::Buy, 30, 5, 0),
+            ],
+            Vec::new(),
+        );
+        let accounts = maker_accounts(&book, TokenType::Yes, OrderSide::Sell, 20, 55, 10);
+        assert_eq!(accounts, makers(&[4, 6]));
+
+        // The NO book is empty
+        assert!(maker_accounts(&book, TokenType::No, OrderSide::Sell, 20, 55, 10).is_empty());
+    }
+
+    #[test]
+    fn a_maker_with_several_orders_is_listed_once_per_match() {
+        let book = book(
+            Vec::new(),
+            vec![
+                order(1, OrderSide::Sell, 40, 10, 0),
+                order(1, OrderSide::Sell, 41, 10, 0),
+            ],
+        );
+        let accounts = maker_accounts(&book, TokenType::Yes, OrderSide::Buy, 20, 41, 10);
+        assert_eq!(accounts, makers(&[1, 1]));
+    }
+}
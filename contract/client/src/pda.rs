@@ -0,0 +1,144 @@
+use anchor_lang::prelude::Pubkey;
+use prediction_market::constants::*;
+
+// Same seeds the program uses in its #[account(seeds = ...)] constraints
+
+pub fn market(market_id: u32) -> Pubkey {
+    Pubkey::find_program_address(&[MARKET_SEED, &market_id.to_le_bytes()], &prediction_market::ID).0
+}
+
+pub fn collateral_vault(market_id: u32) -> Pubkey {
+    Pubkey::find_program_address(&[VAULT_SEED, &market_id.to_le_bytes()], &prediction_market::ID).0
+}
+
+pub fn fee_vault(market_id: u32) -> Pubkey {
+    Pubkey::find_program_address(&[FEE_VAULT_SEED, &market_id.to_le_bytes()], &prediction_market::ID).0
+}
+
+pub fn outcome_yes_mint(market_id: u32) -> Pubkey {
+    Pubkey::find_program_address(&[OUTCOME_YES_SEED, &market_id.to_le_bytes()], &prediction_market::ID).0
+}
+
+pub fn outcome_no_mint(market_id: u32) -> Pubkey {
+    Pubkey::find_program_address(&[OUTCOME_NO_SEED, &market_id.to_le_bytes()], &prediction_market::ID).0
+}
+
+pub fn escrow(market_id: u32, outcome_mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[ESCROW_SEED, &market_id.to_le_bytes(), outcome_mint.as_ref()],
+        &prediction_market::ID,
+    )
+    .0
+}
+
+pub fn orderbook(market_id: u32) -> Pubkey {
+    Pubkey::find_program_address(&[ORDERBOOK_SEED, &market_id.to_le_bytes()], &prediction_market::ID).0
+}
+
+pub fn user_stats(user: &Pubkey, market_id: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[USER_STATS_SEED, user.as_ref(), &market_id.to_le_bytes()],
+        &prediction_market::ID,
+    )
+    .0
+}
+
+pub fn protocol_config() -> Pubkey {
+    Pubkey::find_program_address(&[PROTOCOL_CONFIG_SEED], &prediction_market::ID).0
+}
+
+pub fn market_counter() -> Pubkey {
+    Pubkey::find_program_address(&[MARKET_COUNTER_SEED], &prediction_market::ID).0
+}
+
+pub fn amm_pool(market_id: u32) -> Pubkey {
+    Pubkey::find_program_address(&[AMM_POOL_SEED, &market_id.to_le_bytes()], &prediction_market::ID).0
+}
+
+pub fn amm_lp_mint(market_id: u32) -> Pubkey {
+    Pubkey::find_program_address(&[AMM_LP_MINT_SEED, &market_id.to_le_bytes()], &prediction_market::ID).0
+}
+
+// YES or NO inventory of the market's AMM pool
+pub fn amm_reserve(market_id: u32, outcome_mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[AMM_RESERVE_SEED, &market_id.to_le_bytes(), outcome_mint.as_ref()],
+        &prediction_market::ID,
+    )
+    .0
+}
+
+pub fn lmsr(market_id: u32) -> Pubkey {
+    Pubkey::find_program_address(&[LMSR_SEED, &market_id.to_le_bytes()], &prediction_market::ID).0
+}
+
+// One sponsor's liquidity rewards program on a market
+pub fn liquidity_rewards(market_id: u32, sponsor: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[LIQUIDITY_REWARDS_SEED, &market_id.to_le_bytes(), sponsor.as_ref()],
+        &prediction_market::ID,
+    )
+    .0
+}
+
+pub fn reward_vault(rewards: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[REWARD_VAULT_SEED, rewards.as_ref()], &prediction_market::ID).0
+}
+
+// Cross-market traded volume of a wallet, sets its fee tier
+pub fn trader_volume(user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[TRADER_VOLUME_SEED, user.as_ref()], &prediction_market::ID).0
+}
+
+// Temporary wSOL account native SOL payouts go through, closed within the instruction
+pub fn native_unwrap(user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[NATIVE_UNWRAP_SEED, user.as_ref()], &prediction_market::ID).0
+}
+
+// Signer for emit_cpi! self invocations
+pub fn event_authority() -> Pubkey {
+    Pubkey::find_program_address(&[b"__event_authority"], &prediction_market::ID).0
+}
+
+/// Every program owned address of one market
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketAddresses {
+    pub market_id: u32,
+    pub market: Pubkey,
+    pub collateral_vault: Pubkey,
+    pub fee_vault: Pubkey,
+    pub outcome_yes_mint: Pubkey,
+    pub outcome_no_mint: Pubkey,
+    pub yes_escrow: Pubkey,
+    pub no_escrow: Pubkey,
+    pub orderbook: Pubkey,
+    pub amm_pool: Pubkey,
+    pub amm_lp_mint: Pubkey,
+    pub yes_reserve: Pubkey,
+    pub no_reserve: Pubkey,
+    pub lmsr: Pubkey,
+}
+
+impl MarketAddresses {
+    pub fn new(market_id: u32) -> Self {
+        let outcome_yes_mint = outcome_yes_mint(market_id);
+        let outcome_no_mint = outcome_no_mint(market_id);
+
+        Self {
+            market_id,
+            market: market(market_id),
+            collateral_vault: collateral_vault(market_id),
+            fee_vault: fee_vault(market_id),
+            outcome_yes_mint,
+            outcome_no_mint,
+            yes_escrow: escrow(market_id, &outcome_yes_mint),
+            no_escrow: escrow(market_id, &outcome_no_mint),
+            orderbook: orderbook(market_id),
+            amm_pool: amm_pool(market_id),
+            amm_lp_mint: amm_lp_mint(market_id),
+            yes_reserve: amm_reserve(market_id, &outcome_yes_mint),
+            no_reserve: amm_reserve(market_id, &outcome_no_mint),
+            lmsr: lmsr(market_id),
+        }
+    }
+}
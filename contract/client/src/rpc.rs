@@ -0,0 +1,255 @@
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_hash::Hash;
+use solana_transaction::Transaction;
+
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// getSignaturesForAddress returns at most 1000 signatures per call
+const SIGNATURE_PAGE_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+struct SignatureInfo {
+    signature: String,
+}
+
+/// Minimal blocking JSON-RPC client, just the calls the CLI and the indexer need
+pub struct RpcClient {
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let response: Value = ureq::post(&self.url)
+            .send_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))?
+            .into_json()?;
+
+        result_of(method, response)
+    }
+
+    pub fn latest_blockhash(&self) -> Result<Hash> {
+        let result = self.call("getLatestBlockhash", json!([{ "commitment": "confirmed" }]))?;
+        let blockhash = result["value"]["blockhash"]
+            .as_str()
+            .ok_or_else(|| anyhow!("getLatestBlockhash returned no blockhash"))?;
+        Hash::from_str(blockhash).map_err(|e| anyhow!("invalid blockhash {blockhash}: {e}"))
+    }
+
+    fn account_info(&self, address: &Pubkey) -> Result<Value> {
+        let result = self.call(
+            "getAccountInfo",
+            json!([address.to_string(), { "encoding": "base64", "commitment": "confirmed" }]),
+        )?;
+        Ok(result["value"].clone())
+    }
+
+    /// Account data, None if the account doesn't exist
+    pub fn account_data(&self, address: &Pubkey) -> Result<Option<Vec<u8>>> {
+        account_data_of(address, &self.account_info(address)?)
+    }
+
+    /// Program that owns the account, None if the account doesn't exist
+    pub fn account_owner(&self, address: &Pubkey) -> Result<Option<Pubkey>> {
+        account_owner_of(address, &self.account_info(address)?)
+    }
+
+    /// Sends a signed transaction and waits until it is confirmed, returns its signature
+    pub fn send_and_confirm(&self, tx: &Transaction) -> Result<String> {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(bincode::serialize(tx)?);
+        let signature = self
+            .call(
+                "sendTransaction",
+                json!([encoded, { "encoding": "base64", "preflightCommitment": "confirmed" }]),
+            )?
+            .as_str()
+            .ok_or_else(|| anyhow!("sendTransaction returned no signature"))?
+            .to_string();
+
+        let started = Instant::now();
+        while started.elapsed() < CONFIRM_TIMEOUT {
+            let result = self.call("getSignatureStatuses", json!([[signature]]))?;
+            if signature_confirmed(&signature, &result["value"][0])? {
+                return Ok(signature);
+            }
+            sleep(CONFIRM_POLL_INTERVAL);
+        }
+
+        Err(anyhow!("transaction {signature} was not confirmed in time"))
+    }
+
+    /// Signatures that touched `address` after `until`, oldest first
+    pub fn signatures_since(&self, address: &Pubkey, until: Option<&str>) -> Result<Vec<String>> {
+        let mut signatures = Vec::new();
+        let mut before: Option<String> = None;
+
+        loop {
+            let mut config = json!({ "limit": SIGNATURE_PAGE_LIMIT, "commitment": "confirmed" });
+            if let Some(until) = until {
+                config["until"] = json!(until);
+            }
+            if let Some(before) = &before {
+                config["before"] = json!(before);
+            }
+
+            let page: Vec<SignatureInfo> = serde_json::from_value(
+                self.call("getSignaturesForAddress", json!([address.to_string(), config]))?,
+            )?;
+            let page_len = page.len();
+
+            before = page.last().map(|info| info.signature.clone());
+            signatures.extend(page.into_iter().map(|info| info.signature));
+
+            if page_len < SIGNATURE_PAGE_LIMIT {
+                break;
+            }
+        }
+
+        // RPC returns newest first
+        signatures.reverse();
+        Ok(signatures)
+    }
+
+    /// `getTransaction` with the "json" encoding, None if the node doesn't have it (yet)
+    pub fn transaction<T: DeserializeOwned>(&self, signature: &str) -> Result<Option<T>> {
+        let result = self.call(
+            "getTransaction",
+            json!([
+                signature,
+                {
+                    "encoding": "json",
+                    "commitment": "confirmed",
+                    "maxSupportedTransactionVersion": 0
+                }
+            ]),
+        )?;
+
+        if result.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_value(result)?))
+    }
+
+    /// `getProgramAccounts` with base64 data
+    pub fn program_accounts<T: DeserializeOwned>(&self, program_id: &Pubkey) -> Result<Vec<T>> {
+        let result = self.call(
+            "getProgramAccounts",
+            json!([
+                program_id.to_string(),
+                { "encoding": "base64", "commitment": "confirmed" }
+            ]),
+        )?;
+
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+fn result_of(method: &str, response: Value) -> Result<Value> {
+    if let Some(error) = response.get("error") {
+        return Err(anyhow!("{method} failed: {error}"));
+    }
+
+    Ok(response.get("result").cloned().unwrap_or(Value::Null))
+}
+
+fn account_data_of(address: &Pubkey, value: &Value) -> Result<Option<Vec<u8>>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    let data = value["data"][0]
+        .as_str()
+        .ok_or_else(|| anyhow!("getAccountInfo returned no data for {address}"))?;
+    Ok(Some(
+        base64::engine::general_purpose::STANDARD.decode(data)?,
+    ))
+}
+
+fn account_owner_of(address: &Pubkey, value: &Value) -> Result<Option<Pubkey>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    let owner = value["owner"]
+        .as_str()
+        .ok_or_else(|| anyhow!("getAccountInfo returned no owner for {address}"))?;
+    Ok(Some(
+        Pubkey::from_str(owner).map_err(|e| anyhow!("invalid owner {owner}: {e}"))?,
+    ))
+}
+
+// One entry of getSignatureStatuses, null while the node hasn't seen the transaction
+fn signature_confirmed(signature: &str, status: &Value) -> Result<bool> {
+    if status.is_null() {
+        return Ok(false);
+    }
+    if !status["err"].is_null() {
+        return Err(anyhow!("transaction {signature} failed: {}", status["err"]));
+    }
+    Ok(matches!(
+        status["confirmationStatus"].as_str(),
+        Some("confirmed") | Some("finalized")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpc_errors_become_errors() {
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32602 } });
+        let error = result_of("getAccountInfo", response).unwrap_err();
+        assert!(error.to_string().starts_with("getAccountInfo failed"));
+
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": { "value": null } });
+        assert_eq!(
+            result_of("getAccountInfo", response).unwrap(),
+            json!({ "value": null })
+        );
+    }
+
+    #[test]
+    fn reads_account_data_and_owner() {
+        let address = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let value = json!({
+            "data": [base64::engine::general_purpose::STANDARD.encode([1, 2, 3]), "base64"],
+            "owner": owner.to_string(),
+            "lamports": 1_000,
+        });
+
+        assert_eq!(account_data_of(&address, &value).unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(account_owner_of(&address, &value).unwrap(), Some(owner));
+        assert_eq!(account_data_of(&address, &Value::Null).unwrap(), None);
+        assert_eq!(account_owner_of(&address, &Value::Null).unwrap(), None);
+        assert!(account_owner_of(&address, &json!({ "owner": "not a key" })).is_err());
+    }
+
+    #[test]
+    fn waits_for_confirmed_signatures() {
+        assert!(!signature_confirmed("sig", &Value::Null).unwrap());
+        let processed = json!({ "err": null, "confirmationStatus": "processed" });
+        assert!(!signature_confirmed("sig", &processed).unwrap());
+        let confirmed = json!({ "err": null, "confirmationStatus": "confirmed" });
+        assert!(signature_confirmed("sig", &confirmed).unwrap());
+
+        let failed = json!({ "err": { "InstructionError": [0, "Custom"] }, "confirmationStatus": "confirmed" });
+        assert!(signature_confirmed("sig", &failed).is_err());
+    }
+}
@@ -0,0 +1,2120 @@
+use std::path::Path;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::Result;
+use prediction_market::events::*;
+use prediction_market::state::{
+    Market, MarketStatus, MarketType, Order, OrderBook, OrderSide, TokenType, UserStats,
+    WinningOutcome,
+};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS markets (
+    market_id               INTEGER PRIMARY KEY,
+    address                 TEXT,
+    authority               TEXT NOT NULL,
+    collateral_mint         TEXT NOT NULL,
+    collateral_vault        TEXT,
+    outcome_yes_mint        TEXT,
+    outcome_no_mint         TEXT,
+    settlement_deadline     INTEGER NOT NULL,
+    total_collateral_locked INTEGER NOT NULL DEFAULT 0,
+    is_settled              INTEGER NOT NULL DEFAULT 0,
+    winning_outcome         TEXT,
+    status                  TEXT NOT NULL DEFAULT 'active',
+    meta_data_url           TEXT,
+    question_hash           TEXT, -- hex
+    category                TEXT,
+    resolution_source       TEXT,
+    market_type             TEXT NOT NULL DEFAULT 'binary', -- binary or scalar
+    scalar_low              INTEGER,
+    scalar_high             INTEGER,
+    resolved_value          INTEGER, -- scalar only
+    group_id                INTEGER, -- neg-risk group, only known from MarketAddedToGroup
+    group_index             INTEGER,
+    taker_fee_bps           INTEGER,
+    maker_fee_bps           INTEGER,
+    created_at              INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS orders (
+    market_id       INTEGER NOT NULL,
+    order_id        INTEGER NOT NULL,
+    user            TEXT NOT NULL,
+    side            TEXT NOT NULL,
+    token_type      TEXT NOT NULL,
+    price           INTEGER NOT NULL,
+    quantity        INTEGER NOT NULL,
+    filled_quantity INTEGER NOT NULL DEFAULT 0,
+    status          TEXT NOT NULL, -- open, filled, cancelled, closed (left the book, seen only by snapshot)
+    placed_at       INTEGER NOT NULL,
+    updated_at      INTEGER NOT NULL,
+    PRIMARY KEY (market_id, order_id)
+);
+CREATE INDEX IF NOT EXISTS orders_open ON orders (market_id, status);
+
+CREATE TABLE IF NOT EXISTS fills (
+    signature         TEXT NOT NULL,
+    seq               INTEGER NOT NULL, -- position of the event inside the transaction
+    slot              INTEGER NOT NULL,
+    market_id         INTEGER NOT NULL,
+    taker_order_id    INTEGER NOT NULL,
+    maker_order_id    INTEGER NOT NULL,
+    taker             TEXT NOT NULL,
+    maker             TEXT NOT NULL,
+    taker_side        TEXT NOT NULL,
+    token_type        TEXT NOT NULL,
+    price             INTEGER NOT NULL,
+    quantity          INTEGER NOT NULL,
+    collateral_amount INTEGER NOT NULL,
+    taker_fee         INTEGER NOT NULL,
+    maker_fee         INTEGER NOT NULL,
+    maker_rebate      INTEGER NOT NULL,
+    timestamp         INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+CREATE INDEX IF NOT EXISTS fills_by_market ON fills (market_id, token_type, slot, seq);
+
+CREATE TABLE IF NOT EXISTS user_stats (
+    address              TEXT PRIMARY KEY,
+    user                 TEXT NOT NULL,
+    market_id            INTEGER NOT NULL,
+    claimable_yes        INTEGER NOT NULL,
+    locked_yes           INTEGER NOT NULL,
+    claimable_no         INTEGER NOT NULL,
+    locked_no            INTEGER NOT NULL,
+    claimable_collateral INTEGER NOT NULL,
+    locked_collateral    INTEGER NOT NULL,
+    reward_claimed       INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS settlements (
+    market_id       INTEGER PRIMARY KEY,
+    winning_outcome TEXT NOT NULL,
+    signature       TEXT NOT NULL,
+    slot            INTEGER NOT NULL,
+    timestamp       INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS claims (
+    signature       TEXT NOT NULL,
+    seq             INTEGER NOT NULL,
+    market_id       INTEGER NOT NULL,
+    user            TEXT NOT NULL,
+    winning_outcome TEXT NOT NULL, -- scalar for LONG/SHORT payouts, voided for void refunds
+    amount          INTEGER NOT NULL,
+    timestamp       INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+
+CREATE TABLE IF NOT EXISTS categorical_markets (
+    market_id               INTEGER PRIMARY KEY,
+    authority               TEXT NOT NULL,
+    collateral_mint         TEXT NOT NULL,
+    outcome_count           INTEGER NOT NULL,
+    settlement_deadline     INTEGER NOT NULL,
+    total_collateral_locked INTEGER NOT NULL DEFAULT 0,
+    is_settled              INTEGER NOT NULL DEFAULT 0,
+    winning_outcome         INTEGER,
+    created_at              INTEGER NOT NULL
+);
+
+-- Every outcome of a categorical market has its own book and order ids
+CREATE TABLE IF NOT EXISTS categorical_orders (
+    market_id       INTEGER NOT NULL,
+    outcome_index   INTEGER NOT NULL,
+    order_id        INTEGER NOT NULL,
+    user            TEXT NOT NULL,
+    side            TEXT NOT NULL,
+    price           INTEGER NOT NULL,
+    quantity        INTEGER NOT NULL,
+    filled_quantity INTEGER NOT NULL DEFAULT 0,
+    status          TEXT NOT NULL, -- open, filled, cancelled
+    placed_at       INTEGER NOT NULL,
+    updated_at      INTEGER NOT NULL,
+    PRIMARY KEY (market_id, outcome_index, order_id)
+);
+
+CREATE TABLE IF NOT EXISTS categorical_fills (
+    signature         TEXT NOT NULL,
+    seq               INTEGER NOT NULL,
+    slot              INTEGER NOT NULL,
+    market_id         INTEGER NOT NULL,
+    outcome_index     INTEGER NOT NULL,
+    taker_order_id    INTEGER NOT NULL,
+    maker_order_id    INTEGER NOT NULL,
+    taker             TEXT NOT NULL,
+    maker             TEXT NOT NULL,
+    taker_side        TEXT NOT NULL,
+    price             INTEGER NOT NULL,
+    quantity          INTEGER NOT NULL,
+    collateral_amount INTEGER NOT NULL,
+    timestamp         INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+
+CREATE TABLE IF NOT EXISTS categorical_claims (
+    signature       TEXT NOT NULL,
+    seq             INTEGER NOT NULL,
+    market_id       INTEGER NOT NULL,
+    user            TEXT NOT NULL,
+    winning_outcome INTEGER NOT NULL,
+    amount          INTEGER NOT NULL,
+    timestamp       INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+
+CREATE TABLE IF NOT EXISTS market_groups (
+    group_id          INTEGER PRIMARY KEY,
+    authority         TEXT NOT NULL,
+    is_resolved       INTEGER NOT NULL DEFAULT 0,
+    winning_market_id INTEGER,
+    created_at        INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS conversions (
+    signature           TEXT NOT NULL,
+    seq                 INTEGER NOT NULL,
+    group_id            INTEGER NOT NULL,
+    user                TEXT NOT NULL,
+    no_market_mask      INTEGER NOT NULL, -- bit i = NO of the market at group_index i
+    amount              INTEGER NOT NULL,
+    collateral_released INTEGER NOT NULL,
+    timestamp           INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+
+CREATE TABLE IF NOT EXISTS fee_withdrawals (
+    signature TEXT NOT NULL,
+    seq       INTEGER NOT NULL,
+    market_id INTEGER NOT NULL,
+    recipient TEXT NOT NULL,
+    kind      TEXT NOT NULL, -- creator, protocol or referral
+    amount    INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+
+CREATE TABLE IF NOT EXISTS referrals (
+    market_id     INTEGER NOT NULL,
+    user          TEXT NOT NULL,
+    referrer      TEXT NOT NULL,
+    fees_credited INTEGER NOT NULL DEFAULT 0,
+    recorded_at   INTEGER NOT NULL,
+    PRIMARY KEY (market_id, user)
+);
+
+CREATE TABLE IF NOT EXISTS amm_pools (
+    market_id    INTEGER PRIMARY KEY,
+    lp_mint      TEXT NOT NULL,
+    fee_bps      INTEGER NOT NULL,
+    total_shares INTEGER NOT NULL DEFAULT 0,
+    created_at   INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS liquidity_changes (
+    signature         TEXT NOT NULL,
+    seq               INTEGER NOT NULL,
+    market_id         INTEGER NOT NULL,
+    user              TEXT NOT NULL,
+    kind              TEXT NOT NULL, -- add or remove
+    collateral_amount INTEGER NOT NULL, -- deposited, 0 on remove
+    shares            INTEGER NOT NULL,
+    yes_amount        INTEGER NOT NULL, -- sent to the user: returned on add, withdrawn on remove
+    no_amount         INTEGER NOT NULL,
+    timestamp         INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+
+-- Trades against the market's AMM, the order book's trades are in fills
+CREATE TABLE IF NOT EXISTS amm_trades (
+    signature         TEXT NOT NULL,
+    seq               INTEGER NOT NULL,
+    slot              INTEGER NOT NULL,
+    market_id         INTEGER NOT NULL,
+    venue             TEXT NOT NULL, -- pool (fixed product AmmPool) or lmsr
+    user              TEXT NOT NULL,
+    side              TEXT NOT NULL,
+    token_type        TEXT NOT NULL,
+    collateral_amount INTEGER NOT NULL, -- paid on a buy, received on a sell
+    outcome_amount    INTEGER NOT NULL,
+    fee               INTEGER NOT NULL,
+    timestamp         INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+CREATE INDEX IF NOT EXISTS amm_trades_by_market ON amm_trades (market_id, token_type, slot, seq);
+
+CREATE TABLE IF NOT EXISTS lmsr_makers (
+    market_id         INTEGER PRIMARY KEY,
+    creator           TEXT NOT NULL,
+    liquidity         INTEGER NOT NULL, -- b
+    subsidy           INTEGER NOT NULL,
+    subsidy_withdrawn INTEGER NOT NULL DEFAULT 0,
+    created_at        INTEGER NOT NULL
+);
+
+-- One row per market_order: book fills are in fills under order_id, the AMM part in amm_trades
+CREATE TABLE IF NOT EXISTS market_orders (
+    signature         TEXT NOT NULL,
+    seq               INTEGER NOT NULL,
+    slot              INTEGER NOT NULL,
+    market_id         INTEGER NOT NULL,
+    order_id          INTEGER NOT NULL,
+    user              TEXT NOT NULL,
+    side              TEXT NOT NULL,
+    token_type        TEXT NOT NULL,
+    book_quantity     INTEGER NOT NULL,
+    amm_quantity      INTEGER NOT NULL,
+    collateral_amount INTEGER NOT NULL, -- paid with fees on a buy, received on a sell
+    timestamp         INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+
+-- split_and_sell and buy_and_merge, their book fills are in fills under order_id
+CREATE TABLE IF NOT EXISTS split_merge_orders (
+    signature         TEXT NOT NULL,
+    seq               INTEGER NOT NULL,
+    slot              INTEGER NOT NULL,
+    market_id         INTEGER NOT NULL,
+    order_id          INTEGER NOT NULL,
+    user              TEXT NOT NULL,
+    kind              TEXT NOT NULL, -- split_and_sell or buy_and_merge
+    token_type        TEXT NOT NULL, -- the outcome traded on the book
+    quantity          INTEGER NOT NULL,
+    collateral_amount INTEGER NOT NULL, -- proceeds of the sale or cost of the purchase
+    timestamp         INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+
+-- One row per sponsor's program, the rewards sit in a vault of their own, outside the market's
+CREATE TABLE IF NOT EXISTS liquidity_rewards (
+    market_id         INTEGER NOT NULL,
+    sponsor           TEXT NOT NULL,
+    rewards_per_epoch INTEGER NOT NULL,
+    epoch_duration    INTEGER NOT NULL,
+    epochs            INTEGER NOT NULL,
+    max_spread_bps    INTEGER NOT NULL,
+    sample_interval   INTEGER NOT NULL,
+    min_size          INTEGER NOT NULL,
+    distributed       INTEGER NOT NULL DEFAULT 0, -- credited to makers by closed epochs
+    claimed           INTEGER NOT NULL DEFAULT 0,
+    withdrawn         INTEGER NOT NULL DEFAULT 0, -- returned to the sponsor
+    created_at        INTEGER NOT NULL,
+    PRIMARY KEY (market_id, sponsor)
+);
+
+CREATE TABLE IF NOT EXISTS liquidity_reward_epochs (
+    market_id   INTEGER NOT NULL,
+    sponsor     TEXT NOT NULL,
+    epoch       INTEGER NOT NULL,
+    samples     INTEGER NOT NULL DEFAULT 0,
+    score       TEXT NOT NULL DEFAULT '0', -- u128, too wide for INTEGER
+    distributed INTEGER,
+    closed_at   INTEGER, -- NULL while the epoch is open
+    PRIMARY KEY (market_id, sponsor, epoch)
+);
+
+CREATE TABLE IF NOT EXISTS liquidity_reward_claims (
+    signature TEXT NOT NULL,
+    seq       INTEGER NOT NULL,
+    market_id INTEGER NOT NULL,
+    sponsor   TEXT NOT NULL,
+    maker     TEXT NOT NULL,
+    amount    INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    PRIMARY KEY (signature, seq)
+);
+
+CREATE TABLE IF NOT EXISTS processed_transactions (
+    signature TEXT PRIMARY KEY,
+    slot      INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS cursor (
+    name  TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+pub fn side_str(side: OrderSide) -> &'static str {
+    match side {
+        OrderSide::Buy => "buy",
+        OrderSide::Sell => "sell",
+    }
+}
+
+pub fn token_type_str(token_type: TokenType) -> &'static str {
+    match token_type {
+        TokenType::Yes => "yes",
+        TokenType::No => "no",
+    }
+}
+
+pub fn outcome_str(outcome: WinningOutcome) -> &'static str {
+    match outcome {
+        WinningOutcome::OutcomeA => "outcome_a",
+        WinningOutcome::OutcomeB => "outcome_b",
+        WinningOutcome::Neither => "neither",
+    }
+}
+
+pub fn status_str(status: MarketStatus) -> &'static str {
+    match status {
+        MarketStatus::Active => "active",
+        MarketStatus::Paused => "paused",
+        MarketStatus::CloseOnly => "close_only",
+        MarketStatus::Settled => "settled",
+        MarketStatus::Voided => "voided",
+    }
+}
+
+pub fn market_type_str(market_type: MarketType) -> &'static str {
+    match market_type {
+        MarketType::Binary => "binary",
+        MarketType::Scalar => "scalar",
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// Where an event came from, used as the idempotency key for event rows
+#[derive(Debug, Clone, Copy)]
+pub struct EventOrigin<'a> {
+    pub signature: &'a str,
+    pub slot: u64,
+    pub seq: usize,
+}
+
+// One OHLCV bucket of fills
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candle {
+    pub start: i64,
+    pub open: u64,
+    pub high: u64,
+    pub low: u64,
+    pub close: u64,
+    pub volume: u64,
+    pub trades: u64,
+}
+
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    pub fn transaction(&mut self) -> Result<Transaction<'_>> {
+        Ok(self.conn.transaction()?)
+    }
+
+    pub fn cursor(&self, name: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row("SELECT value FROM cursor WHERE name = ?1", [name], |row| row.get(0))
+            .optional()?)
+    }
+
+    pub fn set_cursor(&self, name: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO cursor (name, value) VALUES (?1, ?2)
+             ON CONFLICT (name) DO UPDATE SET value = excluded.value",
+            params![name, value],
+        )?;
+        Ok(())
+    }
+
+    /// OHLCV candles for one outcome of a market, `interval` seconds wide
+    pub fn candles(&self, market_id: u32, token_type: TokenType, interval: i64) -> Result<Vec<Candle>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT timestamp, price, quantity FROM fills
+             WHERE market_id = ?1 AND token_type = ?2
+             ORDER BY slot, seq",
+        )?;
+        let rows = stmt.query_map(params![market_id, token_type_str(token_type)], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?))
+        })?;
+
+        let interval = interval.max(1);
+        let mut candles: Vec<Candle> = Vec::new();
+        for row in rows {
+            let (timestamp, price, quantity) = row?;
+            let (price, quantity) = (price as u64, quantity as u64);
+            let start = timestamp - timestamp.rem_euclid(interval);
+
+            match candles.last_mut() {
+                Some(candle) if candle.start == start => {
+                    candle.high = candle.high.max(price);
+                    candle.low = candle.low.min(price);
+                    candle.close = price;
+                    candle.volume = candle.volume.saturating_add(quantity);
+                    candle.trades += 1;
+                }
+                _ => candles.push(Candle {
+                    start,
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    volume: quantity,
+                    trades: 1,
+                }),
+            }
+        }
+
+        Ok(candles)
+    }
+}
+
+pub fn is_processed(tx: &Transaction, signature: &str) -> Result<bool> {
+    Ok(tx
+        .query_row(
+            "SELECT 1 FROM processed_transactions WHERE signature = ?1",
+            [signature],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some())
+}
+
+pub fn mark_processed(tx: &Transaction, signature: &str, slot: u64) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO processed_transactions (signature, slot) VALUES (?1, ?2)",
+        params![signature, slot as i64],
+    )?;
+    Ok(())
+}
+
+pub fn apply_market_initialized(tx: &Transaction, e: &MarketInitialized) -> Result<()> {
+    tx.execute(
+        "INSERT INTO markets (market_id, authority, collateral_mint, settlement_deadline, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (market_id) DO UPDATE SET
+            authority = excluded.authority,
+            collateral_mint = excluded.collateral_mint,
+            settlement_deadline = excluded.settlement_deadline,
+            created_at = excluded.created_at",
+        params![
+            e.market_id,
+            e.authority.to_string(),
+            e.collateral_mint.to_string(),
+            e.settlement_deadline,
+            e.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+// Splits add collateral to the vault, merges and claims take it out
+pub fn apply_collateral_change(tx: &Transaction, market_id: u32, delta: i64) -> Result<()> {
+    tx.execute(
+        "UPDATE markets SET total_collateral_locked = total_collateral_locked + ?2 WHERE market_id = ?1",
+        params![market_id, delta],
+    )?;
+    Ok(())
+}
+
+pub fn apply_order_placed(tx: &Transaction, e: &OrderPlaced) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO orders
+            (market_id, order_id, user, side, token_type, price, quantity, filled_quantity, status, placed_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, 'open', ?8, ?8)",
+        params![
+            e.market_id,
+            e.order_id as i64,
+            e.user.to_string(),
+            side_str(e.side),
+            token_type_str(e.token_type),
+            e.price as i64,
+            e.quantity as i64,
+            e.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn apply_order_filled(tx: &Transaction, origin: EventOrigin, e: &OrderFilled) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO fills
+            (signature, seq, slot, market_id, taker_order_id, maker_order_id, taker, maker,
+             taker_side, token_type, price, quantity, collateral_amount, taker_fee, maker_fee,
+             maker_rebate, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            origin.slot as i64,
+            e.market_id,
+            e.taker_order_id as i64,
+            e.maker_order_id as i64,
+            e.taker.to_string(),
+            e.maker.to_string(),
+            side_str(e.taker_side),
+            token_type_str(e.token_type),
+            e.price as i64,
+            e.quantity as i64,
+            e.collateral_amount as i64,
+            e.taker_fee as i64,
+            e.maker_fee as i64,
+            e.maker_rebate as i64,
+            e.timestamp
+        ],
+    )?;
+
+    // Both sides of the match move forward by the same quantity
+    for order_id in [e.taker_order_id, e.maker_order_id] {
+        tx.execute(
+            "UPDATE orders SET
+                filled_quantity = filled_quantity + ?3,
+                status = CASE WHEN filled_quantity + ?3 >= quantity THEN 'filled' ELSE status END,
+                updated_at = ?4
+             WHERE market_id = ?1 AND order_id = ?2",
+            params![e.market_id, order_id as i64, e.quantity as i64, e.timestamp],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn apply_order_cancelled(tx: &Transaction, e: &OrderCancelled) -> Result<()> {
+    tx.execute(
+        "UPDATE orders SET status = 'cancelled', updated_at = ?3 WHERE market_id = ?1 AND order_id = ?2",
+        params![e.market_id, e.order_id as i64, e.timestamp],
+    )?;
+    Ok(())
+}
+
+pub fn apply_market_settled(tx: &Transaction, origin: EventOrigin, e: &MarketSettled) -> Result<()> {
+    tx.execute(
+        "INSERT OR REPLACE INTO settlements (market_id, winning_outcome, signature, slot, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            e.market_id,
+            outcome_str(e.winning_outcome),
+            origin.signature,
+            origin.slot as i64,
+            e.timestamp
+        ],
+    )?;
+    tx.execute(
+        "UPDATE markets SET is_settled = 1, winning_outcome = ?2, status = 'settled' WHERE market_id = ?1",
+        params![e.market_id, outcome_str(e.winning_outcome)],
+    )?;
+    Ok(())
+}
+
+pub fn apply_market_status_changed(tx: &Transaction, e: &MarketStatusChanged) -> Result<()> {
+    tx.execute(
+        "UPDATE markets SET status = ?2 WHERE market_id = ?1",
+        params![e.market_id, status_str(e.status)],
+    )?;
+    Ok(())
+}
+
+pub fn apply_market_metadata_updated(tx: &Transaction, e: &MarketMetadataUpdated) -> Result<()> {
+    tx.execute(
+        "UPDATE markets SET meta_data_url = ?2, question_hash = ?3, category = ?4, resolution_source = ?5
+         WHERE market_id = ?1",
+        params![
+            e.market_id,
+            e.meta_data_url,
+            hex(&e.question_hash),
+            e.category,
+            e.resolution_source
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_claim(
+    tx: &Transaction,
+    origin: EventOrigin,
+    market_id: u32,
+    user: &Pubkey,
+    winning_outcome: &str,
+    amount: u64,
+    timestamp: i64,
+) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO claims (signature, seq, market_id, user, winning_outcome, amount, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            market_id,
+            user.to_string(),
+            winning_outcome,
+            amount as i64,
+            timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn apply_rewards_claimed(tx: &Transaction, origin: EventOrigin, e: &RewardsClaimed) -> Result<()> {
+    insert_claim(
+        tx,
+        origin,
+        e.market_id,
+        &e.user,
+        outcome_str(e.winning_outcome),
+        e.amount,
+        e.timestamp,
+    )
+}
+
+pub fn apply_scalar_market_initialized(tx: &Transaction, e: &ScalarMarketInitialized) -> Result<()> {
+    tx.execute(
+        "INSERT INTO markets
+            (market_id, authority, collateral_mint, settlement_deadline, market_type, scalar_low,
+             scalar_high, created_at)
+         VALUES (?1, ?2, ?3, ?4, 'scalar', ?5, ?6, ?7)
+         ON CONFLICT (market_id) DO UPDATE SET
+            authority = excluded.authority,
+            collateral_mint = excluded.collateral_mint,
+            settlement_deadline = excluded.settlement_deadline,
+            market_type = excluded.market_type,
+            scalar_low = excluded.scalar_low,
+            scalar_high = excluded.scalar_high,
+            created_at = excluded.created_at",
+        params![
+            e.market_id,
+            e.authority.to_string(),
+            e.collateral_mint.to_string(),
+            e.settlement_deadline,
+            e.scalar_low,
+            e.scalar_high,
+            e.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn apply_scalar_market_resolved(tx: &Transaction, e: &ScalarMarketResolved) -> Result<()> {
+    tx.execute(
+        "UPDATE markets SET is_settled = 1, resolved_value = ?2, status = 'settled' WHERE market_id = ?1",
+        params![e.market_id, e.value],
+    )?;
+    Ok(())
+}
+
+pub fn apply_scalar_rewards_claimed(
+    tx: &Transaction,
+    origin: EventOrigin,
+    e: &ScalarRewardsClaimed,
+) -> Result<()> {
+    insert_claim(tx, origin, e.market_id, &e.user, "scalar", e.payout, e.timestamp)
+}
+
+pub fn apply_void_refund_claimed(
+    tx: &Transaction,
+    origin: EventOrigin,
+    e: &VoidRefundClaimed,
+) -> Result<()> {
+    insert_claim(tx, origin, e.market_id, &e.user, "voided", e.payout, e.timestamp)
+}
+
+pub fn apply_categorical_market_initialized(
+    tx: &Transaction,
+    e: &CategoricalMarketInitialized,
+) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO categorical_markets
+            (market_id, authority, collateral_mint, outcome_count, settlement_deadline, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            e.market_id,
+            e.authority.to_string(),
+            e.collateral_mint.to_string(),
+            e.outcome_count,
+            e.settlement_deadline,
+            e.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn apply_categorical_collateral_change(tx: &Transaction, market_id: u32, delta: i64) -> Result<()> {
+    tx.execute(
+        "UPDATE categorical_markets SET total_collateral_locked = total_collateral_locked + ?2
+         WHERE market_id = ?1",
+        params![market_id, delta],
+    )?;
+    Ok(())
+}
+
+pub fn apply_categorical_order_placed(tx: &Transaction, e: &CategoricalOrderPlaced) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO categorical_orders
+            (market_id, outcome_index, order_id, user, side, price, quantity, filled_quantity,
+             status, placed_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, 'open', ?8, ?8)",
+        params![
+            e.market_id,
+            e.outcome_index,
+            e.order_id as i64,
+            e.user.to_string(),
+            side_str(e.side),
+            e.price as i64,
+            e.quantity as i64,
+            e.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn apply_categorical_order_filled(
+    tx: &Transaction,
+    origin: EventOrigin,
+    e: &CategoricalOrderFilled,
+) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO categorical_fills
+            (signature, seq, slot, market_id, outcome_index, taker_order_id, maker_order_id, taker,
+             maker, taker_side, price, quantity, collateral_amount, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            origin.slot as i64,
+            e.market_id,
+            e.outcome_index,
+            e.taker_order_id as i64,
+            e.maker_order_id as i64,
+            e.taker.to_string(),
+            e.maker.to_string(),
+            side_str(e.taker_side),
+            e.price as i64,
+            e.quantity as i64,
+            e.collateral_amount as i64,
+            e.timestamp
+        ],
+    )?;
+
+    for order_id in [e.taker_order_id, e.maker_order_id] {
+        tx.execute(
+            "UPDATE categorical_orders SET
+                filled_quantity = filled_quantity + ?4,
+                status = CASE WHEN filled_quantity + ?4 >= quantity THEN 'filled' ELSE status END,
+                updated_at = ?5
+             WHERE market_id = ?1 AND outcome_index = ?2 AND order_id = ?3",
+            params![
+                e.market_id,
+                e.outcome_index,
+                order_id as i64,
+                e.quantity as i64,
+                e.timestamp
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn apply_categorical_order_cancelled(
+    tx: &Transaction,
+    e: &CategoricalOrderCancelled,
+) -> Result<()> {
+    tx.execute(
+        "UPDATE categorical_orders SET status = 'cancelled', updated_at = ?4
+         WHERE market_id = ?1 AND outcome_index = ?2 AND order_id = ?3",
+        params![e.market_id, e.outcome_index, e.order_id as i64, e.timestamp],
+    )?;
+    Ok(())
+}
+
+pub fn apply_categorical_market_settled(tx: &Transaction, e: &CategoricalMarketSettled) -> Result<()> {
+    tx.execute(
+        "UPDATE categorical_markets SET is_settled = 1, winning_outcome = ?2 WHERE market_id = ?1",
+        params![e.market_id, e.winning_outcome],
+    )?;
+    Ok(())
+}
+
+pub fn apply_categorical_rewards_claimed(
+    tx: &Transaction,
+    origin: EventOrigin,
+    e: &CategoricalRewardsClaimed,
+) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO categorical_claims
+            (signature, seq, market_id, user, winning_outcome, amount, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            e.market_id,
+            e.user.to_string(),
+            e.winning_outcome,
+            e.amount as i64,
+            e.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn apply_market_group_initialized(tx: &Transaction, e: &MarketGroupInitialized) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO market_groups (group_id, authority, created_at) VALUES (?1, ?2, ?3)",
+        params![e.group_id, e.authority.to_string(), e.timestamp],
+    )?;
+    Ok(())
+}
+
+pub fn apply_market_added_to_group(tx: &Transaction, e: &MarketAddedToGroup) -> Result<()> {
+    tx.execute(
+        "UPDATE markets SET group_id = ?2, group_index = ?3 WHERE market_id = ?1",
+        params![e.market_id, e.group_id, e.index],
+    )?;
+    Ok(())
+}
+
+// The collateral moves between the vaults of the group's markets, the market snapshots catch up on it
+pub fn apply_positions_converted(
+    tx: &Transaction,
+    origin: EventOrigin,
+    e: &PositionsConverted,
+) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO conversions
+            (signature, seq, group_id, user, no_market_mask, amount, collateral_released, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            e.group_id,
+            e.user.to_string(),
+            e.no_market_mask,
+            e.amount as i64,
+            e.collateral_released as i64,
+            e.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+// Every market of the group also emits its own MarketSettled
+pub fn apply_market_group_resolved(tx: &Transaction, e: &MarketGroupResolved) -> Result<()> {
+    tx.execute(
+        "UPDATE market_groups SET is_resolved = 1, winning_market_id = ?2 WHERE group_id = ?1",
+        params![e.group_id, e.winning_market_id],
+    )?;
+    Ok(())
+}
+
+pub fn apply_market_fees_updated(tx: &Transaction, e: &MarketFeesUpdated) -> Result<()> {
+    tx.execute(
+        "UPDATE markets SET taker_fee_bps = ?2, maker_fee_bps = ?3 WHERE market_id = ?1",
+        params![e.market_id, e.taker_fee_bps, e.maker_fee_bps],
+    )?;
+    Ok(())
+}
+
+fn insert_fee_withdrawal(
+    tx: &Transaction,
+    origin: EventOrigin,
+    market_id: u32,
+    recipient: &Pubkey,
+    kind: &str,
+    amount: u64,
+    timestamp: i64,
+) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO fee_withdrawals (signature, seq, market_id, recipient, kind, amount, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            market_id,
+            recipient.to_string(),
+            kind,
+            amount as i64,
+            timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn apply_fees_withdrawn(tx: &Transaction, origin: EventOrigin, e: &FeesWithdrawn) -> Result<()> {
+    let kind = if e.creator_fees { "creator" } else { "protocol" };
+    insert_fee_withdrawal(tx, origin, e.market_id, &e.recipient, kind, e.amount, e.timestamp)
+}
+
+pub fn apply_referral_fees_withdrawn(
+    tx: &Transaction,
+    origin: EventOrigin,
+    e: &ReferralFeesWithdrawn,
+) -> Result<()> {
+    insert_fee_withdrawal(tx, origin, e.market_id, &e.referrer, "referral", e.amount, e.timestamp)
+}
+
+pub fn apply_referrer_recorded(tx: &Transaction, e: &ReferrerRecorded) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO referrals (market_id, user, referrer, recorded_at) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            e.market_id,
+            e.user.to_string(),
+            e.referrer.to_string(),
+            e.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn apply_referral_fee_credited(tx: &Transaction, e: &ReferralFeeCredited) -> Result<()> {
+    tx.execute(
+        "UPDATE referrals SET fees_credited = fees_credited + ?3 WHERE market_id = ?1 AND user = ?2",
+        params![e.market_id, e.user.to_string(), e.amount as i64],
+    )?;
+    Ok(())
+}
+
+pub fn apply_amm_pool_initialized(tx: &Transaction, e: &AmmPoolInitialized) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO amm_pools (market_id, lp_mint, fee_bps, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![e.market_id, e.lp_mint.to_string(), e.fee_bps, e.timestamp],
+    )?;
+    Ok(())
+}
+
+// One row of liquidity_changes
+struct LiquidityChange<'a> {
+    market_id: u32,
+    user: &'a Pubkey,
+    kind: &'a str,
+    collateral_amount: u64,
+    shares: u64,
+    yes_amount: u64,
+    no_amount: u64,
+    timestamp: i64,
+}
+
+fn insert_liquidity_change(tx: &Transaction, origin: EventOrigin, c: LiquidityChange) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO liquidity_changes
+            (signature, seq, market_id, user, kind, collateral_amount, shares, yes_amount, no_amount,
+             timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            c.market_id,
+            c.user.to_string(),
+            c.kind,
+            c.collateral_amount as i64,
+            c.shares as i64,
+            c.yes_amount as i64,
+            c.no_amount as i64,
+            c.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+// The deposit is split into outcome tokens, so it backs the market like a split does
+pub fn apply_liquidity_added(tx: &Transaction, origin: EventOrigin, e: &LiquidityAdded) -> Result<()> {
+    insert_liquidity_change(
+        tx,
+        origin,
+        LiquidityChange {
+            market_id: e.market_id,
+            user: &e.user,
+            kind: "add",
+            collateral_amount: e.collateral_amount,
+            shares: e.shares,
+            yes_amount: e.yes_returned,
+            no_amount: e.no_returned,
+            timestamp: e.timestamp,
+        },
+    )?;
+    tx.execute(
+        "UPDATE amm_pools SET total_shares = total_shares + ?2 WHERE market_id = ?1",
+        params![e.market_id, e.shares as i64],
+    )?;
+    apply_collateral_change(tx, e.market_id, e.collateral_amount as i64)
+}
+
+pub fn apply_liquidity_removed(
+    tx: &Transaction,
+    origin: EventOrigin,
+    e: &LiquidityRemoved,
+) -> Result<()> {
+    insert_liquidity_change(
+        tx,
+        origin,
+        LiquidityChange {
+            market_id: e.market_id,
+            user: &e.user,
+            kind: "remove",
+            collateral_amount: 0,
+            shares: e.shares,
+            yes_amount: e.yes_amount,
+            no_amount: e.no_amount,
+            timestamp: e.timestamp,
+        },
+    )?;
+    tx.execute(
+        "UPDATE amm_pools SET total_shares = total_shares - ?2 WHERE market_id = ?1",
+        params![e.market_id, e.shares as i64],
+    )?;
+    Ok(())
+}
+
+// One row of amm_trades
+struct AmmTrade<'a> {
+    venue: &'a str,
+    market_id: u32,
+    user: &'a Pubkey,
+    side: OrderSide,
+    token_type: TokenType,
+    collateral_amount: u64,
+    outcome_amount: u64,
+    fee: u64,
+    timestamp: i64,
+}
+
+// Buys split the collateral into outcome tokens and sells merge them back
+fn insert_amm_trade(tx: &Transaction, origin: EventOrigin, t: AmmTrade) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO amm_trades
+            (signature, seq, slot, market_id, venue, user, side, token_type, collateral_amount,
+             outcome_amount, fee, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            origin.slot as i64,
+            t.market_id,
+            t.venue,
+            t.user.to_string(),
+            side_str(t.side),
+            token_type_str(t.token_type),
+            t.collateral_amount as i64,
+            t.outcome_amount as i64,
+            t.fee as i64,
+            t.timestamp
+        ],
+    )?;
+
+    let delta = match t.side {
+        OrderSide::Buy => t.collateral_amount as i64,
+        OrderSide::Sell => -(t.collateral_amount as i64),
+    };
+    apply_collateral_change(tx, t.market_id, delta)
+}
+
+pub fn apply_amm_traded(tx: &Transaction, origin: EventOrigin, e: &AmmTraded) -> Result<()> {
+    insert_amm_trade(
+        tx,
+        origin,
+        AmmTrade {
+            venue: "pool",
+            market_id: e.market_id,
+            user: &e.user,
+            side: e.side,
+            token_type: e.token_type,
+            collateral_amount: e.collateral_amount,
+            outcome_amount: e.outcome_amount,
+            fee: e.fee,
+            timestamp: e.timestamp,
+        },
+    )
+}
+
+// The subsidy is deposited into the market's vault and backs the maker's worst case loss
+pub fn apply_lmsr_initialized(tx: &Transaction, e: &LmsrInitialized) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO lmsr_makers (market_id, creator, liquidity, subsidy, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            e.market_id,
+            e.creator.to_string(),
+            e.liquidity as i64,
+            e.subsidy as i64,
+            e.timestamp
+        ],
+    )?;
+    apply_collateral_change(tx, e.market_id, e.subsidy as i64)
+}
+
+pub fn apply_lmsr_traded(tx: &Transaction, origin: EventOrigin, e: &LmsrTraded) -> Result<()> {
+    insert_amm_trade(
+        tx,
+        origin,
+        AmmTrade {
+            venue: "lmsr",
+            market_id: e.market_id,
+            user: &e.user,
+            side: e.side,
+            token_type: e.token_type,
+            collateral_amount: e.collateral_amount,
+            outcome_amount: e.shares,
+            fee: 0,
+            timestamp: e.timestamp,
+        },
+    )
+}
+
+pub fn apply_lmsr_subsidy_withdrawn(tx: &Transaction, e: &LmsrSubsidyWithdrawn) -> Result<()> {
+    tx.execute(
+        "UPDATE lmsr_makers SET subsidy_withdrawn = subsidy_withdrawn + ?2 WHERE market_id = ?1",
+        params![e.market_id, e.amount as i64],
+    )?;
+    apply_collateral_change(tx, e.market_id, -(e.amount as i64))
+}
+
+// Collateral and the AMM side are already accounted for by the AMM event of the same order
+pub fn apply_market_order_filled(
+    tx: &Transaction,
+    origin: EventOrigin,
+    e: &MarketOrderFilled,
+) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO market_orders
+            (signature, seq, slot, market_id, order_id, user, side, token_type, book_quantity,
+             amm_quantity, collateral_amount, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            origin.slot as i64,
+            e.market_id,
+            e.order_id as i64,
+            e.user.to_string(),
+            side_str(e.side),
+            token_type_str(e.token_type),
+            e.book_quantity as i64,
+            e.amm_quantity as i64,
+            e.collateral_amount as i64,
+            e.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+// One row of split_merge_orders
+struct SplitMergeOrder<'a> {
+    market_id: u32,
+    order_id: u64,
+    user: &'a Pubkey,
+    kind: &'a str,
+    token_type: TokenType,
+    quantity: u64,
+    collateral_amount: u64,
+    timestamp: i64,
+}
+
+// The split or merge itself comes with its own TokensSplit / TokensMerged event
+fn insert_split_merge_order(tx: &Transaction, origin: EventOrigin, o: SplitMergeOrder) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO split_merge_orders
+            (signature, seq, slot, market_id, order_id, user, kind, token_type, quantity,
+             collateral_amount, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            origin.slot as i64,
+            o.market_id,
+            o.order_id as i64,
+            o.user.to_string(),
+            o.kind,
+            token_type_str(o.token_type),
+            o.quantity as i64,
+            o.collateral_amount as i64,
+            o.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn apply_split_and_sold(tx: &Transaction, origin: EventOrigin, e: &SplitAndSold) -> Result<()> {
+    insert_split_merge_order(
+        tx,
+        origin,
+        SplitMergeOrder {
+            market_id: e.market_id,
+            order_id: e.order_id,
+            user: &e.user,
+            kind: "split_and_sell",
+            token_type: e.token_type,
+            quantity: e.quantity,
+            collateral_amount: e.proceeds,
+            timestamp: e.timestamp,
+        },
+    )
+}
+
+pub fn apply_bought_and_merged(
+    tx: &Transaction,
+    origin: EventOrigin,
+    e: &BoughtAndMerged,
+) -> Result<()> {
+    insert_split_merge_order(
+        tx,
+        origin,
+        SplitMergeOrder {
+            market_id: e.market_id,
+            order_id: e.order_id,
+            user: &e.user,
+            kind: "buy_and_merge",
+            token_type: e.token_type,
+            quantity: e.quantity,
+            collateral_amount: e.cost,
+            timestamp: e.timestamp,
+        },
+    )
+}
+
+pub fn apply_liquidity_rewards_created(tx: &Transaction, e: &LiquidityRewardsCreated) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO liquidity_rewards
+            (market_id, sponsor, rewards_per_epoch, epoch_duration, epochs, max_spread_bps,
+             sample_interval, min_size, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            e.market_id,
+            e.sponsor.to_string(),
+            e.rewards_per_epoch as i64,
+            e.epoch_duration,
+            e.epochs,
+            e.max_spread_bps,
+            e.sample_interval,
+            e.min_size as i64,
+            e.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+// Scores are u128, so they are summed here rather than in SQL
+pub fn apply_liquidity_rewards_sampled(tx: &Transaction, e: &LiquidityRewardsSampled) -> Result<()> {
+    let sponsor = e.sponsor.to_string();
+    let score: Option<String> = tx
+        .query_row(
+            "SELECT score FROM liquidity_reward_epochs
+             WHERE market_id = ?1 AND sponsor = ?2 AND epoch = ?3",
+            params![e.market_id, sponsor, e.epoch],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let score = match score {
+        Some(score) => score.parse::<u128>()?.saturating_add(e.score),
+        None => e.score,
+    };
+
+    tx.execute(
+        "INSERT INTO liquidity_reward_epochs (market_id, sponsor, epoch, samples, score)
+         VALUES (?1, ?2, ?3, 1, ?4)
+         ON CONFLICT (market_id, sponsor, epoch) DO UPDATE SET
+            samples = samples + 1,
+            score = excluded.score",
+        params![e.market_id, sponsor, e.epoch, score.to_string()],
+    )?;
+    Ok(())
+}
+
+// Epochs closed without a sample get a row too, with no samples and no score
+pub fn apply_liquidity_rewards_epoch_closed(
+    tx: &Transaction,
+    e: &LiquidityRewardsEpochClosed,
+) -> Result<()> {
+    let sponsor = e.sponsor.to_string();
+    tx.execute(
+        "INSERT INTO liquidity_reward_epochs (market_id, sponsor, epoch, distributed, closed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (market_id, sponsor, epoch) DO UPDATE SET
+            distributed = excluded.distributed,
+            closed_at = excluded.closed_at",
+        params![e.market_id, sponsor, e.epoch, e.distributed as i64, e.timestamp],
+    )?;
+    tx.execute(
+        "UPDATE liquidity_rewards SET distributed = distributed + ?3
+         WHERE market_id = ?1 AND sponsor = ?2",
+        params![e.market_id, sponsor, e.distributed as i64],
+    )?;
+    Ok(())
+}
+
+pub fn apply_liquidity_rewards_claimed(
+    tx: &Transaction,
+    origin: EventOrigin,
+    e: &LiquidityRewardsClaimed,
+) -> Result<()> {
+    let sponsor = e.sponsor.to_string();
+    tx.execute(
+        "INSERT OR IGNORE INTO liquidity_reward_claims
+            (signature, seq, market_id, sponsor, maker, amount, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            origin.signature,
+            origin.seq as i64,
+            e.market_id,
+            sponsor,
+            e.maker.to_string(),
+            e.amount as i64,
+            e.timestamp
+        ],
+    )?;
+    tx.execute(
+        "UPDATE liquidity_rewards SET claimed = claimed + ?3
+         WHERE market_id = ?1 AND sponsor = ?2",
+        params![e.market_id, sponsor, e.amount as i64],
+    )?;
+    Ok(())
+}
+
+pub fn apply_liquidity_rewards_withdrawn(
+    tx: &Transaction,
+    e: &LiquidityRewardsWithdrawn,
+) -> Result<()> {
+    tx.execute(
+        "UPDATE liquidity_rewards SET withdrawn = withdrawn + ?3
+         WHERE market_id = ?1 AND sponsor = ?2",
+        params![e.market_id, e.sponsor.to_string(), e.amount as i64],
+    )?;
+    Ok(())
+}
+
+pub fn upsert_market(tx: &Transaction, address: &Pubkey, market: &Market) -> Result<()> {
+    let scalar = market.market_type == MarketType::Scalar;
+    tx.execute(
+        "INSERT INTO markets
+            (market_id, address, authority, collateral_mint, collateral_vault, outcome_yes_mint,
+             outcome_no_mint, settlement_deadline, total_collateral_locked, is_settled,
+             winning_outcome, meta_data_url, status, question_hash, category, resolution_source,
+             market_type, scalar_low, scalar_high, resolved_value, taker_fee_bps, maker_fee_bps)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+                 ?19, ?20, ?21, ?22)
+         ON CONFLICT (market_id) DO UPDATE SET
+            address = excluded.address,
+            authority = excluded.authority,
+            collateral_mint = excluded.collateral_mint,
+            collateral_vault = excluded.collateral_vault,
+            outcome_yes_mint = excluded.outcome_yes_mint,
+            outcome_no_mint = excluded.outcome_no_mint,
+            settlement_deadline = excluded.settlement_deadline,
+            total_collateral_locked = excluded.total_collateral_locked,
+            is_settled = excluded.is_settled,
+            winning_outcome = excluded.winning_outcome,
+            meta_data_url = excluded.meta_data_url,
+            status = excluded.status,
+            question_hash = excluded.question_hash,
+            category = excluded.category,
+            resolution_source = excluded.resolution_source,
+            market_type = excluded.market_type,
+            scalar_low = excluded.scalar_low,
+            scalar_high = excluded.scalar_high,
+            resolved_value = excluded.resolved_value,
+            taker_fee_bps = excluded.taker_fee_bps,
+            maker_fee_bps = excluded.maker_fee_bps",
+        params![
+            market.market_id,
+            address.to_string(),
+            market.authority.to_string(),
+            market.collateral_mint.to_string(),
+            market.collateral_vault.to_string(),
+            market.outcome_yes_mint.to_string(),
+            market.outcome_no_mint.to_string(),
+            market.settlement_deadline,
+            market.total_collateral_locked as i64,
+            market.is_settled,
+            market.winning_outcome.map(outcome_str),
+            market.meta_data_url,
+            status_str(market.status),
+            hex(&market.question_hash),
+            market.category,
+            market.resolution_source,
+            market_type_str(market.market_type),
+            scalar.then_some(market.scalar_low),
+            scalar.then_some(market.scalar_high),
+            market.resolved_value,
+            market.taker_fee_bps,
+            market.maker_fee_bps
+        ],
+    )?;
+    Ok(())
+}
+
+fn upsert_resting_order(tx: &Transaction, order: &Order) -> Result<()> {
+    tx.execute(
+        "INSERT INTO orders
+            (market_id, order_id, user, side, token_type, price, quantity, filled_quantity, status, placed_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 'open', ?9, ?9)
+         ON CONFLICT (market_id, order_id) DO UPDATE SET
+            filled_quantity = excluded.filled_quantity,
+            status = 'open'",
+        params![
+            order.market_id,
+            order.id as i64,
+            order.user_key.to_string(),
+            side_str(order.side),
+            token_type_str(order.token_type),
+            order.price as i64,
+            order.quantity as i64,
+            order.filledquantity as i64,
+            order.timestamp
+        ],
+    )?;
+    Ok(())
+}
+
+/// The book snapshot is the source of truth for which orders are resting
+pub fn replace_order_book(tx: &Transaction, orderbook: &OrderBook) -> Result<()> {
+    let resting: Vec<&Order> = orderbook
+        .yes_buy_orders
+        .iter()
+        .chain(orderbook.yes_sell_orders.iter())
+        .chain(orderbook.no_buy_orders.iter())
+        .chain(orderbook.no_sell_orders.iter())
+        .collect();
+
+    tx.execute(
+        "CREATE TEMP TABLE IF NOT EXISTS snapshot_order_ids (order_id INTEGER PRIMARY KEY)",
+        [],
+    )?;
+    tx.execute("DELETE FROM snapshot_order_ids", [])?;
+
+    for order in resting.iter() {
+        upsert_resting_order(tx, order)?;
+        tx.execute(
+            "INSERT OR IGNORE INTO snapshot_order_ids (order_id) VALUES (?1)",
+            [order.id as i64],
+        )?;
+    }
+
+    tx.execute(
+        "UPDATE orders SET status = 'closed'
+         WHERE market_id = ?1 AND status = 'open'
+           AND order_id NOT IN (SELECT order_id FROM snapshot_order_ids)",
+        [orderbook.market_id],
+    )?;
+    Ok(())
+}
+
+pub fn upsert_user_stats(tx: &Transaction, address: &Pubkey, stats: &UserStats) -> Result<()> {
+    tx.execute(
+        "INSERT OR REPLACE INTO user_stats
+            (address, user, market_id, claimable_yes, locked_yes, claimable_no, locked_no,
+             claimable_collateral, locked_collateral, reward_claimed)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            address.to_string(),
+            stats.user.to_string(),
+            stats.market_id,
+            stats.claimable_yes as i64,
+            stats.locked_yes as i64,
+            stats.claimable_no as i64,
+            stats.locked_no as i64,
+            stats.claimable_collateral as i64,
+            stats.locked_collateral as i64,
+            stats.reward_claimed
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNATURE: &str = "sig";
+
+    fn origin(seq: usize) -> EventOrigin<'static> {
+        EventOrigin {
+            signature: SIGNATURE,
+            slot: 7,
+            seq,
+        }
+    }
+
+    fn key(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    fn market_initialized(market_id: u32) -> MarketInitialized {
+        MarketInitialized {
+            market_id,
+            authority: key(1),
+            collateral_mint: key(2),
+            settlement_deadline: 1_000,
+            timestamp: 10,
+        }
+    }
+
+    fn order_placed(order_id: u64, side: OrderSide, quantity: u64) -> OrderPlaced {
+        OrderPlaced {
+            market_id: 1,
+            order_id,
+            user: key(order_id as u8),
+            side,
+            token_type: TokenType::Yes,
+            price: 3,
+            quantity,
+            timestamp: 20,
+        }
+    }
+
+    fn order(id: u64, filledquantity: u64) -> Order {
+        Order {
+            id,
+            market_id: 1,
+            user_key: key(id as u8),
+            user_stats: key(9),
+            side: OrderSide::Buy,
+            token_type: TokenType::Yes,
+            price: 3,
+            quantity: 10,
+            filledquantity,
+            timestamp: 20,
+            fee_reserved: 0,
+        }
+    }
+
+    fn market(market_id: u32) -> Market {
+        Market {
+            authority: key(1),
+            market_id,
+            settlement_deadline: 2_000,
+            collateral_mint: key(2),
+            collateral_vault: key(3),
+            is_settled: false,
+            winning_outcome: None,
+            total_collateral_locked: 500,
+            bump: 255,
+            meta_data_url: "https://example.com/1.json".to_string(),
+            outcome_yes_mint: key(4),
+            outcome_no_mint: key(5),
+            yes_escrow: key(6),
+            no_escrow: key(7),
+            market_type: MarketType::Binary,
+            scalar_low: 0,
+            scalar_high: 0,
+            resolved_value: None,
+            market_group: None,
+            status: MarketStatus::CloseOnly,
+            question_hash: [0xab; 32],
+            category: "sports".to_string(),
+            resolution_source: "league site".to_string(),
+            trading_started: true,
+            fee_vault: key(8),
+            taker_fee_bps: 30,
+            maker_fee_bps: 10,
+            protocol_fees_accrued: 0,
+            creator_fees_accrued: 0,
+            amm: None,
+        }
+    }
+
+    fn order_row(store: &Store, order_id: u64) -> (u64, String) {
+        store
+            .connection()
+            .query_row(
+                "SELECT filled_quantity, status FROM orders WHERE market_id = 1 AND order_id = ?1",
+                [order_id as i64],
+                |row| Ok((row.get::<_, i64>(0)? as u64, row.get(1)?)),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn fills_move_both_orders_and_feed_candles() {
+        let mut store = Store::open_in_memory().unwrap();
+        let tx = store.transaction().unwrap();
+        apply_market_initialized(&tx, &market_initialized(1)).unwrap();
+        apply_order_placed(&tx, &order_placed(1, OrderSide::Sell, 10)).unwrap();
+        apply_order_placed(&tx, &order_placed(2, OrderSide::Buy, 4)).unwrap();
+        let fill = OrderFilled {
+            market_id: 1,
+            taker_order_id: 2,
+            maker_order_id: 1,
+            taker: key(2),
+            maker: key(1),
+            taker_side: OrderSide::Buy,
+            token_type: TokenType::Yes,
+            price: 3,
+            quantity: 4,
+            collateral_amount: 12,
+            taker_fee: 0,
+            maker_fee: 0,
+            maker_rebate: 0,
+            timestamp: 3_605,
+        };
+        apply_order_filled(&tx, origin(0), &fill).unwrap();
+        // Replaying the same event row is ignored, the fill is keyed by signature and seq
+        apply_order_filled(&tx, origin(0), &fill).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(order_row(&store, 1).1, "open");
+        assert_eq!(order_row(&store, 2), (8, "filled".to_string()));
+
+        let fills: i64 = store
+            .connection()
+            .query_row("SELECT COUNT(*) FROM fills", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(fills, 1);
+
+        let candles = store.candles(1, TokenType::Yes, 3_600).unwrap();
+        assert_eq!(
+            candles,
+            vec![Candle {
+                start: 3_600,
+                open: 3,
+                high: 3,
+                low: 3,
+                close: 3,
+                volume: 4,
+                trades: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn market_snapshot_upserts_over_the_event_row() {
+        let mut store = Store::open_in_memory().unwrap();
+        let tx = store.transaction().unwrap();
+        apply_market_initialized(&tx, &market_initialized(1)).unwrap();
+        upsert_market(&tx, &key(42), &market(1)).unwrap();
+        tx.commit().unwrap();
+
+        let row: (String, i64, String, String, Option<i64>, i64, i64) = store
+            .connection()
+            .query_row(
+                "SELECT address, total_collateral_locked, status, question_hash, scalar_low,
+                        taker_fee_bps, created_at
+                 FROM markets WHERE market_id = 1",
+                [],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                        row.get(6)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            row,
+            (
+                key(42).to_string(),
+                500,
+                "close_only".to_string(),
+                "ab".repeat(32),
+                None,
+                30,
+                10
+            )
+        );
+    }
+
+    #[test]
+    fn user_stats_snapshot_replaces_the_previous_one() {
+        let mut stats = UserStats {
+            user: key(1),
+            market_id: 1,
+            claimable_yes: 5,
+            locked_yes: 0,
+            claimable_no: 0,
+            locked_no: 0,
+            claimable_collateral: 0,
+            locked_collateral: 30,
+            reward_claimed: false,
+            referrer: None,
+            referral_fees_claimable: 0,
+            bump: 255,
+        };
+
+        let mut store = Store::open_in_memory().unwrap();
+        let tx = store.transaction().unwrap();
+        upsert_user_stats(&tx, &key(3), &stats).unwrap();
+        stats.claimable_yes = 0;
+        stats.locked_collateral = 12;
+        upsert_user_stats(&tx, &key(3), &stats).unwrap();
+        tx.commit().unwrap();
+
+        let rows: Vec<(i64, i64)> = store
+            .connection()
+            .prepare("SELECT claimable_yes, locked_collateral FROM user_stats")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(rows, vec![(0, 12)]);
+    }
+
+    #[test]
+    fn book_snapshot_closes_orders_that_left_the_book() {
+        let mut store = Store::open_in_memory().unwrap();
+        let tx = store.transaction().unwrap();
+        apply_order_placed(&tx, &order_placed(1, OrderSide::Buy, 10)).unwrap();
+        apply_order_placed(&tx, &order_placed(2, OrderSide::Buy, 10)).unwrap();
+        let orderbook = OrderBook {
+            market_id: 1,
+            next_order_id: 4,
+            yes_buy_orders: vec![order(2, 3), order(3, 0)],
+            yes_sell_orders: vec![],
+            no_buy_orders: vec![],
+            no_sell_orders: vec![],
+            bump: 255,
+        };
+        replace_order_book(&tx, &orderbook).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(order_row(&store, 1), (0, "closed".to_string()));
+        assert_eq!(order_row(&store, 2), (3, "open".to_string()));
+        // Placed in a transaction the indexer never saw, the snapshot still brings it in
+        assert_eq!(order_row(&store, 3), (0, "open".to_string()));
+    }
+
+    #[test]
+    fn claims_take_collateral_out_of_the_market() {
+        let mut store = Store::open_in_memory().unwrap();
+        let tx = store.transaction().unwrap();
+        apply_market_initialized(&tx, &market_initialized(1)).unwrap();
+        apply_collateral_change(&tx, 1, 100).unwrap();
+        let refund = VoidRefundClaimed {
+            market_id: 1,
+            user: key(5),
+            yes_amount: 60,
+            no_amount: 0,
+            payout: 30,
+            timestamp: 50,
+        };
+        apply_void_refund_claimed(&tx, origin(1), &refund).unwrap();
+        apply_collateral_change(&tx, 1, -(refund.payout as i64)).unwrap();
+        tx.commit().unwrap();
+
+        let locked: i64 = store
+            .connection()
+            .query_row(
+                "SELECT total_collateral_locked FROM markets WHERE market_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(locked, 70);
+
+        let claim: (String, i64) = store
+            .connection()
+            .query_row("SELECT winning_outcome, amount FROM claims", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(claim, ("voided".to_string(), 30));
+    }
+
+    #[test]
+    fn amm_events_track_shares_and_collateral() {
+        let mut store = Store::open_in_memory().unwrap();
+        let tx = store.transaction().unwrap();
+        apply_market_initialized(&tx, &market_initialized(1)).unwrap();
+        let pool = AmmPoolInitialized {
+            market_id: 1,
+            lp_mint: key(3),
+            fee_bps: 100,
+            timestamp: 10,
+        };
+        apply_amm_pool_initialized(&tx, &pool).unwrap();
+        let added = LiquidityAdded {
+            market_id: 1,
+            user: key(4),
+            collateral_amount: 1_000,
+            shares: 1_000,
+            yes_returned: 0,
+            no_returned: 0,
+            timestamp: 11,
+        };
+        apply_liquidity_added(&tx, origin(0), &added).unwrap();
+        let removed = LiquidityRemoved {
+            market_id: 1,
+            user: key(4),
+            shares: 400,
+            yes_amount: 380,
+            no_amount: 420,
+            timestamp: 12,
+        };
+        apply_liquidity_removed(&tx, origin(1), &removed).unwrap();
+        for (seq, side, collateral_amount) in [(2, OrderSide::Buy, 100), (3, OrderSide::Sell, 40)] {
+            let trade = AmmTraded {
+                market_id: 1,
+                user: key(5),
+                side,
+                token_type: TokenType::Yes,
+                collateral_amount,
+                outcome_amount: 150,
+                fee: 1,
+                timestamp: 13,
+            };
+            apply_amm_traded(&tx, origin(seq), &trade).unwrap();
+        }
+        tx.commit().unwrap();
+
+        let shares: i64 = store
+            .connection()
+            .query_row("SELECT total_shares FROM amm_pools WHERE market_id = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(shares, 600);
+
+        let locked: i64 = store
+            .connection()
+            .query_row(
+                "SELECT total_collateral_locked FROM markets WHERE market_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(locked, 1_000 + 100 - 40);
+
+        let trades: i64 = store
+            .connection()
+            .query_row("SELECT COUNT(*) FROM amm_trades", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(trades, 2);
+    }
+
+    #[test]
+    fn lmsr_trades_share_the_amm_trade_table() {
+        let mut store = Store::open_in_memory().unwrap();
+        let tx = store.transaction().unwrap();
+        apply_market_initialized(&tx, &market_initialized(1)).unwrap();
+        let maker = LmsrInitialized {
+            market_id: 1,
+            creator: key(3),
+            liquidity: 1_000,
+            subsidy: 694,
+            timestamp: 10,
+        };
+        apply_lmsr_initialized(&tx, &maker).unwrap();
+        let trade = LmsrTraded {
+            market_id: 1,
+            user: key(4),
+            side: OrderSide::Buy,
+            token_type: TokenType::No,
+            shares: 100,
+            collateral_amount: 51,
+            timestamp: 11,
+        };
+        apply_lmsr_traded(&tx, origin(0), &trade).unwrap();
+        let withdrawn = LmsrSubsidyWithdrawn {
+            market_id: 1,
+            creator: key(3),
+            amount: 600,
+            timestamp: 12,
+        };
+        apply_lmsr_subsidy_withdrawn(&tx, &withdrawn).unwrap();
+        tx.commit().unwrap();
+
+        let row: (String, i64, i64) = store
+            .connection()
+            .query_row("SELECT venue, outcome_amount, fee FROM amm_trades", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(row, ("lmsr".to_string(), 100, 0));
+
+        let withdrawn: i64 = store
+            .connection()
+            .query_row("SELECT subsidy_withdrawn FROM lmsr_makers", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(withdrawn, 600);
+
+        let locked: i64 = store
+            .connection()
+            .query_row(
+                "SELECT total_collateral_locked FROM markets WHERE market_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(locked, 694 + 51 - 600);
+    }
+
+    #[test]
+    fn market_orders_are_recorded_once() {
+        let mut store = Store::open_in_memory().unwrap();
+        let tx = store.transaction().unwrap();
+        let order = MarketOrderFilled {
+            market_id: 1,
+            order_id: 5,
+            user: key(4),
+            side: OrderSide::Sell,
+            token_type: TokenType::No,
+            book_quantity: 10,
+            amm_quantity: 25,
+            collateral_amount: 14,
+            timestamp: 30,
+        };
+        apply_market_order_filled(&tx, origin(3), &order).unwrap();
+        apply_market_order_filled(&tx, origin(3), &order).unwrap();
+        tx.commit().unwrap();
+
+        let rows: Vec<(i64, String, i64, i64)> = store
+            .connection()
+            .prepare("SELECT order_id, side, book_quantity, amm_quantity FROM market_orders")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(rows, vec![(5, "sell".to_string(), 10, 25)]);
+    }
+
+    #[test]
+    fn split_and_merge_orders_share_a_table() {
+        let mut store = Store::open_in_memory().unwrap();
+        let tx = store.transaction().unwrap();
+        let sold = SplitAndSold {
+            market_id: 1,
+            order_id: 6,
+            user: key(4),
+            token_type: TokenType::Yes,
+            quantity: 100,
+            proceeds: 60,
+            timestamp: 40,
+        };
+        apply_split_and_sold(&tx, origin(0), &sold).unwrap();
+        let merged = BoughtAndMerged {
+            market_id: 1,
+            order_id: 7,
+            user: key(4),
+            token_type: TokenType::No,
+            quantity: 50,
+            cost: 20,
+            timestamp: 41,
+        };
+        apply_bought_and_merged(&tx, origin(1), &merged).unwrap();
+        tx.commit().unwrap();
+
+        let rows: Vec<(String, String, i64)> = store
+            .connection()
+            .prepare("SELECT kind, token_type, collateral_amount FROM split_merge_orders ORDER BY seq")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("split_and_sell".to_string(), "yes".to_string(), 60),
+                ("buy_and_merge".to_string(), "no".to_string(), 20),
+            ]
+        );
+    }
+
+    #[test]
+    fn liquidity_rewards_track_epochs_and_payouts() {
+        let mut store = Store::open_in_memory().unwrap();
+        let tx = store.transaction().unwrap();
+        apply_liquidity_rewards_created(
+            &tx,
+            &LiquidityRewardsCreated {
+                market_id: 1,
+                sponsor: key(9),
+                rewards_per_epoch: 1_000,
+                epoch_duration: 600,
+                epochs: 2,
+                max_spread_bps: 5_000,
+                sample_interval: 60,
+                min_size: 10,
+                timestamp: 100,
+            },
+        )
+        .unwrap();
+        // Scores past i64 still add up
+        for score in [u64::MAX as u128, 5] {
+            let sampled = LiquidityRewardsSampled {
+                market_id: 1,
+                sponsor: key(9),
+                epoch: 0,
+                score,
+                timestamp: 200,
+            };
+            apply_liquidity_rewards_sampled(&tx, &sampled).unwrap();
+        }
+        for (epoch, distributed) in [(0, 999), (1, 0)] {
+            let closed = LiquidityRewardsEpochClosed {
+                market_id: 1,
+                sponsor: key(9),
+                epoch,
+                distributed,
+                timestamp: 1_300,
+            };
+            apply_liquidity_rewards_epoch_closed(&tx, &closed).unwrap();
+        }
+        let claimed = LiquidityRewardsClaimed {
+            market_id: 1,
+            sponsor: key(9),
+            maker: key(3),
+            amount: 600,
+            timestamp: 1_400,
+        };
+        apply_liquidity_rewards_claimed(&tx, origin(0), &claimed).unwrap();
+        let withdrawn = LiquidityRewardsWithdrawn {
+            market_id: 1,
+            sponsor: key(9),
+            amount: 1_001,
+            timestamp: 1_500,
+        };
+        apply_liquidity_rewards_withdrawn(&tx, &withdrawn).unwrap();
+        tx.commit().unwrap();
+
+        let totals: (i64, i64, i64, i64) = store
+            .connection()
+            .query_row(
+                "SELECT min_size, distributed, claimed, withdrawn FROM liquidity_rewards",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(totals, (10, 999, 600, 1_001));
+
+        let epochs: Vec<(i64, String, Option<i64>)> = store
+            .connection()
+            .prepare("SELECT samples, score, distributed FROM liquidity_reward_epochs ORDER BY epoch")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            epochs,
+            vec![
+                (2, (u64::MAX as u128 + 5).to_string(), Some(999)),
+                (0, "0".to_string(), Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn cursor_is_overwritten() {
+        let store = Store::open_in_memory().unwrap();
+        assert_eq!(store.cursor("last_signature").unwrap(), None);
+        store.set_cursor("last_signature", "a").unwrap();
+        store.set_cursor("last_signature", "b").unwrap();
+        assert_eq!(store.cursor("last_signature").unwrap().as_deref(), Some("b"));
+    }
+}
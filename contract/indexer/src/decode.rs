@@ -0,0 +1,325 @@
+use anchor_lang::event::EVENT_IX_TAG_LE;
+use anchor_lang::{AccountDeserialize, AnchorDeserialize, Discriminator};
+use prediction_market::events::*;
+use prediction_market::state::{Market, OrderBook, UserStats};
+
+// Program owned accounts we keep snapshots of
+pub enum ProgramAccount {
+    Market(Box<Market>),
+    OrderBook(OrderBook),
+    UserStats(UserStats),
+}
+
+fn decode_as<T: AnchorDeserialize + Discriminator>(data: &[u8]) -> Option<T> {
+    let mut body = data.strip_prefix(T::DISCRIMINATOR)?;
+    T::deserialize(&mut body).ok()
+}
+
+// Declares ProgramEvent with one variant per event type and the decoder trying each of them
+macro_rules! program_events {
+    ($($event:ident),* $(,)?) => {
+        // Every event the program emits through emit_cpi!
+        pub enum ProgramEvent {
+            $($event($event),)*
+        }
+
+        /// Decodes the data of a self CPI made by emit_cpi!
+        /// Layout: EVENT_IX_TAG_LE (8) + event discriminator (8) + borsh encoded event
+        pub fn decode_event(ix_data: &[u8]) -> Option<ProgramEvent> {
+            let data = ix_data.strip_prefix(EVENT_IX_TAG_LE)?;
+
+            $(
+                if let Some(e) = decode_as::<$event>(data) {
+                    return Some(ProgramEvent::$event(e));
+                }
+            )*
+
+            None
+        }
+    };
+}
+
+program_events!(
+    MarketInitialized,
+    TokensSplit,
+    TokensMerged,
+    OrderPlaced,
+    OrderFilled,
+    OrderCancelled,
+    MarketSettled,
+    RewardsClaimed,
+    CategoricalMarketInitialized,
+    CategoricalTokensSplit,
+    CategoricalTokensMerged,
+    CategoricalOrderPlaced,
+    CategoricalOrderFilled,
+    CategoricalOrderCancelled,
+    CategoricalMarketSettled,
+    CategoricalRewardsClaimed,
+    ScalarMarketInitialized,
+    ScalarMarketResolved,
+    ScalarRewardsClaimed,
+    VoidRefundClaimed,
+    MarketGroupInitialized,
+    MarketAddedToGroup,
+    PositionsConverted,
+    MarketGroupResolved,
+    MarketStatusChanged,
+    MarketMetadataUpdated,
+    MarketFeesUpdated,
+    FeesWithdrawn,
+    ReferrerRecorded,
+    ReferralFeeCredited,
+    ReferralFeesWithdrawn,
+    AmmPoolInitialized,
+    LiquidityAdded,
+    LiquidityRemoved,
+    AmmTraded,
+    LmsrInitialized,
+    LmsrTraded,
+    LmsrSubsidyWithdrawn,
+    MarketOrderFilled,
+    SplitAndSold,
+    BoughtAndMerged,
+    LiquidityRewardsCreated,
+    LiquidityRewardsSampled,
+    LiquidityRewardsEpochClosed,
+    LiquidityRewardsClaimed,
+    LiquidityRewardsWithdrawn,
+);
+
+/// Decodes raw account data by its Anchor discriminator
+pub fn decode_account(data: &[u8]) -> Option<ProgramAccount> {
+    let discriminator = data.get(..8)?;
+
+    if discriminator == Market::DISCRIMINATOR {
+        Market::try_deserialize(&mut &data[..])
+            .ok()
+            .map(|market| ProgramAccount::Market(Box::new(market)))
+    } else if discriminator == OrderBook::DISCRIMINATOR {
+        OrderBook::try_deserialize(&mut &data[..])
+            .ok()
+            .map(ProgramAccount::OrderBook)
+    } else if discriminator == UserStats::DISCRIMINATOR {
+        UserStats::try_deserialize(&mut &data[..])
+            .ok()
+            .map(ProgramAccount::UserStats)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::Event;
+    use prediction_market::state::{OrderSide, TokenType};
+
+    // What the program's self CPI carries for `event`
+    fn cpi_data(event: &impl Event) -> Vec<u8> {
+        let mut data = EVENT_IX_TAG_LE.to_vec();
+        data.extend(event.data());
+        data
+    }
+
+    #[test]
+    fn decodes_order_filled() {
+        let event = OrderFilled {
+            market_id: 3,
+            taker_order_id: 11,
+            maker_order_id: 4,
+            taker: Pubkey::new_unique(),
+            maker: Pubkey::new_unique(),
+            taker_side: OrderSide::Sell,
+            token_type: TokenType::No,
+            price: 7,
+            quantity: 25,
+            collateral_amount: 175,
+            taker_fee: 2,
+            maker_fee: 1,
+            maker_rebate: 0,
+            timestamp: 1_700_000_000,
+        };
+
+        let Some(ProgramEvent::OrderFilled(decoded)) = decode_event(&cpi_data(&event)) else {
+            panic!("not decoded as OrderFilled");
+        };
+        assert_eq!(decoded.market_id, 3);
+        assert_eq!(decoded.taker_order_id, 11);
+        assert_eq!(decoded.maker, event.maker);
+        assert_eq!(decoded.taker_side, OrderSide::Sell);
+        assert_eq!(decoded.token_type, TokenType::No);
+        assert_eq!(decoded.collateral_amount, 175);
+        assert_eq!(decoded.timestamp, 1_700_000_000);
+    }
+
+    #[test]
+    fn decodes_variable_length_events() {
+        let event = MarketMetadataUpdated {
+            market_id: 9,
+            meta_data_url: "https://example.com/9.json".to_string(),
+            question_hash: [7; 32],
+            category: "sports".to_string(),
+            resolution_source: "league site".to_string(),
+            timestamp: 5,
+        };
+
+        let Some(ProgramEvent::MarketMetadataUpdated(decoded)) = decode_event(&cpi_data(&event))
+        else {
+            panic!("not decoded as MarketMetadataUpdated");
+        };
+        assert_eq!(decoded.meta_data_url, event.meta_data_url);
+        assert_eq!(decoded.question_hash, [7; 32]);
+        assert_eq!(decoded.category, "sports");
+        assert_eq!(decoded.resolution_source, "league site");
+    }
+
+    #[test]
+    fn decodes_categorical_and_group_events() {
+        let fill = CategoricalOrderFilled {
+            market_id: 2,
+            outcome_index: 5,
+            taker_order_id: 1,
+            maker_order_id: 0,
+            taker: Pubkey::new_unique(),
+            maker: Pubkey::new_unique(),
+            taker_side: OrderSide::Buy,
+            price: 4,
+            quantity: 10,
+            collateral_amount: 40,
+            timestamp: 1,
+        };
+        assert!(matches!(
+            decode_event(&cpi_data(&fill)),
+            Some(ProgramEvent::CategoricalOrderFilled(e)) if e.outcome_index == 5
+        ));
+
+        let resolved = MarketGroupResolved {
+            group_id: 1,
+            winning_index: 2,
+            winning_market_id: 6,
+            timestamp: 1,
+        };
+        assert!(matches!(
+            decode_event(&cpi_data(&resolved)),
+            Some(ProgramEvent::MarketGroupResolved(e)) if e.winning_market_id == 6
+        ));
+    }
+
+    #[test]
+    fn decodes_amm_trades() {
+        let event = AmmTraded {
+            market_id: 4,
+            user: Pubkey::new_unique(),
+            side: OrderSide::Sell,
+            token_type: TokenType::Yes,
+            collateral_amount: 90,
+            outcome_amount: 200,
+            fee: 1,
+            timestamp: 3,
+        };
+        assert!(matches!(
+            decode_event(&cpi_data(&event)),
+            Some(ProgramEvent::AmmTraded(e))
+                if e.side == OrderSide::Sell && e.outcome_amount == 200 && e.fee == 1
+        ));
+    }
+
+    #[test]
+    fn decodes_market_order_fills() {
+        let event = MarketOrderFilled {
+            market_id: 8,
+            order_id: 12,
+            user: Pubkey::new_unique(),
+            side: OrderSide::Buy,
+            token_type: TokenType::Yes,
+            book_quantity: 30,
+            amm_quantity: 70,
+            collateral_amount: 55,
+            timestamp: 4,
+        };
+        assert!(matches!(
+            decode_event(&cpi_data(&event)),
+            Some(ProgramEvent::MarketOrderFilled(e))
+                if e.order_id == 12 && e.book_quantity == 30 && e.amm_quantity == 70
+        ));
+    }
+
+    #[test]
+    fn decodes_split_and_merge_orders() {
+        let sold = SplitAndSold {
+            market_id: 2,
+            order_id: 3,
+            user: Pubkey::new_unique(),
+            token_type: TokenType::Yes,
+            quantity: 100,
+            proceeds: 60,
+            timestamp: 1,
+        };
+        assert!(matches!(
+            decode_event(&cpi_data(&sold)),
+            Some(ProgramEvent::SplitAndSold(e)) if e.proceeds == 60
+        ));
+
+        let merged = BoughtAndMerged {
+            market_id: 2,
+            order_id: 4,
+            user: Pubkey::new_unique(),
+            token_type: TokenType::No,
+            quantity: 100,
+            cost: 45,
+            timestamp: 1,
+        };
+        assert!(matches!(
+            decode_event(&cpi_data(&merged)),
+            Some(ProgramEvent::BoughtAndMerged(e)) if e.cost == 45 && e.token_type == TokenType::No
+        ));
+    }
+
+    #[test]
+    fn decodes_liquidity_reward_events() {
+        let sampled = LiquidityRewardsSampled {
+            market_id: 2,
+            sponsor: Pubkey::new_unique(),
+            epoch: 1,
+            score: u128::MAX - 1,
+            timestamp: 1,
+        };
+        assert!(matches!(
+            decode_event(&cpi_data(&sampled)),
+            Some(ProgramEvent::LiquidityRewardsSampled(e)) if e.score == u128::MAX - 1
+        ));
+
+        let claimed = LiquidityRewardsClaimed {
+            market_id: 2,
+            sponsor: Pubkey::new_unique(),
+            maker: Pubkey::new_unique(),
+            amount: 75,
+            timestamp: 1,
+        };
+        assert!(matches!(
+            decode_event(&cpi_data(&claimed)),
+            Some(ProgramEvent::LiquidityRewardsClaimed(e)) if e.amount == 75
+        ));
+    }
+
+    #[test]
+    fn rejects_data_that_is_not_an_event() {
+        let event = ScalarMarketResolved {
+            market_id: 1,
+            value: -40,
+            timestamp: 1,
+        };
+
+        // A regular instruction starts with its own discriminator, not the event tag
+        assert!(decode_event(&event.data()).is_none());
+        // Unknown discriminator
+        let mut unknown = EVENT_IX_TAG_LE.to_vec();
+        unknown.extend([0u8; 16]);
+        assert!(decode_event(&unknown).is_none());
+        // Truncated body
+        let data = cpi_data(&event);
+        assert!(decode_event(&data[..data.len() - 1]).is_none());
+    }
+}
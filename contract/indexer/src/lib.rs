@@ -0,0 +1,201 @@
+//! Rebuilds prediction market state from program events and account snapshots into SQLite.
+//!
+//! Events (emit_cpi!) give the full history: orders, fills, settlements and claims.
+//! Account snapshots (Market, OrderBook, UserStats) are applied on top as the current truth.
+
+pub mod db;
+pub mod decode;
+pub mod source;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::Result;
+
+use crate::db::{EventOrigin, Store};
+use crate::decode::{decode_account, decode_event, ProgramAccount, ProgramEvent};
+use crate::source::{AccountSnapshot, RecordedTransaction};
+
+pub struct Indexer {
+    store: Store,
+    program_id: Pubkey,
+}
+
+impl Indexer {
+    pub fn new(store: Store, program_id: Pubkey) -> Self {
+        Self { store, program_id }
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Applies every event of a transaction, at most once per signature
+    /// Returns false when the transaction was already indexed or failed on chain
+    pub fn apply_transaction(&mut self, recorded: &RecordedTransaction) -> Result<bool> {
+        let signature = recorded.signature()?;
+        let events = recorded.inner_instruction_data(&self.program_id)?;
+
+        let tx = self.store.transaction()?;
+        if db::is_processed(&tx, signature)? {
+            return Ok(false);
+        }
+        db::mark_processed(&tx, signature, recorded.slot)?;
+
+        // Failed transactions roll back their CPIs, so their events never happened
+        if !recorded.succeeded() {
+            tx.commit()?;
+            return Ok(false);
+        }
+
+        for (seq, data) in events.iter().enumerate() {
+            let origin = EventOrigin {
+                signature,
+                slot: recorded.slot,
+                seq,
+            };
+
+            match decode_event(data) {
+                Some(ProgramEvent::MarketInitialized(e)) => db::apply_market_initialized(&tx, &e)?,
+                Some(ProgramEvent::TokensSplit(e)) => {
+                    db::apply_collateral_change(&tx, e.market_id, e.amount as i64)?
+                }
+                Some(ProgramEvent::TokensMerged(e)) => {
+                    db::apply_collateral_change(&tx, e.market_id, -(e.amount as i64))?
+                }
+                Some(ProgramEvent::OrderPlaced(e)) => db::apply_order_placed(&tx, &e)?,
+                Some(ProgramEvent::OrderFilled(e)) => db::apply_order_filled(&tx, origin, &e)?,
+                Some(ProgramEvent::OrderCancelled(e)) => db::apply_order_cancelled(&tx, &e)?,
+                Some(ProgramEvent::MarketSettled(e)) => db::apply_market_settled(&tx, origin, &e)?,
+                Some(ProgramEvent::RewardsClaimed(e)) => {
+                    db::apply_rewards_claimed(&tx, origin, &e)?;
+                    db::apply_collateral_change(&tx, e.market_id, -(e.amount as i64))?;
+                }
+                Some(ProgramEvent::MarketStatusChanged(e)) => {
+                    db::apply_market_status_changed(&tx, &e)?
+                }
+                Some(ProgramEvent::MarketMetadataUpdated(e)) => {
+                    db::apply_market_metadata_updated(&tx, &e)?
+                }
+                Some(ProgramEvent::CategoricalMarketInitialized(e)) => {
+                    db::apply_categorical_market_initialized(&tx, &e)?
+                }
+                Some(ProgramEvent::CategoricalTokensSplit(e)) => {
+                    db::apply_categorical_collateral_change(&tx, e.market_id, e.amount as i64)?
+                }
+                Some(ProgramEvent::CategoricalTokensMerged(e)) => {
+                    db::apply_categorical_collateral_change(&tx, e.market_id, -(e.amount as i64))?
+                }
+                Some(ProgramEvent::CategoricalOrderPlaced(e)) => {
+                    db::apply_categorical_order_placed(&tx, &e)?
+                }
+                Some(ProgramEvent::CategoricalOrderFilled(e)) => {
+                    db::apply_categorical_order_filled(&tx, origin, &e)?
+                }
+                Some(ProgramEvent::CategoricalOrderCancelled(e)) => {
+                    db::apply_categorical_order_cancelled(&tx, &e)?
+                }
+                Some(ProgramEvent::CategoricalMarketSettled(e)) => {
+                    db::apply_categorical_market_settled(&tx, &e)?
+                }
+                Some(ProgramEvent::CategoricalRewardsClaimed(e)) => {
+                    db::apply_categorical_rewards_claimed(&tx, origin, &e)?;
+                    db::apply_categorical_collateral_change(&tx, e.market_id, -(e.amount as i64))?;
+                }
+                Some(ProgramEvent::ScalarMarketInitialized(e)) => {
+                    db::apply_scalar_market_initialized(&tx, &e)?
+                }
+                Some(ProgramEvent::ScalarMarketResolved(e)) => {
+                    db::apply_scalar_market_resolved(&tx, &e)?
+                }
+                Some(ProgramEvent::ScalarRewardsClaimed(e)) => {
+                    db::apply_scalar_rewards_claimed(&tx, origin, &e)?;
+                    db::apply_collateral_change(&tx, e.market_id, -(e.payout as i64))?;
+                }
+                Some(ProgramEvent::VoidRefundClaimed(e)) => {
+                    db::apply_void_refund_claimed(&tx, origin, &e)?;
+                    db::apply_collateral_change(&tx, e.market_id, -(e.payout as i64))?;
+                }
+                Some(ProgramEvent::MarketGroupInitialized(e)) => {
+                    db::apply_market_group_initialized(&tx, &e)?
+                }
+                Some(ProgramEvent::MarketAddedToGroup(e)) => {
+                    db::apply_market_added_to_group(&tx, &e)?
+                }
+                Some(ProgramEvent::PositionsConverted(e)) => {
+                    db::apply_positions_converted(&tx, origin, &e)?
+                }
+                Some(ProgramEvent::MarketGroupResolved(e)) => {
+                    db::apply_market_group_resolved(&tx, &e)?
+                }
+                Some(ProgramEvent::MarketFeesUpdated(e)) => db::apply_market_fees_updated(&tx, &e)?,
+                Some(ProgramEvent::FeesWithdrawn(e)) => db::apply_fees_withdrawn(&tx, origin, &e)?,
+                Some(ProgramEvent::ReferrerRecorded(e)) => db::apply_referrer_recorded(&tx, &e)?,
+                Some(ProgramEvent::ReferralFeeCredited(e)) => {
+                    db::apply_referral_fee_credited(&tx, &e)?
+                }
+                Some(ProgramEvent::ReferralFeesWithdrawn(e)) => {
+                    db::apply_referral_fees_withdrawn(&tx, origin, &e)?
+                }
+                Some(ProgramEvent::AmmPoolInitialized(e)) => {
+                    db::apply_amm_pool_initialized(&tx, &e)?
+                }
+                Some(ProgramEvent::LiquidityAdded(e)) => db::apply_liquidity_added(&tx, origin, &e)?,
+                Some(ProgramEvent::LiquidityRemoved(e)) => {
+                    db::apply_liquidity_removed(&tx, origin, &e)?
+                }
+                Some(ProgramEvent::AmmTraded(e)) => db::apply_amm_traded(&tx, origin, &e)?,
+                Some(ProgramEvent::LmsrInitialized(e)) => db::apply_lmsr_initialized(&tx, &e)?,
+                Some(ProgramEvent::LmsrTraded(e)) => db::apply_lmsr_traded(&tx, origin, &e)?,
+                Some(ProgramEvent::LmsrSubsidyWithdrawn(e)) => {
+                    db::apply_lmsr_subsidy_withdrawn(&tx, &e)?
+                }
+                Some(ProgramEvent::MarketOrderFilled(e)) => {
+                    db::apply_market_order_filled(&tx, origin, &e)?
+                }
+                Some(ProgramEvent::SplitAndSold(e)) => db::apply_split_and_sold(&tx, origin, &e)?,
+                Some(ProgramEvent::BoughtAndMerged(e)) => {
+                    db::apply_bought_and_merged(&tx, origin, &e)?
+                }
+                Some(ProgramEvent::LiquidityRewardsCreated(e)) => {
+                    db::apply_liquidity_rewards_created(&tx, &e)?
+                }
+                Some(ProgramEvent::LiquidityRewardsSampled(e)) => {
+                    db::apply_liquidity_rewards_sampled(&tx, &e)?
+                }
+                Some(ProgramEvent::LiquidityRewardsEpochClosed(e)) => {
+                    db::apply_liquidity_rewards_epoch_closed(&tx, &e)?
+                }
+                Some(ProgramEvent::LiquidityRewardsClaimed(e)) => {
+                    db::apply_liquidity_rewards_claimed(&tx, origin, &e)?
+                }
+                Some(ProgramEvent::LiquidityRewardsWithdrawn(e)) => {
+                    db::apply_liquidity_rewards_withdrawn(&tx, &e)?
+                }
+                None => {}
+            }
+        }
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Applies an account snapshot, ignoring accounts this program doesn't own
+    pub fn apply_account(&mut self, snapshot: &AccountSnapshot) -> Result<bool> {
+        if snapshot.owner()? != self.program_id {
+            return Ok(false);
+        }
+
+        let address = snapshot.address()?;
+        let Some(account) = decode_account(&snapshot.data()?) else {
+            return Ok(false);
+        };
+
+        let tx = self.store.transaction()?;
+        match account {
+            ProgramAccount::Market(market) => db::upsert_market(&tx, &address, &market)?,
+            ProgramAccount::OrderBook(orderbook) => db::replace_order_book(&tx, &orderbook)?,
+            ProgramAccount::UserStats(stats) => db::upsert_user_stats(&tx, &address, &stats)?,
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+}
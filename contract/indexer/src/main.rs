@@ -0,0 +1,154 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use prediction_market::state::TokenType;
+use prediction_market_indexer::db::Store;
+use prediction_market_client::rpc::RpcClient;
+use prediction_market_indexer::source::{
+    load_accounts, load_transactions, AccountSnapshot, RecordedTransaction,
+};
+use prediction_market_indexer::Indexer;
+
+const LAST_SIGNATURE_CURSOR: &str = "last_signature";
+
+#[derive(Parser)]
+#[command(name = "pm-indexer", about = "Index prediction market events and accounts into SQLite")]
+struct Cli {
+    /// SQLite database file, created if missing
+    #[arg(long, default_value = "prediction_market.sqlite")]
+    db: PathBuf,
+
+    /// Program to index, defaults to the deployed prediction_market id
+    #[arg(long)]
+    program_id: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Import recorded `getTransaction` results and account snapshots from disk
+    Import {
+        /// Transaction JSON file or directory of .json files
+        #[arg(long)]
+        transactions: Vec<PathBuf>,
+        /// Account JSON file (`solana account --output json`) or directory of them
+        #[arg(long)]
+        accounts: Vec<PathBuf>,
+    },
+    /// Catch up from an RPC node (e.g. a local validator), then snapshot program accounts
+    Sync {
+        #[arg(long, default_value = "http://127.0.0.1:8899")]
+        url: String,
+    },
+    /// Print OHLCV candles for one outcome of a market
+    Candles {
+        #[arg(long)]
+        market_id: u32,
+        #[arg(long, value_enum)]
+        token: Token,
+        /// Candle width in seconds
+        #[arg(long, default_value_t = 3600)]
+        interval: i64,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Token {
+    Yes,
+    No,
+}
+
+impl From<Token> for TokenType {
+    fn from(token: Token) -> Self {
+        match token {
+            Token::Yes => TokenType::Yes,
+            Token::No => TokenType::No,
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let program_id = match &cli.program_id {
+        Some(id) => Pubkey::from_str(id).map_err(|e| anyhow!("invalid program id: {e}"))?,
+        None => prediction_market::ID,
+    };
+    let mut indexer = Indexer::new(Store::open(&cli.db)?, program_id);
+
+    match cli.command {
+        Command::Import {
+            transactions,
+            accounts,
+        } => {
+            let mut applied = 0;
+            for path in transactions.iter() {
+                for tx in load_transactions(path)? {
+                    if indexer.apply_transaction(&tx)? {
+                        applied += 1;
+                    }
+                }
+            }
+
+            let mut snapshots = 0;
+            for path in accounts.iter() {
+                for account in load_accounts(path)? {
+                    if indexer.apply_account(&account)? {
+                        snapshots += 1;
+                    }
+                }
+            }
+
+            println!("Indexed {applied} transactions and {snapshots} accounts");
+        }
+        Command::Sync { url } => {
+            let rpc = RpcClient::new(url);
+            let until = indexer.store().cursor(LAST_SIGNATURE_CURSOR)?;
+
+            let signatures = rpc.signatures_since(&program_id, until.as_deref())?;
+            let mut applied = 0;
+            for signature in signatures.iter() {
+                // Not served by the node yet, stop so the cursor never moves past it
+                let Some(tx) = rpc.transaction::<RecordedTransaction>(signature)? else {
+                    eprintln!("Transaction {signature} isn't available yet, stopping here");
+                    break;
+                };
+                if indexer.apply_transaction(&tx)? {
+                    applied += 1;
+                }
+                indexer.store().set_cursor(LAST_SIGNATURE_CURSOR, signature)?;
+            }
+
+            let mut snapshots = 0;
+            for account in rpc.program_accounts::<AccountSnapshot>(&program_id)? {
+                if indexer.apply_account(&account)? {
+                    snapshots += 1;
+                }
+            }
+
+            println!(
+                "Indexed {applied} of {} new transactions and {snapshots} accounts",
+                signatures.len()
+            );
+        }
+        Command::Candles {
+            market_id,
+            token,
+            interval,
+        } => {
+            println!("start,open,high,low,close,volume,trades");
+            for c in indexer.store().candles(market_id, token.into(), interval)? {
+                println!(
+                    "{},{},{},{},{},{},{}",
+                    c.start, c.open, c.high, c.low, c.close, c.volume, c.trades
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::Value;
+
+// Subset of a `getTransaction` result (encoding "json") that the indexer needs
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedTransaction {
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub meta: Option<TransactionMeta>,
+    pub transaction: TransactionBody,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionMeta {
+    pub err: Option<Value>,
+    #[serde(default)]
+    pub inner_instructions: Option<Vec<InnerInstructions>>,
+    #[serde(default)]
+    pub loaded_addresses: Option<LoadedAddresses>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InnerInstructions {
+    pub index: u8,
+    pub instructions: Vec<CompiledInstruction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompiledInstruction {
+    pub program_id_index: u8,
+    #[serde(default)]
+    pub accounts: Vec<u8>,
+    pub data: String, // base58
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct LoadedAddresses {
+    #[serde(default)]
+    pub writable: Vec<String>,
+    #[serde(default)]
+    pub readonly: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransactionBody {
+    pub signatures: Vec<String>,
+    pub message: TransactionMessage,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionMessage {
+    pub account_keys: Vec<String>,
+}
+
+impl RecordedTransaction {
+    pub fn signature(&self) -> Result<&str> {
+        self.transaction
+            .signatures
+            .first()
+            .map(String::as_str)
+            .ok_or_else(|| anyhow!("transaction has no signature"))
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.meta.as_ref().is_some_and(|meta| meta.err.is_none())
+    }
+
+    // Static keys followed by the keys loaded from lookup tables, same order the runtime uses
+    pub fn account_keys(&self) -> Result<Vec<Pubkey>> {
+        let loaded = self
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.loaded_addresses.clone())
+            .unwrap_or_default();
+
+        self.transaction
+            .message
+            .account_keys
+            .iter()
+            .chain(loaded.writable.iter())
+            .chain(loaded.readonly.iter())
+            .map(|key| Pubkey::from_str(key).map_err(|e| anyhow!("invalid account key {key}: {e}")))
+            .collect()
+    }
+
+    /// Data of every inner instruction that was invoked on `program_id`, in execution order
+    /// emit_cpi! events show up here as self CPIs
+    pub fn inner_instruction_data(&self, program_id: &Pubkey) -> Result<Vec<Vec<u8>>> {
+        let keys = self.account_keys()?;
+        let mut inner: Vec<&InnerInstructions> = self
+            .meta
+            .iter()
+            .flat_map(|meta| meta.inner_instructions.iter().flatten())
+            .collect();
+        inner.sort_by_key(|group| group.index);
+
+        let mut data = Vec::new();
+        for ix in inner.iter().flat_map(|group| group.instructions.iter()) {
+            let invoked = keys
+                .get(ix.program_id_index as usize)
+                .ok_or_else(|| anyhow!("program id index {} out of range", ix.program_id_index))?;
+            if invoked == program_id {
+                data.push(
+                    bs58::decode(&ix.data)
+                        .into_vec()
+                        .context("inner instruction data is not base58")?,
+                );
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+// Output of `solana account --output json` and the entries of `getProgramAccounts`
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountSnapshot {
+    pub pubkey: String,
+    pub account: SnapshotAccount,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SnapshotAccount {
+    pub data: (String, String), // (payload, encoding)
+    pub owner: String,
+}
+
+impl AccountSnapshot {
+    pub fn address(&self) -> Result<Pubkey> {
+        Pubkey::from_str(&self.pubkey).map_err(|e| anyhow!("invalid pubkey {}: {e}", self.pubkey))
+    }
+
+    pub fn owner(&self) -> Result<Pubkey> {
+        Pubkey::from_str(&self.account.owner)
+            .map_err(|e| anyhow!("invalid owner {}: {e}", self.account.owner))
+    }
+
+    pub fn data(&self) -> Result<Vec<u8>> {
+        let (payload, encoding) = &self.account.data;
+        match encoding.as_str() {
+            "base64" => Ok(base64::engine::general_purpose::STANDARD.decode(payload)?),
+            "base58" => Ok(bs58::decode(payload).into_vec()?),
+            other => Err(anyhow!("unsupported account data encoding {other}")),
+        }
+    }
+}
+
+// Files can hold one item, an array of items, or a raw RPC response wrapping either
+fn unwrap_items(value: Value) -> Vec<Value> {
+    let value = match value {
+        Value::Object(mut map) if map.contains_key("result") => {
+            map.remove("result").unwrap_or(Value::Null)
+        }
+        other => other,
+    };
+
+    match value {
+        Value::Array(items) => items,
+        Value::Null => Vec::new(),
+        item => vec![item],
+    }
+}
+
+fn json_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files: Vec<PathBuf> = fs::read_dir(path)
+        .with_context(|| format!("reading {}", path.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn load<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Vec<T>> {
+    let mut items = Vec::new();
+    for file in json_files(path)? {
+        let raw = fs::read_to_string(&file).with_context(|| format!("reading {}", file.display()))?;
+        let value: Value =
+            serde_json::from_str(&raw).with_context(|| format!("parsing {}", file.display()))?;
+        for item in unwrap_items(value) {
+            items.push(
+                serde_json::from_value(item).with_context(|| format!("decoding {}", file.display()))?,
+            );
+        }
+    }
+    Ok(items)
+}
+
+/// Loads recorded transactions from a file or a directory of .json files, oldest slot first
+pub fn load_transactions(path: &Path) -> Result<Vec<RecordedTransaction>> {
+    let mut transactions: Vec<RecordedTransaction> = load(path)?;
+    transactions.sort_by_key(|tx| tx.slot);
+    Ok(transactions)
+}
+
+/// Loads account snapshots from a file or a directory of .json files
+pub fn load_accounts(path: &Path) -> Result<Vec<AccountSnapshot>> {
+    load(path)
+}
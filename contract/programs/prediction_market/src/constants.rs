@@ -1,14 +1,41 @@
-use anchor_lang::prelude::*;
-
 pub const MARKET_SEED: &[u8] = b"market";
 pub const VAULT_SEED: &[u8] = b"vault";
+pub const FEE_VAULT_SEED: &[u8] = b"fee_vault";
 pub const OUTCOME_YES_SEED: &[u8] = b"outcome_a";
 pub const OUTCOME_NO_SEED: &[u8] = b"outcome_b"; 
 pub const ORDERBOOK_SEED: &[u8] = b"orderbook";
 pub const USER_STATS_SEED: &[u8] = b"user_stats";
 pub const ESCROW_SEED: &[u8] = b"escrow";
+pub const CATEGORICAL_MARKET_SEED: &[u8] = b"categorical_market";
+pub const CATEGORICAL_VAULT_SEED: &[u8] = b"categorical_vault";
+pub const CATEGORICAL_OUTCOME_SEED: &[u8] = b"categorical_outcome"; // + market_id + outcome index
+pub const CATEGORICAL_ORDERBOOK_SEED: &[u8] = b"categorical_orderbook"; // + market_id + outcome index
+pub const CATEGORICAL_USER_STATS_SEED: &[u8] = b"categorical_user_stats";
+pub const MARKET_GROUP_SEED: &[u8] = b"market_group";
+pub const PROTOCOL_CONFIG_SEED: &[u8] = b"protocol_config";
+pub const MARKET_COUNTER_SEED: &[u8] = b"market_counter";
+pub const AMM_POOL_SEED: &[u8] = b"amm_pool";
+pub const AMM_LP_MINT_SEED: &[u8] = b"amm_lp_mint";
+pub const AMM_RESERVE_SEED: &[u8] = b"amm_reserve"; // + market_id + outcome mint
+pub const LMSR_SEED: &[u8] = b"lmsr";
+pub const TRADER_VOLUME_SEED: &[u8] = b"trader_volume"; // + user
+pub const LIQUIDITY_REWARDS_SEED: &[u8] = b"liquidity_rewards"; // + market_id + sponsor
+pub const REWARD_VAULT_SEED: &[u8] = b"reward_vault"; // + LiquidityRewards address
+pub const NATIVE_UNWRAP_SEED: &[u8] = b"native_unwrap"; // + user, temporary wSOL account
 pub const MAX_ORDERBOOK_LENGTH: u32 = 1000; // Can grow up to 1000 orders per side via realloc
 pub const INITIAL_ORDERBOOK_CAPACITY: usize = 10; // Start small, grow as needed
 pub const MAX_ORDERS_PER_SIDE : usize = 100;
+pub const MIN_CATEGORICAL_OUTCOMES: u8 = 2;
+pub const MAX_CATEGORICAL_OUTCOMES: usize = 16;
+pub const MAX_GROUP_MARKETS: usize = 16; // Fits the u16 mask of convert_no_positions
+pub const MAX_ALLOWED_COLLATERAL_MINTS: usize = 10;
+pub const MAX_FEE_BPS: u16 = 10_000;
+pub const MAX_FEE_TIERS: usize = 8;
+pub const DEFAULT_VOLUME_WINDOW: i64 = 30 * 24 * 60 * 60; // 30 days
+pub const MAX_METADATA_URL_LEN: usize = 200;
+pub const MAX_CATEGORY_LEN: usize = 32;
+pub const MAX_RESOLUTION_SOURCE_LEN: usize = 200;
+pub const MAX_REWARD_MAKERS: usize = 64; // Makers scored per LiquidityRewards program
+pub const MAX_DEPTH_LEVELS: usize = 50; // 4 + 50 * 20 bytes stays under the 1024 byte return data limit
 // 1 YES/NO TOKEN = 6 DECIMALS
 // 1 COLLATERAL_TOKEN = 1 YES/NO TOKEN
\ No newline at end of file
@@ -26,13 +26,95 @@ pub enum PredictionMarketError {
     MaxOrdersReached,
     #[msg("Not enough Balance in the account")]
     NotEnoughBalance,
-    #[msg("Seller's UserStats account not provided in remaining_accounts")]
+    #[msg("Seller's UserStats account not provided in remaining_accounts in match order")]
     SellerStatsAccountNotProvided,
-    #[msg("Buyer's UserStats account not provided in remaining_accounts")]
+    #[msg("Buyer's UserStats account not provided in remaining_accounts in match order")]
     BuyerStatsAccountNotProvided,
     #[msg("Not authorized")]
     NotAuthorized,
     #[msg("Order not found")]
-    OrdernotFound
+    OrdernotFound,
+    #[msg("Market orders are not supported yet")]
+    MarketOrderNotSupported,
+    #[msg("Categorical markets need between 2 and 16 outcomes")]
+    InvalidOutcomeCount,
+    #[msg("Invalid outcome index")]
+    InvalidOutcomeIndex,
+    #[msg("Not all outcome mints of this market are initialized yet")]
+    OutcomesNotInitialized,
+    #[msg("Outcome accounts in remaining_accounts don't match the market outcomes")]
+    OutcomeAccountsMismatch,
+    #[msg("Scalar markets need a lower bound below the upper bound")]
+    InvalidScalarBounds,
+    #[msg("This instruction is not supported for this market type")]
+    InvalidMarketType,
+    #[msg("Market group is full")]
+    MarketGroupFull,
+    #[msg("Market collateral doesn't match the market group")]
+    GroupCollateralMismatch,
+    #[msg("Market is already part of a market group")]
+    MarketAlreadyInGroup,
+    #[msg("Markets in a group can only be settled through resolve_market_group")]
+    MarketInGroup,
+    #[msg("Market accounts in remaining_accounts don't match the market group")]
+    GroupAccountsMismatch,
+    #[msg("Invalid set of NO positions to convert")]
+    InvalidPositionSet,
+    #[msg("Market group vaults can't cover the winning supply")]
+    GroupUndercollateralized,
+    #[msg("Trading is paused")]
+    ProtocolPaused,
+    #[msg("Collateral mint is not allowed")]
+    CollateralNotAllowed,
+    #[msg("Too many allowed collateral mints")]
+    TooManyCollateralMints,
+    #[msg("Fee rate can't be more than 10000 bps")]
+    InvalidFeeRate,
+    #[msg("Market is paused")]
+    MarketPaused,
+    #[msg("Market is close-only, positions can only be reduced")]
+    MarketCloseOnly,
+    #[msg("Market has been voided")]
+    MarketVoided,
+    #[msg("Invalid market status change")]
+    InvalidMarketStatus,
+    #[msg("Market metadata field is too long")]
+    MetadataTooLong,
+    #[msg("Market metadata can't change once trading has started or the market is closed")]
+    MetadataFrozen,
+    #[msg("Collateral mint uses an unsupported Token-2022 extension")]
+    UnsupportedCollateralMint,
+    #[msg("Pass user_collateral, or on a native SOL market pay in lamports and pass native_unwrap for payouts")]
+    InvalidCollateralAccounts,
+    #[msg("Maker's TraderVolume account not provided after their UserStats in remaining_accounts")]
+    MakerVolumeAccountNotProvided,
+    #[msg("Too many fee tiers")]
+    TooManyFeeTiers,
+    #[msg("Fee tiers need increasing volumes, rates within 10000 bps and a positive window")]
+    InvalidFeeTiers,
+    #[msg("Traders can't refer themselves")]
+    InvalidReferrer,
+    #[msg("Price moved past the slippage limit")]
+    SlippageExceeded,
+    #[msg("Not enough liquidity in the AMM pool")]
+    AmmInsufficientLiquidity,
+    #[msg("The market already runs an AMM")]
+    AmmAlreadyEnabled,
+    #[msg("LMSR markets can't be part of a market group")]
+    LmsrMarketInGroup,
+    #[msg("The LMSR subsidy was already withdrawn")]
+    SubsidyAlreadyWithdrawn,
+    #[msg("The market's AMM accounts must be passed to route a market order")]
+    AmmAccountNotProvided,
+    #[msg("Liquidity rewards need a positive budget, epoch and sample interval within the epoch, and a spread within 10000 bps")]
+    InvalidRewardParams,
+    #[msg("Nothing to crank yet, the next sample or epoch close isn't due")]
+    RewardCrankNotDue,
+    #[msg("No liquidity rewards to claim")]
+    NoRewardsToClaim,
+    #[msg("Liquidity rewards are still being paid out")]
+    RewardProgramActive,
+    #[msg("Markets can't join a group once it converted NO positions or once they have traded")]
+    MarketGroupFrozen
 
 }
\ No newline at end of file
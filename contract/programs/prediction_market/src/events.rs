@@ -0,0 +1,461 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{MarketStatus, OrderSide, TokenType, WinningOutcome};
+
+// Emitted through emit_cpi! so indexers can read them from inner instructions
+// instead of parsing program logs, which can get truncated
+
+#[event]
+pub struct MarketInitialized {
+    pub market_id: u32,
+    pub authority: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub settlement_deadline: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensSplit {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensMerged {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderPlaced {
+    pub market_id: u32,
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub side: OrderSide,
+    pub token_type: TokenType,
+    pub price: u64,
+    pub quantity: u64,
+    pub timestamp: i64,
+}
+
+// One event per match, taker is the order being placed, maker is the resting order
+#[event]
+pub struct OrderFilled {
+    pub market_id: u32,
+    pub taker_order_id: u64,
+    pub maker_order_id: u64,
+    pub taker: Pubkey,
+    pub maker: Pubkey,
+    pub taker_side: OrderSide,
+    pub token_type: TokenType,
+    pub price: u64,
+    pub quantity: u64,
+    pub collateral_amount: u64,
+    pub taker_fee: u64,
+    pub maker_fee: u64,
+    pub maker_rebate: u64, // Paid to the maker out of taker_fee
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderCancelled {
+    pub market_id: u32,
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub side: OrderSide,
+    pub token_type: TokenType,
+    pub price: u64,
+    pub unfilled_quantity: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MarketSettled {
+    pub market_id: u32,
+    pub winning_outcome: WinningOutcome,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsClaimed {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub winning_outcome: WinningOutcome,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+// Categorical markets have their own market_id space, so they get their own events
+
+#[event]
+pub struct CategoricalMarketInitialized {
+    pub market_id: u32,
+    pub authority: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub outcome_count: u8,
+    pub settlement_deadline: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CategoricalTokensSplit {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CategoricalTokensMerged {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CategoricalOrderPlaced {
+    pub market_id: u32,
+    pub outcome_index: u8,
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub side: OrderSide,
+    pub price: u64,
+    pub quantity: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CategoricalOrderFilled {
+    pub market_id: u32,
+    pub outcome_index: u8,
+    pub taker_order_id: u64,
+    pub maker_order_id: u64,
+    pub taker: Pubkey,
+    pub maker: Pubkey,
+    pub taker_side: OrderSide,
+    pub price: u64,
+    pub quantity: u64,
+    pub collateral_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CategoricalOrderCancelled {
+    pub market_id: u32,
+    pub outcome_index: u8,
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub side: OrderSide,
+    pub price: u64,
+    pub unfilled_quantity: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CategoricalMarketSettled {
+    pub market_id: u32,
+    pub winning_outcome: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CategoricalRewardsClaimed {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub winning_outcome: u8,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+// Scalar markets reuse the binary split/merge/order events, YES = LONG and NO = SHORT
+
+#[event]
+pub struct ScalarMarketInitialized {
+    pub market_id: u32,
+    pub authority: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub scalar_low: i64,
+    pub scalar_high: i64,
+    pub settlement_deadline: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ScalarMarketResolved {
+    pub market_id: u32,
+    pub value: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ScalarRewardsClaimed {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub long_amount: u64,
+    pub short_amount: u64,
+    pub payout: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VoidRefundClaimed {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub yes_amount: u64,
+    pub no_amount: u64,
+    pub payout: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MarketGroupInitialized {
+    pub group_id: u32,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MarketAddedToGroup {
+    pub group_id: u32,
+    pub market_id: u32,
+    pub index: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionsConverted {
+    pub group_id: u32,
+    pub user: Pubkey,
+    pub no_market_mask: u16, // Bit i set = NO of the i-th group market was converted
+    pub amount: u64,
+    pub collateral_released: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MarketGroupResolved {
+    pub group_id: u32,
+    pub winning_index: u8,
+    pub winning_market_id: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MarketStatusChanged {
+    pub market_id: u32,
+    pub previous_status: MarketStatus,
+    pub status: MarketStatus,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MarketMetadataUpdated {
+    pub market_id: u32,
+    pub meta_data_url: String,
+    pub question_hash: [u8; 32],
+    pub category: String,
+    pub resolution_source: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MarketFeesUpdated {
+    pub market_id: u32,
+    pub taker_fee_bps: u16,
+    pub maker_fee_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub market_id: u32,
+    pub recipient: Pubkey,
+    pub creator_fees: bool, // false for the protocol's share
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferrerRecorded {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub referrer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralFeeCredited {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub referrer: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralFeesWithdrawn {
+    pub market_id: u32,
+    pub referrer: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AmmPoolInitialized {
+    pub market_id: u32,
+    pub lp_mint: Pubkey,
+    pub fee_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityAdded {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub collateral_amount: u64,
+    pub shares: u64,
+    pub yes_returned: u64, // Outcome tokens sent back so the deposit doesn't move the price
+    pub no_returned: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityRemoved {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub shares: u64,
+    pub yes_amount: u64,
+    pub no_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AmmTraded {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub side: OrderSide,
+    pub token_type: TokenType,
+    pub collateral_amount: u64,
+    pub outcome_amount: u64,
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LmsrInitialized {
+    pub market_id: u32,
+    pub creator: Pubkey,
+    pub liquidity: u64,
+    pub subsidy: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LmsrTraded {
+    pub market_id: u32,
+    pub user: Pubkey,
+    pub side: OrderSide,
+    pub token_type: TokenType,
+    pub shares: u64,
+    pub collateral_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LmsrSubsidyWithdrawn {
+    pub market_id: u32,
+    pub creator: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+// One market_order, split between the order book (OrderFilled per fill) and the AMM
+#[event]
+pub struct MarketOrderFilled {
+    pub market_id: u32,
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub side: OrderSide,
+    pub token_type: TokenType,
+    pub book_quantity: u64,
+    pub amm_quantity: u64,
+    pub collateral_amount: u64, // Total paid for a buy, fees included, or received for a sell
+    pub timestamp: i64,
+}
+
+// One split_and_sell, the book fills are reported with OrderFilled under order_id
+#[event]
+pub struct SplitAndSold {
+    pub market_id: u32,
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub token_type: TokenType, // Sold on the book, the user keeps the other outcome
+    pub quantity: u64,
+    pub proceeds: u64, // From the book after taker fees, netted against the split's `quantity`
+    pub timestamp: i64,
+}
+
+// One buy_and_merge, the book fills are reported with OrderFilled under order_id
+#[event]
+pub struct BoughtAndMerged {
+    pub market_id: u32,
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub token_type: TokenType, // Bought on the book and merged with the user's other outcome
+    pub quantity: u64,
+    pub cost: u64, // On the book with taker fees, netted against the merge's `quantity`
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityRewardsCreated {
+    pub market_id: u32,
+    pub sponsor: Pubkey,
+    pub rewards_per_epoch: u64,
+    pub epoch_duration: i64,
+    pub epochs: u32,
+    pub max_spread_bps: u16,
+    pub sample_interval: i64,
+    pub min_size: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityRewardsSampled {
+    pub market_id: u32,
+    pub sponsor: Pubkey,
+    pub epoch: u32,
+    pub score: u128, // Added to the epoch by this sample, over every scored maker
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityRewardsEpochClosed {
+    pub market_id: u32,
+    pub sponsor: Pubkey,
+    pub epoch: u32,
+    pub distributed: u64, // Credited to makers, the rest of the epoch's budget stays with the sponsor
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityRewardsClaimed {
+    pub market_id: u32,
+    pub sponsor: Pubkey,
+    pub maker: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityRewardsWithdrawn {
+    pub market_id: u32,
+    pub sponsor: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
@@ -1,18 +1,32 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Token, TokenAccount, Mint};
-use crate::state::{Market, OrderBook, UserStats};
+use anchor_spl::token_interface::{self, Token2022, TokenInterface};
+use crate::state::{
+    AmmPool, CategoricalMarket, CategoricalUserStats, LiquidityRewards, LmsrMaker, Market,
+    MarketCounter, MarketGroup, OrderBook, OutcomeOrderBook, ProtocolConfig, TraderVolume, UserStats,
+};
+use crate::error::PredictionMarketError;
+use crate::program::PredictionMarket;
 use crate::constants::*;
 
 
 
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(market_id: u32)]
 pub struct InitializeMarket<'info> {
+    // The new market gets market_counter.next_market_id, every seed below uses it
+    #[account(
+        mut,
+        seeds = [MARKET_COUNTER_SEED],
+        bump = market_counter.bump
+    )]
+    pub market_counter: Box<Account<'info, MarketCounter>>,
+
     #[account(
         init,
         payer = authority,
         space = 8 + Market::INIT_SPACE,
-        seeds = [MARKET_SEED, market_id.to_le_bytes().as_ref()],
+        seeds = [MARKET_SEED, market_counter.next_market_id.to_le_bytes().as_ref()],
         bump
     )]
     pub market: Account<'info, Market>,
@@ -20,61 +34,94 @@ pub struct InitializeMarket<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
 
-    pub collateral_mint: Account<'info, Mint>,
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        constraint = config.is_collateral_allowed(&collateral_mint.key()) @ PredictionMarketError::CollateralNotAllowed,
+        constraint = crate::is_supported_collateral_mint(&collateral_mint.to_account_info()) @ PredictionMarketError::UnsupportedCollateralMint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = collateral_mint,
+        token::authority = market,
+        seeds = [VAULT_SEED, market_counter.next_market_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub collateral_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
 
+    // Trading fees collect here, apart from the vault backing outcome tokens
     #[account(
         init,
         payer = authority,
         token::mint = collateral_mint,
         token::authority = market,
-        seeds = [VAULT_SEED, market_id.to_le_bytes().as_ref()],
+        token::token_program = token_program,
+        seeds = [FEE_VAULT_SEED, market_counter.next_market_id.to_le_bytes().as_ref()],
         bump
     )]
-    pub collateral_vault: Account<'info, TokenAccount>,
+    pub fee_vault: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    // Outcome mints are Token-2022 mints carrying their own name/symbol/uri (token-metadata
+    // extension, written by the handler), so wallets can show what the user holds
+    // They take the collateral's decimals, split mints them 1:1 with collateral base units
     #[account(
         init,
         payer = authority,
-        mint::decimals = 6,
+        mint::decimals = collateral_mint.decimals,
         mint::authority = market,
-        seeds = [OUTCOME_YES_SEED, market_id.to_le_bytes().as_ref()],
+        mint::token_program = outcome_token_program,
+        extensions::metadata_pointer::authority = market,
+        extensions::metadata_pointer::metadata_address = outcome_yes_mint,
+        seeds = [OUTCOME_YES_SEED, market_counter.next_market_id.to_le_bytes().as_ref()],
         bump
     )]
-    pub outcome_yes_mint: Account<'info, Mint>,
+    pub outcome_yes_mint: InterfaceAccount<'info, token_interface::Mint>,
     #[account(
         init,
         payer = authority,
-        mint::decimals = 6,
+        mint::decimals = collateral_mint.decimals,
         mint::authority = market,
-        seeds = [OUTCOME_NO_SEED, market_id.to_le_bytes().as_ref()],
+        mint::token_program = outcome_token_program,
+        extensions::metadata_pointer::authority = market,
+        extensions::metadata_pointer::metadata_address = outcome_no_mint,
+        seeds = [OUTCOME_NO_SEED, market_counter.next_market_id.to_le_bytes().as_ref()],
         bump
     )]
-    pub outcome_no_mint: Account<'info, Mint>,
+    pub outcome_no_mint: InterfaceAccount<'info, token_interface::Mint>,
 
     #[account(
         init,
         payer = authority,
         token::authority = market,
         token::mint = outcome_yes_mint,
-        seeds = [ESCROW_SEED, market_id.to_le_bytes().as_ref(), outcome_yes_mint.key().as_ref()],
+        token::token_program = outcome_token_program,
+        seeds = [ESCROW_SEED, market_counter.next_market_id.to_le_bytes().as_ref(), outcome_yes_mint.key().as_ref()],
         bump
     )]
-    pub yes_escrow: Account<'info, TokenAccount>,
+    pub yes_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
 
     #[account(
         init,
         payer = authority,
         token::authority = market,
         token::mint = outcome_no_mint,
-        seeds = [ESCROW_SEED, market_id.to_le_bytes().as_ref(), outcome_no_mint.key().as_ref()],
+        token::token_program = outcome_token_program,
+        seeds = [ESCROW_SEED, market_counter.next_market_id.to_le_bytes().as_ref(), outcome_no_mint.key().as_ref()],
         bump
     )]
-    pub no_escrow: Account<'info, TokenAccount>,
+    pub no_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
     
 
     #[account(
         init,
         payer = authority,
-        seeds = [ORDERBOOK_SEED, market_id.to_le_bytes().as_ref()],
+        seeds = [ORDERBOOK_SEED, market_counter.next_market_id.to_le_bytes().as_ref()],
         space = OrderBook::space(0), // Start with 0 orders, will realloc as needed
         bump
     )]
@@ -82,7 +129,8 @@ pub struct InitializeMarket<'info> {
 
 
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
     pub rent: Sysvar<'info, Rent>,
 }
 
@@ -111,6 +159,7 @@ pub struct InitializeMarket<'info> {
  // 2. 2ND BY COMPARING SEEDS FROM THE MARKET_ID WHICH WE REALLY SENDED
  // SO IT DOUBLE CHECKS EVERYTHING
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(market_id: u32)]
 pub struct SplitToken<'info> {
@@ -128,42 +177,55 @@ pub struct SplitToken<'info> {
     #[account(mut)]
     pub user : Signer<'info>,
 
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    // None on native SOL markets, the signer's lamports are wrapped and unwrapped instead
     #[account(
         mut,
         constraint = user_collateral.mint == market.collateral_mint,
         constraint = user_collateral.owner == user.key()
     )]
-    pub user_collateral : Account<'info, TokenAccount>,
+    pub user_collateral : Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
 
     #[account(
         mut,
         constraint = collateral_vault.key() == market.collateral_vault // We can also used the .owner of vault to verify it's authority of market
     )]
-    pub collateral_vault : Account<'info, TokenAccount>,
+    pub collateral_vault : InterfaceAccount<'info, token_interface::TokenAccount>,
 
     #[account(
         mut,
         constraint = outcome_yes_mint.key() == market.outcome_yes_mint
     )]
-    pub outcome_yes_mint : Account<'info,Mint>,
+    pub outcome_yes_mint : InterfaceAccount<'info, token_interface::Mint>,
     #[account(
         mut,
         constraint = outcome_no_mint.key() == market.outcome_no_mint
     )]
-    pub outcome_no_mint : Account<'info,Mint>,
+    pub outcome_no_mint : InterfaceAccount<'info, token_interface::Mint>,
     #[account(
         mut,
         constraint = user_outcome_yes.owner == user.key(),
         constraint = user_outcome_yes.mint == market.outcome_yes_mint
     )]
-    pub user_outcome_yes : Account<'info, TokenAccount>, // Ohh we willn't make this account here,
+    pub user_outcome_yes : InterfaceAccount<'info, token_interface::TokenAccount>, // Ohh we willn't make this account here,
     // we will just check it here , Like is it legit or not
     #[account(
         mut,
         constraint = user_outcome_no.owner == user.key(),
         constraint = user_outcome_no.mint == market.outcome_no_mint
     )]
-    pub user_outcome_no : Account<'info, TokenAccount>,
+    pub user_outcome_no : InterfaceAccount<'info, token_interface::TokenAccount>,
 
     #[account(
         init_if_needed,
@@ -175,9 +237,11 @@ pub struct SplitToken<'info> {
     pub user_stats_account : Box<Account<'info, UserStats>>,
 
     pub system_program: Program<'info, System>,
-    pub token_program : Program<'info,Token>,
+    pub token_program : Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(market_id: u32)]
 pub struct MergeTokens<'info>{
@@ -192,37 +256,62 @@ pub struct MergeTokens<'info>{
     #[account(mut)]
     pub user : Signer<'info>,
 
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    // None on native SOL markets, the signer's lamports are wrapped and unwrapped instead
     #[account(
         mut,
         constraint = user_collateral.mint == market.collateral_mint,
         constraint = user_collateral.owner == user.key()
     )]
-    pub user_collateral : Account<'info, TokenAccount>,
+    pub user_collateral : Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
 
     #[account(
         mut,
         constraint = collateral_vault.key() == market.collateral_vault // We can also used the .owner of vault to verify it's authority of market
     )]
-    pub collateral_vault : Account<'info, TokenAccount>,
+    pub collateral_vault : InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    // Native SOL payouts only: a temporary wSOL account, closed to the user in the same instruction
+    #[account(
+        init,
+        payer = user,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [NATIVE_UNWRAP_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub native_unwrap: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
 
     #[account(
         mut,
         constraint = outcome_yes_mint.key() == market.outcome_yes_mint
     )]
-    pub outcome_yes_mint : Account<'info,Mint>,
+    pub outcome_yes_mint : InterfaceAccount<'info, token_interface::Mint>,
 
     #[account(
         mut,
         constraint = outcome_no_mint.key() == market.outcome_no_mint
     )]
-    pub outcome_no_mint : Account<'info,Mint>,
+    pub outcome_no_mint : InterfaceAccount<'info, token_interface::Mint>,
 
     #[account(
         mut,
         constraint = user_outcome_yes.owner == user.key(),
         constraint = user_outcome_yes.mint == market.outcome_yes_mint
     )]
-    pub user_outcome_yes : Account<'info, TokenAccount>, // Ohh we willn't make this account here,
+    pub user_outcome_yes : InterfaceAccount<'info, token_interface::TokenAccount>, // Ohh we willn't make this account here,
     // we will just check it here , Like is it legit or not
 
     #[account(
@@ -230,12 +319,15 @@ pub struct MergeTokens<'info>{
         constraint = user_outcome_no.owner == user.key(),
         constraint = user_outcome_no.mint == market.outcome_no_mint
     )]
-    pub user_outcome_no : Account<'info, TokenAccount>,
-    pub token_program : Program<'info,Token>
+    pub user_outcome_no : InterfaceAccount<'info, token_interface::TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub token_program : Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
 }
 
 
 //Like who will be the winner
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(market_id :u32)]
 pub struct SetWinner <'info>{
@@ -247,7 +339,8 @@ pub struct SetWinner <'info>{
         mut,
         seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
         bump = market.bump,
-        constraint = market.market_id == market_id
+        constraint = market.market_id == market_id,
+        has_one = authority @ PredictionMarketError::NotAuthorized
     )]
     pub market : Account<'info,Market>,
 
@@ -255,19 +348,69 @@ pub struct SetWinner <'info>{
         mut,
         constraint = outcome_yes_mint.key() == market.outcome_yes_mint
     )]
-    pub outcome_yes_mint : Account<'info,Mint>,
+    pub outcome_yes_mint : InterfaceAccount<'info, token_interface::Mint>,
 
     #[account(
         mut,
         constraint = outcome_no_mint.key() == market.outcome_no_mint
     )]
-    pub outcome_no_mint : Account<'info,Mint>,
-    pub token_program : Program<'info,Token>
+    pub outcome_no_mint : InterfaceAccount<'info, token_interface::Mint>,
+    pub outcome_token_program: Program<'info, Token2022>,
     
 }
 
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct SetMarketStatus<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        has_one = authority @ PredictionMarketError::NotAuthorized,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Account<'info, Market>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct UpdateMarketMetadata<'info> {
+    // Pays for the outcome mints to grow if the new URI is longer
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        has_one = authority @ PredictionMarketError::NotAuthorized,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        constraint = outcome_yes_mint.key() == market.outcome_yes_mint
+    )]
+    pub outcome_yes_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = outcome_no_mint.key() == market.outcome_no_mint
+    )]
+    pub outcome_no_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    pub system_program: Program<'info, System>,
+    pub outcome_token_program: Program<'info, Token2022>,
+}
+
 
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(market_id:u32)]
 pub struct ClaimRewards <'info>{
@@ -281,54 +424,82 @@ pub struct ClaimRewards <'info>{
     )]
     pub market: Account<'info, Market>,
 
+    // None on native SOL markets, the signer's lamports are wrapped and unwrapped instead
     #[account(
         mut,
         constraint = user_collateral.mint == market.collateral_mint,
         constraint = user_collateral.owner == user.key()
     )]
-    pub user_collateral: Account<'info, TokenAccount>,
+    pub user_collateral: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
     
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
     #[account(
         mut,
         constraint = collateral_vault.key() == market.collateral_vault // We can also used the .owner of vault to verify it's authority of market
     )]
-    pub collateral_vault: Account<'info, TokenAccount>,
+    pub collateral_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    // Native SOL payouts only: a temporary wSOL account, closed to the user in the same instruction
+    #[account(
+        init,
+        payer = user,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [NATIVE_UNWRAP_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub native_unwrap: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
      
     #[account(
         mut,
         constraint = outcome_yes_mint.key() == market.outcome_yes_mint
     )]
-    pub outcome_yes_mint: Account<'info, Mint>,
+    pub outcome_yes_mint: InterfaceAccount<'info, token_interface::Mint>,
     
     #[account(
         mut,
         constraint = outcome_no_mint.key() == market.outcome_no_mint
     )]
-    pub outcome_no_mint: Account<'info, Mint>,
+    pub outcome_no_mint: InterfaceAccount<'info, token_interface::Mint>,
     
     #[account(
         mut,
         constraint = user_outcome_yes.mint == market.outcome_yes_mint,
         constraint = user_outcome_yes.owner == user.key()
     )]
-    pub user_outcome_yes: Account<'info, TokenAccount>,
+    pub user_outcome_yes: InterfaceAccount<'info, token_interface::TokenAccount>,
     
     #[account(
         mut,
         constraint = user_outcome_no.mint == market.outcome_no_mint,
         constraint = user_outcome_no.owner == user.key()
     )]
-    pub user_outcome_no: Account<'info, TokenAccount>,
+    pub user_outcome_no: InterfaceAccount<'info, token_interface::TokenAccount>,
     
-    pub token_program: Program<'info, Token>
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(market_id:u32)]
 pub struct PlaceOrder<'info> {
     #[account(mut)]
     pub user : Signer<'info>,
 
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
     #[account(
         mut,
         seeds=[MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
@@ -341,23 +512,38 @@ pub struct PlaceOrder<'info> {
     #[account(
         mut,
         seeds = [ORDERBOOK_SEED ,market.market_id.to_le_bytes().as_ref()],
-        bump = market.bump,
+        bump = orderbook.bump,
+        realloc = orderbook.space_for_next_order(),
+        realloc::payer = user,
+        realloc::zero = false,
         constraint = orderbook.market_id == market_id 
     )]
     pub orderbook : Box<Account<'info, OrderBook>>,
 
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
     #[account(
         mut,
         constraint = collateral_vault.key() == market.collateral_vault // We can also used the .owner of vault to verify it's authority of market
     )]
-    pub collateral_vault : Account<'info, TokenAccount>,
+    pub collateral_vault : InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = fee_vault.key() == market.fee_vault
+    )]
+    pub fee_vault : InterfaceAccount<'info, token_interface::TokenAccount>,
 
+    // None on native SOL markets, the signer's lamports are wrapped and unwrapped instead
     #[account(
         mut,
         constraint = user_collateral.mint == market.collateral_mint,
         constraint = user_collateral.owner == user.key()
     )]
-    pub user_collateral : Account<'info, TokenAccount>,
+    pub user_collateral : Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
 
     #[account(
         mut,
@@ -367,59 +553,96 @@ pub struct PlaceOrder<'info> {
     pub user_stats_account : Box<Account<'info,UserStats>>,
     // this will exist 100% because When user was given Yes NO token, then we made this account 
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + TraderVolume::INIT_SPACE,
+        seeds = [TRADER_VOLUME_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub trader_volume : Box<Account<'info, TraderVolume>>,
+
+    // Optional, recorded as the user's referrer for this market if none is set yet
+    /// CHECK: only its address is used
+    #[account(constraint = referrer.key() != user.key() @ PredictionMarketError::InvalidReferrer)]
+    pub referrer : Option<UncheckedAccount<'info>>,
+
+    // The recorded referrer's UserStats, credited with their share of this order's taker fees
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserStats::INIT_SPACE,
+        seeds = [
+            USER_STATS_SEED,
+            user_stats_account.referrer.or(referrer.as_ref().map(|r| r.key())).unwrap_or_default().as_ref(),
+            market_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub referrer_stats : Option<Box<Account<'info, UserStats>>>,
+
     #[account(
         mut,
         constraint = outcome_yes_mint.key() == market.outcome_yes_mint
     )]
-    pub outcome_yes_mint: Account<'info, Mint>,
+    pub outcome_yes_mint: InterfaceAccount<'info, token_interface::Mint>,
     
     #[account(
         mut,
         constraint = outcome_no_mint.key() == market.outcome_no_mint
     )]
-    pub outcome_no_mint: Account<'info, Mint>,
+    pub outcome_no_mint: InterfaceAccount<'info, token_interface::Mint>,
 
     #[account(
         mut,
         constraint = user_outcome_yes.mint == market.outcome_yes_mint,
         constraint = user_outcome_yes.owner == user.key()
     )]
-    pub user_outcome_yes: Account<'info, TokenAccount>,
+    pub user_outcome_yes: InterfaceAccount<'info, token_interface::TokenAccount>,
     
     #[account(
         mut,
         constraint = user_outcome_no.mint == market.outcome_no_mint,
         constraint = user_outcome_no.owner == user.key()
     )]
-    pub user_outcome_no: Account<'info, TokenAccount>,
+    pub user_outcome_no: InterfaceAccount<'info, token_interface::TokenAccount>,
 
     #[account(
         mut,
         constraint = yes_escrow.mint == market.outcome_yes_mint,
         constraint = yes_escrow.key() == market.yes_escrow
     )]
-    pub yes_escrow : Account<'info, TokenAccount>,
+    pub yes_escrow : InterfaceAccount<'info, token_interface::TokenAccount>,
 
     #[account(
         mut,
         constraint = no_escrow.mint == market.outcome_no_mint,
         constraint = no_escrow.key() == market.no_escrow
     )]
-    pub no_escrow : Account<'info, TokenAccount>,
+    pub no_escrow : InterfaceAccount<'info, token_interface::TokenAccount>,
 
 
     pub system_program: Program<'info, System>,
-    pub token_program : Program<'info, Token>
+    pub token_program : Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
 }
 
 
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(market_id:u32)]
 pub struct CancelOrder<'info> {
     #[account(mut)]
     pub user : Signer<'info>,
 
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
     #[account(
         mut,
         seeds=[MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
@@ -432,23 +655,41 @@ pub struct CancelOrder<'info> {
     #[account(
         mut,
         seeds = [ORDERBOOK_SEED ,market.market_id.to_le_bytes().as_ref()],
-        bump = market.bump,
+        bump = orderbook.bump,
         constraint = orderbook.market_id == market_id 
     )]
     pub orderbook : Box<Account<'info, OrderBook>>,
 
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
     #[account(
         mut,
         constraint = collateral_vault.key() == market.collateral_vault // We can also used the .owner of vault to verify it's authority of market
     )]
-    pub collateral_vault : Account<'info, TokenAccount>,
+    pub collateral_vault : InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    // Native SOL payouts only: a temporary wSOL account, closed to the user in the same instruction
+    #[account(
+        init,
+        payer = user,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [NATIVE_UNWRAP_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub native_unwrap: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
 
+    // None on native SOL markets, the signer's lamports are wrapped and unwrapped instead
     #[account(
         mut,
         constraint = user_collateral.mint == market.collateral_mint,
         constraint = user_collateral.owner == user.key()
     )]
-    pub user_collateral : Account<'info, TokenAccount>,
+    pub user_collateral : Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
 
     #[account(
         mut,
@@ -462,53 +703,62 @@ pub struct CancelOrder<'info> {
         mut,
         constraint = outcome_yes_mint.key() == market.outcome_yes_mint
     )]
-    pub outcome_yes_mint: Account<'info, Mint>,
+    pub outcome_yes_mint: InterfaceAccount<'info, token_interface::Mint>,
     
     #[account(
         mut,
         constraint = outcome_no_mint.key() == market.outcome_no_mint
     )]
-    pub outcome_no_mint: Account<'info, Mint>,
+    pub outcome_no_mint: InterfaceAccount<'info, token_interface::Mint>,
 
     #[account(
         mut,
         constraint = user_outcome_yes.mint == market.outcome_yes_mint,
         constraint = user_outcome_yes.owner == user.key()
     )]
-    pub user_outcome_yes: Account<'info, TokenAccount>,
+    pub user_outcome_yes: InterfaceAccount<'info, token_interface::TokenAccount>,
     
     #[account(
         mut,
         constraint = user_outcome_no.mint == market.outcome_no_mint,
         constraint = user_outcome_no.owner == user.key()
     )]
-    pub user_outcome_no: Account<'info, TokenAccount>,
+    pub user_outcome_no: InterfaceAccount<'info, token_interface::TokenAccount>,
 
     #[account(
         mut,
         constraint = yes_escrow.mint == market.outcome_yes_mint,
         constraint = yes_escrow.key() == market.yes_escrow
     )]
-    pub yes_escrow : Account<'info, TokenAccount>,
+    pub yes_escrow : InterfaceAccount<'info, token_interface::TokenAccount>,
 
     #[account(
         mut,
         constraint = no_escrow.mint == market.outcome_no_mint,
         constraint = no_escrow.key() == market.no_escrow
     )]
-    pub no_escrow : Account<'info, TokenAccount>,
+    pub no_escrow : InterfaceAccount<'info, token_interface::TokenAccount>,
 
 
     pub system_program: Program<'info, System>,
-    pub token_program : Program<'info, Token>
+    pub token_program : Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(market_id:u32)]
 pub struct MarketOrder<'info> {
     #[account(mut)]
     pub user : Signer<'info>,
 
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
     #[account(
         mut,
         seeds=[MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
@@ -521,23 +771,35 @@ pub struct MarketOrder<'info> {
     #[account(
         mut,
         seeds = [ORDERBOOK_SEED ,market.market_id.to_le_bytes().as_ref()],
-        bump = market.bump,
+        bump = orderbook.bump,
         constraint = orderbook.market_id == market_id 
     )]
     pub orderbook : Box<Account<'info, OrderBook>>,
 
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
     #[account(
         mut,
         constraint = collateral_vault.key() == market.collateral_vault // We can also used the .owner of vault to verify it's authority of market
     )]
-    pub collateral_vault : Account<'info, TokenAccount>,
+    pub collateral_vault : InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = fee_vault.key() == market.fee_vault
+    )]
+    pub fee_vault : InterfaceAccount<'info, token_interface::TokenAccount>,
 
+    // None on native SOL markets, the signer's lamports are wrapped and unwrapped instead
     #[account(
         mut,
         constraint = user_collateral.mint == market.collateral_mint,
         constraint = user_collateral.owner == user.key()
     )]
-    pub user_collateral : Account<'info, TokenAccount>,
+    pub user_collateral : Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
 
     #[account(
         mut,
@@ -547,47 +809,1748 @@ pub struct MarketOrder<'info> {
     pub user_stats_account : Box<Account<'info,UserStats>>,
     // this will exist 100% because When user was given Yes NO token, then we made this account 
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + TraderVolume::INIT_SPACE,
+        seeds = [TRADER_VOLUME_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub trader_volume : Box<Account<'info, TraderVolume>>,
+
+    // Optional, recorded as the user's referrer for this market if none is set yet
+    /// CHECK: only its address is used
+    #[account(constraint = referrer.key() != user.key() @ PredictionMarketError::InvalidReferrer)]
+    pub referrer : Option<UncheckedAccount<'info>>,
+
+    // The recorded referrer's UserStats, credited with their share of this order's taker fees
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserStats::INIT_SPACE,
+        seeds = [
+            USER_STATS_SEED,
+            user_stats_account.referrer.or(referrer.as_ref().map(|r| r.key())).unwrap_or_default().as_ref(),
+            market_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub referrer_stats : Option<Box<Account<'info, UserStats>>>,
+
     #[account(
         mut,
         constraint = outcome_yes_mint.key() == market.outcome_yes_mint
     )]
-    pub outcome_yes_mint: Account<'info, Mint>,
+    pub outcome_yes_mint: InterfaceAccount<'info, token_interface::Mint>,
     
     #[account(
         mut,
         constraint = outcome_no_mint.key() == market.outcome_no_mint
     )]
-    pub outcome_no_mint: Account<'info, Mint>,
+    pub outcome_no_mint: InterfaceAccount<'info, token_interface::Mint>,
 
     #[account(
         mut,
         constraint = user_outcome_yes.mint == market.outcome_yes_mint,
         constraint = user_outcome_yes.owner == user.key()
     )]
-    pub user_outcome_yes: Account<'info, TokenAccount>,
+    pub user_outcome_yes: InterfaceAccount<'info, token_interface::TokenAccount>,
     
     #[account(
         mut,
         constraint = user_outcome_no.mint == market.outcome_no_mint,
         constraint = user_outcome_no.owner == user.key()
     )]
-    pub user_outcome_no: Account<'info, TokenAccount>,
+    pub user_outcome_no: InterfaceAccount<'info, token_interface::TokenAccount>,
 
     #[account(
         mut,
         constraint = yes_escrow.mint == market.outcome_yes_mint,
         constraint = yes_escrow.key() == market.yes_escrow
     )]
-    pub yes_escrow : Account<'info, TokenAccount>,
+    pub yes_escrow : InterfaceAccount<'info, token_interface::TokenAccount>,
 
     #[account(
         mut,
         constraint = no_escrow.mint == market.outcome_no_mint,
         constraint = no_escrow.key() == market.no_escrow
     )]
-    pub no_escrow : Account<'info, TokenAccount>,
+    pub no_escrow : InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    // The market's AMM, required when market.amm is set: the pool and both reserves for Fpmm,
+    // the maker for Lmsr
+    #[account(
+        seeds = [AMM_POOL_SEED, market_id.to_le_bytes().as_ref()],
+        bump = pool.bump
+    )]
+    pub pool : Option<Box<Account<'info, AmmPool>>>,
+
+    #[account(
+        mut,
+        constraint = pool.as_ref().is_some_and(|pool| pool.yes_reserve == yes_reserve.key())
+    )]
+    pub yes_reserve : Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = pool.as_ref().is_some_and(|pool| pool.no_reserve == no_reserve.key())
+    )]
+    pub no_reserve : Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        mut,
+        seeds = [LMSR_SEED, market_id.to_le_bytes().as_ref()],
+        bump = lmsr.bump
+    )]
+    pub lmsr : Option<Box<Account<'info, LmsrMaker>>>,
 
+    // Native SOL sells only: a temporary wSOL account for the AMM proceeds, closed to the user
+    // in the same instruction. Book proceeds are credited to UserStats as usual
+    #[account(
+        init,
+        payer = user,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [NATIVE_UNWRAP_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub native_unwrap : Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
 
     pub system_program: Program<'info, System>,
-    pub token_program : Program<'info, Token>
-}
\ No newline at end of file
+    pub token_program : Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
+}
+
+// Shared by split_and_sell and buy_and_merge: the taker's side of the book fills is settled
+// in the same instruction, so there is no UserStats for the user here
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id:u32)]
+pub struct SplitMergeOrder<'info> {
+    #[account(mut)]
+    pub user : Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        mut,
+        seeds=[MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id,
+    )]
+    pub market : Box<Account<'info, Market>>,
+
+    #[account(
+        mut,
+        seeds = [ORDERBOOK_SEED ,market.market_id.to_le_bytes().as_ref()],
+        bump = orderbook.bump,
+        constraint = orderbook.market_id == market_id
+    )]
+    pub orderbook : Box<Account<'info, OrderBook>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault : InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = fee_vault.key() == market.fee_vault
+    )]
+    pub fee_vault : InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    // None on native SOL markets, the signer's lamports are wrapped and unwrapped instead
+    #[account(
+        mut,
+        constraint = user_collateral.mint == market.collateral_mint,
+        constraint = user_collateral.owner == user.key()
+    )]
+    pub user_collateral : Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + TraderVolume::INIT_SPACE,
+        seeds = [TRADER_VOLUME_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub trader_volume : Box<Account<'info, TraderVolume>>,
+
+    #[account(
+        mut,
+        constraint = outcome_yes_mint.key() == market.outcome_yes_mint
+    )]
+    pub outcome_yes_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = outcome_no_mint.key() == market.outcome_no_mint
+    )]
+    pub outcome_no_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = user_outcome_yes.mint == market.outcome_yes_mint,
+        constraint = user_outcome_yes.owner == user.key()
+    )]
+    pub user_outcome_yes: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_outcome_no.mint == market.outcome_no_mint,
+        constraint = user_outcome_no.owner == user.key()
+    )]
+    pub user_outcome_no: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = yes_escrow.mint == market.outcome_yes_mint,
+        constraint = yes_escrow.key() == market.yes_escrow
+    )]
+    pub yes_escrow : InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = no_escrow.mint == market.outcome_no_mint,
+        constraint = no_escrow.key() == market.no_escrow
+    )]
+    pub no_escrow : InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    // Native SOL only: a temporary wSOL account for whatever the user is owed once the split
+    // or merge is netted against the fills, closed to the user in the same instruction
+    #[account(
+        init,
+        payer = user,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [NATIVE_UNWRAP_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub native_unwrap : Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program : Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
+}
+
+// Read-only, used through simulateTransaction to read the book via return data
+#[derive(Accounts)]
+#[instruction(market_id:u32)]
+pub struct ViewOrderBook<'info> {
+    #[account(
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id,
+    )]
+    pub market : Box<Account<'info, Market>>,
+
+    #[account(
+        seeds = [ORDERBOOK_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = orderbook.bump,
+        constraint = orderbook.market_id == market_id
+    )]
+    pub orderbook : Box<Account<'info, OrderBook>>,
+}
+
+
+// ---------------- Categorical markets ----------------
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitializeCategoricalMarket<'info> {
+    // The new market gets market_counter.next_market_id, every seed below uses it
+    #[account(
+        mut,
+        seeds = [MARKET_COUNTER_SEED],
+        bump = market_counter.bump
+    )]
+    pub market_counter: Box<Account<'info, MarketCounter>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + CategoricalMarket::INIT_SPACE,
+        seeds = [CATEGORICAL_MARKET_SEED, market_counter.next_market_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub market: Box<Account<'info, CategoricalMarket>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        constraint = config.is_collateral_allowed(&collateral_mint.key()) @ PredictionMarketError::CollateralNotAllowed,
+        constraint = crate::is_supported_collateral_mint(&collateral_mint.to_account_info()) @ PredictionMarketError::UnsupportedCollateralMint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = collateral_mint,
+        token::authority = market,
+        seeds = [CATEGORICAL_VAULT_SEED, market_counter.next_market_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub collateral_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// Creates the mint, escrow and order book of one outcome
+// Called once per outcome in index order, too many accounts to do all 16 in one tx
+#[derive(Accounts)]
+#[instruction(market_id: u32, outcome_index: u8)]
+pub struct InitializeCategoricalOutcome<'info> {
+    #[account(
+        mut,
+        seeds = [CATEGORICAL_MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        has_one = authority,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, CategoricalMarket>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // Only read for its decimals, outcome mints follow the collateral like in binary markets
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        mint::decimals = collateral_mint.decimals,
+        mint::authority = market,
+        seeds = [CATEGORICAL_OUTCOME_SEED, market_id.to_le_bytes().as_ref(), &[outcome_index]],
+        bump
+    )]
+    pub outcome_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::authority = market,
+        token::mint = outcome_mint,
+        seeds = [ESCROW_SEED, market_id.to_le_bytes().as_ref(), outcome_mint.key().as_ref()],
+        bump
+    )]
+    pub outcome_escrow: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = OutcomeOrderBook::space(0), // grows by realloc when orders are placed
+        seeds = [CATEGORICAL_ORDERBOOK_SEED, market_id.to_le_bytes().as_ref(), &[outcome_index]],
+        bump
+    )]
+    pub orderbook: Box<Account<'info, OutcomeOrderBook>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// remaining_accounts: [outcome_mint_i, user_outcome_i] for every outcome i, in index order
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct SplitCategorical<'info> {
+    #[account(
+        mut,
+        seeds = [CATEGORICAL_MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, CategoricalMarket>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        mut,
+        constraint = user_collateral.mint == market.collateral_mint,
+        constraint = user_collateral.owner == user.key()
+    )]
+    pub user_collateral: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + CategoricalUserStats::INIT_SPACE,
+        seeds = [CATEGORICAL_USER_STATS_SEED, user.key().as_ref(), market_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub user_stats_account: Box<Account<'info, CategoricalUserStats>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token>,
+}
+
+// remaining_accounts: [outcome_mint_i, user_outcome_i] for every outcome i, in index order
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct MergeCategorical<'info> {
+    #[account(
+        mut,
+        seeds = [CATEGORICAL_MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, CategoricalMarket>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        mut,
+        constraint = user_collateral.mint == market.collateral_mint,
+        constraint = user_collateral.owner == user.key()
+    )]
+    pub user_collateral: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token>,
+}
+
+// remaining_accounts: every outcome mint in index order, their mint authority gets revoked
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct SetCategoricalWinner<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [CATEGORICAL_MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        has_one = authority,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, CategoricalMarket>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct ClaimCategorical<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [CATEGORICAL_MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, CategoricalMarket>>,
+
+    #[account(
+        mut,
+        constraint = user_collateral.mint == market.collateral_mint,
+        constraint = user_collateral.owner == user.key()
+    )]
+    pub user_collateral: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    // Checked against market.winning_outcome in the handler
+    #[account(mut)]
+    pub winning_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_winning_outcome.mint == winning_mint.key(),
+        constraint = user_winning_outcome.owner == user.key()
+    )]
+    pub user_winning_outcome: Account<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32, outcome_index: u8)]
+pub struct PlaceCategoricalOrder<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        seeds = [CATEGORICAL_MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id,
+        constraint = outcome_index < market.outcome_count
+    )]
+    pub market: Box<Account<'info, CategoricalMarket>>,
+
+    #[account(
+        mut,
+        seeds = [CATEGORICAL_ORDERBOOK_SEED, market_id.to_le_bytes().as_ref(), &[outcome_index]],
+        bump = orderbook.bump,
+        realloc = orderbook.space_for_next_order(),
+        realloc::payer = user,
+        realloc::zero = false
+    )]
+    pub orderbook: Box<Account<'info, OutcomeOrderBook>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_collateral.mint == market.collateral_mint,
+        constraint = user_collateral.owner == user.key()
+    )]
+    pub user_collateral: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [CATEGORICAL_USER_STATS_SEED, user.key().as_ref(), market_id.to_le_bytes().as_ref()],
+        bump = user_stats_account.bump
+    )]
+    pub user_stats_account: Box<Account<'info, CategoricalUserStats>>,
+
+    #[account(
+        constraint = outcome_mint.key() == market.outcome_mints[outcome_index as usize]
+    )]
+    pub outcome_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_outcome.mint == outcome_mint.key(),
+        constraint = user_outcome.owner == user.key()
+    )]
+    pub user_outcome: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, market_id.to_le_bytes().as_ref(), outcome_mint.key().as_ref()],
+        bump
+    )]
+    pub outcome_escrow: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32, outcome_index: u8)]
+pub struct CancelCategoricalOrder<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        seeds = [CATEGORICAL_MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id,
+        constraint = outcome_index < market.outcome_count
+    )]
+    pub market: Box<Account<'info, CategoricalMarket>>,
+
+    #[account(
+        mut,
+        seeds = [CATEGORICAL_ORDERBOOK_SEED, market_id.to_le_bytes().as_ref(), &[outcome_index]],
+        bump = orderbook.bump
+    )]
+    pub orderbook: Box<Account<'info, OutcomeOrderBook>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_collateral.mint == market.collateral_mint,
+        constraint = user_collateral.owner == user.key()
+    )]
+    pub user_collateral: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [CATEGORICAL_USER_STATS_SEED, user.key().as_ref(), market_id.to_le_bytes().as_ref()],
+        bump = user_stats_account.bump
+    )]
+    pub user_stats_account: Box<Account<'info, CategoricalUserStats>>,
+
+    #[account(
+        constraint = outcome_mint.key() == market.outcome_mints[outcome_index as usize]
+    )]
+    pub outcome_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_outcome.mint == outcome_mint.key(),
+        constraint = user_outcome.owner == user.key()
+    )]
+    pub user_outcome: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, market_id.to_le_bytes().as_ref(), outcome_mint.key().as_ref()],
+        bump
+    )]
+    pub outcome_escrow: Account<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token>,
+}
+
+
+// ---------------- Neg-risk market groups ----------------
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(group_id: u32)]
+pub struct InitializeMarketGroup<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MarketGroup::INIT_SPACE,
+        seeds = [MARKET_GROUP_SEED, group_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub group: Account<'info, MarketGroup>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        constraint = config.is_collateral_allowed(&collateral_mint.key()) @ PredictionMarketError::CollateralNotAllowed,
+        constraint = crate::is_supported_collateral_mint(&collateral_mint.to_account_info()) @ PredictionMarketError::UnsupportedCollateralMint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(group_id: u32)]
+pub struct AddMarketToGroup<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_GROUP_SEED, group_id.to_le_bytes().as_ref()],
+        bump = group.bump,
+        has_one = authority
+    )]
+    pub group: Account<'info, MarketGroup>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        has_one = authority
+    )]
+    pub market: Account<'info, Market>,
+}
+
+// remaining_accounts: [market, collateral_vault, outcome_yes_mint, outcome_no_mint, user_position]
+// for every market of the group, in group order. user_position is the user's NO token account
+// for the markets being converted and their YES token account for the rest
+// Every market account has to be writable, the first conversion marks them as traded
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(group_id: u32)]
+pub struct ConvertNoPositions<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_GROUP_SEED, group_id.to_le_bytes().as_ref()],
+        bump = group.bump
+    )]
+    pub group: Account<'info, MarketGroup>,
+
+    #[account(
+        mut,
+        constraint = user_collateral.mint == group.collateral_mint,
+        constraint = user_collateral.owner == user.key()
+    )]
+    pub user_collateral: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        constraint = collateral_mint.key() == group.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
+}
+
+// remaining_accounts: [market, collateral_vault, outcome_yes_mint, outcome_no_mint]
+// for every market of the group, in group order
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(group_id: u32)]
+pub struct ResolveMarketGroup<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_GROUP_SEED, group_id.to_le_bytes().as_ref()],
+        bump = group.bump,
+        has_one = authority
+    )]
+    pub group: Account<'info, MarketGroup>,
+
+    #[account(
+        constraint = collateral_mint.key() == group.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
+}
+
+
+// ---------------- Protocol config ----------------
+
+#[derive(Accounts)]
+pub struct InitializeProtocolConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ProtocolConfig::INIT_SPACE,
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + MarketCounter::INIT_SPACE,
+        seeds = [MARKET_COUNTER_SEED],
+        bump
+    )]
+    pub market_counter: Account<'info, MarketCounter>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    // Only the upgrade authority can create the config, otherwise anyone could grab the admin role
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, PredictionMarket>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key()) @ PredictionMarketError::NotAuthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProtocolConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ PredictionMarketError::NotAuthorized
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct SetMarketFees<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ PredictionMarketError::NotAuthorized
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Account<'info, Market>,
+}
+
+// Shared by withdraw_protocol_fees (config.fee_recipient) and withdraw_creator_fees (market.authority),
+// the handlers check the recipient
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct WithdrawFees<'info> {
+    pub recipient: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = fee_vault.key() == market.fee_vault
+    )]
+    pub fee_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = recipient_collateral.mint == market.collateral_mint,
+        constraint = recipient_collateral.owner == recipient.key()
+    )]
+    pub recipient_collateral: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Mints are checked for unsupported Token-2022 extensions before they can back any market
+#[derive(Accounts)]
+pub struct AddAllowedCollateral<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ PredictionMarketError::NotAuthorized
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        constraint = crate::is_supported_collateral_mint(&collateral_mint.to_account_info()) @ PredictionMarketError::UnsupportedCollateralMint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct WithdrawReferralFees<'info> {
+    pub referrer: Signer<'info>,
+
+    #[account(
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        mut,
+        seeds = [USER_STATS_SEED, referrer.key().as_ref(), market_id.to_le_bytes().as_ref()],
+        bump = referrer_stats.bump
+    )]
+    pub referrer_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(
+        mut,
+        constraint = fee_vault.key() == market.fee_vault
+    )]
+    pub fee_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = recipient_collateral.mint == market.collateral_mint,
+        constraint = recipient_collateral.owner == referrer.key()
+    )]
+    pub recipient_collateral: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptProtocolAdmin<'info> {
+    pub pending_admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = config.pending_admin == Some(pending_admin.key()) @ PredictionMarketError::NotAuthorized
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct InitializeAmmPool<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id,
+        has_one = authority @ PredictionMarketError::NotAuthorized
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + AmmPool::INIT_SPACE,
+        seeds = [AMM_POOL_SEED, market_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pool: Box<Account<'info, AmmPool>>,
+
+    #[account(
+        constraint = outcome_yes_mint.key() == market.outcome_yes_mint
+    )]
+    pub outcome_yes_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        constraint = outcome_no_mint.key() == market.outcome_no_mint
+    )]
+    pub outcome_no_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    // LP shares carry the outcome decimals, one share per pair on the first deposit
+    #[account(
+        init,
+        payer = authority,
+        mint::decimals = outcome_yes_mint.decimals,
+        mint::authority = market,
+        mint::token_program = outcome_token_program,
+        seeds = [AMM_LP_MINT_SEED, market_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub lp_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = outcome_yes_mint,
+        token::authority = market,
+        token::token_program = outcome_token_program,
+        seeds = [AMM_RESERVE_SEED, market_id.to_le_bytes().as_ref(), outcome_yes_mint.key().as_ref()],
+        bump
+    )]
+    pub yes_reserve: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = outcome_no_mint,
+        token::authority = market,
+        token::token_program = outcome_token_program,
+        seeds = [AMM_RESERVE_SEED, market_id.to_le_bytes().as_ref(), outcome_no_mint.key().as_ref()],
+        bump
+    )]
+    pub no_reserve: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub outcome_token_program: Program<'info, Token2022>,
+}
+
+// Shared by amm_buy and amm_sell, collateral moves between the user and the market vault and
+// outcome tokens between the user and the pool reserves
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct AmmTrade<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        seeds = [AMM_POOL_SEED, market_id.to_le_bytes().as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, AmmPool>>,
+
+    // None on native SOL markets, the signer's lamports are wrapped and unwrapped instead
+    #[account(
+        mut,
+        constraint = user_collateral.mint == market.collateral_mint,
+        constraint = user_collateral.owner == user.key()
+    )]
+    pub user_collateral: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // Native SOL sells only: a temporary wSOL account, closed to the user in the same instruction
+    #[account(
+        init,
+        payer = user,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [NATIVE_UNWRAP_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub native_unwrap: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = outcome_yes_mint.key() == market.outcome_yes_mint
+    )]
+    pub outcome_yes_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = outcome_no_mint.key() == market.outcome_no_mint
+    )]
+    pub outcome_no_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = yes_reserve.key() == pool.yes_reserve
+    )]
+    pub yes_reserve: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = no_reserve.key() == pool.no_reserve
+    )]
+    pub no_reserve: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_outcome_yes.owner == user.key(),
+        constraint = user_outcome_yes.mint == market.outcome_yes_mint
+    )]
+    pub user_outcome_yes: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_outcome_no.owner == user.key(),
+        constraint = user_outcome_no.mint == market.outcome_no_mint
+    )]
+    pub user_outcome_no: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct AddLiquidity<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        seeds = [AMM_POOL_SEED, market_id.to_le_bytes().as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, AmmPool>>,
+
+    // None on native SOL markets, the signer's lamports are wrapped instead
+    #[account(
+        mut,
+        constraint = user_collateral.mint == market.collateral_mint,
+        constraint = user_collateral.owner == user.key()
+    )]
+    pub user_collateral: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = outcome_yes_mint.key() == market.outcome_yes_mint
+    )]
+    pub outcome_yes_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = outcome_no_mint.key() == market.outcome_no_mint
+    )]
+    pub outcome_no_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = yes_reserve.key() == pool.yes_reserve
+    )]
+    pub yes_reserve: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = no_reserve.key() == pool.no_reserve
+    )]
+    pub no_reserve: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_outcome_yes.owner == user.key(),
+        constraint = user_outcome_yes.mint == market.outcome_yes_mint
+    )]
+    pub user_outcome_yes: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_outcome_no.owner == user.key(),
+        constraint = user_outcome_no.mint == market.outcome_no_mint
+    )]
+    pub user_outcome_no: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_lp.mint == pool.lp_mint,
+        constraint = user_lp.owner == user.key()
+    )]
+    pub user_lp: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct RemoveLiquidity<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        seeds = [AMM_POOL_SEED, market_id.to_le_bytes().as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, AmmPool>>,
+
+    #[account(
+        constraint = outcome_yes_mint.key() == market.outcome_yes_mint
+    )]
+    pub outcome_yes_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        constraint = outcome_no_mint.key() == market.outcome_no_mint
+    )]
+    pub outcome_no_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = yes_reserve.key() == pool.yes_reserve
+    )]
+    pub yes_reserve: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = no_reserve.key() == pool.no_reserve
+    )]
+    pub no_reserve: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_outcome_yes.owner == user.key(),
+        constraint = user_outcome_yes.mint == market.outcome_yes_mint
+    )]
+    pub user_outcome_yes: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_outcome_no.owner == user.key(),
+        constraint = user_outcome_no.mint == market.outcome_no_mint
+    )]
+    pub user_outcome_no: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.key() == pool.lp_mint
+    )]
+    pub lp_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_lp.mint == pool.lp_mint,
+        constraint = user_lp.owner == user.key()
+    )]
+    pub user_lp: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    pub outcome_token_program: Program<'info, Token2022>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct InitializeLmsr<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id,
+        has_one = authority @ PredictionMarketError::NotAuthorized
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + LmsrMaker::INIT_SPACE,
+        seeds = [LMSR_SEED, market_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub lmsr: Box<Account<'info, LmsrMaker>>,
+
+    // None on native SOL markets, the subsidy is wrapped from the authority's lamports
+    #[account(
+        mut,
+        constraint = authority_collateral.mint == market.collateral_mint,
+        constraint = authority_collateral.owner == authority.key()
+    )]
+    pub authority_collateral: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Shared by lmsr_buy and lmsr_sell, the maker mints to buyers and burns from sellers
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct LmsrTrade<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ PredictionMarketError::ProtocolPaused
+    )]
+    pub config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        mut,
+        seeds = [LMSR_SEED, market_id.to_le_bytes().as_ref()],
+        bump = lmsr.bump
+    )]
+    pub lmsr: Box<Account<'info, LmsrMaker>>,
+
+    // None on native SOL markets, the signer's lamports are wrapped and unwrapped instead
+    #[account(
+        mut,
+        constraint = user_collateral.mint == market.collateral_mint,
+        constraint = user_collateral.owner == user.key()
+    )]
+    pub user_collateral: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // Native SOL sells only: a temporary wSOL account, closed to the user in the same instruction
+    #[account(
+        init,
+        payer = user,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [NATIVE_UNWRAP_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub native_unwrap: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = outcome_yes_mint.key() == market.outcome_yes_mint
+    )]
+    pub outcome_yes_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = outcome_no_mint.key() == market.outcome_no_mint
+    )]
+    pub outcome_no_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_outcome_yes.owner == user.key(),
+        constraint = user_outcome_yes.mint == market.outcome_yes_mint
+    )]
+    pub user_outcome_yes: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_outcome_no.owner == user.key(),
+        constraint = user_outcome_no.mint == market.outcome_no_mint
+    )]
+    pub user_outcome_no: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub outcome_token_program: Program<'info, Token2022>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct WithdrawLmsrSubsidy<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        mut,
+        seeds = [LMSR_SEED, market_id.to_le_bytes().as_ref()],
+        bump = lmsr.bump,
+        has_one = creator @ PredictionMarketError::NotAuthorized
+    )]
+    pub lmsr: Box<Account<'info, LmsrMaker>>,
+
+    // None on native SOL markets, the leftover is paid out as lamports
+    #[account(
+        mut,
+        constraint = creator_collateral.mint == market.collateral_mint,
+        constraint = creator_collateral.owner == creator.key()
+    )]
+    pub creator_collateral: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault
+    )]
+    pub collateral_vault: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        init,
+        payer = creator,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [NATIVE_UNWRAP_SEED, creator.key().as_ref()],
+        bump
+    )]
+    pub native_unwrap: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct CreateLiquidityRewards<'info> {
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    #[account(
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        init,
+        payer = sponsor,
+        space = 8 + LiquidityRewards::INIT_SPACE,
+        seeds = [LIQUIDITY_REWARDS_SEED, market_id.to_le_bytes().as_ref(), sponsor.key().as_ref()],
+        bump
+    )]
+    pub rewards: Box<Account<'info, LiquidityRewards>>,
+
+    #[account(
+        init,
+        payer = sponsor,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [REWARD_VAULT_SEED, rewards.key().as_ref()],
+        bump
+    )]
+    pub reward_vault: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // None on native SOL markets, the rewards are wrapped from the sponsor's lamports
+    #[account(
+        mut,
+        constraint = sponsor_collateral.mint == market.collateral_mint,
+        constraint = sponsor_collateral.owner == sponsor.key()
+    )]
+    pub sponsor_collateral: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Permissionless, anyone can sample the book or close a finished epoch
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct CrankLiquidityRewards<'info> {
+    #[account(
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        seeds = [ORDERBOOK_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = orderbook.bump,
+        constraint = orderbook.market_id == market_id
+    )]
+    pub orderbook: Box<Account<'info, OrderBook>>,
+
+    #[account(
+        mut,
+        seeds = [
+            LIQUIDITY_REWARDS_SEED,
+            market_id.to_le_bytes().as_ref(),
+            rewards.sponsor.as_ref()
+        ],
+        bump = rewards.bump
+    )]
+    pub rewards: Box<Account<'info, LiquidityRewards>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct ClaimLiquidityRewards<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    #[account(
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        mut,
+        seeds = [
+            LIQUIDITY_REWARDS_SEED,
+            market_id.to_le_bytes().as_ref(),
+            rewards.sponsor.as_ref()
+        ],
+        bump = rewards.bump
+    )]
+    pub rewards: Box<Account<'info, LiquidityRewards>>,
+
+    #[account(
+        mut,
+        constraint = reward_vault.key() == rewards.reward_vault
+    )]
+    pub reward_vault: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // None on native SOL markets, the rewards are paid out as lamports
+    #[account(
+        mut,
+        constraint = maker_collateral.mint == market.collateral_mint,
+        constraint = maker_collateral.owner == maker.key()
+    )]
+    pub maker_collateral: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        init,
+        payer = maker,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [NATIVE_UNWRAP_SEED, maker.key().as_ref()],
+        bump
+    )]
+    pub native_unwrap: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(market_id: u32)]
+pub struct WithdrawLiquidityRewards<'info> {
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    #[account(
+        seeds = [MARKET_SEED, market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        constraint = market.market_id == market_id
+    )]
+    pub market: Box<Account<'info, Market>>,
+
+    #[account(
+        mut,
+        seeds = [LIQUIDITY_REWARDS_SEED, market_id.to_le_bytes().as_ref(), sponsor.key().as_ref()],
+        bump = rewards.bump
+    )]
+    pub rewards: Box<Account<'info, LiquidityRewards>>,
+
+    #[account(
+        mut,
+        constraint = reward_vault.key() == rewards.reward_vault
+    )]
+    pub reward_vault: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // None on native SOL markets, the leftover is paid out as lamports
+    #[account(
+        mut,
+        constraint = sponsor_collateral.mint == market.collateral_mint,
+        constraint = sponsor_collateral.owner == sponsor.key()
+    )]
+    pub sponsor_collateral: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    #[account(
+        constraint = collateral_mint.key() == market.collateral_mint
+    )]
+    pub collateral_mint: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        init,
+        payer = sponsor,
+        token::mint = collateral_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [NATIVE_UNWRAP_SEED, sponsor.key().as_ref()],
+        bump
+    )]
+    pub native_unwrap: Option<Box<InterfaceAccount<'info, token_interface::TokenAccount>>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
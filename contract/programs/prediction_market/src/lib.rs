@@ -1,14 +1,40 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
 use anchor_spl::token::{
-    spl_token::instruction::AuthorityType, Burn, MintTo, SetAuthority, Transfer,
+    self,
+    spl_token::instruction::AuthorityType, Burn, MintTo, SetAuthority,
+    Transfer,
+};
+use anchor_spl::token_2022::{
+    self,
+    spl_token_2022::{
+        extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+        instruction::AuthorityType as OutcomeAuthorityType,
+        state::Mint as MintState,
+    },
+};
+use anchor_spl::token_interface::{
+    self,
+    spl_pod::optional_keys::OptionalNonZeroPubkey,
+    spl_token_metadata_interface::state::{Field, TokenMetadata},
+    TokenInterface, TokenMetadataInitialize, TokenMetadataUpdateField,
 };
 pub mod constants;
 pub mod error;
+pub mod events;
 pub mod instructions;
+pub mod math;
 pub mod state;
 
+use constants::{
+    CATEGORICAL_MARKET_SEED, DEFAULT_VOLUME_WINDOW, MARKET_GROUP_SEED,
+    MAX_ALLOWED_COLLATERAL_MINTS, MAX_CATEGORICAL_OUTCOMES, MAX_FEE_BPS, MAX_FEE_TIERS,
+    MAX_GROUP_MARKETS, MAX_ORDERS_PER_SIDE, MIN_CATEGORICAL_OUTCOMES,
+};
 use error::PredictionMarketError;
+use events::*;
 use instructions::*;
+use math::WAD;
 use state::*;
 
 declare_id!("BnhQSbprbPZoruJ2WG6YwBDGNgjLg2DhcsHKvwwFa16P");
@@ -19,46 +45,81 @@ pub mod prediction_market {
 
     use super::*;
 
+    /// The market id comes from the global MarketCounter and is returned to the caller
     pub fn initialize_market(
         ctx: Context<InitializeMarket>,
-        market_id: u32,
         settlement_deadline: i64,
-    ) -> Result<()> {
-        let market: &mut Account<'_, Market> = &mut ctx.accounts.market;
+        metadata: MarketMetadata,
+    ) -> Result<u32> {
+        let market_id = init_market_accounts(
+            ctx.accounts,
+            &ctx.bumps,
+            settlement_deadline,
+            metadata,
+            MarketType::Binary,
+        )?;
+        let market = &ctx.accounts.market;
+
+        emit_cpi!(MarketInitialized {
+            market_id,
+            authority: market.authority,
+            collateral_mint: market.collateral_mint,
+            settlement_deadline,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Market initialized: {}", market.market_id);
+        Ok(market_id)
+    }
+
+    /// Scalar (range) market: resolves to a value in [scalar_low, scalar_high] instead of a side
+    /// Uses the same mints, escrows and order book as a binary market, YES = LONG and NO = SHORT
+    pub fn initialize_scalar_market(
+        ctx: Context<InitializeMarket>,
+        settlement_deadline: i64,
+        scalar_low: i64,
+        scalar_high: i64,
+        metadata: MarketMetadata,
+    ) -> Result<u32> {
         require!(
-            settlement_deadline > Clock::get()?.unix_timestamp,
-            PredictionMarketError::InvalidSettlementDeadline
+            scalar_low < scalar_high,
+            PredictionMarketError::InvalidScalarBounds
         );
-        market.authority = ctx.accounts.authority.key();
-        market.market_id = market_id;
-        market.settlement_deadline = settlement_deadline;
-        market.collateral_mint = ctx.accounts.collateral_mint.key();
-        market.collateral_vault = ctx.accounts.collateral_vault.key();
-        market.outcome_yes_mint = ctx.accounts.outcome_yes_mint.key();
-        market.outcome_no_mint = ctx.accounts.outcome_no_mint.key();
-        market.yes_escrow = ctx.accounts.yes_escrow.key();
-        market.no_escrow = ctx.accounts.no_escrow.key();
-        market.is_settled = false;
-        market.winning_outcome = None;
-        market.total_collateral_locked = 0;
-        market.bump = ctx.bumps.market;
 
-        let orderbook = &mut ctx.accounts.orderbook;
-        orderbook.bump = ctx.bumps.orderbook;
-        orderbook.market_id = market_id;
-        orderbook.next_order_id = 0;
-        orderbook.yes_buy_orders = Vec::new();
-        orderbook.yes_sell_orders = Vec::new();
-        orderbook.no_buy_orders = Vec::new();
-        orderbook.no_sell_orders = Vec::new();
+        let market_id = init_market_accounts(
+            ctx.accounts,
+            &ctx.bumps,
+            settlement_deadline,
+            metadata,
+            MarketType::Scalar,
+        )?;
+        let market = &mut ctx.accounts.market;
+        market.scalar_low = scalar_low;
+        market.scalar_high = scalar_high;
 
-        msg!("Market initialized: {}", market.market_id);
-        Ok(())
+        emit_cpi!(ScalarMarketInitialized {
+            market_id,
+            authority: market.authority,
+            collateral_mint: market.collateral_mint,
+            scalar_low,
+            scalar_high,
+            settlement_deadline,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Scalar market initialized: {} with range [{}, {}]",
+            market_id,
+            scalar_low,
+            scalar_high
+        );
+        Ok(market_id)
     }
 
     pub fn split_tokens(ctx: Context<SplitToken>, market_id: u32, amount: u64) -> Result<()> {
         let market = &mut ctx.accounts.market;
         require!(amount > 0, PredictionMarketError::InvalidAmount);
+        market.require_status(&[MarketStatus::Active])?;
         require!(
             !market.is_settled,
             PredictionMarketError::MarketAlreadySettled
@@ -73,25 +134,34 @@ pub mod prediction_market {
         //         ctx.accounts.user_collateral.to_account_info()  // Type: AccountInfo
 
         // Transferring the tokens from user account into Collateral Vault
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.user_collateral.to_account_info(),
-                    to: ctx.accounts.collateral_vault.to_account_info(),
-                    authority: ctx.accounts.user.to_account_info(),
-                },
-            ),
-            amount,
-        )?;
+        let vault_before = ctx.accounts.collateral_vault.amount;
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.user,
+            user_collateral: ctx.accounts.user_collateral.as_ref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.deposit(&ctx.accounts.system_program, amount)?;
+
+        // Only what actually reached the vault backs outcome tokens
+        ctx.accounts.collateral_vault.reload()?;
+        let amount = ctx
+            .accounts
+            .collateral_vault
+            .amount
+            .checked_sub(vault_before)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+
         let market_id_bytes = market.market_id.to_le_bytes();
         let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
 
         //Minting Outcome Yes tokens
-        token::mint_to(
+        token_2022::mint_to(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                MintTo {
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::MintTo {
                     mint: ctx.accounts.outcome_yes_mint.to_account_info(),
                     to: ctx.accounts.user_outcome_yes.to_account_info(),
                     authority: market.to_account_info(),
@@ -102,10 +172,10 @@ pub mod prediction_market {
         )?;
 
         //Minting Outcome No tokens
-        token::mint_to(
+        token_2022::mint_to(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                MintTo {
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::MintTo {
                     mint: ctx.accounts.outcome_no_mint.to_account_info(),
                     to: ctx.accounts.user_outcome_no.to_account_info(),
                     authority: market.to_account_info(),
@@ -119,6 +189,7 @@ pub mod prediction_market {
             .total_collateral_locked
             .checked_add(amount)
             .ok_or(PredictionMarketError::MathOverflow)?;
+        market.trading_started = true;
 
         let user_stats = &mut ctx.accounts.user_stats_account;
 
@@ -145,15 +216,29 @@ pub mod prediction_market {
         // .ok_or(error) → converts to Result<u64, Error>:
         // Some(value) → Ok(value)
         // None → Err(PredictionMarketError::MathOverflow)
+        emit_cpi!(TokensSplit {
+            market_id,
+            user: ctx.accounts.user.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
         msg!("Minted {} outcome tokens for user", amount);
         Ok(())
     }
 
     pub fn merge_tokens(ctx: Context<MergeTokens>, _market_id: u32) -> Result<()> {
         let market = &mut ctx.accounts.market;
+        market.require_status(&[
+            MarketStatus::Active,
+            MarketStatus::CloseOnly,
+            MarketStatus::Voided,
+        ])?;
 
+        // A voided market never settles, merging stays open past the deadline
         require!(
-            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            market.status == MarketStatus::Voided
+                || Clock::get()?.unix_timestamp < market.settlement_deadline,
             PredictionMarketError::MarketExpired
         );
         require!(
@@ -168,10 +253,10 @@ pub mod prediction_market {
 
         require!(amount > 0, PredictionMarketError::InvalidAmount);
 
-        token::burn(
+        token_2022::burn(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Burn {
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::Burn {
                     mint: ctx.accounts.outcome_yes_mint.to_account_info(),
                     from: ctx.accounts.user_outcome_yes.to_account_info(),
                     authority: ctx.accounts.user.to_account_info(),
@@ -179,10 +264,10 @@ pub mod prediction_market {
             ),
             amount,
         )?;
-        token::burn(
+        token_2022::burn(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Burn {
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::Burn {
                     mint: ctx.accounts.outcome_no_mint.to_account_info(),
                     from: ctx.accounts.user_outcome_no.to_account_info(),
                     authority: ctx.accounts.user.to_account_info(),
@@ -195,16 +280,17 @@ pub mod prediction_market {
         let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
 
         // Transfering Collateral Back to user collateral Account
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.collateral_vault.to_account_info(),
-                    to: ctx.accounts.user_collateral.to_account_info(),
-                    authority: market.to_account_info(),
-                },
-                &[seeds],
-            ),
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.user,
+            user_collateral: ctx.accounts.user_collateral.as_ref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.pay_out(
+            ctx.accounts.native_unwrap.as_ref(),
+            market.to_account_info(),
+            seeds,
             amount,
         )?;
 
@@ -213,6 +299,13 @@ pub mod prediction_market {
             .checked_sub(amount)
             .ok_or(PredictionMarketError::MathOverflow)?;
 
+        emit_cpi!(TokensMerged {
+            market_id: market.market_id,
+            user: ctx.accounts.user.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
         msg!(
             "Merged {} pairs of outcome tokens back to collateral",
             amount
@@ -236,7 +329,22 @@ pub mod prediction_market {
             PredictionMarketError::MarketAlreadySettled
         );
 
+        require!(
+            market.market_type == MarketType::Binary,
+            PredictionMarketError::InvalidMarketType
+        );
+        require!(
+            market.market_group.is_none(),
+            PredictionMarketError::MarketInGroup
+        );
+        market.require_status(&[
+            MarketStatus::Active,
+            MarketStatus::Paused,
+            MarketStatus::CloseOnly,
+        ])?;
+
         market.is_settled = true;
+        market.status = MarketStatus::Settled;
         // Setting the Winning Outcome
         market.winning_outcome = Some(winning_outcome);
 
@@ -245,45 +353,227 @@ pub mod prediction_market {
         let market_id_bytes = market.market_id.to_le_bytes();
         let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
 
-        token::set_authority(
+        token_2022::set_authority(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                SetAuthority {
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::SetAuthority {
                     current_authority: market.to_account_info(),
                     account_or_mint: ctx.accounts.outcome_yes_mint.to_account_info(),
                 },
                 &[seeds],
             ),
-            AuthorityType::MintTokens,
+            OutcomeAuthorityType::MintTokens,
             None,
         )?;
 
-        token::set_authority(
+        token_2022::set_authority(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                SetAuthority {
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::SetAuthority {
                     current_authority: market.to_account_info(),
                     account_or_mint: ctx.accounts.outcome_no_mint.to_account_info(),
                 },
                 &[seeds],
             ),
-            AuthorityType::MintTokens,
+            OutcomeAuthorityType::MintTokens,
             None,
         )?;
 
+        emit_cpi!(MarketSettled {
+            market_id: market.market_id,
+            winning_outcome,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
         msg!("Wining Outcome is Set to be: {:?}", winning_outcome);
 
         Ok(())
     }
 
+    /// Settles a scalar market on the reported `value`, values outside the range are clamped at claim time
+    pub fn resolve_scalar_market(
+        ctx: Context<SetWinner>,
+        _market_id: u32,
+        value: i64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            market.market_type == MarketType::Scalar,
+            PredictionMarketError::InvalidMarketType
+        );
+        market.require_status(&[
+            MarketStatus::Active,
+            MarketStatus::Paused,
+            MarketStatus::CloseOnly,
+        ])?;
+
+        market.is_settled = true;
+        market.status = MarketStatus::Settled;
+        market.resolved_value = Some(value);
+
+        // Same as binary settlement, no more LONG/SHORT can be minted
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+
+        for mint in [
+            ctx.accounts.outcome_yes_mint.to_account_info(),
+            ctx.accounts.outcome_no_mint.to_account_info(),
+        ] {
+            token_2022::set_authority(
+                CpiContext::new_with_signer(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    token_2022::SetAuthority {
+                        current_authority: market.to_account_info(),
+                        account_or_mint: mint,
+                    },
+                    &[seeds],
+                ),
+                OutcomeAuthorityType::MintTokens,
+                None,
+            )?;
+        }
+
+        emit_cpi!(ScalarMarketResolved {
+            market_id: market.market_id,
+            value,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Scalar market resolved at value {}", value);
+        Ok(())
+    }
+
+    /// Pause, close-only or void a market without settling it
+    /// Settled is only reached through settlement, Settled and Voided markets can't change anymore
+    pub fn set_market_status(
+        ctx: Context<SetMarketStatus>,
+        _market_id: u32,
+        status: MarketStatus,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+
+        require!(
+            status != MarketStatus::Settled,
+            PredictionMarketError::InvalidMarketStatus
+        );
+        market.require_status(&[
+            MarketStatus::Active,
+            MarketStatus::Paused,
+            MarketStatus::CloseOnly,
+        ])?;
+        // resolve_market_group needs every market of the group to settle
+        require!(
+            status != MarketStatus::Voided || market.market_group.is_none(),
+            PredictionMarketError::MarketInGroup
+        );
+
+        let previous_status = market.status;
+        market.status = status;
+
+        emit_cpi!(MarketStatusChanged {
+            market_id: market.market_id,
+            previous_status,
+            status,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Market {} status: {:?} -> {:?}", market.market_id, previous_status, status);
+        Ok(())
+    }
+
+    /// Lets the authority fix the market description until the first split or order
+    /// After that (or once the market is settled or voided) the question is final
+    /// A new URL is also written to the token metadata of both outcome mints
+    pub fn update_market_metadata(
+        ctx: Context<UpdateMarketMetadata>,
+        _market_id: u32,
+        metadata: MarketMetadata,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+
+        require!(
+            !market.trading_started
+                && !market.is_settled
+                && !matches!(market.status, MarketStatus::Settled | MarketStatus::Voided),
+            PredictionMarketError::MetadataFrozen
+        );
+        let previous_url_len = market.meta_data_url.len();
+        let url_changed = metadata.meta_data_url != market.meta_data_url;
+        market.set_metadata(metadata)?;
+
+        if url_changed {
+            let market = &ctx.accounts.market;
+            let market_id_bytes = market.market_id.to_le_bytes();
+            let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+            let growth = market.meta_data_url.len().saturating_sub(previous_url_len);
+
+            for mint in [
+                ctx.accounts.outcome_yes_mint.to_account_info(),
+                ctx.accounts.outcome_no_mint.to_account_info(),
+            ] {
+                fund_realloc(
+                    ctx.accounts.authority.to_account_info(),
+                    mint.clone(),
+                    ctx.accounts.system_program.to_account_info(),
+                    mint.data_len() + growth,
+                )?;
+                token_interface::token_metadata_update_field(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.outcome_token_program.to_account_info(),
+                        TokenMetadataUpdateField {
+                            program_id: ctx.accounts.outcome_token_program.to_account_info(),
+                            metadata: mint,
+                            update_authority: market.to_account_info(),
+                        },
+                        &[seeds],
+                    ),
+                    Field::Uri,
+                    market.meta_data_url.clone(),
+                )?;
+            }
+        }
+
+        let market = &ctx.accounts.market;
+        emit_cpi!(MarketMetadataUpdated {
+            market_id: market.market_id,
+            meta_data_url: market.meta_data_url.clone(),
+            question_hash: market.question_hash,
+            category: market.category.clone(),
+            resolution_source: market.resolution_source.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Market {} metadata updated", market.market_id);
+        Ok(())
+    }
+
+    /// Burns the caller's outcome tokens for collateral once the market is settled
+    /// On a voided market every YES and every NO token refunds half a unit of collateral
     pub fn claim_rewards(ctx: Context<ClaimRewards>, _market_id: u32) -> Result<()> {
         let market: &mut Account<'_, Market> = &mut ctx.accounts.market;
 
+        if market.status == MarketStatus::Voided {
+            return claim_both_sides(ctx);
+        }
+
         require!(market.is_settled, PredictionMarketError::MarketNotSettled);
 
+        if market.market_type == MarketType::Scalar {
+            return claim_both_sides(ctx);
+        }
+
         let winner = market
             .winning_outcome
-            .ok_or_else(|| PredictionMarketError::WinningOutcomeNotSet)?;
+            .ok_or(PredictionMarketError::WinningOutcomeNotSet)?;
 
         let (winner_user_ata, winner_mint) = match winner {
             WinningOutcome::OutcomeA => (
@@ -301,10 +591,10 @@ pub mod prediction_market {
         let amount = winner_user_ata.amount;
 
         // Burning Winnning Tokens
-        token::burn(
+        token_2022::burn(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Burn {
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::Burn {
                     mint: winner_mint,
                     from: winner_user_ata.to_account_info(),
                     authority: ctx.accounts.user.to_account_info(),
@@ -318,16 +608,17 @@ pub mod prediction_market {
         let market_id_bytes = market.market_id.to_le_bytes();
         let signer = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
 
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.collateral_vault.to_account_info(),
-                    to: ctx.accounts.user_collateral.to_account_info(),
-                    authority: market.to_account_info(),
-                },
-                &[signer],
-            ),
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.user,
+            user_collateral: ctx.accounts.user_collateral.as_ref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.pay_out(
+            ctx.accounts.native_unwrap.as_ref(),
+            market.to_account_info(),
+            signer,
             amount,
         )?;
 
@@ -336,6 +627,14 @@ pub mod prediction_market {
             .checked_sub(amount)
             .ok_or(PredictionMarketError::MathOverflow)?;
 
+        emit_cpi!(RewardsClaimed {
+            market_id: market.market_id,
+            user: ctx.accounts.user.key(),
+            winning_outcome: winner,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
         msg!("Claimed Awards by user {}", amount);
 
         Ok(())
@@ -350,8 +649,11 @@ pub mod prediction_market {
     /// - When matched:
     ///   - Buyer's & Sellers claimable amount will be incremented in their UserStats Account (user can claim later from dashboard)
     ///   - Person whose order is on the orderbook first can withdraw collateral from vault separately
+    /// - remaining_accounts: a [UserStats, TraderVolume] pair for each matched maker, in the order
+    ///   the orders get matched (repeat the pair if the same maker is matched more than once)
     pub fn place_order(
         ctx: Context<PlaceOrder>,
+        _market_id: u32,
         side: OrderSide,
         token_type: TokenType,
         quantity: u64,
@@ -371,6 +673,14 @@ pub mod prediction_market {
             PredictionMarketError::MarketAlreadySettled
         );
 
+        // Close-only markets still take sells, they can only reduce a position
+        match side {
+            OrderSide::Buy => market.require_status(&[MarketStatus::Active])?,
+            OrderSide::Sell => {
+                market.require_status(&[MarketStatus::Active, MarketStatus::CloseOnly])?
+            }
+        }
+
         require!(quantity > 0, PredictionMarketError::InvalidOrderQuantity);
         // There should be another checks for Lamports, We can't pay less than the minimum decimals of the Token
         require!(price > 0, PredictionMarketError::InvalidOrderPrice);
@@ -378,14 +688,14 @@ pub mod prediction_market {
         // Now I will Swap Assests
         // taking yes/no tokens from the User Account and Transfer into the escrow account
 
-        let (_mint_type, user_token_account, token_escrow) = match token_type {
+        let (outcome_mint, user_token_account, token_escrow) = match token_type {
             TokenType::Yes => (
-                market.outcome_yes_mint,
+                &ctx.accounts.outcome_yes_mint,
                 &ctx.accounts.user_outcome_yes,
                 &ctx.accounts.yes_escrow,
             ),
             TokenType::No => (
-                market.outcome_no_mint,
+                &ctx.accounts.outcome_no_mint,
                 &ctx.accounts.user_outcome_no,
                 &ctx.accounts.no_escrow,
             ),
@@ -406,16 +716,18 @@ pub mod prediction_market {
                 PredictionMarketError::NotEnoughBalance
             );
 
-            token::transfer(
+            token_2022::transfer_checked(
                 CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    token_2022::TransferChecked {
                         from: user_token_account.to_account_info(),
+                        mint: outcome_mint.to_account_info(),
                         to: token_escrow.to_account_info(),
                         authority: ctx.accounts.user.to_account_info(),
                     },
                 ),
                 quantity,
+                outcome_mint.decimals,
             )?;
 
             let user_stats = &mut ctx.accounts.user_stats_account;
@@ -435,22 +747,21 @@ pub mod prediction_market {
                 }
             }
         } else {
-            require!(
-                ctx.accounts.user_collateral.amount >= amount,
-                PredictionMarketError::NotEnoughBalance
-            );
+            // Native SOL buyers without a wSOL account are limited by their lamports
+            let balance = match &ctx.accounts.user_collateral {
+                Some(user_collateral) => user_collateral.amount,
+                None => ctx.accounts.user.lamports(),
+            };
+            require!(balance >= amount, PredictionMarketError::NotEnoughBalance);
 
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.user_collateral.to_account_info(),
-                        to: ctx.accounts.collateral_vault.to_account_info(),
-                        authority: ctx.accounts.user.to_account_info(),
-                    },
-                ),
-                amount,
-            )?;
+            let collateral = CollateralAccounts {
+                user: &ctx.accounts.user,
+                user_collateral: ctx.accounts.user_collateral.as_ref(),
+                mint: &ctx.accounts.collateral_mint,
+                vault: &ctx.accounts.collateral_vault,
+                token_program: &ctx.accounts.token_program,
+            };
+            collateral.deposit(&ctx.accounts.system_program, amount)?;
 
             // Locking the collateral
             let user_stats = &mut ctx.accounts.user_stats_account;
@@ -459,21 +770,36 @@ pub mod prediction_market {
                 .checked_add(amount)
                 .ok_or(PredictionMarketError::MathOverflow)?;
         }
+        // A resting bid is already a bet on the question, the metadata is frozen from here on
+        market.trading_started = true;
 
         let mut order = Order {
             id: orderbook.next_order_id,
             market_id: market.market_id,
             user_key: ctx.accounts.user.key(),
+            user_stats: ctx.accounts.user_stats_account.key(),
             side,
             token_type,
             price,
             quantity,
             filledquantity: 0,
+            fee_reserved: 0,
             timestamp: Clock::get()?.unix_timestamp,
         };
 
         orderbook.next_order_id += 1;
 
+        emit_cpi!(OrderPlaced {
+            market_id: market.market_id,
+            order_id: order.id,
+            user: order.user_key,
+            side,
+            token_type,
+            price,
+            quantity,
+            timestamp: order.timestamp,
+        });
+
         // let order_vec = match (token_type, side) {
         //     (TokenType::Yes, OrderSide::Buy) => yes_buy_orders,
         //     (TokenType::Yes, OrderSide::Sell) => yes_sell_orders,
@@ -490,6 +816,40 @@ pub mod prediction_market {
         let mut idx = 0;
         let mut iteration = 0;
         let mut completed_orders: Vec<Order> = Vec::new();
+        let mut maker_accounts = ctx.remaining_accounts.iter();
+        // Taker buy fees are paid on top of the order, everything else comes out of the vault
+        let mut taker_fees_owed: u64 = 0;
+        let mut collected_fees: u64 = 0;
+        let mut taker_fees_net: u64 = 0; // Taker fees minus rebates, the referral share is taken from this
+        let mut taker_volume: u64 = 0;
+
+        // The first referrer sticks, later ones are ignored
+        if let Some(referrer) = &ctx.accounts.referrer {
+            let user_stats = &mut ctx.accounts.user_stats_account;
+            if user_stats.referrer.is_none() {
+                user_stats.referrer = Some(referrer.key());
+
+                emit_cpi!(ReferrerRecorded {
+                    market_id: market.market_id,
+                    user: user_stats.user,
+                    referrer: referrer.key(),
+                    timestamp: order.timestamp,
+                });
+            }
+        }
+
+        // Fee tiers go by the volume traded before this order
+        let now = Clock::get()?.unix_timestamp;
+        let volume_window = ctx.accounts.config.volume_window;
+        let trader_volume = &mut ctx.accounts.trader_volume;
+        if trader_volume.owner == Pubkey::default() {
+            trader_volume.owner = ctx.accounts.user.key();
+            trader_volume.window_start = now;
+            trader_volume.bump = ctx.bumps.trader_volume;
+        }
+        trader_volume.roll(now, volume_window);
+        let (taker_fee_bps, own_maker_fee_bps) =
+            market.fee_rates(&ctx.accounts.config, trader_volume.rolling_volume());
 
         // Get the appropriate order vectors based on token type and side
         let (matching_orders, is_buy_order) = match (token_type, side) {
@@ -498,6 +858,14 @@ pub mod prediction_market {
             (TokenType::No, OrderSide::Buy) => (&mut orderbook.no_sell_orders, true),
             (TokenType::No, OrderSide::Sell) => (&mut orderbook.no_buy_orders, false),
         };
+        let mut taker = BookTaker {
+            stats: Some(&mut ctx.accounts.user_stats_account),
+            volume_account: ctx.accounts.trader_volume.key(),
+            rolling_volume: ctx.accounts.trader_volume.rolling_volume(),
+            fee_bps: taker_fee_bps,
+            token_type,
+            is_buy_order,
+        };
 
         // Generalized matching logic for both YES and NO tokens
         while idx < matching_orders.len() && iteration <= max_iteration {
@@ -535,146 +903,60 @@ pub mod prediction_market {
                     continue;
                 }
 
-                // Update filled quantities
-                matching_orders[idx].filledquantity = book_filled_qty
-                    .checked_add(min_qty)
-                    .ok_or(PredictionMarketError::MathOverflow)?;
-
                 order.filledquantity = order
                     .filledquantity
                     .checked_add(min_qty)
                     .ok_or(PredictionMarketError::MathOverflow)?;
 
-                let collateral_amount = min_qty
-                    .checked_mul(book_price)
+                let BookFill {
+                    collateral_amount,
+                    taker_fee,
+                    maker_fee,
+                    maker_rebate,
+                } = settle_book_fill(
+                    &mut maker_accounts,
+                    &mut matching_orders[idx],
+                    &mut taker,
+                    market,
+                    &ctx.accounts.config,
+                    min_qty,
+                    now,
+                )?;
+                taker_volume = taker_volume
+                    .checked_add(collateral_amount)
                     .ok_or(PredictionMarketError::MathOverflow)?;
-
-                // Credit the appropriate user stats based on whether this is a buy or sell order
                 if is_buy_order {
-                    // When user is BUYER - credit YES/NO tokens
-                    match token_type {
-                        TokenType::Yes => {
-                            ctx.accounts.user_stats_account.claimable_yes = ctx
-                                .accounts
-                                .user_stats_account
-                                .claimable_yes
-                                .checked_add(min_qty)
-                                .ok_or(PredictionMarketError::MathOverflow)?;
-                        }
-                        TokenType::No => {
-                            ctx.accounts.user_stats_account.claimable_no = ctx
-                                .accounts
-                                .user_stats_account
-                                .claimable_no
-                                .checked_add(min_qty)
-                                .ok_or(PredictionMarketError::MathOverflow)?;
-                        }
-                    }
-
-                    // Credit SELLER (from matching order) with collateral
-                    let seller_pubkey = matching_orders[idx].user_key;
-                    let seller_stats_pda = Pubkey::find_program_address(
-                        &[
-                            b"user_stats",
-                            seller_pubkey.as_ref(),
-                            market.market_id.to_le_bytes().as_ref(),
-                        ],
-                        ctx.program_id,
-                    )
-                    .0;
-
-                    let mut seller_credited = false;
-                    for account_info in ctx.remaining_accounts.iter() {
-                        if account_info.key == &seller_stats_pda {
-                            let mut data = account_info.try_borrow_mut_data()?;
-                            let mut seller_stats = UserStats::try_deserialize(&mut &data[..])?;
-
-                            seller_stats.claimable_collateral = seller_stats
-                                .claimable_collateral
-                                .checked_add(collateral_amount)
-                                .ok_or(PredictionMarketError::MathOverflow)?;
-
-                            let mut writer = &mut data[..];
-                            seller_stats.try_serialize(&mut writer)?;
-
-                            seller_credited = true;
-                            break;
-                        }
-                    }
-
-                    require!(
-                        seller_credited,
-                        PredictionMarketError::SellerStatsAccountNotProvided
-                    );
-
-                    msg!(
-                        "Trade: Buyer +{} claimable {:?}, Seller +{} claimable collateral",
-                        min_qty,
-                        token_type,
-                        collateral_amount
-                    );
-                } else {
-                    // When user is SELLER - credit collateral
-                    ctx.accounts.user_stats_account.claimable_collateral = ctx
-                        .accounts
-                        .user_stats_account
-                        .claimable_collateral
-                        .checked_add(collateral_amount)
+                    taker_fees_owed = taker_fees_owed
+                        .checked_add(taker_fee)
                         .ok_or(PredictionMarketError::MathOverflow)?;
+                }
 
-                    // Credit BUYER (from matching order) with YES/NO tokens
-                    let buyer_pubkey = matching_orders[idx].user_key;
-                    let buyer_stats_pda = Pubkey::find_program_address(
-                        &[
-                            b"user_stats",
-                            buyer_pubkey.as_ref(),
-                            market.market_id.to_le_bytes().as_ref(),
-                        ],
-                        ctx.program_id,
-                    )
-                    .0;
-
-                    let mut buyer_credited = false;
-                    for account_info in ctx.remaining_accounts.iter() {
-                        if account_info.key == &buyer_stats_pda {
-                            let mut data = account_info.try_borrow_mut_data()?;
-                            let mut buyer_stats = UserStats::try_deserialize(&mut &data[..])?;
-
-                            match token_type {
-                                TokenType::Yes => {
-                                    buyer_stats.claimable_yes = buyer_stats
-                                        .claimable_yes
-                                        .checked_add(min_qty)
-                                        .ok_or(PredictionMarketError::MathOverflow)?;
-                                }
-                                TokenType::No => {
-                                    buyer_stats.claimable_no = buyer_stats
-                                        .claimable_no
-                                        .checked_add(min_qty)
-                                        .ok_or(PredictionMarketError::MathOverflow)?;
-                                }
-                            }
-
-                            let mut writer = &mut data[..];
-                            buyer_stats.try_serialize(&mut writer)?;
-
-                            buyer_credited = true;
-                            break;
-                        }
-                    }
+                emit_cpi!(OrderFilled {
+                    market_id: market.market_id,
+                    taker_order_id: order.id,
+                    maker_order_id: matching_orders[idx].id,
+                    taker: order.user_key,
+                    maker: matching_orders[idx].user_key,
+                    taker_side: side,
+                    token_type,
+                    price: book_price,
+                    quantity: min_qty,
+                    collateral_amount,
+                    taker_fee,
+                    maker_fee,
+                    maker_rebate,
+                    timestamp: order.timestamp,
+                });
 
-                    require!(
-                        buyer_credited,
-                        PredictionMarketError::BuyerStatsAccountNotProvided
-                    );
-
-                    msg!(
-                        "Trade: Seller +{} claimable collateral, Buyer +{} claimable {:?}",
-                        collateral_amount,
-                        min_qty,
-                        token_type
-                    );
-                }
+                collected_fees = taker_fee
+                    .checked_add(maker_fee)
+                    .and_then(|fees| fees.checked_sub(maker_rebate))
+                    .and_then(|fees| collected_fees.checked_add(fees))
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                taker_fees_net = taker_fee
+                    .checked_sub(maker_rebate)
+                    .and_then(|fee| taker_fees_net.checked_add(fee))
+                    .ok_or(PredictionMarketError::MathOverflow)?;
 
                 // Remove completed orders
                 if matching_orders[idx].filledquantity == matching_orders[idx].quantity {
@@ -699,26 +981,119 @@ pub mod prediction_market {
             matching_orders.sort_by(|a, b| b.price.cmp(&a.price));
         }
 
-        // If order is not fully filled, add it to the appropriate order book
-        if order.filledquantity < order.quantity {
-            let order_vec = match (token_type, side) {
-                (TokenType::Yes, OrderSide::Buy) => &mut orderbook.yes_buy_orders,
-                (TokenType::Yes, OrderSide::Sell) => &mut orderbook.yes_sell_orders,
-                (TokenType::No, OrderSide::Buy) => &mut orderbook.no_buy_orders,
-                (TokenType::No, OrderSide::Sell) => &mut orderbook.no_sell_orders,
-            };
-
-            order_vec.push(order);
+        ctx.accounts.trader_volume.add(taker_volume)?;
 
-            // Sorting Buy order in Decrement & Sell orders in Increment acc. to price
-            if side == OrderSide::Buy {
-                order_vec.sort_by(|a, b| b.price.cmp(&a.price));
-            } else {
-                order_vec.sort_by(|a, b| a.price.cmp(&b.price));
-            }
-        }
+        // A resting buy reserves its maker fee now, sellers pay theirs out of the proceeds.
+        // Nothing to reserve when the buyer's tier earns a rebate
+        if side == OrderSide::Buy && order.filledquantity < order.quantity && own_maker_fee_bps > 0
+        {
+            order.fee_reserved = fee_amount(
+                (order.quantity - order.filledquantity)
+                    .checked_mul(order.price)
+                    .ok_or(PredictionMarketError::MathOverflow)?,
+                own_maker_fee_bps as u16,
+            )
+            .ok_or(PredictionMarketError::MathOverflow)?;
 
-        msg!(
+            ctx.accounts.user_stats_account.locked_collateral = ctx
+                .accounts
+                .user_stats_account
+                .locked_collateral
+                .checked_add(order.fee_reserved)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+        }
+
+        let taker_deposit = taker_fees_owed
+            .checked_add(order.fee_reserved)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        if taker_deposit > 0 {
+            let collateral = CollateralAccounts {
+                user: &ctx.accounts.user,
+                user_collateral: ctx.accounts.user_collateral.as_ref(),
+                mint: &ctx.accounts.collateral_mint,
+                vault: &ctx.accounts.collateral_vault,
+                token_program: &ctx.accounts.token_program,
+            };
+            collateral.deposit(&ctx.accounts.system_program, taker_deposit)?;
+        }
+
+        // One transfer moves every fee of this order out of the vault, rebates stay behind
+        let total_fees = collected_fees;
+        if total_fees > 0 {
+            let market_id_bytes = market.market_id.to_le_bytes();
+            let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: ctx.accounts.collateral_vault.to_account_info(),
+                        mint: ctx.accounts.collateral_mint.to_account_info(),
+                        to: ctx.accounts.fee_vault.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                total_fees,
+                ctx.accounts.collateral_mint.decimals,
+            )?;
+
+            // Without the referrer's UserStats the protocol keeps the referral share
+            let mut referral_fee = 0;
+            if let (Some(referrer), Some(referrer_stats)) = (
+                ctx.accounts.user_stats_account.referrer,
+                ctx.accounts.referrer_stats.as_mut(),
+            ) {
+                referral_fee = fee_amount(taker_fees_net, ctx.accounts.config.referral_fee_share_bps)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+
+                if referrer_stats.user == Pubkey::default() {
+                    referrer_stats.user = referrer;
+                    referrer_stats.market_id = market.market_id;
+                    referrer_stats.bump = ctx.bumps.referrer_stats.unwrap_or_default();
+                }
+                referrer_stats.referral_fees_claimable = referrer_stats
+                    .referral_fees_claimable
+                    .checked_add(referral_fee)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+
+                if referral_fee > 0 {
+                    emit_cpi!(ReferralFeeCredited {
+                        market_id: market.market_id,
+                        user: ctx.accounts.user.key(),
+                        referrer,
+                        amount: referral_fee,
+                        timestamp: order.timestamp,
+                    });
+                }
+            }
+
+            market.accrue_fees(
+                total_fees - referral_fee,
+                ctx.accounts.config.creator_fee_share_bps,
+            )?;
+        }
+
+        // If order is not fully filled, add it to the appropriate order book
+        if order.filledquantity < order.quantity {
+            let order_vec = match (token_type, side) {
+                (TokenType::Yes, OrderSide::Buy) => &mut orderbook.yes_buy_orders,
+                (TokenType::Yes, OrderSide::Sell) => &mut orderbook.yes_sell_orders,
+                (TokenType::No, OrderSide::Buy) => &mut orderbook.no_buy_orders,
+                (TokenType::No, OrderSide::Sell) => &mut orderbook.no_sell_orders,
+            };
+
+            order_vec.push(order);
+
+            // Sorting Buy order in Decrement & Sell orders in Increment acc. to price
+            if side == OrderSide::Buy {
+                order_vec.sort_by(|a, b| b.price.cmp(&a.price));
+            } else {
+                order_vec.sort_by(|a, b| a.price.cmp(&b.price));
+            }
+        }
+
+        msg!(
             "Order processed: {} filled, {} remaining",
             order.filledquantity,
             order.quantity - order.filledquantity
@@ -727,7 +1102,7 @@ pub mod prediction_market {
         Ok(())
     }
 
-    pub fn cancel_order(ctx: Context<CancelOrder>, order_id: u64) -> Result<()> {
+    pub fn cancel_order(ctx: Context<CancelOrder>, _market_id: u32, order_id: u64) -> Result<()> {
         // Order
         // Iterate from the OrderBook, Remove that Order
         // Reduce the Locked Amount from the User Stats Account
@@ -736,8 +1111,10 @@ pub mod prediction_market {
         let market = &mut ctx.accounts.market;
         let orderbook = &mut ctx.accounts.orderbook;
 
+        // A voided market never settles, resting orders can still be pulled past the deadline
         require!(
-            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            market.status == MarketStatus::Voided
+                || Clock::get()?.unix_timestamp < market.settlement_deadline,
             PredictionMarketError::MarketExpired
         );
 
@@ -746,6 +1123,12 @@ pub mod prediction_market {
             PredictionMarketError::MarketAlreadySettled
         );
 
+        market.require_status(&[
+            MarketStatus::Active,
+            MarketStatus::CloseOnly,
+            MarketStatus::Voided,
+        ])?;
+
         // Search for the order across all order books sequentially
         let mut found_order: Option<Order> = None;
         let mut order_side = OrderSide::Buy;
@@ -803,6 +1186,11 @@ pub mod prediction_market {
                 .checked_mul(order_found.price)
                 .ok_or(PredictionMarketError::MathOverflow)?;
 
+            // The unused maker fee reservation goes back with it
+            let locked_amount = locked_amount
+                .checked_add(order_found.fee_reserved)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+
             ctx.accounts.user_stats_account.locked_collateral = ctx
                 .accounts
                 .user_stats_account
@@ -814,16 +1202,17 @@ pub mod prediction_market {
             let market_id_bytes = market.market_id.to_le_bytes();
             let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
 
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.collateral_vault.to_account_info(),
-                        to: ctx.accounts.user_collateral.to_account_info(),
-                        authority: market.to_account_info(),
-                    },
-                    &[seeds],
-                ),
+            let collateral = CollateralAccounts {
+                user: &ctx.accounts.user,
+                user_collateral: ctx.accounts.user_collateral.as_ref(),
+                mint: &ctx.accounts.collateral_mint,
+                vault: &ctx.accounts.collateral_vault,
+                token_program: &ctx.accounts.token_program,
+            };
+            collateral.pay_out(
+                ctx.accounts.native_unwrap.as_ref(),
+                market.to_account_info(),
+                seeds,
                 locked_amount,
             )?;
         } else {
@@ -833,9 +1222,17 @@ pub mod prediction_market {
                 .checked_sub(order_found.filledquantity)
                 .ok_or(PredictionMarketError::MathOverflow)?;
 
-            let (user_token_account, token_escrow) = match order_token_type {
-                TokenType::Yes => (&ctx.accounts.user_outcome_yes, &ctx.accounts.yes_escrow),
-                TokenType::No => (&ctx.accounts.user_outcome_no, &ctx.accounts.no_escrow),
+            let (outcome_mint, user_token_account, token_escrow) = match order_token_type {
+                TokenType::Yes => (
+                    &ctx.accounts.outcome_yes_mint,
+                    &ctx.accounts.user_outcome_yes,
+                    &ctx.accounts.yes_escrow,
+                ),
+                TokenType::No => (
+                    &ctx.accounts.outcome_no_mint,
+                    &ctx.accounts.user_outcome_no,
+                    &ctx.accounts.no_escrow,
+                ),
             };
 
             match order_token_type {
@@ -861,20 +1258,36 @@ pub mod prediction_market {
             let market_id_bytes = market.market_id.to_le_bytes();
             let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
 
-            token::transfer(
+            token_2022::transfer_checked(
                 CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    token_2022::TransferChecked {
                         from: token_escrow.to_account_info(),
+                        mint: outcome_mint.to_account_info(),
                         to: user_token_account.to_account_info(),
                         authority: market.to_account_info(),
                     },
                     &[seeds],
                 ),
                 locked_quantity,
+                outcome_mint.decimals,
             )?;
         }
 
+        emit_cpi!(OrderCancelled {
+            market_id: market.market_id,
+            order_id,
+            user: order_found.user_key,
+            side: order_side,
+            token_type: order_token_type,
+            price: order_found.price,
+            unfilled_quantity: order_found
+                .quantity
+                .checked_sub(order_found.filledquantity)
+                .ok_or(PredictionMarketError::MathOverflow)?,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
         msg!("Order {} cancelled successfully", order_id);
 
         // Reducing the Locked amount from the User Userstats account
@@ -882,9 +1295,4154 @@ pub mod prediction_market {
         Ok(())
     }
 
-    pub fn market_order(ctx: Context<MarketOrder>,) -> Result<()> {
-        
+    /// Aggregated price levels (price, total size, order count) for one side of the book, best price first
+    /// Meant to be called through simulateTransaction, the levels come back as return data
+    pub fn get_order_book_depth(
+        ctx: Context<ViewOrderBook>,
+        _market_id: u32,
+        token_type: TokenType,
+        side: OrderSide,
+        max_levels: u16,
+    ) -> Result<Vec<PriceLevel>> {
+        Ok(ctx
+            .accounts
+            .orderbook
+            .depth(token_type, side, max_levels as usize))
+    }
+
+    /// Simulates a taker order of `quantity` against the book without changing any state
+    /// Returns the fillable quantity, average and worst price as return data
+    pub fn quote(
+        ctx: Context<ViewOrderBook>,
+        _market_id: u32,
+        token_type: TokenType,
+        side: OrderSide,
+        quantity: u64,
+    ) -> Result<Quote> {
+        require!(quantity > 0, PredictionMarketError::InvalidOrderQuantity);
+
+        let quote = ctx
+            .accounts
+            .orderbook
+            .quote(token_type, side, quantity)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        Ok(quote)
+    }
+
+    // ---------------- Categorical markets ----------------
+    // Same flow as the YES/NO market but with 2 - 16 outcome mints derived by index
+    // Every outcome has its own escrow and OutcomeOrderBook, created by initialize_categorical_outcome
+    // Only split, merge, limit orders, settlement and claims are supported: no status, metadata,
+    // fees, referrals, AMMs, market orders, groups, rewards, native SOL or Token-2022 outcome mints
+
+    /// The market id comes from the global MarketCounter and is returned to the caller
+    pub fn initialize_categorical_market(
+        ctx: Context<InitializeCategoricalMarket>,
+        outcome_count: u8,
+        settlement_deadline: i64,
+    ) -> Result<u32> {
+        require!(
+            settlement_deadline > Clock::get()?.unix_timestamp,
+            PredictionMarketError::InvalidSettlementDeadline
+        );
+        require!(
+            (MIN_CATEGORICAL_OUTCOMES as usize..=MAX_CATEGORICAL_OUTCOMES)
+                .contains(&(outcome_count as usize)),
+            PredictionMarketError::InvalidOutcomeCount
+        );
+
+        let market_id = ctx.accounts.market_counter.take_next_id()?;
+
+        let market = &mut ctx.accounts.market;
+        market.authority = ctx.accounts.authority.key();
+        market.market_id = market_id;
+        market.settlement_deadline = settlement_deadline;
+        market.collateral_mint = ctx.accounts.collateral_mint.key();
+        market.collateral_vault = ctx.accounts.collateral_vault.key();
+        market.outcome_count = outcome_count;
+        market.outcomes_initialized = 0;
+        market.outcome_mints = [Pubkey::default(); MAX_CATEGORICAL_OUTCOMES];
+        market.is_settled = false;
+        market.winning_outcome = None;
+        market.total_collateral_locked = 0;
+        market.bump = ctx.bumps.market;
+
+        emit_cpi!(CategoricalMarketInitialized {
+            market_id,
+            authority: market.authority,
+            collateral_mint: market.collateral_mint,
+            outcome_count,
+            settlement_deadline,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Categorical market initialized: {} with {} outcomes",
+            market_id,
+            outcome_count
+        );
+        Ok(market_id)
+    }
+
+    /// Creates the mint, escrow and order book of the next outcome
+    /// Outcomes have to be initialized in index order, trading opens once all of them exist
+    pub fn initialize_categorical_outcome(
+        ctx: Context<InitializeCategoricalOutcome>,
+        market_id: u32,
+        outcome_index: u8,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            outcome_index < market.outcome_count
+                && outcome_index == market.outcomes_initialized,
+            PredictionMarketError::InvalidOutcomeIndex
+        );
+
+        market.outcome_mints[outcome_index as usize] = ctx.accounts.outcome_mint.key();
+        market.outcomes_initialized += 1;
+
+        let orderbook = &mut ctx.accounts.orderbook;
+        orderbook.market_id = market_id;
+        orderbook.outcome_index = outcome_index;
+        orderbook.next_order_id = 0;
+        orderbook.buy_orders = Vec::new();
+        orderbook.sell_orders = Vec::new();
+        orderbook.bump = ctx.bumps.orderbook;
+
+        msg!(
+            "Outcome {} of categorical market {} initialized",
+            outcome_index,
+            market_id
+        );
+        Ok(())
+    }
+
+    /// Locks `amount` collateral and mints `amount` of every outcome token
+    /// remaining_accounts: [outcome_mint, user_outcome_token_account] for every outcome, in index order
+    pub fn split_categorical<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SplitCategorical<'info>>,
+        market_id: u32,
+        amount: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+        require!(market.is_ready(), PredictionMarketError::OutcomesNotInitialized);
+
+        let outcomes =
+            categorical_outcome_accounts(market, &ctx.accounts.user.key(), ctx.remaining_accounts)?;
+
+        let vault_before = ctx.accounts.collateral_vault.amount;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: ctx.accounts.user_collateral.to_account_info(),
+                    mint: ctx.accounts.collateral_mint.to_account_info(),
+                    to: ctx.accounts.collateral_vault.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.collateral_mint.decimals,
+        )?;
+
+        // Same as split_tokens, only what reached the vault gets minted
+        ctx.accounts.collateral_vault.reload()?;
+        let amount = ctx
+            .accounts
+            .collateral_vault
+            .amount
+            .checked_sub(vault_before)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[CATEGORICAL_MARKET_SEED, market_id_bytes.as_ref(), &[market.bump]];
+
+        // One of every outcome, exactly one of them will be redeemable for the collateral
+        for (outcome_mint, user_outcome) in outcomes.iter() {
+            token::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    MintTo {
+                        mint: outcome_mint.to_account_info(),
+                        to: user_outcome.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                amount,
+            )?;
+        }
+
+        market.total_collateral_locked = market
+            .total_collateral_locked
+            .checked_add(amount)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        let user_stats = &mut ctx.accounts.user_stats_account;
+        if user_stats.user == Pubkey::default() {
+            user_stats.user = ctx.accounts.user.key();
+            user_stats.market_id = market_id;
+            user_stats.claimable_outcome = [0; MAX_CATEGORICAL_OUTCOMES];
+            user_stats.locked_outcome = [0; MAX_CATEGORICAL_OUTCOMES];
+            user_stats.claimable_collateral = 0;
+            user_stats.locked_collateral = 0;
+            user_stats.bump = ctx.bumps.user_stats_account;
+        }
+
+        emit_cpi!(CategoricalTokensSplit {
+            market_id,
+            user: ctx.accounts.user.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Minted {} of each outcome token for user", amount);
+        Ok(())
+    }
+
+    /// Burns complete sets (one of every outcome) and returns the collateral
+    /// remaining_accounts: [outcome_mint, user_outcome_token_account] for every outcome, in index order
+    pub fn merge_categorical<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MergeCategorical<'info>>,
+        _market_id: u32,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(market.is_ready(), PredictionMarketError::OutcomesNotInitialized);
+
+        let outcomes =
+            categorical_outcome_accounts(market, &ctx.accounts.user.key(), ctx.remaining_accounts)?;
+
+        // Only full sets can be merged
+        let amount = outcomes
+            .iter()
+            .map(|(_, user_outcome)| user_outcome.amount)
+            .min()
+            .unwrap_or(0);
+
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+
+        for (outcome_mint, user_outcome) in outcomes.iter() {
+            token::burn(
+                CpiContext::new(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    Burn {
+                        mint: outcome_mint.to_account_info(),
+                        from: user_outcome.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                amount,
+            )?;
+        }
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[CATEGORICAL_MARKET_SEED, market_id_bytes.as_ref(), &[market.bump]];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: ctx.accounts.collateral_vault.to_account_info(),
+                    mint: ctx.accounts.collateral_mint.to_account_info(),
+                    to: ctx.accounts.user_collateral.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                &[seeds],
+            ),
+            amount,
+            ctx.accounts.collateral_mint.decimals,
+        )?;
+
+        market.total_collateral_locked = market
+            .total_collateral_locked
+            .checked_sub(amount)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        emit_cpi!(CategoricalTokensMerged {
+            market_id: market.market_id,
+            user: ctx.accounts.user.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Merged {} outcome sets back to collateral", amount);
+        Ok(())
     }
+
+    /// Settles the market on `winning_outcome` and revokes the mint authority of every outcome
+    /// remaining_accounts: every outcome mint, in index order
+    pub fn set_categorical_winner<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SetCategoricalWinner<'info>>,
+        _market_id: u32,
+        winning_outcome: u8,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(market.is_ready(), PredictionMarketError::OutcomesNotInitialized);
+        require!(
+            winning_outcome < market.outcome_count,
+            PredictionMarketError::InvalidOutcomeIndex
+        );
+        require!(
+            ctx.remaining_accounts.len() == market.outcome_count as usize,
+            PredictionMarketError::OutcomeAccountsMismatch
+        );
+
+        market.is_settled = true;
+        market.winning_outcome = Some(winning_outcome);
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[CATEGORICAL_MARKET_SEED, market_id_bytes.as_ref(), &[market.bump]];
+
+        for (index, outcome_mint) in ctx.remaining_accounts.iter().enumerate() {
+            require_keys_eq!(
+                outcome_mint.key(),
+                market.outcome_mints[index],
+                PredictionMarketError::OutcomeAccountsMismatch
+            );
+
+            token::set_authority(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    SetAuthority {
+                        current_authority: market.to_account_info(),
+                        account_or_mint: outcome_mint.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                AuthorityType::MintTokens,
+                None,
+            )?;
+        }
+
+        emit_cpi!(CategoricalMarketSettled {
+            market_id: market.market_id,
+            winning_outcome,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Winning outcome is set to index {}", winning_outcome);
+        Ok(())
+    }
+
+    /// Burns the user's winning outcome tokens and pays out the same amount of collateral
+    pub fn claim_categorical(ctx: Context<ClaimCategorical>, _market_id: u32) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+
+        require!(market.is_settled, PredictionMarketError::MarketNotSettled);
+
+        let winner = market
+            .winning_outcome
+            .ok_or(PredictionMarketError::WinningOutcomeNotSet)?;
+
+        require_keys_eq!(
+            ctx.accounts.winning_mint.key(),
+            market.outcome_mints[winner as usize],
+            PredictionMarketError::OutcomeAccountsMismatch
+        );
+
+        let amount = ctx.accounts.user_winning_outcome.amount;
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.outcome_token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.winning_mint.to_account_info(),
+                    from: ctx.accounts.user_winning_outcome.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[CATEGORICAL_MARKET_SEED, market_id_bytes.as_ref(), &[market.bump]];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: ctx.accounts.collateral_vault.to_account_info(),
+                    mint: ctx.accounts.collateral_mint.to_account_info(),
+                    to: ctx.accounts.user_collateral.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                &[seeds],
+            ),
+            amount,
+            ctx.accounts.collateral_mint.decimals,
+        )?;
+
+        market.total_collateral_locked = market
+            .total_collateral_locked
+            .checked_sub(amount)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        emit_cpi!(CategoricalRewardsClaimed {
+            market_id: market.market_id,
+            user: ctx.accounts.user.key(),
+            winning_outcome: winner,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Claimed {} collateral for outcome {}", amount, winner);
+        Ok(())
+    }
+
+    /// Place an order to buy or sell one outcome of a categorical market against collateral
+    ///
+    /// Works like place_order: funds are locked on placing, fills are credited to the
+    /// CategoricalUserStats of both sides
+    /// - remaining_accounts: CategoricalUserStats of each matched maker, in the order the orders get matched
+    pub fn place_categorical_order(
+        ctx: Context<PlaceCategoricalOrder>,
+        _market_id: u32,
+        outcome_index: u8,
+        side: OrderSide,
+        quantity: u64,
+        price: u64,
+        max_iteration: u64,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let orderbook = &mut ctx.accounts.orderbook;
+        let outcome = outcome_index as usize;
+
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(market.is_ready(), PredictionMarketError::OutcomesNotInitialized);
+        require!(quantity > 0, PredictionMarketError::InvalidOrderQuantity);
+        require!(price > 0, PredictionMarketError::InvalidOrderPrice);
+
+        let amount = quantity
+            .checked_mul(price)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        // Lock funds immediately when placing order
+        if side == OrderSide::Sell {
+            require!(
+                ctx.accounts.user_outcome.amount >= quantity,
+                PredictionMarketError::NotEnoughBalance
+            );
+
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.user_outcome.to_account_info(),
+                        to: ctx.accounts.outcome_escrow.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                quantity,
+            )?;
+
+            let user_stats = &mut ctx.accounts.user_stats_account;
+            user_stats.locked_outcome[outcome] = user_stats.locked_outcome[outcome]
+                .checked_add(quantity)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+        } else {
+            require!(
+                ctx.accounts.user_collateral.amount >= amount,
+                PredictionMarketError::NotEnoughBalance
+            );
+
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: ctx.accounts.user_collateral.to_account_info(),
+                        mint: ctx.accounts.collateral_mint.to_account_info(),
+                        to: ctx.accounts.collateral_vault.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                amount,
+                ctx.accounts.collateral_mint.decimals,
+            )?;
+
+            let user_stats = &mut ctx.accounts.user_stats_account;
+            user_stats.locked_collateral = user_stats
+                .locked_collateral
+                .checked_add(amount)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+        }
+
+        let mut order = CategoricalOrder {
+            id: orderbook.next_order_id,
+            user_key: ctx.accounts.user.key(),
+            user_stats: ctx.accounts.user_stats_account.key(),
+            side,
+            price,
+            quantity,
+            filledquantity: 0,
+            timestamp: Clock::get()?.unix_timestamp,
+        };
+
+        orderbook.next_order_id += 1;
+
+        emit_cpi!(CategoricalOrderPlaced {
+            market_id: market.market_id,
+            outcome_index,
+            order_id: order.id,
+            user: order.user_key,
+            side,
+            price,
+            quantity,
+            timestamp: order.timestamp,
+        });
+
+        let mut idx = 0;
+        let mut iteration = 0;
+        let mut maker_stats_accounts = ctx.remaining_accounts.iter();
+
+        let (matching_orders, is_buy_order) = match side {
+            OrderSide::Buy => (&mut orderbook.sell_orders, true),
+            OrderSide::Sell => (&mut orderbook.buy_orders, false),
+        };
+
+        while idx < matching_orders.len() && iteration <= max_iteration {
+            let (book_price, book_qty, book_filled_qty) = {
+                let book_order = &matching_orders[idx];
+                (
+                    book_order.price,
+                    book_order.quantity,
+                    book_order.filledquantity,
+                )
+            };
+
+            let price_matches = if is_buy_order {
+                order.price >= book_price
+            } else {
+                order.price <= book_price
+            };
+
+            if !price_matches {
+                break;
+            }
+
+            let our_left_qty = order
+                .quantity
+                .checked_sub(order.filledquantity)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+            let book_left_qty = book_qty
+                .checked_sub(book_filled_qty)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+            let min_qty = our_left_qty.min(book_left_qty);
+
+            if min_qty == 0 {
+                idx += 1;
+                continue;
+            }
+
+            matching_orders[idx].filledquantity = book_filled_qty
+                .checked_add(min_qty)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+
+            order.filledquantity = order
+                .filledquantity
+                .checked_add(min_qty)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+
+            let collateral_amount = min_qty
+                .checked_mul(book_price)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+
+            let maker_stats_info = maker_stats_accounts.next().ok_or(if is_buy_order {
+                PredictionMarketError::SellerStatsAccountNotProvided
+            } else {
+                PredictionMarketError::BuyerStatsAccountNotProvided
+            })?;
+            require_keys_eq!(
+                maker_stats_info.key(),
+                matching_orders[idx].user_stats,
+                if is_buy_order {
+                    PredictionMarketError::SellerStatsAccountNotProvided
+                } else {
+                    PredictionMarketError::BuyerStatsAccountNotProvided
+                }
+            );
+
+            let taker_stats = &mut ctx.accounts.user_stats_account;
+            let mut data = maker_stats_info.try_borrow_mut_data()?;
+            let mut maker_stats = CategoricalUserStats::try_deserialize(&mut &data[..])?;
+
+            if is_buy_order {
+                // Taker buys outcome tokens, maker (seller) gets the collateral
+                taker_stats.claimable_outcome[outcome] = taker_stats.claimable_outcome[outcome]
+                    .checked_add(min_qty)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                maker_stats.claimable_collateral = maker_stats
+                    .claimable_collateral
+                    .checked_add(collateral_amount)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+            } else {
+                // Taker sells outcome tokens, maker (buyer) gets them
+                taker_stats.claimable_collateral = taker_stats
+                    .claimable_collateral
+                    .checked_add(collateral_amount)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                maker_stats.claimable_outcome[outcome] = maker_stats.claimable_outcome[outcome]
+                    .checked_add(min_qty)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+            }
+
+            let mut writer = &mut data[..];
+            maker_stats.try_serialize(&mut writer)?;
+            drop(data);
+
+            emit_cpi!(CategoricalOrderFilled {
+                market_id: market.market_id,
+                outcome_index,
+                taker_order_id: order.id,
+                maker_order_id: matching_orders[idx].id,
+                taker: order.user_key,
+                maker: matching_orders[idx].user_key,
+                taker_side: side,
+                price: book_price,
+                quantity: min_qty,
+                collateral_amount,
+                timestamp: order.timestamp,
+            });
+
+            if matching_orders[idx].filledquantity == matching_orders[idx].quantity {
+                matching_orders.remove(idx);
+            } else {
+                idx += 1;
+            }
+
+            iteration += 1;
+        }
+
+        // Rest the unfilled part on this outcome's book
+        if order.filledquantity < order.quantity {
+            let order_vec = match side {
+                OrderSide::Buy => &mut orderbook.buy_orders,
+                OrderSide::Sell => &mut orderbook.sell_orders,
+            };
+
+            require!(
+                order_vec.len() < MAX_ORDERS_PER_SIDE,
+                PredictionMarketError::MaxOrdersReached
+            );
+
+            order_vec.push(order);
+
+            // Buy orders best (highest) price first, sell orders lowest first
+            if side == OrderSide::Buy {
+                order_vec.sort_by(|a, b| b.price.cmp(&a.price));
+            } else {
+                order_vec.sort_by(|a, b| a.price.cmp(&b.price));
+            }
+        }
+
+        msg!(
+            "Order processed: {} filled, {} remaining",
+            order.filledquantity,
+            order.quantity - order.filledquantity
+        );
+
+        Ok(())
+    }
+
+    pub fn cancel_categorical_order(
+        ctx: Context<CancelCategoricalOrder>,
+        _market_id: u32,
+        outcome_index: u8,
+        order_id: u64,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let orderbook = &mut ctx.accounts.orderbook;
+
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+
+        let (order_found, order_side) =
+            if let Some(idx) = orderbook.buy_orders.iter().position(|o| o.id == order_id) {
+                (orderbook.buy_orders.remove(idx), OrderSide::Buy)
+            } else if let Some(idx) = orderbook.sell_orders.iter().position(|o| o.id == order_id) {
+                (orderbook.sell_orders.remove(idx), OrderSide::Sell)
+            } else {
+                return err!(PredictionMarketError::OrdernotFound);
+            };
+
+        require!(
+            ctx.accounts.user.key() == order_found.user_key,
+            PredictionMarketError::NotAuthorized
+        );
+
+        let unfilled_quantity = order_found
+            .quantity
+            .checked_sub(order_found.filledquantity)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[CATEGORICAL_MARKET_SEED, market_id_bytes.as_ref(), &[market.bump]];
+        let user_stats = &mut ctx.accounts.user_stats_account;
+
+        if order_side == OrderSide::Buy {
+            let locked_amount = unfilled_quantity
+                .checked_mul(order_found.price)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+
+            user_stats.locked_collateral = user_stats
+                .locked_collateral
+                .checked_sub(locked_amount)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: ctx.accounts.collateral_vault.to_account_info(),
+                        mint: ctx.accounts.collateral_mint.to_account_info(),
+                        to: ctx.accounts.user_collateral.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                locked_amount,
+                ctx.accounts.collateral_mint.decimals,
+            )?;
+        } else {
+            let outcome = outcome_index as usize;
+            user_stats.locked_outcome[outcome] = user_stats.locked_outcome[outcome]
+                .checked_sub(unfilled_quantity)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.outcome_escrow.to_account_info(),
+                        to: ctx.accounts.user_outcome.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                unfilled_quantity,
+            )?;
+        }
+
+        emit_cpi!(CategoricalOrderCancelled {
+            market_id: market.market_id,
+            outcome_index,
+            order_id,
+            user: order_found.user_key,
+            side: order_side,
+            price: order_found.price,
+            unfilled_quantity,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Order {} cancelled successfully", order_id);
+        Ok(())
+    }
+
+    // ---------------- Neg-risk market groups ----------------
+    // One binary market per candidate of an event, exactly one of them resolves YES
+    // A NO in k of the markets is worth the same as a YES in every other market plus (k - 1) collateral,
+    // convert_no_positions does that swap and resolve_market_group settles all markets at once
+
+    pub fn initialize_market_group(ctx: Context<InitializeMarketGroup>, group_id: u32) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        group.authority = ctx.accounts.authority.key();
+        group.group_id = group_id;
+        group.collateral_mint = ctx.accounts.collateral_mint.key();
+        group.market_count = 0;
+        group.market_ids = [0; MAX_GROUP_MARKETS];
+        group.is_resolved = false;
+        group.winning_index = None;
+        group.conversions_started = false;
+        group.bump = ctx.bumps.group;
+
+        emit_cpi!(MarketGroupInitialized {
+            group_id,
+            authority: group.authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Market group initialized: {}", group_id);
+        Ok(())
+    }
+
+    /// Links a binary market into the group, it can't be settled on its own anymore
+    pub fn add_market_to_group(ctx: Context<AddMarketToGroup>, _group_id: u32) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        let market = &mut ctx.accounts.market;
+
+        require!(
+            !group.is_resolved,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        market.require_status(&[
+            MarketStatus::Active,
+            MarketStatus::Paused,
+            MarketStatus::CloseOnly,
+        ])?;
+        require!(
+            market.market_type == MarketType::Binary,
+            PredictionMarketError::InvalidMarketType
+        );
+        require!(
+            market.market_group.is_none(),
+            PredictionMarketError::MarketAlreadyInGroup
+        );
+        // One NO in every market has to equal one YES in any other: a market that already has
+        // holders, or a group that already converted, can't take on that rule anymore
+        require!(
+            !group.conversions_started && !market.trading_started,
+            PredictionMarketError::MarketGroupFrozen
+        );
+        // Group resolution moves collateral between vaults, which the LMSR's books can't follow
+        require!(
+            market.amm != Some(AmmKind::Lmsr),
+            PredictionMarketError::LmsrMarketInGroup
+        );
+        require_keys_eq!(
+            market.collateral_mint,
+            group.collateral_mint,
+            PredictionMarketError::GroupCollateralMismatch
+        );
+        require!(
+            (group.market_count as usize) < MAX_GROUP_MARKETS,
+            PredictionMarketError::MarketGroupFull
+        );
+
+        let index = group.market_count;
+        group.market_ids[index as usize] = market.market_id;
+        group.market_count += 1;
+        market.market_group = Some(group.key());
+
+        emit_cpi!(MarketAddedToGroup {
+            group_id: group.group_id,
+            market_id: market.market_id,
+            index,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Market {} added to group {} at index {}",
+            market.market_id,
+            group.group_id,
+            index
+        );
+        Ok(())
+    }
+
+    /// Burns `amount` NO in every market whose bit is set in `no_market_mask`, mints `amount` YES
+    /// in every other market of the group and releases (k - 1) * `amount` collateral to the user
+    /// remaining_accounts: see ConvertNoPositions
+    pub fn convert_no_positions<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ConvertNoPositions<'info>>,
+        _group_id: u32,
+        no_market_mask: u16,
+        amount: u64,
+    ) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+
+        require!(
+            !group.is_resolved,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+        require!(
+            no_market_mask != 0 && (no_market_mask as u32) < (1u32 << group.market_count),
+            PredictionMarketError::InvalidPositionSet
+        );
+        group.conversions_started = true;
+
+        let markets = group_market_accounts(group, ctx.remaining_accounts, 5)?;
+        let now = Clock::get()?.unix_timestamp;
+        let mut collateral_released: u64 = 0;
+        let mut first_no_market = true;
+
+        for (index, (mut market, accounts)) in markets.into_iter().enumerate() {
+            require!(
+                !market.is_settled,
+                PredictionMarketError::MarketAlreadySettled
+            );
+            market.require_status(&[MarketStatus::Active])?;
+            require!(
+                now < market.settlement_deadline,
+                PredictionMarketError::MarketExpired
+            );
+
+            let user_position =
+                InterfaceAccount::<token_interface::TokenAccount>::try_from(&accounts[4])?;
+            require_keys_eq!(
+                user_position.owner,
+                ctx.accounts.user.key(),
+                PredictionMarketError::GroupAccountsMismatch
+            );
+
+            let market_id_bytes = market.market_id.to_le_bytes();
+            let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+
+            if no_market_mask & (1 << index) != 0 {
+                require_keys_eq!(
+                    user_position.mint,
+                    market.outcome_no_mint,
+                    PredictionMarketError::GroupAccountsMismatch
+                );
+
+                token_2022::burn(
+                    CpiContext::new(
+                        ctx.accounts.outcome_token_program.to_account_info(),
+                        token_2022::Burn {
+                            mint: accounts[3].to_account_info(),
+                            from: accounts[4].to_account_info(),
+                            authority: ctx.accounts.user.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+
+                // The first NO market keeps its backing, it pays the minted YES if one of them wins
+                if first_no_market {
+                    first_no_market = false;
+                    continue;
+                }
+
+                token_interface::transfer_checked(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        token_interface::TransferChecked {
+                            from: accounts[1].to_account_info(),
+                            mint: ctx.accounts.collateral_mint.to_account_info(),
+                            to: ctx.accounts.user_collateral.to_account_info(),
+                            authority: market.to_account_info(),
+                        },
+                        &[seeds],
+                    ),
+                    amount,
+                    ctx.accounts.collateral_mint.decimals,
+                )?;
+
+                market.total_collateral_locked = market
+                    .total_collateral_locked
+                    .checked_sub(amount)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                market.exit(&crate::ID)?;
+
+                collateral_released = collateral_released
+                    .checked_add(amount)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+            } else {
+                require_keys_eq!(
+                    user_position.mint,
+                    market.outcome_yes_mint,
+                    PredictionMarketError::GroupAccountsMismatch
+                );
+
+                token_2022::mint_to(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.outcome_token_program.to_account_info(),
+                        token_2022::MintTo {
+                            mint: accounts[2].to_account_info(),
+                            to: accounts[4].to_account_info(),
+                            authority: market.to_account_info(),
+                        },
+                        &[seeds],
+                    ),
+                    amount,
+                )?;
+
+                // Holders now exist even if nobody split in this market
+                if !market.trading_started {
+                    market.trading_started = true;
+                    market.exit(&crate::ID)?;
+                }
+            }
+        }
+
+        emit_cpi!(PositionsConverted {
+            group_id: group.group_id,
+            user: ctx.accounts.user.key(),
+            no_market_mask,
+            amount,
+            collateral_released,
+            timestamp: now,
+        });
+
+        msg!(
+            "Converted {} NO positions, released {} collateral",
+            amount,
+            collateral_released
+        );
+        Ok(())
+    }
+
+    /// Settles every market of the group, the one at `winning_index` YES and all others NO
+    /// Conversions leave the collateral spread unevenly over the market vaults, so after
+    /// settling the backing is moved between vaults until each covers its winning supply
+    /// remaining_accounts: see ResolveMarketGroup
+    pub fn resolve_market_group<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ResolveMarketGroup<'info>>,
+        _group_id: u32,
+        winning_index: u8,
+    ) -> Result<()> {
+        require!(
+            !ctx.accounts.group.is_resolved,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            winning_index < ctx.accounts.group.market_count,
+            PredictionMarketError::InvalidWinningOutcome
+        );
+
+        let mut markets = group_market_accounts(&ctx.accounts.group, ctx.remaining_accounts, 4)?;
+        let now = Clock::get()?.unix_timestamp;
+
+        // Backing left over (or missing) once every winning token is paid out
+        let mut surplus: Vec<i128> = Vec::with_capacity(markets.len());
+
+        for (index, (market, accounts)) in markets.iter_mut().enumerate() {
+            require!(
+                now < market.settlement_deadline,
+                PredictionMarketError::MarketExpired
+            );
+            require!(
+                !market.is_settled,
+                PredictionMarketError::MarketAlreadySettled
+            );
+
+            let winning_outcome = if index == winning_index as usize {
+                WinningOutcome::OutcomeA
+            } else {
+                WinningOutcome::OutcomeB
+            };
+            market.require_status(&[
+                MarketStatus::Active,
+                MarketStatus::Paused,
+                MarketStatus::CloseOnly,
+            ])?;
+            market.is_settled = true;
+            market.status = MarketStatus::Settled;
+            market.winning_outcome = Some(winning_outcome);
+
+            let market_id_bytes = market.market_id.to_le_bytes();
+            let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+
+            for mint in [&accounts[2], &accounts[3]] {
+                token_2022::set_authority(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.outcome_token_program.to_account_info(),
+                        token_2022::SetAuthority {
+                            current_authority: market.to_account_info(),
+                            account_or_mint: mint.to_account_info(),
+                        },
+                        &[seeds],
+                    ),
+                    OutcomeAuthorityType::MintTokens,
+                    None,
+                )?;
+            }
+
+            let winning_mint = match winning_outcome {
+                WinningOutcome::OutcomeA => &accounts[2],
+                _ => &accounts[3],
+            };
+            let winning_supply = InterfaceAccount::<token_interface::Mint>::try_from(winning_mint)?.supply;
+            surplus.push(market.total_collateral_locked as i128 - winning_supply as i128);
+
+            emit_cpi!(MarketSettled {
+                market_id: market.market_id,
+                winning_outcome,
+                timestamp: now,
+            });
+        }
+
+        // Top up every market that is short from the ones with spare backing
+        let mut donor = 0;
+        for receiver in 0..markets.len() {
+            while surplus[receiver] < 0 {
+                while donor < markets.len() && surplus[donor] <= 0 {
+                    donor += 1;
+                }
+                require!(
+                    donor < markets.len(),
+                    PredictionMarketError::GroupUndercollateralized
+                );
+
+                let amount = (-surplus[receiver]).min(surplus[donor]) as u64;
+                let donor_market = &markets[donor].0;
+                let market_id_bytes = donor_market.market_id.to_le_bytes();
+                let seeds = &[b"market", market_id_bytes.as_ref(), &[donor_market.bump]];
+
+                token_interface::transfer_checked(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        token_interface::TransferChecked {
+                            from: markets[donor].1[1].to_account_info(),
+                            mint: ctx.accounts.collateral_mint.to_account_info(),
+                            to: markets[receiver].1[1].to_account_info(),
+                            authority: donor_market.to_account_info(),
+                        },
+                        &[seeds],
+                    ),
+                    amount,
+                    ctx.accounts.collateral_mint.decimals,
+                )?;
+
+                surplus[donor] = surplus[donor]
+                    .checked_sub(amount as i128)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                surplus[receiver] = surplus[receiver]
+                    .checked_add(amount as i128)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                markets[donor].0.total_collateral_locked = markets[donor]
+                    .0
+                    .total_collateral_locked
+                    .checked_sub(amount)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                markets[receiver].0.total_collateral_locked = markets[receiver]
+                    .0
+                    .total_collateral_locked
+                    .checked_add(amount)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+            }
+        }
+
+        for (market, _) in markets.iter() {
+            market.exit(&crate::ID)?;
+        }
+
+        let group = &mut ctx.accounts.group;
+        group.is_resolved = true;
+        group.winning_index = Some(winning_index);
+
+        emit_cpi!(MarketGroupResolved {
+            group_id: group.group_id,
+            winning_index,
+            winning_market_id: group.market_ids[winning_index as usize],
+            timestamp: now,
+        });
+
+        msg!(
+            "Market group {} resolved, market {} won",
+            group.group_id,
+            group.market_ids[winning_index as usize]
+        );
+        Ok(())
+    }
+
+    // ---------------- Protocol config ----------------
+
+    pub fn initialize_protocol_config(
+        ctx: Context<InitializeProtocolConfig>,
+        fee_recipient: Pubkey,
+        taker_fee_bps: u16,
+        maker_fee_bps: u16,
+        creator_fee_share_bps: u16,
+    ) -> Result<()> {
+        require!(
+            taker_fee_bps <= MAX_FEE_BPS
+                && maker_fee_bps <= MAX_FEE_BPS
+                && creator_fee_share_bps <= MAX_FEE_BPS,
+            PredictionMarketError::InvalidFeeRate
+        );
+
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.pending_admin = None;
+        config.fee_recipient = fee_recipient;
+        config.taker_fee_bps = taker_fee_bps;
+        config.maker_fee_bps = maker_fee_bps;
+        config.creator_fee_share_bps = creator_fee_share_bps;
+        config.referral_fee_share_bps = 0;
+        config.paused = false;
+        config.allowed_collateral_mints = Vec::new();
+        config.fee_tiers = Vec::new();
+        config.volume_window = DEFAULT_VOLUME_WINDOW;
+        config.bump = ctx.bumps.config;
+
+        let market_counter = &mut ctx.accounts.market_counter;
+        market_counter.next_market_id = 1;
+        market_counter.bump = ctx.bumps.market_counter;
+
+        msg!("Protocol config initialized, admin: {}", config.admin);
+        Ok(())
+    }
+
+    pub fn update_protocol_config(
+        ctx: Context<UpdateProtocolConfig>,
+        fee_recipient: Pubkey,
+        taker_fee_bps: u16,
+        maker_fee_bps: u16,
+        creator_fee_share_bps: u16,
+    ) -> Result<()> {
+        require!(
+            taker_fee_bps <= MAX_FEE_BPS
+                && maker_fee_bps <= MAX_FEE_BPS
+                && creator_fee_share_bps <= MAX_FEE_BPS,
+            PredictionMarketError::InvalidFeeRate
+        );
+
+        let config = &mut ctx.accounts.config;
+        config.fee_recipient = fee_recipient;
+        config.taker_fee_bps = taker_fee_bps;
+        config.maker_fee_bps = maker_fee_bps;
+        config.creator_fee_share_bps = creator_fee_share_bps;
+
+        msg!(
+            "Protocol fees set to taker {} bps, maker {} bps, creator share {} bps",
+            taker_fee_bps,
+            maker_fee_bps,
+            creator_fee_share_bps
+        );
+        Ok(())
+    }
+
+    /// Part of a referred trader's taker fees (after rebates) credited to their referrer
+    pub fn set_referral_fee_share(
+        ctx: Context<UpdateProtocolConfig>,
+        referral_fee_share_bps: u16,
+    ) -> Result<()> {
+        require!(
+            referral_fee_share_bps <= MAX_FEE_BPS,
+            PredictionMarketError::InvalidFeeRate
+        );
+        ctx.accounts.config.referral_fee_share_bps = referral_fee_share_bps;
+
+        msg!("Referral fee share set to {} bps", referral_fee_share_bps);
+        Ok(())
+    }
+
+    /// Volume based fee schedule, replaces the whole list. A trader's rates come from the highest
+    /// tier their TraderVolume reaches, traders below the first tier pay the market's own rates
+    pub fn set_fee_tiers(
+        ctx: Context<UpdateProtocolConfig>,
+        fee_tiers: Vec<FeeTier>,
+        volume_window: i64,
+    ) -> Result<()> {
+        require!(
+            fee_tiers.len() <= MAX_FEE_TIERS,
+            PredictionMarketError::TooManyFeeTiers
+        );
+        let max_fee_bps = MAX_FEE_BPS as i16;
+        require!(
+            volume_window > 0
+                && fee_tiers.windows(2).all(|w| w[0].min_volume < w[1].min_volume)
+                && fee_tiers.iter().all(|tier| {
+                    tier.taker_fee_bps <= MAX_FEE_BPS
+                        && (-max_fee_bps..=max_fee_bps).contains(&tier.maker_fee_bps)
+                }),
+            PredictionMarketError::InvalidFeeTiers
+        );
+
+        let config = &mut ctx.accounts.config;
+        config.fee_tiers = fee_tiers;
+        config.volume_window = volume_window;
+
+        msg!(
+            "{} fee tiers set, volume window {}s",
+            config.fee_tiers.len(),
+            volume_window
+        );
+        Ok(())
+    }
+
+    /// New markets take the protocol default rates, this overrides them for one market
+    /// Resting buy orders keep the maker fee they reserved when they were placed
+    pub fn set_market_fees(
+        ctx: Context<SetMarketFees>,
+        _market_id: u32,
+        taker_fee_bps: u16,
+        maker_fee_bps: u16,
+    ) -> Result<()> {
+        require!(
+            taker_fee_bps <= MAX_FEE_BPS && maker_fee_bps <= MAX_FEE_BPS,
+            PredictionMarketError::InvalidFeeRate
+        );
+
+        let market = &mut ctx.accounts.market;
+        market.taker_fee_bps = taker_fee_bps;
+        market.maker_fee_bps = maker_fee_bps;
+
+        emit_cpi!(MarketFeesUpdated {
+            market_id: market.market_id,
+            taker_fee_bps,
+            maker_fee_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Market {} fees set to taker {} bps, maker {} bps",
+            market.market_id,
+            taker_fee_bps,
+            maker_fee_bps
+        );
+        Ok(())
+    }
+
+    /// Pays the protocol's accrued share of a market's fees to config.fee_recipient
+    pub fn withdraw_protocol_fees(ctx: Context<WithdrawFees>, _market_id: u32) -> Result<()> {
+        require_keys_eq!(
+            ctx.accounts.recipient.key(),
+            ctx.accounts.config.fee_recipient,
+            PredictionMarketError::NotAuthorized
+        );
+
+        let amount = ctx.accounts.market.protocol_fees_accrued;
+        ctx.accounts.market.protocol_fees_accrued = 0;
+        withdraw_fees(
+            &ctx.accounts.market,
+            &ctx.accounts.fee_vault,
+            &ctx.accounts.collateral_mint,
+            &ctx.accounts.recipient_collateral,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        emit_cpi!(FeesWithdrawn {
+            market_id: ctx.accounts.market.market_id,
+            recipient: ctx.accounts.recipient.key(),
+            creator_fees: false,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Pays a referrer what their referred traders earned them in this market
+    pub fn withdraw_referral_fees(
+        ctx: Context<WithdrawReferralFees>,
+        _market_id: u32,
+    ) -> Result<()> {
+        let amount = ctx.accounts.referrer_stats.referral_fees_claimable;
+        ctx.accounts.referrer_stats.referral_fees_claimable = 0;
+        withdraw_fees(
+            &ctx.accounts.market,
+            &ctx.accounts.fee_vault,
+            &ctx.accounts.collateral_mint,
+            &ctx.accounts.recipient_collateral,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        emit_cpi!(ReferralFeesWithdrawn {
+            market_id: ctx.accounts.market.market_id,
+            referrer: ctx.accounts.referrer.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Pays the creator's accrued share of the market's fees to the market authority
+    pub fn withdraw_creator_fees(ctx: Context<WithdrawFees>, _market_id: u32) -> Result<()> {
+        require_keys_eq!(
+            ctx.accounts.recipient.key(),
+            ctx.accounts.market.authority,
+            PredictionMarketError::NotAuthorized
+        );
+
+        let amount = ctx.accounts.market.creator_fees_accrued;
+        ctx.accounts.market.creator_fees_accrued = 0;
+        withdraw_fees(
+            &ctx.accounts.market,
+            &ctx.accounts.fee_vault,
+            &ctx.accounts.collateral_mint,
+            &ctx.accounts.recipient_collateral,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        emit_cpi!(FeesWithdrawn {
+            market_id: ctx.accounts.market.market_id,
+            recipient: ctx.accounts.recipient.key(),
+            creator_fees: true,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Global kill switch, every trading instruction fails while paused
+    pub fn set_protocol_paused(ctx: Context<UpdateProtocolConfig>, paused: bool) -> Result<()> {
+        ctx.accounts.config.paused = paused;
+
+        msg!("Protocol paused: {}", paused);
+        Ok(())
+    }
+
+    /// Token-2022 mints are accepted unless they carry an extension is_supported_collateral_mint rejects
+    pub fn add_allowed_collateral(ctx: Context<AddAllowedCollateral>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let mint = ctx.accounts.collateral_mint.key();
+
+        if config.is_collateral_allowed(&mint) {
+            return Ok(());
+        }
+        require!(
+            config.allowed_collateral_mints.len() < MAX_ALLOWED_COLLATERAL_MINTS,
+            PredictionMarketError::TooManyCollateralMints
+        );
+        config.allowed_collateral_mints.push(mint);
+
+        msg!("Collateral mint allowed: {}", mint);
+        Ok(())
+    }
+
+    /// Only affects new markets, existing markets keep trading with their collateral
+    pub fn remove_allowed_collateral(
+        ctx: Context<UpdateProtocolConfig>,
+        mint: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(
+            config.is_collateral_allowed(&mint),
+            PredictionMarketError::CollateralNotAllowed
+        );
+        config.allowed_collateral_mints.retain(|m| *m != mint);
+
+        msg!("Collateral mint removed: {}", mint);
+        Ok(())
+    }
+
+    /// First step of the admin handover, `new_admin` has to call accept_protocol_admin
+    /// Passing the current admin cancels a pending transfer
+    pub fn transfer_protocol_admin(
+        ctx: Context<UpdateProtocolConfig>,
+        new_admin: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.pending_admin = if new_admin == config.admin {
+            None
+        } else {
+            Some(new_admin)
+        };
+
+        msg!("Pending protocol admin: {:?}", config.pending_admin);
+        Ok(())
+    }
+
+    pub fn accept_protocol_admin(ctx: Context<AcceptProtocolAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.pending_admin.key();
+        config.pending_admin = None;
+
+        msg!("Protocol admin is now {}", config.admin);
+        Ok(())
+    }
+
+    /// Fills a taker order across the order book and the market's AMM. Each fill comes from
+    /// whichever venue has the better marginal price, fees included, until `quantity` is filled
+    /// or both venues are past `limit_price`. Nothing rests on the book, and `slippage_limit`
+    /// bounds the whole order: the most a buy pays or the least a sell receives
+    pub fn market_order(
+        ctx: Context<MarketOrder>,
+        market_id: u32,
+        params: MarketOrderParams,
+    ) -> Result<()> {
+        let MarketOrderParams {
+            side,
+            token_type,
+            quantity,
+            limit_price,
+            slippage_limit,
+            max_iteration,
+        } = params;
+        let market = &mut ctx.accounts.market;
+        let orderbook = &mut ctx.accounts.orderbook;
+        let user = ctx.accounts.user.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            now < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        match side {
+            OrderSide::Buy => market.require_status(&[MarketStatus::Active])?,
+            OrderSide::Sell => {
+                market.require_status(&[MarketStatus::Active, MarketStatus::CloseOnly])?
+            }
+        }
+        require!(quantity > 0, PredictionMarketError::InvalidOrderQuantity);
+        // A sell limit of 0 takes any price, the slippage limit still bounds the proceeds
+        require!(
+            limit_price > 0 || side == OrderSide::Sell,
+            PredictionMarketError::InvalidOrderPrice
+        );
+
+        // Skipping the AMM could only give the taker a worse price, but the router has to see it
+        let amm = market.amm;
+        let amm_accounts_provided = match amm {
+            Some(AmmKind::Fpmm) => {
+                ctx.accounts.pool.is_some()
+                    && ctx.accounts.yes_reserve.is_some()
+                    && ctx.accounts.no_reserve.is_some()
+            }
+            Some(AmmKind::Lmsr) => ctx.accounts.lmsr.is_some(),
+            None => true,
+        };
+        require!(
+            amm_accounts_provided,
+            PredictionMarketError::AmmAccountNotProvided
+        );
+
+        let (outcome_mint, user_token_account, token_escrow) = match token_type {
+            TokenType::Yes => (
+                &ctx.accounts.outcome_yes_mint,
+                &ctx.accounts.user_outcome_yes,
+                &ctx.accounts.yes_escrow,
+            ),
+            TokenType::No => (
+                &ctx.accounts.outcome_no_mint,
+                &ctx.accounts.user_outcome_no,
+                &ctx.accounts.no_escrow,
+            ),
+        };
+        if side == OrderSide::Sell {
+            require!(
+                user_token_account.amount >= quantity,
+                PredictionMarketError::NotEnoughBalance
+            );
+        }
+
+        // The pool is simulated on copies of its reserves, the tokens move once routing is done
+        let pool_fee_bps = ctx.accounts.pool.as_ref().map_or(0, |pool| pool.fee_bps);
+        let (mut token_reserve, mut other_reserve) =
+            match (&ctx.accounts.yes_reserve, &ctx.accounts.no_reserve) {
+                (Some(yes_reserve), Some(no_reserve)) => match token_type {
+                    TokenType::Yes => (yes_reserve.amount, no_reserve.amount),
+                    TokenType::No => (no_reserve.amount, yes_reserve.amount),
+                },
+                _ => (0, 0),
+            };
+        let mut lmsr = ctx.accounts.lmsr.as_deref_mut();
+
+        // Fills reference it like a placed order's id
+        let order_id = orderbook.next_order_id;
+        orderbook.next_order_id += 1;
+
+        // The first referrer sticks, later ones are ignored
+        if let Some(referrer) = &ctx.accounts.referrer {
+            let user_stats = &mut ctx.accounts.user_stats_account;
+            if user_stats.referrer.is_none() {
+                user_stats.referrer = Some(referrer.key());
+
+                emit_cpi!(ReferrerRecorded {
+                    market_id,
+                    user,
+                    referrer: referrer.key(),
+                    timestamp: now,
+                });
+            }
+        }
+
+        // Fee tiers go by the volume traded before this order
+        let volume_window = ctx.accounts.config.volume_window;
+        let trader_volume = &mut ctx.accounts.trader_volume;
+        if trader_volume.owner == Pubkey::default() {
+            trader_volume.owner = user;
+            trader_volume.window_start = now;
+            trader_volume.bump = ctx.bumps.trader_volume;
+        }
+        trader_volume.roll(now, volume_window);
+        let (taker_fee_bps, _) =
+            market.fee_rates(&ctx.accounts.config, trader_volume.rolling_volume());
+
+        let (matching_orders, is_buy_order) = match (token_type, side) {
+            (TokenType::Yes, OrderSide::Buy) => (&mut orderbook.yes_sell_orders, true),
+            (TokenType::Yes, OrderSide::Sell) => (&mut orderbook.yes_buy_orders, false),
+            (TokenType::No, OrderSide::Buy) => (&mut orderbook.no_sell_orders, true),
+            (TokenType::No, OrderSide::Sell) => (&mut orderbook.no_buy_orders, false),
+        };
+        let mut taker = BookTaker {
+            stats: Some(&mut ctx.accounts.user_stats_account),
+            volume_account: ctx.accounts.trader_volume.key(),
+            rolling_volume: ctx.accounts.trader_volume.rolling_volume(),
+            fee_bps: taker_fee_bps,
+            token_type,
+            is_buy_order,
+        };
+        let mut maker_accounts = ctx.remaining_accounts.iter();
+
+        // Both venues are compared in WAD scaled collateral per outcome token
+        let limit = limit_price as u128 * WAD;
+        let mut filled: u64 = 0;
+        let mut iteration = 0;
+        let mut book_quantity: u64 = 0;
+        let mut book_collateral: u64 = 0;
+        let mut taker_fees: u64 = 0;
+        let mut collected_fees: u64 = 0;
+        let mut taker_fees_net: u64 = 0; // Taker fees minus rebates, the referral share is taken from this
+        let mut amm_quantity: u64 = 0;
+        let mut amm_collateral: u64 = 0; // Paid into the AMM for a buy, paid out by it for a sell
+        let mut amm_fee: u64 = 0;
+
+        while filled < quantity && iteration <= max_iteration {
+            let remaining = quantity - filled;
+
+            // Best resting order inside the limit, with the taker fee priced in
+            let book_price = matching_orders
+                .first()
+                .map(|order| order.price)
+                .filter(|price| {
+                    if is_buy_order {
+                        *price <= limit_price
+                    } else {
+                        *price >= limit_price
+                    }
+                })
+                .map(|price| {
+                    let price = price as u128 * WAD;
+                    let fee = price * taker_fee_bps as u128 / MAX_FEE_BPS as u128;
+                    if is_buy_order {
+                        price + fee
+                    } else {
+                        price - fee
+                    }
+                });
+
+            // The AMM takes the fill while it beats the book, and then only up to the book's
+            // price (or the limit once the book is out), where the book takes over
+            let target = match book_price {
+                Some(price) if is_buy_order => price.min(limit),
+                Some(price) => price.max(limit),
+                None => limit,
+            };
+            let amm_price = match (amm, lmsr.as_deref()) {
+                (Some(AmmKind::Fpmm), _) if token_reserve > 0 && other_reserve > 0 => {
+                    AmmPool::marginal_price(side, pool_fee_bps, token_reserve, other_reserve)
+                }
+                (Some(AmmKind::Lmsr), Some(lmsr)) => lmsr.marginal_price(token_type),
+                _ => None,
+            };
+            let amm_step = match amm_price {
+                Some(price)
+                    if (is_buy_order && price < target) || (!is_buy_order && price > target) =>
+                {
+                    match lmsr.as_deref() {
+                        Some(lmsr) => lmsr.quantity_to_price(token_type, side, target),
+                        None => AmmPool::quantity_to_price(
+                            side,
+                            pool_fee_bps,
+                            token_reserve,
+                            other_reserve,
+                            target,
+                        ),
+                    }
+                    .ok_or(PredictionMarketError::MathOverflow)?
+                    .min(remaining)
+                }
+                _ => 0,
+            };
+
+            if amm_step > 0 {
+                let (collateral_amount, fee) = match (lmsr.as_deref_mut(), is_buy_order) {
+                    (Some(lmsr), true) => {
+                        let cost = lmsr
+                            .buy_cost(token_type, amm_step)
+                            .ok_or(PredictionMarketError::MathOverflow)?;
+                        lmsr.apply_trade(
+                            token_type,
+                            i64::try_from(amm_step)
+                                .map_err(|_| PredictionMarketError::MathOverflow)?,
+                            i64::try_from(cost)
+                                .map_err(|_| PredictionMarketError::MathOverflow)?,
+                        )?;
+                        (cost, 0)
+                    }
+                    (Some(lmsr), false) => {
+                        let proceeds = lmsr
+                            .sell_proceeds(token_type, amm_step)
+                            .ok_or(PredictionMarketError::MathOverflow)?;
+                        lmsr.apply_trade(
+                            token_type,
+                            -i64::try_from(amm_step)
+                                .map_err(|_| PredictionMarketError::MathOverflow)?,
+                            -i64::try_from(proceeds)
+                                .map_err(|_| PredictionMarketError::MathOverflow)?,
+                        )?;
+                        (proceeds, 0)
+                    }
+                    (None, true) => {
+                        let (cost, fee) = AmmPool::buy_cost(
+                            amm_step,
+                            pool_fee_bps,
+                            token_reserve,
+                            other_reserve,
+                        )
+                        .ok_or(PredictionMarketError::MathOverflow)?;
+                        token_reserve = token_reserve
+                            .checked_add(cost)
+                            .and_then(|reserve| reserve.checked_sub(amm_step))
+                            .ok_or(PredictionMarketError::MathOverflow)?;
+                        other_reserve = other_reserve
+                            .checked_add(cost)
+                            .ok_or(PredictionMarketError::MathOverflow)?;
+                        (cost, fee)
+                    }
+                    (None, false) => {
+                        let (return_amount, fee) = AmmPool::sell_proceeds(
+                            amm_step,
+                            pool_fee_bps,
+                            token_reserve,
+                            other_reserve,
+                        )
+                        .ok_or(PredictionMarketError::AmmInsufficientLiquidity)?;
+                        token_reserve = token_reserve
+                            .checked_add(amm_step - return_amount)
+                            .ok_or(PredictionMarketError::MathOverflow)?;
+                        other_reserve -= return_amount;
+                        (return_amount, fee)
+                    }
+                };
+
+                filled += amm_step;
+                amm_quantity += amm_step;
+                amm_collateral = amm_collateral
+                    .checked_add(collateral_amount)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                amm_fee += fee;
+            } else if book_price.is_some() {
+                let book_order = &mut matching_orders[0];
+                let fill_quantity = remaining.min(book_order.quantity - book_order.filledquantity);
+
+                let BookFill {
+                    collateral_amount,
+                    taker_fee,
+                    maker_fee,
+                    maker_rebate,
+                } = settle_book_fill(
+                    &mut maker_accounts,
+                    book_order,
+                    &mut taker,
+                    market,
+                    &ctx.accounts.config,
+                    fill_quantity,
+                    now,
+                )?;
+
+                emit_cpi!(OrderFilled {
+                    market_id,
+                    taker_order_id: order_id,
+                    maker_order_id: book_order.id,
+                    taker: user,
+                    maker: book_order.user_key,
+                    taker_side: side,
+                    token_type,
+                    price: book_order.price,
+                    quantity: fill_quantity,
+                    collateral_amount,
+                    taker_fee,
+                    maker_fee,
+                    maker_rebate,
+                    timestamp: now,
+                });
+
+                filled += fill_quantity;
+                book_quantity += fill_quantity;
+                book_collateral = book_collateral
+                    .checked_add(collateral_amount)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                taker_fees = taker_fees
+                    .checked_add(taker_fee)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                collected_fees = taker_fee
+                    .checked_add(maker_fee)
+                    .and_then(|fees| fees.checked_sub(maker_rebate))
+                    .and_then(|fees| collected_fees.checked_add(fees))
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+                taker_fees_net = taker_fee
+                    .checked_sub(maker_rebate)
+                    .and_then(|fee| taker_fees_net.checked_add(fee))
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+
+                if book_order.filledquantity == book_order.quantity {
+                    matching_orders.remove(0);
+                }
+            } else {
+                break;
+            }
+
+            iteration += 1;
+        }
+
+        // Routed AMM fills count toward the taker's fee tier like book fills do
+        ctx.accounts.trader_volume.add(
+            book_collateral
+                .checked_add(amm_collateral)
+                .ok_or(PredictionMarketError::MathOverflow)?,
+        )?;
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.user,
+            user_collateral: ctx.accounts.user_collateral.as_ref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+
+        // Book fills settle through UserStats like place_order, so only the AMM leg moves
+        // outcome tokens to or from the user here
+        let collateral_amount = if is_buy_order {
+            let total_cost = book_collateral
+                .checked_add(taker_fees)
+                .and_then(|cost| cost.checked_add(amm_collateral))
+                .ok_or(PredictionMarketError::MathOverflow)?;
+            require!(
+                total_cost <= slippage_limit,
+                PredictionMarketError::SlippageExceeded
+            );
+            if total_cost > 0 {
+                collateral.deposit(&ctx.accounts.system_program, total_cost)?;
+            }
+            total_cost
+        } else {
+            let proceeds = book_collateral
+                .checked_sub(taker_fees)
+                .and_then(|proceeds| proceeds.checked_add(amm_collateral))
+                .ok_or(PredictionMarketError::MathOverflow)?;
+            require!(
+                proceeds >= slippage_limit,
+                PredictionMarketError::SlippageExceeded
+            );
+            if book_quantity > 0 {
+                token_2022::transfer_checked(
+                    CpiContext::new(
+                        ctx.accounts.outcome_token_program.to_account_info(),
+                        token_2022::TransferChecked {
+                            from: user_token_account.to_account_info(),
+                            mint: outcome_mint.to_account_info(),
+                            to: token_escrow.to_account_info(),
+                            authority: ctx.accounts.user.to_account_info(),
+                        },
+                    ),
+                    book_quantity,
+                    outcome_mint.decimals,
+                )?;
+            }
+            proceeds
+        };
+
+        if amm_quantity > 0 {
+            match (amm, is_buy_order) {
+                (Some(AmmKind::Lmsr), true) => {
+                    token_2022::mint_to(
+                        CpiContext::new_with_signer(
+                            ctx.accounts.outcome_token_program.to_account_info(),
+                            token_2022::MintTo {
+                                mint: outcome_mint.to_account_info(),
+                                to: user_token_account.to_account_info(),
+                                authority: market.to_account_info(),
+                            },
+                            &[seeds],
+                        ),
+                        amm_quantity,
+                    )?;
+                }
+                (Some(AmmKind::Lmsr), false) => {
+                    token_2022::burn(
+                        CpiContext::new(
+                            ctx.accounts.outcome_token_program.to_account_info(),
+                            token_2022::Burn {
+                                mint: outcome_mint.to_account_info(),
+                                from: user_token_account.to_account_info(),
+                                authority: ctx.accounts.user.to_account_info(),
+                            },
+                        ),
+                        amm_quantity,
+                    )?;
+                }
+                _ => {
+                    let (Some(yes_reserve), Some(no_reserve)) = (
+                        ctx.accounts.yes_reserve.as_deref(),
+                        ctx.accounts.no_reserve.as_deref(),
+                    ) else {
+                        return err!(PredictionMarketError::AmmAccountNotProvided);
+                    };
+                    let token_reserve_account = match token_type {
+                        TokenType::Yes => yes_reserve,
+                        TokenType::No => no_reserve,
+                    };
+
+                    // Same token moves as amm_buy and amm_sell, for the whole AMM leg at once
+                    if !is_buy_order {
+                        token_2022::transfer_checked(
+                            CpiContext::new(
+                                ctx.accounts.outcome_token_program.to_account_info(),
+                                token_2022::TransferChecked {
+                                    from: user_token_account.to_account_info(),
+                                    mint: outcome_mint.to_account_info(),
+                                    to: token_reserve_account.to_account_info(),
+                                    authority: ctx.accounts.user.to_account_info(),
+                                },
+                            ),
+                            amm_quantity,
+                            outcome_mint.decimals,
+                        )?;
+                    }
+                    for (mint, reserve) in [
+                        (&ctx.accounts.outcome_yes_mint, yes_reserve),
+                        (&ctx.accounts.outcome_no_mint, no_reserve),
+                    ] {
+                        if is_buy_order {
+                            token_2022::mint_to(
+                                CpiContext::new_with_signer(
+                                    ctx.accounts.outcome_token_program.to_account_info(),
+                                    token_2022::MintTo {
+                                        mint: mint.to_account_info(),
+                                        to: reserve.to_account_info(),
+                                        authority: market.to_account_info(),
+                                    },
+                                    &[seeds],
+                                ),
+                                amm_collateral,
+                            )?;
+                        } else if amm_collateral > 0 {
+                            token_2022::burn(
+                                CpiContext::new_with_signer(
+                                    ctx.accounts.outcome_token_program.to_account_info(),
+                                    token_2022::Burn {
+                                        mint: mint.to_account_info(),
+                                        from: reserve.to_account_info(),
+                                        authority: market.to_account_info(),
+                                    },
+                                    &[seeds],
+                                ),
+                                amm_collateral,
+                            )?;
+                        }
+                    }
+                    if is_buy_order {
+                        transfer_from_reserve(
+                            &ctx.accounts.outcome_token_program,
+                            token_reserve_account,
+                            outcome_mint,
+                            user_token_account,
+                            market.to_account_info(),
+                            seeds,
+                            amm_quantity,
+                        )?;
+                    }
+                }
+            }
+
+            market.total_collateral_locked = if is_buy_order {
+                market.total_collateral_locked.checked_add(amm_collateral)
+            } else {
+                market.total_collateral_locked.checked_sub(amm_collateral)
+            }
+            .ok_or(PredictionMarketError::MathOverflow)?;
+            if is_buy_order {
+                market.trading_started = true;
+            }
+
+            if amm == Some(AmmKind::Lmsr) {
+                emit_cpi!(LmsrTraded {
+                    market_id,
+                    user,
+                    side,
+                    token_type,
+                    shares: amm_quantity,
+                    collateral_amount: amm_collateral,
+                    timestamp: now,
+                });
+            } else {
+                emit_cpi!(AmmTraded {
+                    market_id,
+                    user,
+                    side,
+                    token_type,
+                    collateral_amount: amm_collateral,
+                    outcome_amount: amm_quantity,
+                    fee: amm_fee,
+                    timestamp: now,
+                });
+            }
+        }
+
+        // AMM proceeds are paid out directly, the native SOL account is closed either way
+        if !is_buy_order && (amm_collateral > 0 || ctx.accounts.native_unwrap.is_some()) {
+            collateral.pay_out(
+                ctx.accounts.native_unwrap.as_deref(),
+                market.to_account_info(),
+                seeds,
+                amm_collateral,
+            )?;
+        }
+
+        // One transfer moves every fee of this order out of the vault, rebates stay behind
+        if collected_fees > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: ctx.accounts.collateral_vault.to_account_info(),
+                        mint: ctx.accounts.collateral_mint.to_account_info(),
+                        to: ctx.accounts.fee_vault.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                collected_fees,
+                ctx.accounts.collateral_mint.decimals,
+            )?;
+
+            // Without the referrer's UserStats the protocol keeps the referral share
+            let mut referral_fee = 0;
+            if let (Some(referrer), Some(referrer_stats)) = (
+                ctx.accounts.user_stats_account.referrer,
+                ctx.accounts.referrer_stats.as_mut(),
+            ) {
+                referral_fee = fee_amount(taker_fees_net, ctx.accounts.config.referral_fee_share_bps)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+
+                if referrer_stats.user == Pubkey::default() {
+                    referrer_stats.user = referrer;
+                    referrer_stats.market_id = market_id;
+                    referrer_stats.bump = ctx.bumps.referrer_stats.unwrap_or_default();
+                }
+                referrer_stats.referral_fees_claimable = referrer_stats
+                    .referral_fees_claimable
+                    .checked_add(referral_fee)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+
+                if referral_fee > 0 {
+                    emit_cpi!(ReferralFeeCredited {
+                        market_id,
+                        user,
+                        referrer,
+                        amount: referral_fee,
+                        timestamp: now,
+                    });
+                }
+            }
+
+            market.accrue_fees(
+                collected_fees - referral_fee,
+                ctx.accounts.config.creator_fee_share_bps,
+            )?;
+        }
+
+        emit_cpi!(MarketOrderFilled {
+            market_id,
+            order_id,
+            user,
+            side,
+            token_type,
+            book_quantity,
+            amm_quantity,
+            collateral_amount,
+            timestamp: now,
+        });
+
+        msg!(
+            "Market order filled {} on the book and {} on the AMM, {} unfilled",
+            book_quantity,
+            amm_quantity,
+            quantity - filled
+        );
+        Ok(())
+    }
+
+    /// Shorts `token_type` in one step: splits collateral into both outcomes and sells
+    /// `token_type` to the resting bids at `limit_price` or better, keeping the other outcome.
+    /// Only the filled quantity is split and the sale's proceeds are netted against it
+    pub fn split_and_sell(
+        ctx: Context<SplitMergeOrder>,
+        market_id: u32,
+        params: SplitMergeParams,
+    ) -> Result<()> {
+        split_merge_order(ctx, market_id, OrderSide::Sell, params)
+    }
+
+    /// Closes a short in one step: buys `token_type` from the resting asks at `limit_price` or
+    /// better and merges it with the user's other outcome. The purchase is netted against the
+    /// merged collateral
+    pub fn buy_and_merge(
+        ctx: Context<SplitMergeOrder>,
+        market_id: u32,
+        params: SplitMergeParams,
+    ) -> Result<()> {
+        split_merge_order(ctx, market_id, OrderSide::Buy, params)
+    }
+
+    // ---------------- AMM ----------------
+
+    /// Fixed product pool next to the order book, priced so that yes_reserve * no_reserve
+    /// never drops. `fee_bps` of every trade stays in the reserves for the LPs
+    pub fn initialize_amm_pool(
+        ctx: Context<InitializeAmmPool>,
+        market_id: u32,
+        fee_bps: u16,
+    ) -> Result<()> {
+        require!(fee_bps < MAX_FEE_BPS, PredictionMarketError::InvalidFeeRate);
+        let market = &mut ctx.accounts.market;
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            market.amm.is_none(),
+            PredictionMarketError::AmmAlreadyEnabled
+        );
+        market.amm = Some(AmmKind::Fpmm);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.market_id = market_id;
+        pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.yes_reserve = ctx.accounts.yes_reserve.key();
+        pool.no_reserve = ctx.accounts.no_reserve.key();
+        pool.fee_bps = fee_bps;
+        pool.bump = ctx.bumps.pool;
+
+        emit_cpi!(AmmPoolInitialized {
+            market_id,
+            lp_mint: pool.lp_mint,
+            fee_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("AMM pool for market {} with a {} bps fee", market_id, fee_bps);
+        Ok(())
+    }
+
+    /// Splits `amount` collateral into the pool for LP shares. Whatever the pool doesn't keep
+    /// at its current odds comes back to the LP as outcome tokens
+    pub fn add_liquidity(ctx: Context<AddLiquidity>, market_id: u32, amount: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+        market.require_status(&[MarketStatus::Active])?;
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+
+        let vault_before = ctx.accounts.collateral_vault.amount;
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.user,
+            user_collateral: ctx.accounts.user_collateral.as_deref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.deposit(&ctx.accounts.system_program, amount)?;
+
+        ctx.accounts.collateral_vault.reload()?;
+        let amount = ctx
+            .accounts
+            .collateral_vault
+            .amount
+            .checked_sub(vault_before)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+
+        let (shares, yes_returned, no_returned) = AmmPool::liquidity_shares(
+            amount,
+            ctx.accounts.yes_reserve.amount,
+            ctx.accounts.no_reserve.amount,
+            ctx.accounts.lp_mint.supply,
+        )
+        .ok_or(PredictionMarketError::MathOverflow)?;
+        require!(shares > 0, PredictionMarketError::InvalidAmount);
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+
+        // The split pair goes to the reserves, the surplus side straight to the LP
+        let yes_mint = &ctx.accounts.outcome_yes_mint;
+        let no_mint = &ctx.accounts.outcome_no_mint;
+        for (mint, to, mint_amount) in [
+            (yes_mint, ctx.accounts.yes_reserve.to_account_info(), amount - yes_returned),
+            (no_mint, ctx.accounts.no_reserve.to_account_info(), amount - no_returned),
+            (yes_mint, ctx.accounts.user_outcome_yes.to_account_info(), yes_returned),
+            (no_mint, ctx.accounts.user_outcome_no.to_account_info(), no_returned),
+            (&ctx.accounts.lp_mint, ctx.accounts.user_lp.to_account_info(), shares),
+        ] {
+            if mint_amount == 0 {
+                continue;
+            }
+            token_2022::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    token_2022::MintTo {
+                        mint: mint.to_account_info(),
+                        to,
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                mint_amount,
+            )?;
+        }
+
+        market.total_collateral_locked = market
+            .total_collateral_locked
+            .checked_add(amount)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        market.trading_started = true;
+
+        emit_cpi!(LiquidityAdded {
+            market_id,
+            user: ctx.accounts.user.key(),
+            collateral_amount: amount,
+            shares,
+            yes_returned,
+            no_returned,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Added {} collateral to the AMM for {} shares", amount, shares);
+        Ok(())
+    }
+
+    /// Burns `shares` for the same part of both reserves, fees included. Works in any market
+    /// state and through a protocol pause so LPs can always get out and claim or merge what they hold
+    pub fn remove_liquidity(
+        ctx: Context<RemoveLiquidity>,
+        market_id: u32,
+        shares: u64,
+    ) -> Result<()> {
+        require!(
+            shares > 0 && shares <= ctx.accounts.user_lp.amount,
+            PredictionMarketError::InvalidAmount
+        );
+
+        let supply = ctx.accounts.lp_mint.supply;
+        let yes_amount = AmmPool::share_of(ctx.accounts.yes_reserve.amount, shares, supply)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        let no_amount = AmmPool::share_of(ctx.accounts.no_reserve.amount, shares, supply)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        token_2022::burn(
+            CpiContext::new(
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            shares,
+        )?;
+
+        let market = &ctx.accounts.market;
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+        let accounts = &ctx.accounts;
+        for (reserve, mint, to, amount) in [
+            (&accounts.yes_reserve, &accounts.outcome_yes_mint, &accounts.user_outcome_yes, yes_amount),
+            (&accounts.no_reserve, &accounts.outcome_no_mint, &accounts.user_outcome_no, no_amount),
+        ] {
+            transfer_from_reserve(
+                &ctx.accounts.outcome_token_program,
+                reserve,
+                mint,
+                to,
+                market.to_account_info(),
+                seeds,
+                amount,
+            )?;
+        }
+
+        emit_cpi!(LiquidityRemoved {
+            market_id,
+            user: ctx.accounts.user.key(),
+            shares,
+            yes_amount,
+            no_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Removed {} shares for {} YES and {} NO", shares, yes_amount, no_amount);
+        Ok(())
+    }
+
+    /// Buys `token_type` from the pool with `amount` collateral, which is split into both
+    /// reserves before the bought side is paid out
+    pub fn amm_buy(
+        ctx: Context<AmmTrade>,
+        market_id: u32,
+        token_type: TokenType,
+        amount: u64,
+        min_tokens_out: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+        market.require_status(&[MarketStatus::Active])?;
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+
+        let vault_before = ctx.accounts.collateral_vault.amount;
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.user,
+            user_collateral: ctx.accounts.user_collateral.as_deref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.deposit(&ctx.accounts.system_program, amount)?;
+
+        ctx.accounts.collateral_vault.reload()?;
+        let amount = ctx
+            .accounts
+            .collateral_vault
+            .amount
+            .checked_sub(vault_before)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+
+        let (buy_reserve, other_reserve, buy_mint, user_account) = match token_type {
+            TokenType::Yes => (
+                &ctx.accounts.yes_reserve,
+                &ctx.accounts.no_reserve,
+                &ctx.accounts.outcome_yes_mint,
+                &ctx.accounts.user_outcome_yes,
+            ),
+            TokenType::No => (
+                &ctx.accounts.no_reserve,
+                &ctx.accounts.yes_reserve,
+                &ctx.accounts.outcome_no_mint,
+                &ctx.accounts.user_outcome_no,
+            ),
+        };
+        require!(
+            buy_reserve.amount > 0 && other_reserve.amount > 0,
+            PredictionMarketError::AmmInsufficientLiquidity
+        );
+
+        let (tokens_out, fee) = AmmPool::buy_amount(
+            amount,
+            ctx.accounts.pool.fee_bps,
+            buy_reserve.amount,
+            other_reserve.amount,
+        )
+        .ok_or(PredictionMarketError::MathOverflow)?;
+        require!(
+            tokens_out >= min_tokens_out,
+            PredictionMarketError::SlippageExceeded
+        );
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+
+        for (mint, reserve) in [
+            (&ctx.accounts.outcome_yes_mint, &ctx.accounts.yes_reserve),
+            (&ctx.accounts.outcome_no_mint, &ctx.accounts.no_reserve),
+        ] {
+            token_2022::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    token_2022::MintTo {
+                        mint: mint.to_account_info(),
+                        to: reserve.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                amount,
+            )?;
+        }
+
+        transfer_from_reserve(
+            &ctx.accounts.outcome_token_program,
+            buy_reserve,
+            buy_mint,
+            user_account,
+            market.to_account_info(),
+            seeds,
+            tokens_out,
+        )?;
+
+        market.total_collateral_locked = market
+            .total_collateral_locked
+            .checked_add(amount)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        market.trading_started = true;
+
+        emit_cpi!(AmmTraded {
+            market_id,
+            user: ctx.accounts.user.key(),
+            side: OrderSide::Buy,
+            token_type,
+            collateral_amount: amount,
+            outcome_amount: tokens_out,
+            fee,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Bought {} outcome tokens from the AMM for {}", tokens_out, amount);
+        Ok(())
+    }
+
+    /// Sells `token_type` to the pool for exactly `return_amount` collateral, spending at most
+    /// `max_tokens_in`. The pool merges the pairs it pays out with
+    pub fn amm_sell(
+        ctx: Context<AmmTrade>,
+        market_id: u32,
+        token_type: TokenType,
+        return_amount: u64,
+        max_tokens_in: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(return_amount > 0, PredictionMarketError::InvalidAmount);
+        market.require_status(&[MarketStatus::Active, MarketStatus::CloseOnly])?;
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+
+        let (sell_reserve, other_reserve, sell_mint, user_account) = match token_type {
+            TokenType::Yes => (
+                &ctx.accounts.yes_reserve,
+                &ctx.accounts.no_reserve,
+                &ctx.accounts.outcome_yes_mint,
+                &ctx.accounts.user_outcome_yes,
+            ),
+            TokenType::No => (
+                &ctx.accounts.no_reserve,
+                &ctx.accounts.yes_reserve,
+                &ctx.accounts.outcome_no_mint,
+                &ctx.accounts.user_outcome_no,
+            ),
+        };
+
+        let (tokens_in, fee) = AmmPool::sell_amount(
+            return_amount,
+            ctx.accounts.pool.fee_bps,
+            sell_reserve.amount,
+            other_reserve.amount,
+        )
+        .ok_or(PredictionMarketError::AmmInsufficientLiquidity)?;
+        require!(
+            tokens_in <= max_tokens_in,
+            PredictionMarketError::SlippageExceeded
+        );
+
+        token_2022::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::TransferChecked {
+                    from: user_account.to_account_info(),
+                    mint: sell_mint.to_account_info(),
+                    to: sell_reserve.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            tokens_in,
+            sell_mint.decimals,
+        )?;
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+
+        for (mint, reserve) in [
+            (&ctx.accounts.outcome_yes_mint, &ctx.accounts.yes_reserve),
+            (&ctx.accounts.outcome_no_mint, &ctx.accounts.no_reserve),
+        ] {
+            token_2022::burn(
+                CpiContext::new_with_signer(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    token_2022::Burn {
+                        mint: mint.to_account_info(),
+                        from: reserve.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                return_amount,
+            )?;
+        }
+
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.user,
+            user_collateral: ctx.accounts.user_collateral.as_deref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.pay_out(
+            ctx.accounts.native_unwrap.as_deref(),
+            market.to_account_info(),
+            seeds,
+            return_amount,
+        )?;
+
+        market.total_collateral_locked = market
+            .total_collateral_locked
+            .checked_sub(return_amount)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        emit_cpi!(AmmTraded {
+            market_id,
+            user: ctx.accounts.user.key(),
+            side: OrderSide::Sell,
+            token_type,
+            collateral_amount: return_amount,
+            outcome_amount: tokens_in,
+            fee,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Sold {} outcome tokens to the AMM for {}", tokens_in, return_amount);
+        Ok(())
+    }
+
+    /// Alternative to the AMM pool: the market authority funds a logarithmic market scoring
+    /// rule maker with liquidity `liquidity` (b). The subsidy is b * ln(2), the most it can lose
+    pub fn initialize_lmsr(
+        ctx: Context<InitializeLmsr>,
+        market_id: u32,
+        liquidity: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(liquidity > 0, PredictionMarketError::InvalidAmount);
+        require!(
+            market.market_type == MarketType::Binary,
+            PredictionMarketError::InvalidMarketType
+        );
+        require!(
+            market.market_group.is_none(),
+            PredictionMarketError::LmsrMarketInGroup
+        );
+        require!(
+            market.amm.is_none(),
+            PredictionMarketError::AmmAlreadyEnabled
+        );
+        market.require_status(&[MarketStatus::Active])?;
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+
+        let subsidy =
+            LmsrMaker::subsidy_for(liquidity).ok_or(PredictionMarketError::MathOverflow)?;
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.authority,
+            user_collateral: ctx.accounts.authority_collateral.as_deref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.deposit(&ctx.accounts.system_program, subsidy)?;
+
+        market.total_collateral_locked = market
+            .total_collateral_locked
+            .checked_add(subsidy)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        market.amm = Some(AmmKind::Lmsr);
+
+        let lmsr = &mut ctx.accounts.lmsr;
+        lmsr.market_id = market_id;
+        lmsr.creator = ctx.accounts.authority.key();
+        lmsr.liquidity = liquidity;
+        lmsr.yes_shares = 0;
+        lmsr.no_shares = 0;
+        lmsr.collateral = subsidy;
+        lmsr.subsidy = subsidy;
+        lmsr.subsidy_withdrawn = false;
+        lmsr.bump = ctx.bumps.lmsr;
+
+        emit_cpi!(LmsrInitialized {
+            market_id,
+            creator: lmsr.creator,
+            liquidity,
+            subsidy,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("LMSR for market {} with b = {}, subsidy {}", market_id, liquidity, subsidy);
+        Ok(())
+    }
+
+    /// Mints `shares` of `token_type` to the buyer for the cost function difference, at most
+    /// `max_cost` collateral
+    pub fn lmsr_buy(
+        ctx: Context<LmsrTrade>,
+        market_id: u32,
+        token_type: TokenType,
+        shares: u64,
+        max_cost: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(shares > 0, PredictionMarketError::InvalidAmount);
+        market.require_status(&[MarketStatus::Active])?;
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+
+        let lmsr = &mut ctx.accounts.lmsr;
+        let cost = lmsr
+            .buy_cost(token_type, shares)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        require!(cost <= max_cost, PredictionMarketError::SlippageExceeded);
+
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.user,
+            user_collateral: ctx.accounts.user_collateral.as_deref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.deposit(&ctx.accounts.system_program, cost)?;
+
+        let (mint, to) = match token_type {
+            TokenType::Yes => (&ctx.accounts.outcome_yes_mint, &ctx.accounts.user_outcome_yes),
+            TokenType::No => (&ctx.accounts.outcome_no_mint, &ctx.accounts.user_outcome_no),
+        };
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+        token_2022::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::MintTo {
+                    mint: mint.to_account_info(),
+                    to: to.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                &[seeds],
+            ),
+            shares,
+        )?;
+
+        lmsr.apply_trade(
+            token_type,
+            i64::try_from(shares).map_err(|_| PredictionMarketError::MathOverflow)?,
+            i64::try_from(cost).map_err(|_| PredictionMarketError::MathOverflow)?,
+        )?;
+        market.total_collateral_locked = market
+            .total_collateral_locked
+            .checked_add(cost)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        market.trading_started = true;
+
+        emit_cpi!(LmsrTraded {
+            market_id,
+            user: ctx.accounts.user.key(),
+            side: OrderSide::Buy,
+            token_type,
+            shares,
+            collateral_amount: cost,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Bought {} outcome tokens from the LMSR for {}", shares, cost);
+        Ok(())
+    }
+
+    /// Burns `shares` of `token_type` from the seller for the cost function difference, at least
+    /// `min_proceeds` collateral. Tokens from a split can be sold too
+    pub fn lmsr_sell(
+        ctx: Context<LmsrTrade>,
+        market_id: u32,
+        token_type: TokenType,
+        shares: u64,
+        min_proceeds: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(shares > 0, PredictionMarketError::InvalidAmount);
+        market.require_status(&[MarketStatus::Active, MarketStatus::CloseOnly])?;
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            Clock::get()?.unix_timestamp < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+
+        let lmsr = &mut ctx.accounts.lmsr;
+        let proceeds = lmsr
+            .sell_proceeds(token_type, shares)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        require!(
+            proceeds >= min_proceeds,
+            PredictionMarketError::SlippageExceeded
+        );
+
+        let (mint, from) = match token_type {
+            TokenType::Yes => (&ctx.accounts.outcome_yes_mint, &ctx.accounts.user_outcome_yes),
+            TokenType::No => (&ctx.accounts.outcome_no_mint, &ctx.accounts.user_outcome_no),
+        };
+        token_2022::burn(
+            CpiContext::new(
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::Burn {
+                    mint: mint.to_account_info(),
+                    from: from.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            shares,
+        )?;
+
+        lmsr.apply_trade(
+            token_type,
+            -i64::try_from(shares).map_err(|_| PredictionMarketError::MathOverflow)?,
+            -i64::try_from(proceeds).map_err(|_| PredictionMarketError::MathOverflow)?,
+        )?;
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.user,
+            user_collateral: ctx.accounts.user_collateral.as_deref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.pay_out(
+            ctx.accounts.native_unwrap.as_deref(),
+            market.to_account_info(),
+            seeds,
+            proceeds,
+        )?;
+
+        market.total_collateral_locked = market
+            .total_collateral_locked
+            .checked_sub(proceeds)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        emit_cpi!(LmsrTraded {
+            market_id,
+            user: ctx.accounts.user.key(),
+            side: OrderSide::Sell,
+            token_type,
+            shares,
+            collateral_amount: proceeds,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Sold {} outcome tokens to the LMSR for {}", shares, proceeds);
+        Ok(())
+    }
+
+    /// After settlement the creator takes back whatever the LMSR holds beyond what its
+    /// winning tokens will claim. On a voided market it keeps half a unit per outstanding share
+    pub fn withdraw_lmsr_subsidy(ctx: Context<WithdrawLmsrSubsidy>, market_id: u32) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let lmsr = &mut ctx.accounts.lmsr;
+        require!(
+            !lmsr.subsidy_withdrawn,
+            PredictionMarketError::SubsidyAlreadyWithdrawn
+        );
+
+        let amount = if market.status == MarketStatus::Voided {
+            lmsr.void_leftover()
+        } else {
+            require!(market.is_settled, PredictionMarketError::MarketNotSettled);
+            let winning_outcome = market
+                .winning_outcome
+                .ok_or(PredictionMarketError::WinningOutcomeNotSet)?;
+            lmsr.leftover(winning_outcome)
+        }
+        .ok_or(PredictionMarketError::MathOverflow)?;
+        lmsr.subsidy_withdrawn = true;
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.creator,
+            user_collateral: ctx.accounts.creator_collateral.as_deref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.pay_out(
+            ctx.accounts.native_unwrap.as_deref(),
+            market.to_account_info(),
+            seeds,
+            amount,
+        )?;
+
+        market.total_collateral_locked = market
+            .total_collateral_locked
+            .checked_sub(amount)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        emit_cpi!(LmsrSubsidyWithdrawn {
+            market_id,
+            creator: lmsr.creator,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Returned {} of the LMSR subsidy to the creator", amount);
+        Ok(())
+    }
+
+    // ---------------- Liquidity Rewards ----------------
+
+    /// Opens a rewards program for makers on the market's book. The sponsor deposits
+    /// rewards_per_epoch * epochs of collateral up front, the first epoch starts now
+    pub fn create_liquidity_rewards(
+        ctx: Context<CreateLiquidityRewards>,
+        market_id: u32,
+        params: LiquidityRewardsParams,
+    ) -> Result<()> {
+        let LiquidityRewardsParams {
+            rewards_per_epoch,
+            epoch_duration,
+            epochs,
+            max_spread_bps,
+            sample_interval,
+            min_size,
+        } = params;
+        let market = &ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            rewards_per_epoch > 0
+                && epochs > 0
+                && epoch_duration > 0
+                && sample_interval > 0
+                && sample_interval <= epoch_duration
+                && max_spread_bps > 0
+                && max_spread_bps <= MAX_FEE_BPS
+                && min_size > 0,
+            PredictionMarketError::InvalidRewardParams
+        );
+        market.require_status(&[MarketStatus::Active])?;
+        require!(
+            !market.is_settled,
+            PredictionMarketError::MarketAlreadySettled
+        );
+        require!(
+            now < market.settlement_deadline,
+            PredictionMarketError::MarketExpired
+        );
+
+        let total = rewards_per_epoch
+            .checked_mul(epochs as u64)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.sponsor,
+            user_collateral: ctx.accounts.sponsor_collateral.as_deref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.reward_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.deposit(&ctx.accounts.system_program, total)?;
+
+        let rewards = &mut ctx.accounts.rewards;
+        rewards.market_id = market_id;
+        rewards.sponsor = ctx.accounts.sponsor.key();
+        rewards.reward_vault = ctx.accounts.reward_vault.key();
+        rewards.rewards_per_epoch = rewards_per_epoch;
+        rewards.epoch_duration = epoch_duration;
+        rewards.epochs = epochs;
+        rewards.start_time = now;
+        rewards.max_spread_bps = max_spread_bps;
+        rewards.sample_interval = sample_interval;
+        rewards.min_size = min_size;
+        rewards.last_sample = now;
+        rewards.epoch = 0;
+        rewards.total_score = 0;
+        rewards.total_claimable = 0;
+        rewards.makers = Vec::new();
+        rewards.bump = ctx.bumps.rewards;
+        rewards
+            .end_time()
+            .ok_or(PredictionMarketError::InvalidRewardParams)?;
+
+        emit_cpi!(LiquidityRewardsCreated {
+            market_id,
+            sponsor: rewards.sponsor,
+            rewards_per_epoch,
+            epoch_duration,
+            epochs,
+            max_spread_bps,
+            sample_interval,
+            min_size,
+            timestamp: now,
+        });
+
+        msg!(
+            "Liquidity rewards of {} over {} epochs for market {}",
+            total,
+            epochs,
+            market_id
+        );
+        Ok(())
+    }
+
+    /// Permissionless crank: closes the open epoch once it's over, and samples the book at
+    /// most once per sample_interval while the market is active. Fails if neither is due
+    pub fn crank_liquidity_rewards(
+        ctx: Context<CrankLiquidityRewards>,
+        market_id: u32,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let rewards = &mut ctx.accounts.rewards;
+        let now = Clock::get()?.unix_timestamp;
+
+        let closed = rewards
+            .roll_epoch(now, market.is_trading_over(now))
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        if let Some((epoch, distributed)) = closed {
+            emit_cpi!(LiquidityRewardsEpochClosed {
+                market_id,
+                sponsor: rewards.sponsor,
+                epoch,
+                distributed,
+                timestamp: now,
+            });
+        }
+
+        // Paused and close-only books don't earn, their makers keep what they scored so far
+        let sample_due = !rewards.is_over()
+            && market.status == MarketStatus::Active
+            && now >= rewards.last_sample.saturating_add(rewards.sample_interval);
+        if !sample_due {
+            require!(closed.is_some(), PredictionMarketError::RewardCrankNotDue);
+            return Ok(());
+        }
+
+        let score = rewards
+            .sample(&ctx.accounts.orderbook)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        rewards.last_sample = now;
+
+        emit_cpi!(LiquidityRewardsSampled {
+            market_id,
+            sponsor: rewards.sponsor,
+            epoch: rewards.epoch,
+            score,
+            timestamp: now,
+        });
+
+        msg!("Sampled the book for epoch {}, score {}", rewards.epoch, score);
+        Ok(())
+    }
+
+    /// Pays the maker everything the closed epochs credited to them
+    pub fn claim_liquidity_rewards(
+        ctx: Context<ClaimLiquidityRewards>,
+        market_id: u32,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let rewards = &mut ctx.accounts.rewards;
+        let maker = ctx.accounts.maker.key();
+
+        let index = rewards
+            .makers
+            .iter()
+            .position(|entry| entry.maker == maker && entry.claimable > 0)
+            .ok_or(PredictionMarketError::NoRewardsToClaim)?;
+        let amount = rewards.makers[index].claimable;
+        // Nothing left to track once the open epoch hasn't scored them either, the slot frees up
+        if rewards.makers[index].score == 0 {
+            rewards.makers.remove(index);
+        } else {
+            rewards.makers[index].claimable = 0;
+        }
+        rewards.total_claimable = rewards
+            .total_claimable
+            .checked_sub(amount)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.maker,
+            user_collateral: ctx.accounts.maker_collateral.as_deref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.reward_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.pay_out(
+            ctx.accounts.native_unwrap.as_deref(),
+            market.to_account_info(),
+            seeds,
+            amount,
+        )?;
+
+        emit_cpi!(LiquidityRewardsClaimed {
+            market_id,
+            sponsor: rewards.sponsor,
+            maker,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Paid {} of liquidity rewards to {}", amount, maker);
+        Ok(())
+    }
+
+    /// Returns what the program didn't pay out to the sponsor once every epoch is closed,
+    /// closing the last one first if it's due. Unclaimed maker rewards stay in the vault
+    pub fn withdraw_liquidity_rewards(
+        ctx: Context<WithdrawLiquidityRewards>,
+        market_id: u32,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let rewards = &mut ctx.accounts.rewards;
+        let now = Clock::get()?.unix_timestamp;
+
+        if let Some((epoch, distributed)) = rewards
+            .roll_epoch(now, market.is_trading_over(now))
+            .ok_or(PredictionMarketError::MathOverflow)?
+        {
+            emit_cpi!(LiquidityRewardsEpochClosed {
+                market_id,
+                sponsor: rewards.sponsor,
+                epoch,
+                distributed,
+                timestamp: now,
+            });
+        }
+        require!(
+            rewards.is_over(),
+            PredictionMarketError::RewardProgramActive
+        );
+
+        let amount = ctx
+            .accounts
+            .reward_vault
+            .amount
+            .checked_sub(rewards.total_claimable)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        require!(amount > 0, PredictionMarketError::InvalidAmount);
+
+        let market_id_bytes = market.market_id.to_le_bytes();
+        let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.sponsor,
+            user_collateral: ctx.accounts.sponsor_collateral.as_deref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.reward_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.pay_out(
+            ctx.accounts.native_unwrap.as_deref(),
+            market.to_account_info(),
+            seeds,
+            amount,
+        )?;
+
+        emit_cpi!(LiquidityRewardsWithdrawn {
+            market_id,
+            sponsor: rewards.sponsor,
+            amount,
+            timestamp: now,
+        });
+
+        msg!("Returned {} of unpaid liquidity rewards to the sponsor", amount);
+        Ok(())
+    }
+}
+
+// Moves `amount` outcome tokens out of an AMM reserve, signed by the market that owns it
+fn transfer_from_reserve<'info>(
+    outcome_token_program: &Program<'info, token_interface::Token2022>,
+    reserve: &InterfaceAccount<'info, token_interface::TokenAccount>,
+    mint: &InterfaceAccount<'info, token_interface::Mint>,
+    to: &InterfaceAccount<'info, token_interface::TokenAccount>,
+    market: AccountInfo<'info>,
+    signer_seeds: &[&[u8]],
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    token_2022::transfer_checked(
+        CpiContext::new_with_signer(
+            outcome_token_program.to_account_info(),
+            token_2022::TransferChecked {
+                from: reserve.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: market,
+            },
+            &[signer_seeds],
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+// Fills in a new Market and its OrderBook, returns the market id taken from the counter
+fn init_market_accounts(
+    accounts: &mut InitializeMarket,
+    bumps: &InitializeMarketBumps,
+    settlement_deadline: i64,
+    metadata: MarketMetadata,
+    market_type: MarketType,
+) -> Result<u32> {
+    require!(
+        settlement_deadline > Clock::get()?.unix_timestamp,
+        PredictionMarketError::InvalidSettlementDeadline
+    );
+
+    let market_id = accounts.market_counter.take_next_id()?;
+
+    let market = &mut accounts.market;
+    market.authority = accounts.authority.key();
+    market.market_id = market_id;
+    market.settlement_deadline = settlement_deadline;
+    market.collateral_mint = accounts.collateral_mint.key();
+    market.collateral_vault = accounts.collateral_vault.key();
+    market.fee_vault = accounts.fee_vault.key();
+    market.taker_fee_bps = accounts.config.taker_fee_bps;
+    market.maker_fee_bps = accounts.config.maker_fee_bps;
+    market.protocol_fees_accrued = 0;
+    market.creator_fees_accrued = 0;
+    market.outcome_yes_mint = accounts.outcome_yes_mint.key();
+    market.outcome_no_mint = accounts.outcome_no_mint.key();
+    market.yes_escrow = accounts.yes_escrow.key();
+    market.no_escrow = accounts.no_escrow.key();
+    market.is_settled = false;
+    market.winning_outcome = None;
+    market.total_collateral_locked = 0;
+    market.market_type = market_type;
+    market.scalar_low = 0;
+    market.scalar_high = 0;
+    market.resolved_value = None;
+    market.market_group = None;
+    market.status = MarketStatus::Active;
+    market.trading_started = false;
+    market.amm = None;
+    market.set_metadata(metadata)?;
+    market.bump = bumps.market;
+
+    let orderbook = &mut accounts.orderbook;
+    orderbook.bump = bumps.orderbook;
+    orderbook.market_id = market_id;
+    orderbook.next_order_id = 0;
+    orderbook.yes_buy_orders = Vec::new();
+    orderbook.yes_sell_orders = Vec::new();
+    orderbook.no_buy_orders = Vec::new();
+    orderbook.no_sell_orders = Vec::new();
+
+    init_outcome_metadata(accounts, bumps.market, market_type)?;
+
+    Ok(market_id)
+}
+
+// Writes name ("M12-YES"), symbol and the market's metadata URL into both outcome mints
+// The metadata pointer of each mint points at the mint itself, so no extra account is needed
+fn init_outcome_metadata(
+    accounts: &InitializeMarket,
+    market_bump: u8,
+    market_type: MarketType,
+) -> Result<()> {
+    let market = &accounts.market;
+    let (yes_label, no_label) = match market_type {
+        MarketType::Binary => ("YES", "NO"),
+        MarketType::Scalar => ("LONG", "SHORT"),
+    };
+
+    let market_id_bytes = market.market_id.to_le_bytes();
+    let seeds = &[b"market", market_id_bytes.as_ref(), &[market_bump]];
+
+    for (mint, label) in [
+        (accounts.outcome_yes_mint.to_account_info(), yes_label),
+        (accounts.outcome_no_mint.to_account_info(), no_label),
+    ] {
+        let metadata = TokenMetadata {
+            update_authority: OptionalNonZeroPubkey::try_from(Some(market.key()))?,
+            mint: mint.key(),
+            name: format!("M{}-{}", market.market_id, label),
+            symbol: label.to_string(),
+            uri: market.meta_data_url.clone(),
+            additional_metadata: Vec::new(),
+        };
+
+        // Token-2022 reallocs the mint for the metadata but doesn't fund it
+        fund_realloc(
+            accounts.authority.to_account_info(),
+            mint.clone(),
+            accounts.system_program.to_account_info(),
+            mint.data_len() + metadata.tlv_size_of()?,
+        )?;
+
+        token_interface::token_metadata_initialize(
+            CpiContext::new_with_signer(
+                accounts.outcome_token_program.to_account_info(),
+                TokenMetadataInitialize {
+                    program_id: accounts.outcome_token_program.to_account_info(),
+                    metadata: mint.clone(),
+                    update_authority: market.to_account_info(),
+                    mint_authority: market.to_account_info(),
+                    mint,
+                },
+                &[seeds],
+            ),
+            metadata.name,
+            metadata.symbol,
+            metadata.uri,
+        )?;
+    }
+
+    Ok(())
+}
+
+// Moves `amount` from the market's fee vault to the recipient, the caller zeroes what was owed
+fn withdraw_fees<'info>(
+    market: &Account<'info, Market>,
+    fee_vault: &InterfaceAccount<'info, token_interface::TokenAccount>,
+    mint: &InterfaceAccount<'info, token_interface::Mint>,
+    recipient_collateral: &InterfaceAccount<'info, token_interface::TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<()> {
+    require!(amount > 0, PredictionMarketError::InvalidAmount);
+
+    let market_id_bytes = market.market_id.to_le_bytes();
+    let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            token_interface::TransferChecked {
+                from: fee_vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: recipient_collateral.to_account_info(),
+                authority: market.to_account_info(),
+            },
+            &[seeds],
+        ),
+        amount,
+        mint.decimals,
+    )?;
+
+    msg!("Withdrew {} in fees from market {}", amount, market.market_id);
+    Ok(())
+}
+
+// Rolls a maker's TraderVolume (from remaining_accounts) forward and adds the fill,
+// returns the volume from before the fill, which decides the maker's tier
+fn record_maker_volume(
+    info: &AccountInfo,
+    maker: &Pubkey,
+    amount: u64,
+    now: i64,
+    window: i64,
+) -> Result<u64> {
+    // Only this program writes TraderVolume accounts, one per owner
+    require_keys_eq!(
+        *info.owner,
+        crate::ID,
+        PredictionMarketError::MakerVolumeAccountNotProvided
+    );
+    let mut data = info.try_borrow_mut_data()?;
+    let mut volume = TraderVolume::try_deserialize(&mut &data[..])?;
+    require_keys_eq!(
+        volume.owner,
+        *maker,
+        PredictionMarketError::MakerVolumeAccountNotProvided
+    );
+
+    volume.roll(now, window);
+    let rolling_volume = volume.rolling_volume();
+    volume.add(amount)?;
+
+    let mut writer = &mut data[..];
+    volume.try_serialize(&mut writer)?;
+    Ok(rolling_volume)
+}
+
+// Taker side of the fills of one place_order or market_order. Without stats the caller
+// settles the taker's side of every fill itself, as split_and_sell and buy_and_merge do
+struct BookTaker<'a> {
+    stats: Option<&'a mut UserStats>,
+    volume_account: Pubkey,
+    rolling_volume: u64, // Before this order, decides the taker's tier
+    fee_bps: u16,
+    token_type: TokenType,
+    is_buy_order: bool,
+}
+
+// Collateral and fees of one fill, the caller moves the fees out of the vault in one transfer
+struct BookFill {
+    collateral_amount: u64,
+    taker_fee: u64,
+    maker_fee: u64,
+    maker_rebate: u64,
+}
+
+// Fills `quantity` of a resting order at its price and credits both sides' UserStats.
+// The maker's UserStats and TraderVolume are the next pair taken from `maker_accounts`
+fn settle_book_fill<'a, 'info: 'a>(
+    maker_accounts: &mut impl Iterator<Item = &'a AccountInfo<'info>>,
+    maker_order: &mut Order,
+    taker: &mut BookTaker,
+    market: &Market,
+    config: &ProtocolConfig,
+    quantity: u64,
+    now: i64,
+) -> Result<BookFill> {
+    let is_buy_order = taker.is_buy_order;
+    maker_order.filledquantity = maker_order
+        .filledquantity
+        .checked_add(quantity)
+        .ok_or(PredictionMarketError::MathOverflow)?;
+
+    let collateral_amount = quantity
+        .checked_mul(maker_order.price)
+        .ok_or(PredictionMarketError::MathOverflow)?;
+
+    // Maker UserStats and TraderVolume accounts must be passed in remaining_accounts in match order
+    let maker_stats_info = maker_accounts.next().ok_or(if is_buy_order {
+        PredictionMarketError::SellerStatsAccountNotProvided
+    } else {
+        PredictionMarketError::BuyerStatsAccountNotProvided
+    })?;
+    require_keys_eq!(
+        maker_stats_info.key(),
+        maker_order.user_stats,
+        if is_buy_order {
+            PredictionMarketError::SellerStatsAccountNotProvided
+        } else {
+            PredictionMarketError::BuyerStatsAccountNotProvided
+        }
+    );
+    let maker_volume_info = maker_accounts
+        .next()
+        .ok_or(PredictionMarketError::MakerVolumeAccountNotProvided)?;
+    // Crossing your own order, the taker side already counts the volume
+    let maker_volume = if maker_volume_info.key() == taker.volume_account {
+        taker.rolling_volume
+    } else {
+        record_maker_volume(
+            maker_volume_info,
+            &maker_order.user_key,
+            collateral_amount,
+            now,
+            config.volume_window,
+        )?
+    };
+    let (_, maker_fee_bps) = market.fee_rates(config, maker_volume);
+
+    let taker_fee = fee_amount(collateral_amount, taker.fee_bps)
+        .ok_or(PredictionMarketError::MathOverflow)?;
+    // Rebates are paid out of the same fill's taker fee, never out of the vault
+    let (mut maker_fee, maker_rebate) = if maker_fee_bps >= 0 {
+        let fee = fee_amount(collateral_amount, maker_fee_bps as u16)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        (fee, 0)
+    } else {
+        let rebate = fee_amount(collateral_amount, maker_fee_bps.unsigned_abs())
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        (0, rebate.min(taker_fee))
+    };
+
+    let mut data = maker_stats_info.try_borrow_mut_data()?;
+    let mut maker_stats = UserStats::try_deserialize(&mut &data[..])?;
+
+    if is_buy_order {
+        // When user is BUYER - credit YES/NO tokens
+        match (taker.stats.as_deref_mut(), taker.token_type) {
+            (Some(stats), TokenType::Yes) => {
+                stats.claimable_yes = stats
+                    .claimable_yes
+                    .checked_add(quantity)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+            }
+            (Some(stats), TokenType::No) => {
+                stats.claimable_no = stats
+                    .claimable_no
+                    .checked_add(quantity)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+            }
+            (None, _) => {}
+        }
+
+        // Credit SELLER (from matching order) with collateral, the maker seller's fee comes
+        // out of their proceeds
+        maker_stats.claimable_collateral = collateral_amount
+            .checked_sub(maker_fee)
+            .and_then(|proceeds| proceeds.checked_add(maker_rebate))
+            .and_then(|proceeds| maker_stats.claimable_collateral.checked_add(proceeds))
+            .ok_or(PredictionMarketError::MathOverflow)?;
+    } else {
+        // When user is SELLER - credit collateral minus the taker fee
+        if let Some(stats) = taker.stats.as_deref_mut() {
+            stats.claimable_collateral = collateral_amount
+                .checked_sub(taker_fee)
+                .and_then(|proceeds| stats.claimable_collateral.checked_add(proceeds))
+                .ok_or(PredictionMarketError::MathOverflow)?;
+        }
+
+        // The maker buyer's fee was reserved with the order, orders placed at a
+        // lower fee rate only pay what they reserved
+        maker_fee = maker_fee.min(maker_order.fee_reserved);
+        maker_order.fee_reserved -= maker_fee;
+
+        // A completed order gives back what's left of its reserve: the rounding dust of its
+        // fills, or all of it when the maker's rate dropped while the order rested
+        let released_fee = if maker_order.filledquantity == maker_order.quantity {
+            std::mem::take(&mut maker_order.fee_reserved)
+        } else {
+            0
+        };
+
+        // Credit BUYER (from matching order) with YES/NO tokens
+        maker_stats.locked_collateral = maker_stats
+            .locked_collateral
+            .checked_sub(maker_fee)
+            .and_then(|locked| locked.checked_sub(released_fee))
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        maker_stats.claimable_collateral = maker_stats
+            .claimable_collateral
+            .checked_add(maker_rebate)
+            .and_then(|claimable| claimable.checked_add(released_fee))
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        match taker.token_type {
+            TokenType::Yes => {
+                maker_stats.claimable_yes = maker_stats
+                    .claimable_yes
+                    .checked_add(quantity)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+            }
+            TokenType::No => {
+                maker_stats.claimable_no = maker_stats
+                    .claimable_no
+                    .checked_add(quantity)
+                    .ok_or(PredictionMarketError::MathOverflow)?;
+            }
+        }
+    }
+
+    let mut writer = &mut data[..];
+    maker_stats.try_serialize(&mut writer)?;
+
+    Ok(BookFill {
+        collateral_amount,
+        taker_fee,
+        maker_fee,
+        maker_rebate,
+    })
+}
+
+// split_and_sell (side Sell) and buy_and_merge (side Buy) against the `token_type` book. The
+// taker's fills are settled right here: a sell is backed by splitting the filled quantity, a buy
+// is burned straight out of escrow with the user's other outcome, and only the net collateral
+// moves between the user and the vault
+fn split_merge_order(
+    ctx: Context<SplitMergeOrder>,
+    market_id: u32,
+    side: OrderSide,
+    params: SplitMergeParams,
+) -> Result<()> {
+    let SplitMergeParams {
+        token_type,
+        quantity,
+        limit_price,
+        max_iteration,
+    } = params;
+    let market = &mut ctx.accounts.market;
+    let orderbook = &mut ctx.accounts.orderbook;
+    let user = ctx.accounts.user.key();
+    let now = Clock::get()?.unix_timestamp;
+    let is_buy_order = side == OrderSide::Buy;
+
+    require!(
+        now < market.settlement_deadline,
+        PredictionMarketError::MarketExpired
+    );
+    require!(
+        !market.is_settled,
+        PredictionMarketError::MarketAlreadySettled
+    );
+    // Buying back into a merge can only reduce a position, a split opens one
+    if is_buy_order {
+        market.require_status(&[MarketStatus::Active, MarketStatus::CloseOnly])?;
+    } else {
+        market.require_status(&[MarketStatus::Active])?;
+    }
+    require!(quantity > 0, PredictionMarketError::InvalidOrderQuantity);
+    require!(limit_price > 0, PredictionMarketError::InvalidOrderPrice);
+
+    let (token_mint, token_escrow, other_mint, user_other_account) = match token_type {
+        TokenType::Yes => (
+            &ctx.accounts.outcome_yes_mint,
+            &ctx.accounts.yes_escrow,
+            &ctx.accounts.outcome_no_mint,
+            &ctx.accounts.user_outcome_no,
+        ),
+        TokenType::No => (
+            &ctx.accounts.outcome_no_mint,
+            &ctx.accounts.no_escrow,
+            &ctx.accounts.outcome_yes_mint,
+            &ctx.accounts.user_outcome_yes,
+        ),
+    };
+    if is_buy_order {
+        require!(
+            user_other_account.amount >= quantity,
+            PredictionMarketError::NotEnoughBalance
+        );
+    }
+
+    // Fills reference it like a placed order's id
+    let order_id = orderbook.next_order_id;
+    orderbook.next_order_id += 1;
+
+    // Fee tiers go by the volume traded before this order
+    let trader_volume = &mut ctx.accounts.trader_volume;
+    if trader_volume.owner == Pubkey::default() {
+        trader_volume.owner = user;
+        trader_volume.window_start = now;
+        trader_volume.bump = ctx.bumps.trader_volume;
+    }
+    trader_volume.roll(now, ctx.accounts.config.volume_window);
+    let (taker_fee_bps, _) = market.fee_rates(&ctx.accounts.config, trader_volume.rolling_volume());
+
+    let matching_orders = match (token_type, side) {
+        (TokenType::Yes, OrderSide::Buy) => &mut orderbook.yes_sell_orders,
+        (TokenType::Yes, OrderSide::Sell) => &mut orderbook.yes_buy_orders,
+        (TokenType::No, OrderSide::Buy) => &mut orderbook.no_sell_orders,
+        (TokenType::No, OrderSide::Sell) => &mut orderbook.no_buy_orders,
+    };
+    let mut taker = BookTaker {
+        stats: None,
+        volume_account: ctx.accounts.trader_volume.key(),
+        rolling_volume: ctx.accounts.trader_volume.rolling_volume(),
+        fee_bps: taker_fee_bps,
+        token_type,
+        is_buy_order,
+    };
+    let mut maker_accounts = ctx.remaining_accounts.iter();
+
+    let mut filled: u64 = 0;
+    let mut iteration = 0;
+    let mut book_collateral: u64 = 0;
+    let mut taker_fees: u64 = 0;
+    let mut collected_fees: u64 = 0;
+
+    while filled < quantity && iteration <= max_iteration {
+        let Some(book_order) = matching_orders.first_mut() else {
+            break;
+        };
+        if (is_buy_order && book_order.price > limit_price)
+            || (!is_buy_order && book_order.price < limit_price)
+        {
+            break;
+        }
+        let fill_quantity =
+            (quantity - filled).min(book_order.quantity - book_order.filledquantity);
+
+        let BookFill {
+            collateral_amount,
+            taker_fee,
+            maker_fee,
+            maker_rebate,
+        } = settle_book_fill(
+            &mut maker_accounts,
+            book_order,
+            &mut taker,
+            market,
+            &ctx.accounts.config,
+            fill_quantity,
+            now,
+        )?;
+
+        emit_cpi!(OrderFilled {
+            market_id,
+            taker_order_id: order_id,
+            maker_order_id: book_order.id,
+            taker: user,
+            maker: book_order.user_key,
+            taker_side: side,
+            token_type,
+            price: book_order.price,
+            quantity: fill_quantity,
+            collateral_amount,
+            taker_fee,
+            maker_fee,
+            maker_rebate,
+            timestamp: now,
+        });
+
+        filled += fill_quantity;
+        book_collateral = book_collateral
+            .checked_add(collateral_amount)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        taker_fees = taker_fees
+            .checked_add(taker_fee)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        collected_fees = taker_fee
+            .checked_add(maker_fee)
+            .and_then(|fees| fees.checked_sub(maker_rebate))
+            .and_then(|fees| collected_fees.checked_add(fees))
+            .ok_or(PredictionMarketError::MathOverflow)?;
+
+        if book_order.filledquantity == book_order.quantity {
+            matching_orders.remove(0);
+        }
+        iteration += 1;
+    }
+
+    ctx.accounts.trader_volume.add(book_collateral)?;
+
+    let market_id_bytes = market.market_id.to_le_bytes();
+    let seeds = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+
+    if filled > 0 {
+        if is_buy_order {
+            // The bought tokens never leave escrow, the makers' side of each pair burns there
+            token_2022::burn(
+                CpiContext::new_with_signer(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    token_2022::Burn {
+                        mint: token_mint.to_account_info(),
+                        from: token_escrow.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                filled,
+            )?;
+            token_2022::burn(
+                CpiContext::new(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    token_2022::Burn {
+                        mint: other_mint.to_account_info(),
+                        from: user_other_account.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                filled,
+            )?;
+
+            market.total_collateral_locked = market
+                .total_collateral_locked
+                .checked_sub(filled)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+        } else {
+            // The sold side goes to escrow, where it backs the makers' claimable tokens
+            token_2022::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    token_2022::MintTo {
+                        mint: token_mint.to_account_info(),
+                        to: token_escrow.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                filled,
+            )?;
+            token_2022::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.outcome_token_program.to_account_info(),
+                    token_2022::MintTo {
+                        mint: other_mint.to_account_info(),
+                        to: user_other_account.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    &[seeds],
+                ),
+                filled,
+            )?;
+
+            market.total_collateral_locked = market
+                .total_collateral_locked
+                .checked_add(filled)
+                .ok_or(PredictionMarketError::MathOverflow)?;
+            market.trading_started = true;
+        }
+    }
+
+    // A split takes `filled` and the sale pays the fills net of fees, a merge pays `filled`
+    // and the purchase takes the fills plus fees. Only the difference moves
+    let book_amount = if is_buy_order {
+        book_collateral.checked_add(taker_fees)
+    } else {
+        book_collateral.checked_sub(taker_fees)
+    }
+    .ok_or(PredictionMarketError::MathOverflow)?;
+    let (paid_in, paid_out) = if is_buy_order {
+        (book_amount, filled)
+    } else {
+        (filled, book_amount)
+    };
+    let collateral = CollateralAccounts {
+        user: &ctx.accounts.user,
+        user_collateral: ctx.accounts.user_collateral.as_ref(),
+        mint: &ctx.accounts.collateral_mint,
+        vault: &ctx.accounts.collateral_vault,
+        token_program: &ctx.accounts.token_program,
+    };
+    if paid_in > paid_out {
+        collateral.deposit(&ctx.accounts.system_program, paid_in - paid_out)?;
+    }
+    // The native SOL account is closed either way
+    if paid_out > paid_in || ctx.accounts.native_unwrap.is_some() {
+        collateral.pay_out(
+            ctx.accounts.native_unwrap.as_deref(),
+            market.to_account_info(),
+            seeds,
+            paid_out.saturating_sub(paid_in),
+        )?;
+    }
+
+    // One transfer moves every fee of this order out of the vault, rebates stay behind. There
+    // is no UserStats here to record a referrer, so the protocol keeps the referral share
+    if collected_fees > 0 {
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: ctx.accounts.collateral_vault.to_account_info(),
+                    mint: ctx.accounts.collateral_mint.to_account_info(),
+                    to: ctx.accounts.fee_vault.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                &[seeds],
+            ),
+            collected_fees,
+            ctx.accounts.collateral_mint.decimals,
+        )?;
+        market.accrue_fees(collected_fees, ctx.accounts.config.creator_fee_share_bps)?;
+    }
+
+    if is_buy_order {
+        if filled > 0 {
+            emit_cpi!(TokensMerged {
+                market_id,
+                user,
+                amount: filled,
+                timestamp: now,
+            });
+        }
+        emit_cpi!(BoughtAndMerged {
+            market_id,
+            order_id,
+            user,
+            token_type,
+            quantity: filled,
+            cost: book_amount,
+            timestamp: now,
+        });
+    } else {
+        if filled > 0 {
+            emit_cpi!(TokensSplit {
+                market_id,
+                user,
+                amount: filled,
+                timestamp: now,
+            });
+        }
+        emit_cpi!(SplitAndSold {
+            market_id,
+            order_id,
+            user,
+            token_type,
+            quantity: filled,
+            proceeds: book_amount,
+            timestamp: now,
+        });
+    }
+
+    msg!(
+        "Filled {} of {} against the book, {} collateral in and {} out",
+        filled,
+        quantity,
+        paid_in,
+        paid_out
+    );
+    Ok(())
+}
+
+fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == token::spl_token::native_mint::ID || *mint == token_2022::spl_token_2022::native_mint::ID
+}
+
+// Collateral side of a binary/scalar market instruction. user_collateral is None when the
+// market is native SOL and the user pays and gets paid in lamports instead of wSOL
+struct CollateralAccounts<'a, 'info> {
+    user: &'a Signer<'info>,
+    user_collateral: Option<&'a InterfaceAccount<'info, token_interface::TokenAccount>>,
+    mint: &'a InterfaceAccount<'info, token_interface::Mint>,
+    vault: &'a InterfaceAccount<'info, token_interface::TokenAccount>,
+    token_program: &'a Interface<'info, TokenInterface>,
+}
+
+impl<'info> CollateralAccounts<'_, 'info> {
+    // Moves `amount` from the user into the vault, native SOL goes in as lamports and gets synced
+    fn deposit(&self, system_program: &Program<'info, System>, amount: u64) -> Result<()> {
+        match self.user_collateral {
+            Some(user_collateral) => token_interface::transfer_checked(
+                CpiContext::new(
+                    self.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: user_collateral.to_account_info(),
+                        mint: self.mint.to_account_info(),
+                        to: self.vault.to_account_info(),
+                        authority: self.user.to_account_info(),
+                    },
+                ),
+                amount,
+                self.mint.decimals,
+            ),
+            None => {
+                require!(
+                    is_native_mint(&self.mint.key()),
+                    PredictionMarketError::InvalidCollateralAccounts
+                );
+                system_program::transfer(
+                    CpiContext::new(
+                        system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: self.user.to_account_info(),
+                            to: self.vault.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+                token_interface::sync_native(CpiContext::new(
+                    self.token_program.to_account_info(),
+                    token_interface::SyncNative {
+                        account: self.vault.to_account_info(),
+                    },
+                ))
+            }
+        }
+    }
+
+    // Pays `amount` out of the vault, signed by the market. Native SOL is paid through
+    // native_unwrap: the wSOL moves there and the account is closed to the user
+    fn pay_out(
+        &self,
+        native_unwrap: Option<&InterfaceAccount<'info, token_interface::TokenAccount>>,
+        market: AccountInfo<'info>,
+        signer_seeds: &[&[u8]],
+        amount: u64,
+    ) -> Result<()> {
+        let to = match (self.user_collateral, native_unwrap) {
+            (Some(user_collateral), None) => user_collateral.to_account_info(),
+            (None, Some(native_unwrap)) if is_native_mint(&self.mint.key()) => {
+                native_unwrap.to_account_info()
+            }
+            _ => return err!(PredictionMarketError::InvalidCollateralAccounts),
+        };
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: self.vault.to_account_info(),
+                    mint: self.mint.to_account_info(),
+                    to: to.clone(),
+                    authority: market.clone(),
+                },
+                &[signer_seeds],
+            ),
+            amount,
+            self.mint.decimals,
+        )?;
+
+        if native_unwrap.is_some() {
+            token_interface::close_account(CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                token_interface::CloseAccount {
+                    account: to,
+                    destination: self.user.to_account_info(),
+                    authority: market,
+                },
+                &[signer_seeds],
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+// Tops up `account` so it stays rent exempt once it grows to `new_len` bytes
+fn fund_realloc<'info>(
+    payer: AccountInfo<'info>,
+    account: AccountInfo<'info>,
+    system_program_info: AccountInfo<'info>,
+    new_len: usize,
+) -> Result<()> {
+    let missing = Rent::get()?
+        .minimum_balance(new_len)
+        .saturating_sub(account.lamports());
+    if missing > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program_info,
+                system_program::Transfer {
+                    from: payer,
+                    to: account,
+                },
+            ),
+            missing,
+        )?;
+    }
+    Ok(())
+}
+
+// Collateral has to move 1:1 between users and vaults with nothing but a plain transfer_checked:
+// no transfer fees (vault accounting), no hook programs (extra accounts on every CPI),
+// no non-transferable mints and no permanent delegate that could pull funds out of a vault
+pub(crate) fn is_supported_collateral_mint(mint: &AccountInfo) -> bool {
+    if *mint.owner == token::ID {
+        return true;
+    }
+    let Ok(data) = mint.try_borrow_data() else {
+        return false;
+    };
+    let Ok(state) = StateWithExtensions::<MintState>::unpack(&data) else {
+        return false;
+    };
+    let Ok(extensions) = state.get_extension_types() else {
+        return false;
+    };
+    !extensions.iter().any(|extension| {
+        matches!(
+            extension,
+            ExtensionType::TransferFeeConfig
+                | ExtensionType::TransferHook
+                | ExtensionType::NonTransferable
+                | ExtensionType::PermanentDelegate
+        )
+    })
+}
+
+// Scalar payout: burns all of the user's LONG and SHORT tokens and pays their share of the range
+// Burns both outcome sides of the caller: scalar payouts, or the void refund
+fn claim_both_sides(ctx: Context<ClaimRewards>) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let voided = market.status == MarketStatus::Voided;
+
+    let long_amount = ctx.accounts.user_outcome_yes.amount;
+    let short_amount = ctx.accounts.user_outcome_no.amount;
+    require!(
+        long_amount > 0 || short_amount > 0,
+        PredictionMarketError::InvalidAmount
+    );
+
+    let payout = if voided {
+        Market::void_payout(long_amount, short_amount)
+    } else {
+        market.scalar_payout(long_amount, short_amount)
+    }
+    .ok_or(PredictionMarketError::MathOverflow)?;
+
+    for (mint, from, amount) in [
+        (
+            ctx.accounts.outcome_yes_mint.to_account_info(),
+            ctx.accounts.user_outcome_yes.to_account_info(),
+            long_amount,
+        ),
+        (
+            ctx.accounts.outcome_no_mint.to_account_info(),
+            ctx.accounts.user_outcome_no.to_account_info(),
+            short_amount,
+        ),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+        token_2022::burn(
+            CpiContext::new(
+                ctx.accounts.outcome_token_program.to_account_info(),
+                token_2022::Burn {
+                    mint,
+                    from,
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+    }
+
+    let market_id_bytes = market.market_id.to_le_bytes();
+    let signer = &[b"market", market_id_bytes.as_ref(), &[market.bump]];
+
+    if payout > 0 {
+        let collateral = CollateralAccounts {
+            user: &ctx.accounts.user,
+            user_collateral: ctx.accounts.user_collateral.as_ref(),
+            mint: &ctx.accounts.collateral_mint,
+            vault: &ctx.accounts.collateral_vault,
+            token_program: &ctx.accounts.token_program,
+        };
+        collateral.pay_out(
+            ctx.accounts.native_unwrap.as_ref(),
+            market.to_account_info(),
+            signer,
+            payout,
+        )?;
+    }
+
+    market.total_collateral_locked = market
+        .total_collateral_locked
+        .checked_sub(payout)
+        .ok_or(PredictionMarketError::MathOverflow)?;
+
+    if voided {
+        emit_cpi!(VoidRefundClaimed {
+            market_id: market.market_id,
+            user: ctx.accounts.user.key(),
+            yes_amount: long_amount,
+            no_amount: short_amount,
+            payout,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Refunded {} collateral on voided market", payout);
+        return Ok(());
+    }
+
+    emit_cpi!(ScalarRewardsClaimed {
+        market_id: market.market_id,
+        user: ctx.accounts.user.key(),
+        long_amount,
+        short_amount,
+        payout,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Claimed {} collateral for scalar positions", payout);
+    Ok(())
+}
+
+// Loads the group's markets from remaining_accounts, `stride` accounts per market in group order
+// The first four of each chunk are checked: [market, collateral_vault, outcome_yes_mint, outcome_no_mint]
+fn group_market_accounts<'info>(
+    group: &MarketGroup,
+    remaining_accounts: &'info [AccountInfo<'info>],
+    stride: usize,
+) -> Result<Vec<(Account<'info, Market>, &'info [AccountInfo<'info>])>> {
+    require!(
+        remaining_accounts.len() == group.market_count as usize * stride,
+        PredictionMarketError::GroupAccountsMismatch
+    );
+
+    let group_key = Pubkey::find_program_address(
+        &[MARKET_GROUP_SEED, group.group_id.to_le_bytes().as_ref()],
+        &crate::ID,
+    )
+    .0;
+
+    let mut markets = Vec::with_capacity(group.market_count as usize);
+    for (index, accounts) in remaining_accounts.chunks(stride).enumerate() {
+        let market = Account::<Market>::try_from(&accounts[0])?;
+        require!(
+            market.market_id == group.market_ids[index]
+                && market.market_group == Some(group_key)
+                && accounts[1].key() == market.collateral_vault
+                && accounts[2].key() == market.outcome_yes_mint
+                && accounts[3].key() == market.outcome_no_mint,
+            PredictionMarketError::GroupAccountsMismatch
+        );
+        markets.push((market, accounts));
+    }
+
+    Ok(markets)
+}
+
+// Pairs up [outcome_mint, user_outcome_token_account] from remaining_accounts and checks them
+// against the market's outcome mints, in index order
+fn categorical_outcome_accounts<'info>(
+    market: &CategoricalMarket,
+    user: &Pubkey,
+    remaining_accounts: &'info [AccountInfo<'info>],
+) -> Result<Vec<(&'info AccountInfo<'info>, InterfaceAccount<'info, token_interface::TokenAccount>)>> {
+    require!(
+        remaining_accounts.len() == market.outcome_count as usize * 2,
+        PredictionMarketError::OutcomeAccountsMismatch
+    );
+
+    let mut outcomes = Vec::with_capacity(market.outcome_count as usize);
+    for (index, pair) in remaining_accounts.chunks(2).enumerate() {
+        let outcome_mint = &pair[0];
+        require_keys_eq!(
+            outcome_mint.key(),
+            market.outcome_mints[index],
+            PredictionMarketError::OutcomeAccountsMismatch
+        );
+
+        let user_outcome = InterfaceAccount::<token_interface::TokenAccount>::try_from(&pair[1])?;
+        require_keys_eq!(
+            user_outcome.mint,
+            outcome_mint.key(),
+            PredictionMarketError::OutcomeAccountsMismatch
+        );
+        require_keys_eq!(
+            user_outcome.owner,
+            *user,
+            PredictionMarketError::OutcomeAccountsMismatch
+        );
+
+        outcomes.push((outcome_mint, user_outcome));
+    }
+
+    Ok(outcomes)
 }
 
 // Things remaining
@@ -0,0 +1,183 @@
+// Fixed-point math for the LMSR cost function. Values are u128 scaled by WAD (1e18) and only
+// integer operations are used, so every validator computes the same bits
+
+pub const WAD: u128 = 1_000_000_000_000_000_000;
+pub const LN_2: u128 = 693_147_180_559_945_309; // ln(2) in WAD, rounded down
+pub const SQRT_WAD: u128 = 1_000_000_000;
+
+// e^(-x) for x >= 0, both in WAD. x = k * ln(2) + r with r < ln(2), so e^(-x) = e^(-r) / 2^k
+// and the Taylor series of e^(-r) converges in about 20 terms
+pub fn exp_neg(x: u128) -> u128 {
+    let k = x / LN_2;
+    if k >= 128 {
+        return 0;
+    }
+    let r = x - k * LN_2;
+
+    // Alternating series, the positive and negative terms are summed apart to stay unsigned
+    let (mut positive, mut negative) = (WAD, 0u128);
+    let mut term = WAD;
+    let mut n = 1;
+    loop {
+        term = term * r / WAD / n;
+        if term == 0 {
+            break;
+        }
+        if n % 2 == 1 {
+            negative += term;
+        } else {
+            positive += term;
+        }
+        n += 1;
+    }
+
+    (positive - negative) >> k
+}
+
+// ln(1 + y) for 0 <= y <= 1, both in WAD. Uses ln(1 + y) = 2 * atanh(z) with z = y / (2 + y),
+// z is at most 1/3 so every term of z + z^3/3 + z^5/5 + ... is 9 times smaller than the last
+pub fn ln_1p(y: u128) -> u128 {
+    let z = y * WAD / (2 * WAD + y);
+    let z_squared = z * z / WAD;
+
+    let mut sum = z;
+    let mut power = z;
+    let mut n = 3;
+    loop {
+        power = power * z_squared / WAD;
+        if power == 0 {
+            break;
+        }
+        sum += power / n;
+        n += 2;
+    }
+
+    2 * sum
+}
+
+// LMSR cost b * ln(e^(q_yes / b) + e^(q_no / b)) in WAD scaled collateral units, computed as
+// max(q_yes, q_no) + b * ln(1 + e^(-|q_yes - q_no| / b)) so the exponent never goes positive
+pub fn lmsr_cost(q_yes: i64, q_no: i64, liquidity: u64) -> Option<i128> {
+    if liquidity == 0 {
+        return None;
+    }
+
+    let gap = (q_yes as i128 - q_no as i128).unsigned_abs();
+    let tail = ln_1p(exp_neg(gap.checked_mul(WAD)? / liquidity as u128));
+    let max = q_yes.max(q_no) as i128;
+
+    max.checked_mul(WAD as i128)?
+        .checked_add(i128::try_from(liquidity as u128 * tail).ok()?)
+}
+
+// Marginal YES price 1 / (1 + e^(-(q_yes - q_no) / b)) in WAD, NO is WAD minus it
+pub fn lmsr_yes_price(q_yes: i64, q_no: i64, liquidity: u64) -> Option<u128> {
+    if liquidity == 0 {
+        return None;
+    }
+
+    let gap = q_yes as i128 - q_no as i128;
+    let odds = exp_neg(gap.unsigned_abs().checked_mul(WAD)? / liquidity as u128);
+    let lower = odds * WAD / (WAD + odds);
+    Some(if gap >= 0 { WAD - lower } else { lower })
+}
+
+// ln(p / (1 - p)) in WAD for a WAD price 0 < p < WAD, the LMSR share gap (over b) at which
+// the marginal price is p. The ratio is split as 2^k * (1 + m) so ln_1p only sees m < 1
+pub fn logit(price: u128) -> Option<i128> {
+    if price == 0 || price >= WAD {
+        return None;
+    }
+
+    let (numerator, denominator) = if price >= WAD / 2 {
+        (price, WAD - price)
+    } else {
+        (WAD - price, price)
+    };
+    let ratio = numerator.checked_mul(WAD)? / denominator;
+    let k = (ratio / WAD).ilog2();
+    let ln = k as u128 * LN_2 + ln_1p((ratio >> k) - WAD);
+
+    let ln = i128::try_from(ln).ok()?;
+    Some(if price >= WAD / 2 { ln } else { -ln })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::LmsrMaker;
+
+    // Series and division rounding lose a few units in the last place
+    const TOLERANCE: u128 = 1_000;
+
+    fn assert_close(actual: u128, expected: u128) {
+        assert!(
+            actual.abs_diff(expected) <= TOLERANCE,
+            "{actual} is not within {TOLERANCE} of {expected}"
+        );
+    }
+
+    #[test]
+    fn exp_neg_of_zero_is_one() {
+        assert_eq!(exp_neg(0), WAD);
+    }
+
+    #[test]
+    fn exp_neg_halves_every_ln_2() {
+        assert_close(exp_neg(LN_2), WAD / 2);
+        assert_close(exp_neg(10 * LN_2), WAD >> 10);
+    }
+
+    #[test]
+    fn exp_neg_underflows_to_zero_from_128_ln_2() {
+        assert_eq!(exp_neg(128 * LN_2), 0);
+        assert_eq!(exp_neg(128 * LN_2 + 1), 0);
+        assert_eq!(exp_neg(u128::MAX / 2), 0);
+    }
+
+    #[test]
+    fn ln_1p_of_one_is_ln_2() {
+        assert_eq!(ln_1p(0), 0);
+        assert_close(ln_1p(WAD), LN_2);
+    }
+
+    #[test]
+    fn logit_inverts_the_yes_price() {
+        let liquidity = 1_000_000u64;
+        for gap in [-5_000_000i64, -700_000, -1, 0, 1, 123_456, 2_000_000, 9_000_000] {
+            let price = lmsr_yes_price(gap, 0, liquidity).unwrap();
+            let recovered = logit(price).unwrap() * liquidity as i128 / WAD as i128;
+            // The price is only WAD precise, far from the midpoint one WAD unit of price spans
+            // more shares
+            assert!(
+                (recovered - gap as i128).abs() <= 1 + gap.unsigned_abs() as i128 / 1_000_000,
+                "gap {gap} came back as {recovered}"
+            );
+        }
+    }
+
+    #[test]
+    fn logit_rejects_prices_outside_zero_to_one() {
+        assert_eq!(logit(0), None);
+        assert_eq!(logit(WAD), None);
+        assert_eq!(logit(WAD / 2), Some(0));
+    }
+
+    #[test]
+    fn worst_case_loss_is_covered_by_the_subsidy() {
+        for liquidity in [1u64, 1_000, 1_000_000, 1_000_000_000_000] {
+            let subsidy = LmsrMaker::subsidy_for(liquidity).unwrap() as i128 * WAD as i128;
+            let start = lmsr_cost(0, 0, liquidity).unwrap();
+
+            // Traders buy q YES and YES wins: the maker collected C(q, 0) - C(0, 0) and owes q
+            for shares in [1i64, liquidity as i64, 10 * liquidity as i64, 200 * liquidity as i64] {
+                let collected = lmsr_cost(shares, 0, liquidity).unwrap() - start;
+                let loss = shares as i128 * WAD as i128 - collected;
+                assert!(
+                    loss <= subsidy,
+                    "b = {liquidity}, q = {shares}: loss {loss} over subsidy {subsidy}"
+                );
+            }
+        }
+    }
+}
@@ -1,6 +1,12 @@
 use anchor_lang::prelude::*;
 
-use crate::constants::MAX_ORDERBOOK_LENGTH;
+use crate::error::PredictionMarketError;
+use crate::math::{lmsr_cost, lmsr_yes_price, logit, LN_2, SQRT_WAD, WAD};
+use crate::constants::{
+    MAX_ALLOWED_COLLATERAL_MINTS, MAX_CATEGORICAL_OUTCOMES, MAX_CATEGORY_LEN, MAX_DEPTH_LEVELS,
+    MAX_FEE_BPS, MAX_FEE_TIERS, MAX_GROUP_MARKETS, MAX_METADATA_URL_LEN, MAX_RESOLUTION_SOURCE_LEN,
+    MAX_REWARD_MAKERS,
+};
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
 pub enum WinningOutcome {
@@ -21,6 +27,34 @@ pub enum OrderSide {
     Sell
 }
 
+// Trading state of a market, set by the market authority (Settled by settlement only)
+// Paused blocks all trading, CloseOnly only lets users reduce positions (merge, cancel, sell)
+// Voided is final: nothing can be opened or settled anymore, users can still merge and cancel
+// and claim_rewards refunds half a unit per outcome token
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum MarketStatus {
+    Active,
+    Paused,
+    CloseOnly,
+    Settled,
+    Voided
+}
+
+// Binary markets settle on a WinningOutcome, scalar markets on a reported value
+// For scalar markets the YES mint is the LONG token and the NO mint is the SHORT token
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum MarketType {
+    Binary,
+    Scalar
+}
+
+// Automated liquidity a market runs next to its order book, at most one per market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum AmmKind {
+    Fpmm, // LP funded AmmPool
+    Lmsr, // Creator subsidized LmsrMaker
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct Market {
@@ -36,12 +70,128 @@ pub struct Market {
     // We can also Put META data URL, which is stored offchain in some S3 storage
     // That Meta URL data consists of market image, Name , kind of like an object 
     // put some max lenght on that metadata_url
-    #[max_len(200)]
+    #[max_len(MAX_METADATA_URL_LEN)]
     pub meta_data_url : String,
     pub outcome_yes_mint: Pubkey, // Type of mint of YES & NO
     pub outcome_no_mint: Pubkey,
     pub yes_escrow : Pubkey, // Escrow Account to store the Yes/No
     pub no_escrow : Pubkey,
+    pub market_type : MarketType,
+    pub scalar_low : i64, // Range of a scalar market, unused for binary
+    pub scalar_high : i64,
+    pub resolved_value : Option<i64>, // Value reported by the resolver, scalar only
+    pub market_group : Option<Pubkey>, // Set when the market is part of a neg-risk MarketGroup
+    pub status : MarketStatus,
+    pub question_hash : [u8; 32], // Hash of the full question text, the text itself lives off chain
+    #[max_len(MAX_CATEGORY_LEN)]
+    pub category : String,
+    #[max_len(MAX_RESOLUTION_SOURCE_LEN)]
+    pub resolution_source : String, // Where the answer will be taken from, URL or free text
+    pub trading_started : bool, // Set by the first split, freezes the metadata
+    pub fee_vault : Pubkey, // Collateral token account fees accrue in, owned by the market
+    pub taker_fee_bps : u16, // Copied from the protocol defaults on creation
+    pub maker_fee_bps : u16,
+    pub protocol_fees_accrued : u64, // Fee vault balance owed to the protocol fee recipient
+    pub creator_fees_accrued : u64, // and to the market authority
+    pub amm : Option<AmmKind>,
+}
+
+// Market description passed on creation and to update_market_metadata
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MarketMetadata {
+    pub meta_data_url: String,
+    pub question_hash: [u8; 32],
+    pub category: String,
+    pub resolution_source: String,
+}
+
+impl Market {
+    // Validates the lengths against the account space and stores the metadata
+    pub fn set_metadata(&mut self, metadata: MarketMetadata) -> Result<()> {
+        require!(
+            metadata.meta_data_url.len() <= MAX_METADATA_URL_LEN
+                && metadata.category.len() <= MAX_CATEGORY_LEN
+                && metadata.resolution_source.len() <= MAX_RESOLUTION_SOURCE_LEN,
+            PredictionMarketError::MetadataTooLong
+        );
+
+        self.meta_data_url = metadata.meta_data_url;
+        self.question_hash = metadata.question_hash;
+        self.category = metadata.category;
+        self.resolution_source = metadata.resolution_source;
+        Ok(())
+    }
+
+    // Splits collected fees between the protocol and the market creator, dust goes to the protocol
+    // Referral shares are taken out before and tracked on the referrer's UserStats
+    pub fn accrue_fees(&mut self, fees: u64, creator_fee_share_bps: u16) -> Result<()> {
+        let creator_share = fee_amount(fees, creator_fee_share_bps)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        self.creator_fees_accrued = self
+            .creator_fees_accrued
+            .checked_add(creator_share)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        self.protocol_fees_accrued = self
+            .protocol_fees_accrued
+            .checked_add(fees - creator_share)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        Ok(())
+    }
+
+    // Taker and maker rates for a trader, a qualifying volume tier replaces the market's own rates
+    pub fn fee_rates(&self, config: &ProtocolConfig, volume: u64) -> (u16, i16) {
+        match config.fee_tier(volume) {
+            Some(tier) => (tier.taker_fee_bps, tier.maker_fee_bps),
+            None => (self.taker_fee_bps, self.maker_fee_bps as i16),
+        }
+    }
+
+    // Fails with the error matching the current status unless it is one of `allowed`
+    pub fn require_status(&self, allowed: &[MarketStatus]) -> Result<()> {
+        if allowed.contains(&self.status) {
+            return Ok(());
+        }
+
+        match self.status {
+            MarketStatus::Paused => err!(PredictionMarketError::MarketPaused),
+            MarketStatus::CloseOnly => err!(PredictionMarketError::MarketCloseOnly),
+            MarketStatus::Settled => err!(PredictionMarketError::MarketAlreadySettled),
+            MarketStatus::Voided => err!(PredictionMarketError::MarketVoided),
+            MarketStatus::Active => err!(PredictionMarketError::InvalidMarketStatus),
+        }
+    }
+
+    // Settled, expired or voided, the book won't trade again
+    pub fn is_trading_over(&self, now: i64) -> bool {
+        self.is_settled
+            || now >= self.settlement_deadline
+            || matches!(self.status, MarketStatus::Settled | MarketStatus::Voided)
+    }
+
+    // Collateral paid for `long_amount` LONG and `short_amount` SHORT tokens once resolved
+    // LONG gets (value - low) / (high - low) per token, SHORT the remainder, value clamped to the range
+    // Both sides round down, the dust stays in the vault
+    pub fn scalar_payout(&self, long_amount: u64, short_amount: u64) -> Option<u64> {
+        let value = self.resolved_value?.clamp(self.scalar_low, self.scalar_high);
+        let range = (self.scalar_high as i128).checked_sub(self.scalar_low as i128)?;
+        if range <= 0 {
+            return None;
+        }
+
+        let long_share = (value as i128).checked_sub(self.scalar_low as i128)?;
+        let short_share = range.checked_sub(long_share)?;
+
+        let long_payout = (long_amount as i128).checked_mul(long_share)?.checked_div(range)?;
+        let short_payout = (short_amount as i128).checked_mul(short_share)?.checked_div(range)?;
+
+        u64::try_from(long_payout.checked_add(short_payout)?).ok()
+    }
+
+    // Collateral refunded for outcome tokens of a voided market: half a unit per token on either side
+    // A full set still returns one unit, an odd total rounds down and the dust stays in the vault
+    pub fn void_payout(yes_amount: u64, no_amount: u64) -> Option<u64> {
+        Some(((yes_amount as u128).checked_add(no_amount as u128)? / 2) as u64)
+    }
 }
 
 
@@ -69,20 +219,610 @@ pub struct UserStats { // User Account associated with the particular market
     // pub free_collateral: u64,
 
     pub reward_claimed : bool,
+    pub referrer : Option<Pubkey>, // First referrer passed with one of the user's orders, never changes
+    pub referral_fees_claimable : u64, // This user's earnings as a referrer, paid from the fee vault
     pub bump : u8
 }
 
+// Fixed product market maker of one binary or scalar market. The YES and NO reserves are
+// token accounts owned by the market, LPs hold shares through lp_mint
+#[account]
+#[derive(InitSpace)]
+pub struct AmmPool {
+    pub market_id: u32,
+    pub lp_mint: Pubkey,
+    pub yes_reserve: Pubkey,
+    pub no_reserve: Pubkey,
+    pub fee_bps: u16, // Taken on every trade and left in the reserves for the LPs
+    pub bump: u8,
+}
+
+impl AmmPool {
+    // Outcome tokens bought with `amount` collateral and the fee. All of `amount` is split into
+    // the pool, but the invariant only counts what's left after the fee
+    pub fn buy_amount(
+        amount: u64,
+        fee_bps: u16,
+        buy_reserve: u64,
+        other_reserve: u64,
+    ) -> Option<(u64, u64)> {
+        let fee = fee_amount(amount, fee_bps)?;
+        let net = (amount - fee) as u128;
+        let (buy_reserve, other_reserve) = (buy_reserve as u128, other_reserve as u128);
+
+        let new_buy_reserve = (buy_reserve * other_reserve).div_ceil(other_reserve + net);
+        let tokens_out = buy_reserve + net - new_buy_reserve;
+        Some((u64::try_from(tokens_out).ok()?, fee))
+    }
+
+    // Outcome tokens a seller puts in to get `return_amount` collateral out, and the fee.
+    // The pool merges return_amount + fee of both sides, the fee part stays in the reserves
+    pub fn sell_amount(
+        return_amount: u64,
+        fee_bps: u16,
+        sell_reserve: u64,
+        other_reserve: u64,
+    ) -> Option<(u64, u64)> {
+        let gross = (return_amount as u128 * MAX_FEE_BPS as u128)
+            .div_ceil(MAX_FEE_BPS.checked_sub(fee_bps)? as u128);
+        let (sell_reserve, other_reserve) = (sell_reserve as u128, other_reserve as u128);
+        if other_reserve <= gross {
+            return None;
+        }
+
+        let tokens_in =
+            (sell_reserve * other_reserve).div_ceil(other_reserve - gross) + gross - sell_reserve;
+        Some((
+            u64::try_from(tokens_in).ok()?,
+            u64::try_from(gross).ok()? - return_amount,
+        ))
+    }
+
+    // LP shares for `amount` collateral and the YES/NO sent back to the depositor, so the pool
+    // grows without its price moving. The first deposit starts at even odds
+    pub fn liquidity_shares(
+        amount: u64,
+        yes_reserve: u64,
+        no_reserve: u64,
+        supply: u64,
+    ) -> Option<(u64, u64, u64)> {
+        if supply == 0 {
+            return Some((amount, 0, 0));
+        }
+
+        let pool_weight = yes_reserve.max(no_reserve) as u128;
+        if pool_weight == 0 {
+            return None;
+        }
+        let shares = amount as u128 * supply as u128 / pool_weight;
+        let yes_kept = (amount as u128 * yes_reserve as u128 / pool_weight) as u64;
+        let no_kept = (amount as u128 * no_reserve as u128 / pool_weight) as u64;
+        Some((u64::try_from(shares).ok()?, amount - yes_kept, amount - no_kept))
+    }
+
+    // Part of `reserve` that `shares` out of `supply` own
+    pub fn share_of(reserve: u64, shares: u64, supply: u64) -> Option<u64> {
+        if supply == 0 {
+            return None;
+        }
+        u64::try_from(reserve as u128 * shares as u128 / supply as u128).ok()
+    }
+
+    // Collateral a buyer pays for exactly `tokens_out` and the fee, the smallest net amount
+    // n with (buy_reserve + n - tokens_out) * (other_reserve + n) >= buy_reserve * other_reserve
+    pub fn buy_cost(
+        tokens_out: u64,
+        fee_bps: u16,
+        buy_reserve: u64,
+        other_reserve: u64,
+    ) -> Option<(u64, u64)> {
+        let (tokens_out, buy_reserve, other_reserve) =
+            (tokens_out as u128, buy_reserve as u128, other_reserve as u128);
+        let invariant = buy_reserve * other_reserve;
+
+        // Positive root of n^2 + (buy + other - out) * n - out * other, then nudged up past
+        // the isqrt rounding
+        let linear = buy_reserve as i128 + other_reserve as i128 - tokens_out as i128;
+        let discriminant = linear
+            .unsigned_abs()
+            .checked_mul(linear.unsigned_abs())?
+            .checked_add(tokens_out.checked_mul(other_reserve)?.checked_mul(4)?)?;
+        let mut net = ((discriminant.isqrt() as i128 - linear).max(0) as u128).div_ceil(2);
+        while buy_reserve + net < tokens_out
+            || (buy_reserve + net - tokens_out).checked_mul(other_reserve + net)? < invariant
+        {
+            net += 1;
+        }
+
+        let amount = (net * MAX_FEE_BPS as u128).div_ceil(MAX_FEE_BPS.checked_sub(fee_bps)? as u128);
+        Some((u64::try_from(amount).ok()?, u64::try_from(amount - net).ok()?))
+    }
+
+    // Collateral a seller gets for exactly `tokens_in` and the fee, the largest gross amount
+    // g with (sell_reserve + tokens_in - g) * (other_reserve - g) >= sell_reserve * other_reserve
+    pub fn sell_proceeds(
+        tokens_in: u64,
+        fee_bps: u16,
+        sell_reserve: u64,
+        other_reserve: u64,
+    ) -> Option<(u64, u64)> {
+        let (tokens_in, sell_reserve, other_reserve) =
+            (tokens_in as u128, sell_reserve as u128, other_reserve as u128);
+        let invariant = sell_reserve * other_reserve;
+
+        // Smaller root of g^2 - (sell + in + other) * g + in * other, then nudged down past
+        // the isqrt rounding
+        let sum = sell_reserve + tokens_in + other_reserve;
+        let discriminant = sum
+            .checked_mul(sum)?
+            .checked_sub(tokens_in.checked_mul(other_reserve)?.checked_mul(4)?)?;
+        let mut gross = (sum - discriminant.isqrt()) / 2;
+        while gross > 0
+            && (gross >= other_reserve
+                || (sell_reserve + tokens_in - gross).checked_mul(other_reserve - gross)?
+                    < invariant)
+        {
+            gross -= 1;
+        }
+
+        let return_amount =
+            gross * MAX_FEE_BPS.checked_sub(fee_bps)? as u128 / MAX_FEE_BPS as u128;
+        Some((u64::try_from(return_amount).ok()?, u64::try_from(gross - return_amount).ok()?))
+    }
+
+    // Price in WAD of the next token bought from (or sold to) the pool, fee included
+    pub fn marginal_price(
+        side: OrderSide,
+        fee_bps: u16,
+        token_reserve: u64,
+        other_reserve: u64,
+    ) -> Option<u128> {
+        let price = (other_reserve as u128 * WAD)
+            .checked_div(token_reserve as u128 + other_reserve as u128)?;
+        let fee_free = MAX_FEE_BPS.checked_sub(fee_bps)? as u128;
+        match side {
+            OrderSide::Buy => Some(price * MAX_FEE_BPS as u128 / fee_free),
+            OrderSide::Sell => Some(price * fee_free / MAX_FEE_BPS as u128),
+        }
+    }
+
+    // Tokens to trade on `side` before the marginal price reaches `price` (WAD, fee included).
+    // Ignores the fee that stays in the reserves, so it's a routing target and not a quote
+    pub fn quantity_to_price(
+        side: OrderSide,
+        fee_bps: u16,
+        token_reserve: u64,
+        other_reserve: u64,
+        price: u128,
+    ) -> Option<u64> {
+        let fee_free = MAX_FEE_BPS.checked_sub(fee_bps)? as u128;
+        let pool_price = match side {
+            OrderSide::Buy => price.checked_mul(fee_free)? / MAX_FEE_BPS as u128,
+            OrderSide::Sell => price.checked_mul(MAX_FEE_BPS as u128)? / fee_free,
+        };
+        // The pool price stays strictly between 0 and 1
+        if pool_price >= WAD || pool_price == 0 {
+            let unbounded = (pool_price >= WAD) == (side == OrderSide::Buy);
+            return Some(if unbounded { u64::MAX } else { 0 });
+        }
+
+        // token * other stays put, so at price p the other reserve is sqrt(k * p / (1 - p))
+        let (token_reserve, other_reserve) = (token_reserve as u128, other_reserve as u128);
+        let invariant = token_reserve * other_reserve;
+        let other_after = invariant
+            .isqrt()
+            .checked_mul((pool_price * WAD / (WAD - pool_price)).isqrt())?
+            / SQRT_WAD;
+        if other_after == 0 {
+            return Some(if side == OrderSide::Buy { 0 } else { u64::MAX });
+        }
+        let token_after = invariant / other_after;
+
+        let quantity = match side {
+            OrderSide::Buy if other_after > other_reserve => {
+                (token_reserve + (other_after - other_reserve)).saturating_sub(token_after)
+            }
+            OrderSide::Sell if other_after < other_reserve => {
+                (token_after + (other_reserve - other_after)).saturating_sub(token_reserve)
+            }
+            _ => 0,
+        };
+        Some(u64::try_from(quantity).unwrap_or(u64::MAX))
+    }
+}
+
+// Logarithmic market scoring rule maker of one binary market, funded by the market creator.
+// yes_shares and no_shares are the net amounts it has minted, negative once it bought back more
+// than it sold. collateral is the subsidy plus everything traders paid in, all in the market vault
+#[account]
+#[derive(InitSpace)]
+pub struct LmsrMaker {
+    pub market_id: u32,
+    pub creator: Pubkey,
+    pub liquidity: u64, // b, the worst case loss is b * ln(2)
+    pub yes_shares: i64,
+    pub no_shares: i64,
+    pub collateral: u64,
+    pub subsidy: u64,
+    pub subsidy_withdrawn: bool,
+    pub bump: u8,
+}
+
+impl LmsrMaker {
+    // What the creator deposits for a given b, ln(2) rounded up so the rounding never leaves
+    // the maker short
+    pub fn subsidy_for(liquidity: u64) -> Option<u64> {
+        u64::try_from((liquidity as u128 * (LN_2 + 1)).div_ceil(WAD)).ok()
+    }
+
+    fn shares_after(&self, token_type: TokenType, delta: i64) -> Option<(i64, i64)> {
+        match token_type {
+            TokenType::Yes => Some((self.yes_shares.checked_add(delta)?, self.no_shares)),
+            TokenType::No => Some((self.yes_shares, self.no_shares.checked_add(delta)?)),
+        }
+    }
+
+    // Collateral a buyer pays for `shares`, rounded up in the maker's favour
+    pub fn buy_cost(&self, token_type: TokenType, shares: u64) -> Option<u64> {
+        let (yes_after, no_after) = self.shares_after(token_type, i64::try_from(shares).ok()?)?;
+        let before = lmsr_cost(self.yes_shares, self.no_shares, self.liquidity)?;
+        let after = lmsr_cost(yes_after, no_after, self.liquidity)?;
+        u64::try_from((after.checked_sub(before)?.max(0) as u128).div_ceil(WAD)).ok()
+    }
+
+    // Collateral a seller gets for `shares`, rounded down
+    pub fn sell_proceeds(&self, token_type: TokenType, shares: u64) -> Option<u64> {
+        let (yes_after, no_after) = self.shares_after(token_type, -i64::try_from(shares).ok()?)?;
+        let before = lmsr_cost(self.yes_shares, self.no_shares, self.liquidity)?;
+        let after = lmsr_cost(yes_after, no_after, self.liquidity)?;
+        u64::try_from(before.checked_sub(after)?.max(0) as u128 / WAD).ok()
+    }
+
+    // Price in WAD of the next `token_type` share, the maker charges no fee
+    pub fn marginal_price(&self, token_type: TokenType) -> Option<u128> {
+        let yes_price = lmsr_yes_price(self.yes_shares, self.no_shares, self.liquidity)?;
+        Some(match token_type {
+            TokenType::Yes => yes_price,
+            TokenType::No => WAD - yes_price,
+        })
+    }
+
+    // Shares to trade on `side` before the marginal price of `token_type` reaches `price`.
+    // The price is a logistic function of the share gap, so the target gap is b * logit(price)
+    pub fn quantity_to_price(
+        &self,
+        token_type: TokenType,
+        side: OrderSide,
+        price: u128,
+    ) -> Option<u64> {
+        if price >= WAD || price == 0 {
+            let unbounded = (price >= WAD) == (side == OrderSide::Buy);
+            return Some(if unbounded { u64::MAX } else { 0 });
+        }
+
+        let gap = match token_type {
+            TokenType::Yes => self.yes_shares as i128 - self.no_shares as i128,
+            TokenType::No => self.no_shares as i128 - self.yes_shares as i128,
+        };
+        let target = logit(price)?.checked_mul(self.liquidity as i128)? / WAD as i128;
+        let shares = match side {
+            OrderSide::Buy => target - gap,
+            OrderSide::Sell => gap - target,
+        };
+        Some(u64::try_from(shares.max(0)).unwrap_or(u64::MAX))
+    }
+
+    // Records a trade, negative shares for a sell. Fails if the maker's collateral would no
+    // longer cover the side it is most exposed on, which the cost function rules out unless
+    // the fixed-point rounding drifts
+    pub fn apply_trade(&mut self, token_type: TokenType, shares: i64, collateral: i64) -> Result<()> {
+        let (yes_shares, no_shares) = self
+            .shares_after(token_type, shares)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        let collateral = u64::try_from(self.collateral as i128 + collateral as i128)
+            .map_err(|_| PredictionMarketError::MathOverflow)?;
+        require!(
+            collateral as i128 >= yes_shares.max(no_shares) as i128,
+            PredictionMarketError::AmmInsufficientLiquidity
+        );
+
+        self.yes_shares = yes_shares;
+        self.no_shares = no_shares;
+        self.collateral = collateral;
+        Ok(())
+    }
+
+    // Collateral left to the creator once winners are paid. Claims pay the YES holders when
+    // OutcomeA wins and the NO holders otherwise, so that side is what the maker owes
+    pub fn leftover(&self, winning_outcome: WinningOutcome) -> Option<u64> {
+        let owed = match winning_outcome {
+            WinningOutcome::OutcomeA => self.yes_shares,
+            _ => self.no_shares,
+        };
+        u64::try_from(self.collateral as i128 - owed as i128).ok()
+    }
+
+    // Collateral left to the creator of a voided market, where every outstanding share refunds
+    // half a unit through claim_rewards or a merge. Rounded up in the holders' favour
+    pub fn void_leftover(&self) -> Option<u64> {
+        let owed = (self.yes_shares as i128 + self.no_shares as i128 + 1).div_euclid(2);
+        u64::try_from(self.collateral as i128 - owed).ok()
+    }
+}
+
+// Sponsor funded rewards for makers resting near the midpoint of one market's book. Every
+// epoch pays rewards_per_epoch out pro rata to the scores the permissionless crank samples:
+// unfilled size * sample_interval * spread_score. Whatever an epoch can't pay out (no scores,
+// rounding, makers that were evicted) goes back to the sponsor once the program is over
+#[account]
+#[derive(InitSpace)]
+pub struct LiquidityRewards {
+    pub market_id: u32,
+    pub sponsor: Pubkey,
+    pub reward_vault: Pubkey, // Market collateral, owned by the market like the AMM reserves
+    pub rewards_per_epoch: u64,
+    pub epoch_duration: i64,
+    pub epochs: u32,
+    pub start_time: i64,
+    pub max_spread_bps: u16, // Orders this far from the midpoint or further score nothing
+    pub sample_interval: i64, // Least time between two samples, each one counts for this long
+    pub min_size: u64, // Orders with less unfilled quantity score nothing
+    pub last_sample: i64,
+    pub epoch: u32, // Epoch the scores are collected for, `epochs` once the program is over
+    pub total_score: u128,
+    pub total_claimable: u64,
+    #[max_len(MAX_REWARD_MAKERS)]
+    pub makers: Vec<MakerReward>,
+    pub bump: u8,
+}
+
+// One maker's share of a LiquidityRewards program
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct MakerReward {
+    pub maker: Pubkey,
+    pub score: u128, // Open epoch only
+    pub claimable: u64, // Paid out of closed epochs, not claimed yet
+}
+
+impl LiquidityRewards {
+    pub fn end_time(&self) -> Option<i64> {
+        self.epoch_duration
+            .checked_mul(self.epochs as i64)?
+            .checked_add(self.start_time)
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.epoch == self.epochs
+    }
+
+    // Epoch `now` falls in, `epochs` from the end time on
+    pub fn epoch_at(&self, now: i64) -> u32 {
+        let elapsed = now.saturating_sub(self.start_time).max(0) / self.epoch_duration;
+        u32::try_from(elapsed).unwrap_or(u32::MAX).min(self.epochs)
+    }
+
+    // Closes the open epoch once `now` is past it, or right away when the market stopped
+    // trading for good. Returns the closed epoch and what it paid out. Epochs that passed
+    // without a sample pay nothing
+    pub fn roll_epoch(&mut self, now: i64, market_closed: bool) -> Option<Option<(u32, u64)>> {
+        let next_epoch = if market_closed {
+            self.epochs
+        } else {
+            self.epoch_at(now)
+        };
+        if self.is_over() || next_epoch <= self.epoch {
+            return Some(None);
+        }
+
+        let closed_epoch = self.epoch;
+        let mut distributed: u64 = 0;
+        for entry in self.makers.iter_mut() {
+            if self.total_score > 0 {
+                let reward = u64::try_from(
+                    self.rewards_per_epoch as u128 * entry.score / self.total_score,
+                )
+                .ok()?;
+                entry.claimable = entry.claimable.checked_add(reward)?;
+                distributed = distributed.checked_add(reward)?;
+            }
+            entry.score = 0;
+        }
+        self.makers.retain(|entry| entry.claimable > 0);
+        self.total_claimable = self.total_claimable.checked_add(distributed)?;
+        self.total_score = 0;
+        self.epoch = next_epoch;
+
+        Some(Some((closed_epoch, distributed)))
+    }
+
+    // Adds one sample of the book to the open epoch, returns the score it added net of the
+    // scores evicted makers lost. Each outcome is scored around its own midpoint and only while
+    // both of its sides have orders
+    pub fn sample(&mut self, book: &OrderBook) -> Option<u128> {
+        let total_before = self.total_score;
+
+        for token_type in [TokenType::Yes, TokenType::No] {
+            let bids = book.orders(token_type, OrderSide::Buy);
+            let asks = book.orders(token_type, OrderSide::Sell);
+            let (Some(best_bid), Some(best_ask)) = (bids.first(), asks.first()) else {
+                continue;
+            };
+            // Twice the midpoint keeps it whole
+            let double_mid = best_bid.price.checked_add(best_ask.price)?;
+
+            for order in bids.iter().chain(asks.iter()) {
+                let spread_score =
+                    Self::spread_score(order.price, double_mid, self.max_spread_bps)?;
+                let size = order.quantity.saturating_sub(order.filledquantity);
+                if spread_score == 0 || size < self.min_size {
+                    continue;
+                }
+
+                let score = (size as u128)
+                    .checked_mul(self.sample_interval as u128)?
+                    .checked_mul(spread_score as u128)?;
+                self.add_score(order.user_key, score)?;
+            }
+        }
+
+        self.total_score.checked_sub(total_before)
+    }
+
+    // Quadratic in the distance from the midpoint: MAX_FEE_BPS at the midpoint, 0 from
+    // max_spread_bps away on
+    pub fn spread_score(price: u64, double_mid: u64, max_spread_bps: u16) -> Option<u64> {
+        let distance = (price as u128 * 2).abs_diff(double_mid as u128);
+        let spread_bps = distance.checked_mul(MAX_FEE_BPS as u128)? / double_mid as u128;
+        let max_spread_bps = max_spread_bps as u128;
+        if spread_bps >= max_spread_bps {
+            return Some(0);
+        }
+        let closeness = max_spread_bps - spread_bps;
+        let score = closeness * closeness * MAX_FEE_BPS as u128 / (max_spread_bps * max_spread_bps);
+        u64::try_from(score).ok()
+    }
+
+    // A maker without a slot takes a free one, or else the slot of the lowest scored maker
+    // with nothing left to claim when it scores more. Otherwise the score is dropped
+    fn add_score(&mut self, maker: Pubkey, score: u128) -> Option<()> {
+        let new_entry = MakerReward {
+            maker,
+            score: 0,
+            claimable: 0,
+        };
+        let index = match self.makers.iter().position(|entry| entry.maker == maker) {
+            Some(index) => index,
+            None if self.makers.len() < MAX_REWARD_MAKERS => {
+                self.makers.push(new_entry);
+                self.makers.len() - 1
+            }
+            None => {
+                let lowest = self
+                    .makers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| entry.claimable == 0)
+                    .min_by_key(|(_, entry)| entry.score);
+                match lowest {
+                    Some((index, entry)) if entry.score < score => {
+                        self.total_score = self.total_score.checked_sub(entry.score)?;
+                        self.makers[index] = new_entry;
+                        index
+                    }
+                    _ => return Some(()),
+                }
+            }
+        };
+        let entry = &mut self.makers[index];
+        entry.score = entry.score.checked_add(score)?;
+        self.total_score = self.total_score.checked_add(score)?;
+        Some(())
+    }
+}
+
+// Traded volume of one wallet across every market, decides its fee tier
+// Counted in collateral base units over two fixed windows: the current one and the one before
+#[account]
+#[derive(InitSpace)]
+pub struct TraderVolume {
+    pub owner: Pubkey,
+    pub window_start: i64,
+    pub current_volume: u64,
+    pub previous_volume: u64, // Keeps the tier from dropping to zero at every window boundary
+    pub bump: u8,
+}
+
+impl TraderVolume {
+    pub fn rolling_volume(&self) -> u64 {
+        self.current_volume.saturating_add(self.previous_volume)
+    }
+
+    // Moves the windows forward to the one containing `now`
+    pub fn roll(&mut self, now: i64, window: i64) {
+        if window <= 0 || now < self.window_start.saturating_add(window) {
+            return;
+        }
+
+        let elapsed_windows = (now - self.window_start) / window;
+        self.previous_volume = if elapsed_windows == 1 {
+            self.current_volume
+        } else {
+            0
+        };
+        self.current_volume = 0;
+        self.window_start += elapsed_windows * window;
+    }
+
+    pub fn add(&mut self, amount: u64) -> Result<()> {
+        self.current_volume = self
+            .current_volume
+            .checked_add(amount)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        Ok(())
+    }
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
 pub struct Order {
     pub id : u64,
     pub market_id : u32,
     pub user_key : Pubkey,
+    pub user_stats : Pubkey, // Maker's UserStats PDA, so matching doesn't have to re-derive it
     pub side : OrderSide,
     pub token_type : TokenType,
     pub price : u64,
     pub quantity : u64,
     pub filledquantity : u64,
-    pub timestamp : i64
+    pub timestamp : i64,
+    pub fee_reserved : u64, // Maker fee locked with a resting buy order, refunded on cancel
+}
+
+// Aggregated view of all resting orders at one price
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceLevel {
+    pub price : u64,
+    pub total_size : u64, // Unfilled quantity resting at this price
+    pub order_count : u32
+}
+
+// Result of simulating a taker against one side of the book
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Quote {
+    pub fillable_quantity : u64,
+    pub total_cost : u64, // Sum of quantity * price over every fill
+    pub average_price : u64, // total_cost / fillable_quantity, rounded down
+    pub worst_price : u64
+}
+
+// Taker order routed between the book and the market's AMM by market_order, nothing rests
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MarketOrderParams {
+    pub side : OrderSide,
+    pub token_type : TokenType,
+    pub quantity : u64,
+    pub limit_price : u64, // In book price units, no fill on either venue goes past it
+    pub slippage_limit : u64, // Most a buy pays, fees included, or least a sell receives
+    pub max_iteration : u64, // Bounds book fills and AMM legs together
+}
+
+// split_and_sell and buy_and_merge, both only fill against the order book
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitMergeParams {
+    pub token_type : TokenType, // Sold after the split, or bought before the merge
+    pub quantity : u64,
+    pub limit_price : u64, // In book price units, no fill goes past it
+    pub max_iteration : u64,
+}
+
+// Terms of a LiquidityRewards program, the sponsor deposits rewards_per_epoch * epochs up front
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiquidityRewardsParams {
+    pub rewards_per_epoch : u64,
+    pub epoch_duration : i64,
+    pub epochs : u32,
+    pub max_spread_bps : u16,
+    pub sample_interval : i64, // Within epoch_duration
+    pub min_size : u64, // At least 1
 }
 
 #[account]
@@ -102,7 +842,7 @@ impl OrderBook {
     pub const BASE_SIZE: usize = 8 + 4 + 8 + 1 + 16;
     
     // Size of each Order struct
-    pub const ORDER_SIZE: usize = 80; // Padded size
+    pub const ORDER_SIZE: usize = 120; // Padded size
     
     // Calculate space needed for N orders per side
     pub fn space(orders_per_side: usize) -> usize {
@@ -117,14 +857,400 @@ impl OrderBook {
         self.no_sell_orders.len()
     }
     
-    // Calculate required space based on current orders
-    pub fn current_space_needed(&self) -> usize {
+    // Room for one more order on the fullest side, used to realloc before placing
+    pub fn space_for_next_order(&self) -> usize {
         let max_per_side = self.yes_buy_orders.len()
             .max(self.yes_sell_orders.len())
             .max(self.no_buy_orders.len())
             .max(self.no_sell_orders.len());
-        Self::space(max_per_side)
+        Self::space(max_per_side + 1)
+    }
+
+    // Resting orders for one side, best price first
+    pub fn orders(&self, token_type: TokenType, side: OrderSide) -> &Vec<Order> {
+        match (token_type, side) {
+            (TokenType::Yes, OrderSide::Buy) => &self.yes_buy_orders,
+            (TokenType::Yes, OrderSide::Sell) => &self.yes_sell_orders,
+            (TokenType::No, OrderSide::Buy) => &self.no_buy_orders,
+            (TokenType::No, OrderSide::Sell) => &self.no_sell_orders,
+        }
+    }
+
+    // Aggregate one side into price levels, best price first
+    pub fn depth(&self, token_type: TokenType, side: OrderSide, max_levels: usize) -> Vec<PriceLevel> {
+        let max_levels = max_levels.min(MAX_DEPTH_LEVELS);
+        let mut levels: Vec<PriceLevel> = Vec::new();
+
+        for order in self.orders(token_type, side).iter() {
+            let left_qty = order.quantity.saturating_sub(order.filledquantity);
+            if left_qty == 0 {
+                continue;
+            }
+
+            match levels.last_mut() {
+                Some(level) if level.price == order.price => {
+                    level.total_size = level.total_size.saturating_add(left_qty);
+                    level.order_count += 1;
+                }
+                _ => {
+                    if levels.len() == max_levels {
+                        break;
+                    }
+                    levels.push(PriceLevel {
+                        price: order.price,
+                        total_size: left_qty,
+                        order_count: 1,
+                    });
+                }
+            }
+        }
+
+        levels
+    }
+
+    // Walk the opposite side the way place_order would, without touching any state
+    // A taker Buy walks the sell orders and a taker Sell walks the buy orders
+    pub fn quote(&self, token_type: TokenType, taker_side: OrderSide, quantity: u64) -> Option<Quote> {
+        let book_side = match taker_side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        };
+
+        let mut quote = Quote::default();
+
+        for order in self.orders(token_type, book_side).iter() {
+            let our_left_qty = quantity.checked_sub(quote.fillable_quantity)?;
+            if our_left_qty == 0 {
+                break;
+            }
+
+            let book_left_qty = order.quantity.checked_sub(order.filledquantity)?;
+            let min_qty = our_left_qty.min(book_left_qty);
+            if min_qty == 0 {
+                continue;
+            }
+
+            quote.fillable_quantity = quote.fillable_quantity.checked_add(min_qty)?;
+            quote.total_cost = quote
+                .total_cost
+                .checked_add(min_qty.checked_mul(order.price)?)?;
+            quote.worst_price = order.price;
+        }
+
+        if quote.fillable_quantity > 0 {
+            quote.average_price = quote.total_cost / quote.fillable_quantity;
+        }
+
+        Some(quote)
+    }
+}
+
+// Categorical market: 2 - 16 mutually exclusive outcomes, one outcome mint per index
+// Each outcome trades against collateral on its own OutcomeOrderBook
+#[account]
+#[derive(InitSpace)]
+pub struct CategoricalMarket {
+    pub authority: Pubkey,
+    pub market_id: u32,
+    pub settlement_deadline: i64,
+    pub collateral_mint: Pubkey,
+    pub collateral_vault: Pubkey,
+    pub outcome_count: u8,
+    pub outcomes_initialized: u8, // Outcome mints/books are created one per tx, trading waits for all of them
+    pub outcome_mints: [Pubkey; MAX_CATEGORICAL_OUTCOMES], // Only the first outcome_count are used
+    pub is_settled: bool,
+    pub winning_outcome: Option<u8>, // Index of the winning outcome
+    pub total_collateral_locked: u64,
+    pub bump: u8,
+}
+
+impl CategoricalMarket {
+    pub fn is_ready(&self) -> bool {
+        self.outcomes_initialized == self.outcome_count
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct CategoricalUserStats {
+    pub user : Pubkey,
+    pub market_id : u32,
+    pub claimable_outcome : [u64; MAX_CATEGORICAL_OUTCOMES], // Indexed by outcome
+    pub locked_outcome : [u64; MAX_CATEGORICAL_OUTCOMES],
+    pub claimable_collateral: u64,
+    pub locked_collateral: u64,
+    pub bump : u8
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct CategoricalOrder {
+    pub id : u64,
+    pub user_key : Pubkey,
+    pub user_stats : Pubkey, // Maker's CategoricalUserStats PDA
+    pub side : OrderSide,
+    pub price : u64,
+    pub quantity : u64,
+    pub filledquantity : u64,
+    pub timestamp : i64
+}
+
+#[account]
+pub struct OutcomeOrderBook {
+    pub market_id : u32,
+    pub outcome_index : u8,
+    pub next_order_id : u64,
+    pub buy_orders : Vec<CategoricalOrder>,
+    pub sell_orders : Vec<CategoricalOrder>,
+    pub bump : u8
+}
+
+impl OutcomeOrderBook {
+    // Discriminator (8) + market_id (4) + outcome_index (1) + next_order_id (8) + bump (1)
+    // + 2 Vec length prefixes (8)
+    pub const BASE_SIZE: usize = 8 + 4 + 1 + 8 + 1 + 8;
+
+    pub fn space(orders_per_side: usize) -> usize {
+        Self::BASE_SIZE + (orders_per_side * CategoricalOrder::INIT_SPACE * 2)
     }
+
+    // Room for one more order on the fullest side, used to realloc before placing
+    pub fn space_for_next_order(&self) -> usize {
+        Self::space(self.buy_orders.len().max(self.sell_orders.len()) + 1)
+    }
+}
+
+// Neg-risk group: binary markets on one event where exactly one resolves YES
+// (one market per candidate). Lets NO positions be converted into YES on the other
+// markets plus collateral, and settles all markets together
+#[account]
+#[derive(InitSpace)]
+pub struct MarketGroup {
+    pub authority: Pubkey,
+    pub group_id: u32,
+    pub collateral_mint: Pubkey, // Every market in the group has to use it, collateral moves between their vaults
+    pub market_count: u8,
+    pub market_ids: [u32; MAX_GROUP_MARKETS], // Only the first market_count are used, in the order they were added
+    pub is_resolved: bool,
+    pub winning_index: Option<u8>, // Index into market_ids of the market that resolved YES
+    // Set by the first convert_no_positions. The YES minted so far is only backed by the markets
+    // that were in the group then, so no market can join afterwards
+    pub conversions_started: bool,
+    pub bump: u8,
 }
 
 
+// Hands out market ids, binary/scalar and categorical markets draw from the same sequence
+// Created together with the ProtocolConfig, ids start at 1
+#[account]
+#[derive(InitSpace)]
+pub struct MarketCounter {
+    pub next_market_id: u32,
+    pub bump: u8,
+}
+
+impl MarketCounter {
+    // Returns the id for the market being created and advances the counter
+    pub fn take_next_id(&mut self) -> Result<u32> {
+        let market_id = self.next_market_id;
+        self.next_market_id = market_id
+            .checked_add(1)
+            .ok_or(PredictionMarketError::MathOverflow)?;
+        Ok(market_id)
+    }
+}
+
+// Program wide settings, a single PDA created by the upgrade authority
+#[account]
+#[derive(InitSpace)]
+pub struct ProtocolConfig {
+    pub admin: Pubkey,
+    pub pending_admin: Option<Pubkey>, // Set by transfer_protocol_admin, becomes admin once it accepts
+    pub fee_recipient: Pubkey,
+    pub taker_fee_bps: u16, // Default fee rates
+    pub maker_fee_bps: u16,
+    pub creator_fee_share_bps: u16, // Part of every market's fees that goes to its creator
+    pub referral_fee_share_bps: u16, // Part of a referred trader's taker fees that goes to the referrer
+    pub paused: bool, // Stops every trading instruction
+    #[max_len(MAX_ALLOWED_COLLATERAL_MINTS)]
+    pub allowed_collateral_mints: Vec<Pubkey>, // Mints new markets can use as collateral
+    #[max_len(MAX_FEE_TIERS)]
+    pub fee_tiers: Vec<FeeTier>, // Sorted by min_volume, empty means every market charges its own rates
+    pub volume_window: i64, // Length of a TraderVolume window in seconds
+    pub bump: u8,
+}
+
+impl ProtocolConfig {
+    pub fn is_collateral_allowed(&self, mint: &Pubkey) -> bool {
+        self.allowed_collateral_mints.contains(mint)
+    }
+
+    // Highest tier the volume reaches
+    pub fn fee_tier(&self, volume: u64) -> Option<&FeeTier> {
+        self.fee_tiers
+            .iter()
+            .rev()
+            .find(|tier| volume >= tier.min_volume)
+    }
+}
+
+// One step of the volume fee schedule, a negative maker rate is a rebate
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct FeeTier {
+    pub min_volume: u64,
+    pub taker_fee_bps: u16,
+    pub maker_fee_bps: i16,
+}
+
+// `bps` basis points of `amount`, rounded down
+pub fn fee_amount(amount: u64, bps: u16) -> Option<u64> {
+    u64::try_from(amount as u128 * bps as u128 / MAX_FEE_BPS as u128).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_INTERVAL: i64 = 60;
+    // Score of one unit of size resting at the midpoint for one sample
+    const UNIT: u128 = SAMPLE_INTERVAL as u128 * MAX_FEE_BPS as u128;
+
+    fn key(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    fn rewards(min_size: u64) -> LiquidityRewards {
+        LiquidityRewards {
+            market_id: 1,
+            sponsor: key(250),
+            reward_vault: key(251),
+            rewards_per_epoch: 1_000,
+            epoch_duration: 600,
+            epochs: 1,
+            start_time: 0,
+            max_spread_bps: 5_000,
+            sample_interval: SAMPLE_INTERVAL,
+            min_size,
+            last_sample: 0,
+            epoch: 0,
+            total_score: 0,
+            total_claimable: 0,
+            makers: Vec::new(),
+            bump: 255,
+        }
+    }
+
+    // Every order rests at the midpoint, so each scores its unfilled size * UNIT
+    fn order(maker: u8, side: OrderSide, quantity: u64, filled: u64) -> Order {
+        Order {
+            id: 0,
+            market_id: 1,
+            user_key: key(maker),
+            user_stats: key(maker),
+            side,
+            token_type: TokenType::Yes,
+            price: 500_000,
+            quantity,
+            filledquantity: filled,
+            timestamp: 0,
+            fee_reserved: 0,
+        }
+    }
+
+    fn book(bids: Vec<Order>, asks: Vec<Order>) -> OrderBook {
+        OrderBook {
+            market_id: 1,
+            next_order_id: 0,
+            yes_buy_orders: bids,
+            yes_sell_orders: asks,
+            no_buy_orders: Vec::new(),
+            no_sell_orders: Vec::new(),
+            bump: 255,
+        }
+    }
+
+    fn score_of(rewards: &LiquidityRewards, maker: u8) -> Option<u128> {
+        rewards
+            .makers
+            .iter()
+            .find(|entry| entry.maker == key(maker))
+            .map(|entry| entry.score)
+    }
+
+    #[test]
+    fn orders_under_the_minimum_size_score_nothing() {
+        let mut rewards = rewards(5);
+        let book = book(
+            vec![order(1, OrderSide::Buy, 5, 0), order(2, OrderSide::Buy, 10, 6)],
+            vec![order(3, OrderSide::Sell, 4, 0)],
+        );
+
+        assert_eq!(rewards.sample(&book), Some(5 * UNIT));
+        assert_eq!(score_of(&rewards, 1), Some(5 * UNIT));
+        assert_eq!(score_of(&rewards, 2), None);
+        assert_eq!(score_of(&rewards, 3), None);
+        assert_eq!(rewards.total_score, 5 * UNIT);
+    }
+
+    #[test]
+    fn full_slots_evict_the_lowest_scored_maker() {
+        let mut rewards = rewards(5);
+        // Still owed from a closed epoch, so its slot is kept even though it scores nothing
+        rewards.makers.push(MakerReward {
+            maker: key(200),
+            score: 0,
+            claimable: 50,
+        });
+        // Fills the other 63 slots, maker 7 scores the least
+        let bids = (1..=63)
+            .map(|maker| order(maker, OrderSide::Buy, if maker == 7 { 6 } else { 10 }, 0))
+            .collect();
+        let asks = vec![
+            order(100, OrderSide::Sell, 20, 0), // Beats maker 7
+            order(101, OrderSide::Sell, 1, 0),  // Under the minimum size
+            order(102, OrderSide::Sell, 8, 0),  // Beats nobody left
+        ];
+
+        let score = rewards.sample(&book(bids, asks)).unwrap();
+
+        assert_eq!(rewards.makers.len(), MAX_REWARD_MAKERS);
+        assert_eq!(score_of(&rewards, 7), None);
+        assert_eq!(score_of(&rewards, 100), Some(20 * UNIT));
+        assert_eq!(score_of(&rewards, 101), None);
+        assert_eq!(score_of(&rewards, 102), None);
+        assert_eq!(score_of(&rewards, 200), Some(0));
+        // Maker 7's score left the total with it
+        assert_eq!(score, (62 * 10 + 20) * UNIT);
+        assert_eq!(rewards.total_score, score);
+        assert_eq!(
+            rewards.makers.iter().map(|entry| entry.score).sum::<u128>(),
+            rewards.total_score
+        );
+    }
+
+    #[test]
+    fn a_maker_with_a_slot_keeps_adding_to_it_when_slots_are_full() {
+        let mut rewards = rewards(1);
+        let bids = (1..=64).map(|maker| order(maker, OrderSide::Buy, 10, 0)).collect();
+        let asks = vec![order(1, OrderSide::Sell, 3, 0)];
+
+        rewards.sample(&book(bids, asks)).unwrap();
+
+        assert_eq!(rewards.makers.len(), MAX_REWARD_MAKERS);
+        assert_eq!(score_of(&rewards, 1), Some(13 * UNIT));
+        assert_eq!(rewards.total_score, (64 * 10 + 3) * UNIT);
+    }
+
+    #[test]
+    fn a_grown_book_has_room_for_the_next_order() {
+        let mut book = book(Vec::new(), Vec::new());
+
+        // Grow the fullest side the way place_order does, one realloc per order
+        for maker in 1..=3 {
+            let space = book.space_for_next_order();
+            book.yes_sell_orders.push(order(maker, OrderSide::Sell, 10, 0));
+            assert!(8 + book.try_to_vec().unwrap().len() <= space);
+        }
+        book.no_buy_orders.push(order(4, OrderSide::Buy, 10, 0));
+        assert_eq!(book.space_for_next_order(), OrderBook::space(4));
+    }
+}
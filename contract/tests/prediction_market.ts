@@ -7,16 +7,28 @@ import {
   SystemProgram,
   LAMPORTS_PER_SOL,
   Connection,
+  Transaction,
+  sendAndConfirmTransaction,
 } from "@solana/web3.js";
 import {
   createMint,
   getOrCreateAssociatedTokenAccount,
   mintTo,
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
   getAccount,
+  getMint,
+  getTokenMetadata,
+  transferChecked,
+  ExtensionType,
+  getMintLen,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  NATIVE_MINT,
 } from "@solana/spl-token";
 import { BN } from "bn.js";
 import { assert, expect } from "chai";
+import { createHash } from "crypto";
 
 describe("prediction_market", () => {
   // Configure the client to use the local cluster.
@@ -32,6 +44,7 @@ describe("prediction_market", () => {
   // Mints and Accounts
   let collateralMint: PublicKey;
   let collateralVault: PublicKey;
+  let feeVault: PublicKey;
   let outcomeYesMint: PublicKey;
   let outcomeNoMint: PublicKey;
   let marketPda: PublicKey;
@@ -42,9 +55,57 @@ describe("prediction_market", () => {
   let userOutcomeYesAccount: PublicKey;
   let userOutcomeNoAccount: PublicKey;
 
+  // Ids come from the program's market counter in creation order: this market is 1,
+  // then the categorical (2), scalar (3) and grouped (4-6) markets below
   let marketId = 1;
+  const [marketCounter] = PublicKey.findProgramAddressSync(
+    [Buffer.from("market_counter")],
+    program.programId
+  );
   const initialCollateralAmount = 10000000;
 
+  // Outcome mints of binary and scalar markets are Token-2022 mints, most tests use classic SPL collateral
+  const getOutcomeAccount = (address: PublicKey) =>
+    getAccount(provider.connection, address, undefined, TOKEN_2022_PROGRAM_ID);
+  const createOutcomeAccount = (mint: PublicKey, owner: PublicKey) =>
+    getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      authority.payer,
+      mint,
+      owner,
+      false,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+  // Events emitted through emit_cpi! come back as inner instructions to our own program
+  const cpiEvents = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const accountKeys = tx.transaction.message.getAccountKeys();
+    return tx.meta.innerInstructions
+      .flatMap((inner) => inner.instructions)
+      .filter((ix) =>
+        accountKeys.get(ix.programIdIndex).equals(program.programId)
+      )
+      .map((ix) => {
+        const data = anchor.utils.bytes.bs58.decode(ix.data);
+        return program.coder.events.decode(
+          anchor.utils.bytes.base64.encode(data.subarray(8))
+        );
+      });
+  };
+
+  const marketMetadata = (question: string, category = "test") => ({
+    metaDataUrl: "https://example.com/markets/meta.json",
+    questionHash: Array.from(createHash("sha256").update(question).digest()),
+    category,
+    resolutionSource: "https://example.com/results",
+  });
+
   before(async () => {
     user = Keypair.generate();
 
@@ -64,6 +125,23 @@ describe("prediction_market", () => {
     );
 
     console.log("Collateral Mint:", collateralMint.toBase58());
+
+    // Protocol config has to exist before any market, the provider wallet is the upgrade authority
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    await program.methods
+      .initializeProtocolConfig(authority.publicKey, 0, 0, 0)
+      .accounts({
+        admin: authority.publicKey,
+        programData,
+      })
+      .rpc();
+    await program.methods
+      .addAllowedCollateral()
+      .accounts({ admin: authority.publicKey, collateralMint })
+      .rpc();
   });
 
   describe("Initialize Market", () => {
@@ -82,6 +160,10 @@ describe("prediction_market", () => {
         [Buffer.from("vault"), marketIdLE],
         program.programId
       );
+      [feeVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("fee_vault"), marketIdLE],
+        program.programId
+      );
       [outcomeYesMint] = PublicKey.findProgramAddressSync(
         [Buffer.from("outcome_a"), marketIdLE],
         program.programId
@@ -97,12 +179,13 @@ describe("prediction_market", () => {
 
       // Now you can see all the accounts needed for initializeMarket!
       await program.methods
-        .initializeMarket(marketId, settlementDeadline)
+        .initializeMarket(settlementDeadline, marketMetadata("Will it rain?"))
         .accounts({
           market: marketPda,
           authority: authority.publicKey,
           collateralMint: collateralMint,
           collateralVault,
+          feeVault,
           outcomeYesMint,
           outcomeNoMint,
           orderbook,
@@ -114,6 +197,113 @@ describe("prediction_market", () => {
 
       console.log("Market initialized successfully!");
       console.log("Market PDA:", marketPda.toBase58());
+
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.marketId, marketId);
+      assert.equal(market.metaDataUrl, "https://example.com/markets/meta.json");
+      assert.equal(market.category, "test");
+      const counter = await program.account.marketCounter.fetch(marketCounter);
+      assert.equal(counter.nextMarketId, marketId + 1);
+    });
+
+    it("Outcome mints carry Token-2022 metadata", async () => {
+      const yes = await getTokenMetadata(
+        provider.connection,
+        outcomeYesMint,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      assert.equal(yes.name, `M${marketId}-YES`);
+      assert.equal(yes.symbol, "YES");
+      assert.equal(yes.uri, "https://example.com/markets/meta.json");
+      assert.isTrue(yes.updateAuthority.equals(marketPda));
+
+      const no = await getTokenMetadata(
+        provider.connection,
+        outcomeNoMint,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      assert.equal(no.name, `M${marketId}-NO`);
+    });
+
+    it("Authority can fix the metadata before trading starts", async () => {
+      const metadata = {
+        ...marketMetadata("Will it rain tomorrow?", "weather"),
+        metaDataUrl: "https://example.com/markets/will-it-rain-tomorrow.json",
+      };
+      await program.methods
+        .updateMarketMetadata(marketId, metadata)
+        .accounts({
+          authority: authority.publicKey,
+          market: marketPda,
+          outcomeYesMint,
+          outcomeNoMint,
+        })
+        .rpc();
+
+      const market = await program.account.market.fetch(marketPda);
+      assert.equal(market.category, "weather");
+      assert.deepEqual(market.questionHash, metadata.questionHash);
+
+      // The longer URL is written to both mints as well
+      for (const mint of [outcomeYesMint, outcomeNoMint]) {
+        const tokenMetadata = await getTokenMetadata(
+          provider.connection,
+          mint,
+          undefined,
+          TOKEN_2022_PROGRAM_ID
+        );
+        assert.equal(tokenMetadata.uri, metadata.metaDataUrl);
+      }
+    });
+
+    it("Rejects metadata over the length limits", async () => {
+      try {
+        await program.methods
+          .updateMarketMetadata(
+            marketId,
+            marketMetadata("Will it rain tomorrow?", "x".repeat(33))
+          )
+          .accounts({
+            authority: authority.publicKey,
+            market: marketPda,
+            outcomeYesMint,
+            outcomeNoMint,
+          })
+          .rpc();
+
+        assert.fail("MetadataTooLong");
+      } catch (err) {
+        expect(err.toString()).to.include("MetadataTooLong");
+      }
+    });
+
+    it("Market accounts have to match the counter's next id", async () => {
+      // Id 1 is taken, deriving the accounts from it again can't collide with the existing market
+      try {
+        await program.methods
+          .initializeMarket(
+            new BN(Math.floor(Date.now() / 1000) + 86400),
+            marketMetadata("Will it rain?")
+          )
+          .accounts({
+            market: marketPda,
+            authority: authority.publicKey,
+            collateralMint,
+            collateralVault,
+            feeVault,
+            outcomeYesMint,
+            outcomeNoMint,
+            orderbook,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+
+        assert.fail("ConstraintSeeds");
+      } catch (err) {
+        expect(err.toString()).to.include("ConstraintSeeds");
+      }
     });
   });
 
@@ -140,17 +330,13 @@ describe("prediction_market", () => {
       );
       console.log("Tokens Minted to the User wallet");
 
-      let outcomeAAccountInfo = await getOrCreateAssociatedTokenAccount(
-        provider.connection,
-        authority.payer,
+      let outcomeAAccountInfo = await createOutcomeAccount(
         outcomeYesMint,
         user.publicKey
       );
       userOutcomeYesAccount = outcomeAAccountInfo.address;
 
-      let outcomeBAccountInfo = await getOrCreateAssociatedTokenAccount(
-        provider.connection,
-        authority.payer,
+      let outcomeBAccountInfo = await createOutcomeAccount(
         outcomeNoMint,
         user.publicKey
       );
@@ -180,6 +366,9 @@ describe("prediction_market", () => {
           outcomeNoMint,
           userOutcomeYes: userOutcomeYesAccount,
           userOutcomeNo: userOutcomeNoAccount,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
         })
         .signers([user])
         .rpc();
@@ -192,14 +381,8 @@ describe("prediction_market", () => {
       //Ok Now we have to verify does the tokens really Split
 
       //Getting the Outcome Account
-      let outcomeAAccount = await getAccount(
-        provider.connection,
-        userOutcomeYesAccount
-      );
-      let outcomeBAccount = await getAccount(
-        provider.connection,
-        userOutcomeNoAccount
-      );
+      let outcomeAAccount = await getOutcomeAccount(userOutcomeYesAccount);
+      let outcomeBAccount = await getOutcomeAccount(userOutcomeNoAccount);
       let vault = await getAccount(provider.connection, collateralVault);
       assert.equal(Number(outcomeAAccount.amount), splitAmount);
       assert.equal(Number(outcomeBAccount.amount), splitAmount);
@@ -230,6 +413,9 @@ describe("prediction_market", () => {
             outcomeNoMint,
             userOutcomeYes: userOutcomeYesAccount,
             userOutcomeNo: userOutcomeNoAccount,
+            collateralMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            nativeUnwrap: null,
           })
           .signers([user])
           .rpc();
@@ -240,17 +426,264 @@ describe("prediction_market", () => {
         expect(err.toString()).to.include("InvalidAmount");
       }
     });
+
+    it("Metadata is frozen once users hold positions", async () => {
+      try {
+        await program.methods
+          .updateMarketMetadata(marketId, marketMetadata("Will it snow?"))
+          .accounts({
+            authority: authority.publicKey,
+            market: marketPda,
+            outcomeYesMint,
+            outcomeNoMint,
+          })
+          .rpc();
+
+        assert.fail("MetadataFrozen");
+      } catch (err) {
+        expect(err.toString()).to.include("MetadataFrozen");
+      }
+    });
+  });
+
+  describe("Protocol Config", () => {
+    it("Pausing stops trading until unpaused", async () => {
+      await program.methods
+        .setProtocolPaused(true)
+        .accounts({ admin: authority.publicKey })
+        .rpc();
+
+      try {
+        await program.methods
+          .splitTokens(marketId, new BN(1000))
+          .accounts({
+            market: marketPda,
+            user: user.publicKey,
+            userCollateral: userCollateralAccount,
+            collateralVault,
+            outcomeYesMint,
+            outcomeNoMint,
+            userOutcomeYes: userOutcomeYesAccount,
+            userOutcomeNo: userOutcomeNoAccount,
+            collateralMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            nativeUnwrap: null,
+          })
+          .signers([user])
+          .rpc();
+
+        assert.fail("ProtocolPaused");
+      } catch (err) {
+        expect(err.toString()).to.include("ProtocolPaused");
+      } finally {
+        await program.methods
+          .setProtocolPaused(false)
+          .accounts({ admin: authority.publicKey })
+          .rpc();
+      }
+    });
+
+    it("Only the admin can update the config", async () => {
+      try {
+        await program.methods
+          .setProtocolPaused(true)
+          .accounts({ admin: user.publicKey })
+          .signers([user])
+          .rpc();
+
+        assert.fail("NotAuthorized");
+      } catch (err) {
+        expect(err.toString()).to.include("NotAuthorized");
+      }
+    });
+
+    it("Markets can't use collateral that isn't allowed", async () => {
+      const otherMint = await createMint(
+        provider.connection,
+        authority.payer,
+        authority.publicKey,
+        null,
+        6
+      );
+      const { nextMarketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      );
+      const idLE = new BN(nextMarketId).toArrayLike(Buffer, "le", 4);
+      const pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      try {
+        await program.methods
+          .initializeMarket(
+            new BN(Math.floor(Date.now() / 1000) + 86400),
+            marketMetadata("Unlisted collateral")
+          )
+          .accounts({
+            market: pda("market"),
+            authority: authority.publicKey,
+            collateralMint: otherMint,
+            collateralVault: pda("vault"),
+            feeVault: pda("fee_vault"),
+            outcomeYesMint: pda("outcome_a"),
+            outcomeNoMint: pda("outcome_b"),
+            orderbook: pda("orderbook"),
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+
+        assert.fail("CollateralNotAllowed");
+      } catch (err) {
+        expect(err.toString()).to.include("CollateralNotAllowed");
+      }
+    });
+
+    it("Admin transfer takes effect only once accepted", async () => {
+      const [config] = PublicKey.findProgramAddressSync(
+        [Buffer.from("protocol_config")],
+        program.programId
+      );
+
+      await program.methods
+        .transferProtocolAdmin(user.publicKey)
+        .accounts({ admin: authority.publicKey })
+        .rpc();
+      let configAccount = await program.account.protocolConfig.fetch(config);
+      assert.isTrue(configAccount.admin.equals(authority.publicKey));
+      assert.isTrue(configAccount.pendingAdmin.equals(user.publicKey));
+
+      await program.methods
+        .acceptProtocolAdmin()
+        .accounts({ pendingAdmin: user.publicKey })
+        .signers([user])
+        .rpc();
+      configAccount = await program.account.protocolConfig.fetch(config);
+      assert.isTrue(configAccount.admin.equals(user.publicKey));
+
+      // Hand it back so the rest of the suite keeps working
+      await program.methods
+        .transferProtocolAdmin(authority.publicKey)
+        .accounts({ admin: user.publicKey })
+        .signers([user])
+        .rpc();
+      await program.methods
+        .acceptProtocolAdmin()
+        .accounts({ pendingAdmin: authority.publicKey })
+        .rpc();
+    });
+  });
+
+  describe("Events", () => {
+    it("Split emits a TokensSplit event through a self CPI", async () => {
+      const splitAmount = 1000;
+
+      const signature = await program.methods
+        .splitTokens(marketId, new anchor.BN(splitAmount))
+        .accounts({
+          market: marketPda,
+          user: user.publicKey,
+          userCollateral: userCollateralAccount,
+          collateralVault,
+          outcomeYesMint,
+          outcomeNoMint,
+          userOutcomeYes: userOutcomeYesAccount,
+          userOutcomeNo: userOutcomeNoAccount,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
+        })
+        .signers([user])
+        .rpc({ commitment: "confirmed" });
+
+      const events = await cpiEvents(signature);
+      const splitEvent = events.find((e) => e?.name === "tokensSplit");
+      assert.isDefined(splitEvent);
+      assert.equal(splitEvent.data.marketId, marketId);
+      assert.isTrue(splitEvent.data.user.equals(user.publicKey));
+      assert.equal(Number(splitEvent.data.amount), splitAmount);
+    });
+  });
+
+  describe("Market Status", () => {
+    const setStatus = (status: object) =>
+      program.methods
+        .setMarketStatus(marketId, status)
+        .accounts({ authority: authority.publicKey, market: marketPda })
+        .rpc();
+    const splitMergeAccounts = () => ({
+      market: marketPda,
+      user: user.publicKey,
+      userCollateral: userCollateralAccount,
+      collateralVault,
+      outcomeYesMint,
+      outcomeNoMint,
+      userOutcomeYes: userOutcomeYesAccount,
+      userOutcomeNo: userOutcomeNoAccount,
+      collateralMint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      nativeUnwrap: null,
+    });
+
+    it("Close-only rejects new positions", async () => {
+      await setStatus({ closeOnly: {} });
+
+      try {
+        await program.methods
+          .splitTokens(marketId, new BN(1000))
+          .accounts(splitMergeAccounts())
+          .signers([user])
+          .rpc();
+
+        assert.fail("MarketCloseOnly");
+      } catch (err) {
+        expect(err.toString()).to.include("MarketCloseOnly");
+      }
+    });
+
+    it("Paused rejects merges too", async () => {
+      await setStatus({ paused: {} });
+
+      try {
+        await program.methods
+          .mergeTokens(marketId)
+          .accounts(splitMergeAccounts())
+          .signers([user])
+          .rpc();
+
+        assert.fail("MarketPaused");
+      } catch (err) {
+        expect(err.toString()).to.include("MarketPaused");
+      }
+    });
+
+    it("Only the market authority can change the status", async () => {
+      try {
+        await program.methods
+          .setMarketStatus(marketId, { active: {} })
+          .accounts({ authority: user.publicKey, market: marketPda })
+          .signers([user])
+          .rpc();
+
+        assert.fail("NotAuthorized");
+      } catch (err) {
+        expect(err.toString()).to.include("NotAuthorized");
+      }
+
+      await setStatus({ active: {} });
+      const market = await program.account.market.fetch(marketPda);
+      assert.deepEqual(market.status, { active: {} });
+    });
   });
 
   describe("Merge Tokens", () => {
     // Now we will merge token
     it("Merges outcome tokens back to collateral", async () => {
-      let userOutcomeYesAccountInfoBefore = await getAccount(
-        provider.connection,
+      let userOutcomeYesAccountInfoBefore = await getOutcomeAccount(
         userOutcomeYesAccount
       );
-      let userOutcomeNoAccountInfoBefore = await getAccount(
-        provider.connection,
+      let userOutcomeNoAccountInfoBefore = await getOutcomeAccount(
         userOutcomeNoAccount
       );
       let userCollateralAccountInfoBefore = await getAccount(
@@ -269,6 +702,9 @@ describe("prediction_market", () => {
           userOutcomeNo: userOutcomeNoAccount,
           userCollateral: userCollateralAccount,
           collateralVault,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
         })
         .signers([user])
         .rpc();
@@ -280,12 +716,10 @@ describe("prediction_market", () => {
         Number(userOutcomeNoAccountInfoBefore.amount)
       );
 
-      let userOutcomeYesAccountInfoAfter = await getAccount(
-        provider.connection,
+      let userOutcomeYesAccountInfoAfter = await getOutcomeAccount(
         userOutcomeYesAccount
       );
-      let userOutcomeNoAccountInfoAfter = await getAccount(
-        provider.connection,
+      let userOutcomeNoAccountInfoAfter = await getOutcomeAccount(
         userOutcomeNoAccount
       );
       let userCollateralAccountInfoAfter = await getAccount(
@@ -327,6 +761,9 @@ describe("prediction_market", () => {
           userOutcomeNo: userOutcomeNoAccount,
           userCollateral: userCollateralAccount,
           collateralVault,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
         });
 
         assert.fail("InvalidAmount");
@@ -336,20 +773,3135 @@ describe("prediction_market", () => {
     });
   });
 
-  describe("Claiming Rewards & Set Winning Side", () => {
-    //Defining accounts of user & then funding them
-    let winningUser: Keypair;
-    let winnerCollateralAcount: PublicKey;
-    let winnerOutcomeYesAccount: PublicKey;
-    let winnerOutcomeNoAccount: PublicKey;
+  describe("Order Book Views", () => {
+    it("Reading depth of an empty book through simulation", async () => {
+      const levels = await program.methods
+        .getOrderBookDepth(marketId, { yes: {} }, { buy: {} }, 10)
+        .accounts({
+          market: marketPda,
+          orderbook,
+        })
+        .view();
+
+      assert.equal(levels.length, 0);
+    });
+
+    it("Quoting a taker against an empty book fills nothing", async () => {
+      const quote = await program.methods
+        .quote(marketId, { yes: {} }, { buy: {} }, new BN(1000))
+        .accounts({
+          market: marketPda,
+          orderbook,
+        })
+        .view();
+
+      assert.equal(Number(quote.fillableQuantity), 0);
+      assert.equal(Number(quote.averagePrice), 0);
+      assert.equal(Number(quote.worstPrice), 0);
+    });
+  });
+
+  describe("Categorical Markets", () => {
+    const categoricalMarketId = 2;
+    const outcomeCount = 3;
+    const categoricalIdLE = new BN(categoricalMarketId).toArrayLike(
+      Buffer,
+      "le",
+      4
+    );
+
+    let categoricalMarket: PublicKey;
+    let categoricalVault: PublicKey;
+    let outcomeMints: PublicKey[] = [];
+    let userOutcomeAccounts: PublicKey[] = [];
+
+    // [outcome_mint, user_outcome] for every outcome, in index order
+    const outcomeRemainingAccounts = () =>
+      outcomeMints.flatMap((mint, i) => [
+        { pubkey: mint, isWritable: true, isSigner: false },
+        { pubkey: userOutcomeAccounts[i], isWritable: true, isSigner: false },
+      ]);
 
     before(async () => {
-      winningUser = Keypair.generate();
-      let requestAirdropSig = await provider.connection.requestAirdrop(
-        winningUser.publicKey,
-        anchor.web3.LAMPORTS_PER_SOL * 5
+      [categoricalMarket] = PublicKey.findProgramAddressSync(
+        [Buffer.from("categorical_market"), categoricalIdLE],
+        program.programId
       );
-      await provider.connection.confirmTransaction(requestAirdropSig);
+      [categoricalVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("categorical_vault"), categoricalIdLE],
+        program.programId
+      );
+      for (let i = 0; i < outcomeCount; i++) {
+        const [mint] = PublicKey.findProgramAddressSync(
+          [Buffer.from("categorical_outcome"), categoricalIdLE, Buffer.from([i])],
+          program.programId
+        );
+        outcomeMints.push(mint);
+      }
+    });
+
+    it("Initializes a market with 3 outcomes", async () => {
+      const settlementDeadline = new BN(Math.floor(Date.now() / 1000) + 86400);
+
+      await program.methods
+        .initializeCategoricalMarket(
+          outcomeCount,
+          settlementDeadline
+        )
+        .accounts({
+          market: categoricalMarket,
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: categoricalVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      for (let i = 0; i < outcomeCount; i++) {
+        await program.methods
+          .initializeCategoricalOutcome(categoricalMarketId, i)
+          .accounts({
+            market: categoricalMarket,
+            authority: authority.publicKey,
+            collateralMint,
+          })
+          .rpc();
+      }
+
+      const marketAccount = await program.account.categoricalMarket.fetch(
+        categoricalMarket
+      );
+      assert.equal(marketAccount.outcomeCount, outcomeCount);
+      assert.equal(marketAccount.outcomesInitialized, outcomeCount);
+      for (let i = 0; i < outcomeCount; i++) {
+        assert.isTrue(marketAccount.outcomeMints[i].equals(outcomeMints[i]));
+      }
+
+      for (const mint of outcomeMints) {
+        // Categorical outcome mints are still classic SPL mints
+        const account = await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          authority.payer,
+          mint,
+          user.publicKey
+        );
+        userOutcomeAccounts.push(account.address);
+      }
+    });
+
+    it("Split mints one of every outcome", async () => {
+      const splitAmount = 1000000;
+
+      await program.methods
+        .splitCategorical(categoricalMarketId, new BN(splitAmount))
+        .accounts({
+          market: categoricalMarket,
+          user: user.publicKey,
+          userCollateral: userCollateralAccount,
+          collateralVault: categoricalVault,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(outcomeRemainingAccounts())
+        .signers([user])
+        .rpc();
+
+      for (const account of userOutcomeAccounts) {
+        const info = await getAccount(provider.connection, account);
+        assert.equal(Number(info.amount), splitAmount);
+      }
+
+      const marketAccount = await program.account.categoricalMarket.fetch(
+        categoricalMarket
+      );
+      assert.equal(Number(marketAccount.totalCollateralLocked), splitAmount);
+    });
+
+    it("Merge burns complete sets back to collateral", async () => {
+      const collateralBefore = await getAccount(
+        provider.connection,
+        userCollateralAccount
+      );
+
+      await program.methods
+        .mergeCategorical(categoricalMarketId)
+        .accounts({
+          market: categoricalMarket,
+          user: user.publicKey,
+          userCollateral: userCollateralAccount,
+          collateralVault: categoricalVault,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(outcomeRemainingAccounts())
+        .signers([user])
+        .rpc();
+
+      const collateralAfter = await getAccount(
+        provider.connection,
+        userCollateralAccount
+      );
+      assert.equal(
+        Number(collateralAfter.amount) - Number(collateralBefore.amount),
+        1000000
+      );
+
+      for (const account of userOutcomeAccounts) {
+        const info = await getAccount(provider.connection, account);
+        assert.equal(Number(info.amount), 0);
+      }
+    });
+
+    it("Rejects outcome accounts out of index order", async () => {
+      const swapped = outcomeRemainingAccounts();
+      [swapped[0], swapped[2]] = [swapped[2], swapped[0]];
+
+      try {
+        await program.methods
+          .splitCategorical(categoricalMarketId, new BN(1000))
+          .accounts({
+            market: categoricalMarket,
+            user: user.publicKey,
+            userCollateral: userCollateralAccount,
+            collateralVault: categoricalVault,
+            collateralMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .remainingAccounts(swapped)
+          .signers([user])
+          .rpc();
+
+        assert.fail("OutcomeAccountsMismatch");
+      } catch (err) {
+        expect(err.toString()).to.include("OutcomeAccountsMismatch");
+      }
+    });
+  });
+
+  describe("Scalar Markets", () => {
+    const scalarMarketId = 3;
+    const scalarIdLE = new BN(scalarMarketId).toArrayLike(Buffer, "le", 4);
+    const pda = (seed: string, ...extra: Buffer[]) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from(seed), scalarIdLE, ...extra],
+        program.programId
+      )[0];
+
+    const scalarMarket = pda("market");
+    const scalarVault = pda("vault");
+    const longMint = pda("outcome_a");
+    const shortMint = pda("outcome_b");
+    let userLong: PublicKey;
+    let userShort: PublicKey;
+
+    const scalarAccounts = () => ({
+      market: scalarMarket,
+      user: user.publicKey,
+      userCollateral: userCollateralAccount,
+      collateralVault: scalarVault,
+      outcomeYesMint: longMint,
+      outcomeNoMint: shortMint,
+      userOutcomeYes: userLong,
+      userOutcomeNo: userShort,
+      collateralMint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      nativeUnwrap: null,
+    });
+
+    it("Creates a market on the range [0, 100]", async () => {
+      await program.methods
+        .initializeScalarMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          new BN(0),
+          new BN(100),
+          marketMetadata("Rainfall in mm", "weather")
+        )
+        .accounts({
+          market: scalarMarket,
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: scalarVault,
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: longMint,
+          outcomeNoMint: shortMint,
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const market = await program.account.market.fetch(scalarMarket);
+      assert.deepEqual(market.marketType, { scalar: {} });
+      assert.equal(Number(market.scalarLow), 0);
+      assert.equal(Number(market.scalarHigh), 100);
+
+      userLong = (
+        await createOutcomeAccount(longMint, user.publicKey)
+      ).address;
+      userShort = (
+        await createOutcomeAccount(shortMint, user.publicKey)
+      ).address;
+
+      await program.methods
+        .splitTokens(scalarMarketId, new BN(1000000))
+        .accounts(scalarAccounts())
+        .signers([user])
+        .rpc();
+    });
+
+    it("Binary settlement is rejected on a scalar market", async () => {
+      try {
+        await program.methods
+          .setWinningSide(scalarMarketId, { outcomeA: {} })
+          .accounts({
+            authority: authority.publicKey,
+            market: scalarMarket,
+            outcomeYesMint: longMint,
+            outcomeNoMint: shortMint,
+          })
+          .rpc();
+
+        assert.fail("InvalidMarketType");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidMarketType");
+      }
+    });
+
+    it("Only the market authority can resolve it", async () => {
+      try {
+        await program.methods
+          .resolveScalarMarket(scalarMarketId, new BN(100))
+          .accounts({
+            authority: user.publicKey,
+            market: scalarMarket,
+            outcomeYesMint: longMint,
+            outcomeNoMint: shortMint,
+          })
+          .signers([user])
+          .rpc();
+
+        assert.fail("NotAuthorized");
+      } catch (err) {
+        expect(err.toString()).to.include("NotAuthorized");
+      }
+    });
+
+    it("Resolving at 25 pays LONG 25% and SHORT 75%", async () => {
+      await program.methods
+        .resolveScalarMarket(scalarMarketId, new BN(25))
+        .accounts({
+          authority: authority.publicKey,
+          market: scalarMarket,
+          outcomeYesMint: longMint,
+          outcomeNoMint: shortMint,
+        })
+        .rpc();
+
+      // Sell half of the SHORT side away so the payout isn't just the full set back
+      const other = await createOutcomeAccount(shortMint, authority.publicKey);
+      await transferChecked(
+        provider.connection,
+        authority.payer,
+        userShort,
+        shortMint,
+        other.address,
+        user,
+        500000,
+        6,
+        [],
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+
+      const before = await getAccount(provider.connection, userCollateralAccount);
+
+      await program.methods
+        .claimRewards(scalarMarketId)
+        .accounts(scalarAccounts())
+        .signers([user])
+        .rpc();
+
+      const after = await getAccount(provider.connection, userCollateralAccount);
+      // 1_000_000 LONG * 25% + 500_000 SHORT * 75%
+      assert.equal(
+        Number(after.amount) - Number(before.amount),
+        250000 + 375000
+      );
+      assert.equal(
+        Number((await getOutcomeAccount(userLong)).amount),
+        0
+      );
+    });
+  });
+
+  describe("Neg-Risk Market Groups", () => {
+    const groupId = 1;
+    const marketIds = [4, 5, 6];
+    const amount = 100000;
+    const [group] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market_group"), new BN(groupId).toArrayLike(Buffer, "le", 4)],
+      program.programId
+    );
+
+    const addresses = (id: number) => {
+      const idLE = new BN(id).toArrayLike(Buffer, "le", 4);
+      const pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+      return {
+        market: pda("market"),
+        collateralVault: pda("vault"),
+        feeVault: pda("fee_vault"),
+        outcomeYesMint: pda("outcome_a"),
+        outcomeNoMint: pda("outcome_b"),
+        orderbook: pda("orderbook"),
+      };
+    };
+    const ata = async (mint: PublicKey) =>
+      (
+        await createOutcomeAccount(mint, user.publicKey)
+      ).address;
+    const meta = (pubkey: PublicKey) => ({
+      pubkey,
+      isWritable: true,
+      isSigner: false,
+    });
+
+    it("Groups three candidate markets", async () => {
+      await program.methods
+        .initializeMarketGroup(groupId)
+        .accounts({ group, authority: authority.publicKey, collateralMint })
+        .rpc();
+
+      for (const id of marketIds) {
+        const a = addresses(id);
+        await program.methods
+          .initializeMarket(
+            new BN(Math.floor(Date.now() / 1000) + 86400),
+            marketMetadata(`Candidate ${id} wins`, "politics")
+          )
+          .accounts({
+            ...a,
+            authority: authority.publicKey,
+            collateralMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+        await program.methods
+          .addMarketToGroup(groupId)
+          .accounts({ authority: authority.publicKey, group, market: a.market })
+          .rpc();
+      }
+
+      const groupAccount = await program.account.marketGroup.fetch(group);
+      assert.equal(groupAccount.marketCount, 3);
+    });
+
+    it("Converts NO on two markets into YES on the third plus collateral", async () => {
+      // Get NO positions on the first two candidates
+      for (const id of marketIds.slice(0, 2)) {
+        const a = addresses(id);
+        await program.methods
+          .splitTokens(id, new BN(amount))
+          .accounts({
+            market: a.market,
+            user: user.publicKey,
+            userCollateral: userCollateralAccount,
+            collateralVault: a.collateralVault,
+            outcomeYesMint: a.outcomeYesMint,
+            outcomeNoMint: a.outcomeNoMint,
+            userOutcomeYes: await ata(a.outcomeYesMint),
+            userOutcomeNo: await ata(a.outcomeNoMint),
+            collateralMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            nativeUnwrap: null,
+          })
+          .signers([user])
+          .rpc();
+      }
+
+      const remaining = [];
+      for (const [i, id] of marketIds.entries()) {
+        const a = addresses(id);
+        const position = i < 2 ? a.outcomeNoMint : a.outcomeYesMint;
+        remaining.push(
+          meta(a.market),
+          meta(a.collateralVault),
+          meta(a.outcomeYesMint),
+          meta(a.outcomeNoMint),
+          meta(await ata(position))
+        );
+      }
+
+      const collateralBefore = await getAccount(
+        provider.connection,
+        userCollateralAccount
+      );
+
+      await program.methods
+        .convertNoPositions(groupId, 0b011, new BN(amount))
+        .accounts({
+          user: user.publicKey,
+          group,
+          userCollateral: userCollateralAccount,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(remaining)
+        .signers([user])
+        .rpc();
+
+      const collateralAfter = await getAccount(
+        provider.connection,
+        userCollateralAccount
+      );
+      // k = 2 NO positions release k - 1 collateral
+      assert.equal(
+        Number(collateralAfter.amount) - Number(collateralBefore.amount),
+        amount
+      );
+
+      const yes = await getOutcomeAccount(
+        await ata(addresses(marketIds[2]).outcomeYesMint)
+      );
+      assert.equal(Number(yes.amount), amount);
+    });
+
+    it("A group takes no new markets once NO positions were converted", async () => {
+      try {
+        await program.methods
+          .addMarketToGroup(groupId)
+          .accounts({
+            authority: authority.publicKey,
+            group,
+            market: addresses(1).market,
+          })
+          .rpc();
+
+        assert.fail("MarketGroupFrozen");
+      } catch (err) {
+        expect(err.toString()).to.include("MarketGroupFrozen");
+      }
+    });
+
+    it("Grouped markets can't be settled one by one", async () => {
+      const a = addresses(marketIds[0]);
+      try {
+        await program.methods
+          .setWinningSide(marketIds[0], { outcomeA: {} })
+          .accounts({
+            authority: authority.publicKey,
+            market: a.market,
+            outcomeYesMint: a.outcomeYesMint,
+            outcomeNoMint: a.outcomeNoMint,
+          })
+          .rpc();
+
+        assert.fail("MarketInGroup");
+      } catch (err) {
+        expect(err.toString()).to.include("MarketInGroup");
+      }
+    });
+
+    it("Resolving the group moves backing to the winning market", async () => {
+      const remaining = marketIds.flatMap((id) => {
+        const a = addresses(id);
+        return [
+          meta(a.market),
+          meta(a.collateralVault),
+          meta(a.outcomeYesMint),
+          meta(a.outcomeNoMint),
+        ];
+      });
+
+      await program.methods
+        .resolveMarketGroup(groupId, 2)
+        .accounts({
+          authority: authority.publicKey,
+          group,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(remaining)
+        .rpc();
+
+      const winner = addresses(marketIds[2]);
+      const vault = await getAccount(provider.connection, winner.collateralVault);
+      assert.equal(Number(vault.amount), amount);
+
+      for (const [i, id] of marketIds.entries()) {
+        const market = await program.account.market.fetch(addresses(id).market);
+        assert.deepEqual(
+          market.winningOutcome,
+          i == 2 ? { outcomeA: {} } : { outcomeB: {} }
+        );
+      }
+    });
+  });
+
+  describe("Token-2022 Collateral", () => {
+    const marketId = 7;
+    const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+    const pda = (seed: string) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from(seed), idLE],
+        program.programId
+      )[0];
+    const amount = 1000000;
+
+    const transferFeeMint = async () => {
+      const mint = Keypair.generate();
+      const extensions = [ExtensionType.TransferFeeConfig];
+      const space = getMintLen(extensions);
+      const lamports =
+        await provider.connection.getMinimumBalanceForRentExemption(space);
+      await sendAndConfirmTransaction(
+        provider.connection,
+        new Transaction().add(
+          SystemProgram.createAccount({
+            fromPubkey: authority.publicKey,
+            newAccountPubkey: mint.publicKey,
+            space,
+            lamports,
+            programId: TOKEN_2022_PROGRAM_ID,
+          }),
+          createInitializeTransferFeeConfigInstruction(
+            mint.publicKey,
+            authority.publicKey,
+            authority.publicKey,
+            100,
+            BigInt(1000000),
+            TOKEN_2022_PROGRAM_ID
+          ),
+          createInitializeMintInstruction(
+            mint.publicKey,
+            6,
+            authority.publicKey,
+            null,
+            TOKEN_2022_PROGRAM_ID
+          )
+        ),
+        [authority.payer, mint]
+      );
+      return mint.publicKey;
+    };
+
+    it("Rejects collateral mints with a transfer fee", async () => {
+      const mint = await transferFeeMint();
+
+      try {
+        await program.methods
+          .addAllowedCollateral()
+          .accounts({ admin: authority.publicKey, collateralMint: mint })
+          .rpc();
+
+        assert.fail("UnsupportedCollateralMint");
+      } catch (err) {
+        expect(err.toString()).to.include("UnsupportedCollateralMint");
+      }
+    });
+
+    it("Splits and merges against a Token-2022 collateral mint", async () => {
+      const mint = await createMint(
+        provider.connection,
+        authority.payer,
+        authority.publicKey,
+        null,
+        6,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      await program.methods
+        .addAllowedCollateral()
+        .accounts({ admin: authority.publicKey, collateralMint: mint })
+        .rpc();
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Token-2022 collateral")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint: mint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .rpc();
+
+      const userCollateral = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          authority.payer,
+          mint,
+          user.publicKey,
+          false,
+          undefined,
+          undefined,
+          TOKEN_2022_PROGRAM_ID
+        )
+      ).address;
+      await mintTo(
+        provider.connection,
+        authority.payer,
+        mint,
+        userCollateral,
+        authority.publicKey,
+        amount,
+        [],
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+
+      const accounts = {
+        market: pda("market"),
+        user: user.publicKey,
+        userCollateral,
+        collateralVault: pda("vault"),
+        outcomeYesMint: pda("outcome_a"),
+        outcomeNoMint: pda("outcome_b"),
+        userOutcomeYes: (
+          await createOutcomeAccount(pda("outcome_a"), user.publicKey)
+        ).address,
+        userOutcomeNo: (
+          await createOutcomeAccount(pda("outcome_b"), user.publicKey)
+        ).address,
+        collateralMint: mint,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        nativeUnwrap: null,
+      };
+
+      await program.methods
+        .splitTokens(marketId, new BN(amount))
+        .accounts(accounts)
+        .signers([user])
+        .rpc();
+
+      const vault = await getAccount(
+        provider.connection,
+        pda("vault"),
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      assert.equal(Number(vault.amount), amount);
+      const market = await program.account.market.fetch(pda("market"));
+      assert.equal(market.totalCollateralLocked.toNumber(), amount);
+
+      await program.methods
+        .mergeTokens(marketId)
+        .accounts(accounts)
+        .signers([user])
+        .rpc();
+
+      const collateral = await getAccount(
+        provider.connection,
+        userCollateral,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      assert.equal(Number(collateral.amount), amount);
+    });
+  });
+
+  describe("Outcome Decimals", () => {
+    // Split mints outcome tokens 1:1 with collateral base units, so one whole
+    // collateral token has to split into one whole outcome token at any precision
+    for (const decimals of [0, 6, 8, 9]) {
+      it(`Outcome mints follow ${decimals} decimal collateral`, async () => {
+        const mint = await createMint(
+          provider.connection,
+          authority.payer,
+          authority.publicKey,
+          null,
+          decimals
+        );
+        await program.methods
+          .addAllowedCollateral()
+          .accounts({ admin: authority.publicKey, collateralMint: mint })
+          .rpc();
+
+        const { nextMarketId } = await program.account.marketCounter.fetch(
+          marketCounter
+        );
+        const idLE = new BN(nextMarketId).toArrayLike(Buffer, "le", 4);
+        const pda = (seed: string) =>
+          PublicKey.findProgramAddressSync(
+            [Buffer.from(seed), idLE],
+            program.programId
+          )[0];
+
+        await program.methods
+          .initializeMarket(
+            new BN(Math.floor(Date.now() / 1000) + 86400),
+            marketMetadata(`${decimals} decimal collateral`)
+          )
+          .accounts({
+            market: pda("market"),
+            authority: authority.publicKey,
+            collateralMint: mint,
+            collateralVault: pda("vault"),
+            feeVault: pda("fee_vault"),
+            outcomeYesMint: pda("outcome_a"),
+            outcomeNoMint: pda("outcome_b"),
+            orderbook: pda("orderbook"),
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+
+        for (const seed of ["outcome_a", "outcome_b"]) {
+          const outcomeMint = await getMint(
+            provider.connection,
+            pda(seed),
+            undefined,
+            TOKEN_2022_PROGRAM_ID
+          );
+          assert.equal(outcomeMint.decimals, decimals);
+        }
+
+        const oneToken = BigInt(10) ** BigInt(decimals);
+        const userCollateral = (
+          await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            authority.payer,
+            mint,
+            user.publicKey
+          )
+        ).address;
+        await mintTo(
+          provider.connection,
+          authority.payer,
+          mint,
+          userCollateral,
+          authority.publicKey,
+          oneToken
+        );
+
+        const userOutcomeYes = (
+          await createOutcomeAccount(pda("outcome_a"), user.publicKey)
+        ).address;
+        await program.methods
+          .splitTokens(nextMarketId, new BN(oneToken.toString()))
+          .accounts({
+            market: pda("market"),
+            user: user.publicKey,
+            userCollateral,
+            collateralVault: pda("vault"),
+            outcomeYesMint: pda("outcome_a"),
+            outcomeNoMint: pda("outcome_b"),
+            userOutcomeYes,
+            userOutcomeNo: (
+              await createOutcomeAccount(pda("outcome_b"), user.publicKey)
+            ).address,
+            collateralMint: mint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            nativeUnwrap: null,
+          })
+          .signers([user])
+          .rpc();
+
+        const yes = await getOutcomeAccount(userOutcomeYes);
+        assert.equal(yes.amount, oneToken);
+      });
+    }
+  });
+
+  describe("Native SOL Collateral", () => {
+    const amount = LAMPORTS_PER_SOL / 10;
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let accounts: any;
+
+    before(async () => {
+      await program.methods
+        .addAllowedCollateral()
+        .accounts({ admin: authority.publicKey, collateralMint: NATIVE_MINT })
+        .rpc();
+
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Settled in SOL")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint: NATIVE_MINT,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // No wSOL account anywhere, the user pays and gets paid in lamports
+      accounts = {
+        market: pda("market"),
+        user: user.publicKey,
+        userCollateral: null,
+        collateralVault: pda("vault"),
+        outcomeYesMint: pda("outcome_a"),
+        outcomeNoMint: pda("outcome_b"),
+        userOutcomeYes: (
+          await createOutcomeAccount(pda("outcome_a"), user.publicKey)
+        ).address,
+        userOutcomeNo: (
+          await createOutcomeAccount(pda("outcome_b"), user.publicKey)
+        ).address,
+        collateralMint: NATIVE_MINT,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+    });
+
+    it("Split wraps the signer's lamports into the vault", async () => {
+      const before = await provider.connection.getBalance(user.publicKey);
+
+      await program.methods
+        .splitTokens(marketId, new BN(amount))
+        .accounts(accounts)
+        .signers([user])
+        .rpc();
+
+      const after = await provider.connection.getBalance(user.publicKey);
+      const vault = await getAccount(provider.connection, pda("vault"));
+      assert.equal(Number(vault.amount), amount);
+      assert.isAtLeast(before - after, amount);
+
+      const yes = await getOutcomeAccount(accounts.userOutcomeYes);
+      assert.equal(Number(yes.amount), amount);
+    });
+
+    it("Merge unwraps the payout back to lamports", async () => {
+      const nativeUnwrap = PublicKey.findProgramAddressSync(
+        [Buffer.from("native_unwrap"), user.publicKey.toBuffer()],
+        program.programId
+      )[0];
+      const before = await provider.connection.getBalance(user.publicKey);
+
+      await program.methods
+        .mergeTokens(marketId)
+        .accounts({ ...accounts, nativeUnwrap })
+        .signers([user])
+        .rpc();
+
+      const after = await provider.connection.getBalance(user.publicKey);
+      // The temporary wSOL account's rent comes back when it is closed, only the fee is lost
+      assert.isAbove(after - before, amount - 10000);
+      assert.isNull(await provider.connection.getAccountInfo(nativeUnwrap));
+
+      const vault = await getAccount(provider.connection, pda("vault"));
+      assert.equal(Number(vault.amount), 0);
+    });
+
+    it("Payouts need native_unwrap when there is no collateral account", async () => {
+      await program.methods
+        .splitTokens(marketId, new BN(amount))
+        .accounts(accounts)
+        .signers([user])
+        .rpc();
+
+      try {
+        await program.methods
+          .mergeTokens(marketId)
+          .accounts({ ...accounts, nativeUnwrap: null })
+          .signers([user])
+          .rpc();
+
+        assert.fail("InvalidCollateralAccounts");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidCollateralAccounts");
+      }
+    });
+  });
+
+  describe("Trading Fees", () => {
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let maker: Keypair;
+    let taker: Keypair;
+    const accountsOf: Record<string, any> = {};
+    const quantity = 1000;
+    const price = 100;
+
+    const userStats = (owner: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_stats"),
+          owner.toBuffer(),
+          new BN(marketId).toArrayLike(Buffer, "le", 4),
+        ],
+        program.programId
+      )[0];
+    const traderVolume = (owner: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("trader_volume"), owner.toBuffer()],
+        program.programId
+      )[0];
+    // Each maker's UserStats followed by their TraderVolume
+    const makerAccounts = () =>
+      [userStats(maker.publicKey), traderVolume(maker.publicKey)].map(
+        (pubkey) => ({ pubkey, isSigner: false, isWritable: true })
+      );
+
+    before(async () => {
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Fees")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // 1% taker, 0.5% maker
+      await program.methods
+        .setMarketFees(marketId, 100, 50)
+        .accounts({ admin: authority.publicKey, market: pda("market") })
+        .rpc();
+
+      maker = Keypair.generate();
+      taker = Keypair.generate();
+      for (const trader of [maker, taker]) {
+        await provider.connection.confirmTransaction(
+          await provider.connection.requestAirdrop(
+            trader.publicKey,
+            LAMPORTS_PER_SOL
+          )
+        );
+        const userCollateral = (
+          await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            authority.payer,
+            collateralMint,
+            trader.publicKey
+          )
+        ).address;
+        await mintTo(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          userCollateral,
+          authority.publicKey,
+          initialCollateralAmount
+        );
+
+        accountsOf[trader.publicKey.toBase58()] = {
+          market: pda("market"),
+          user: trader.publicKey,
+          userCollateral,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          userOutcomeYes: (
+            await createOutcomeAccount(pda("outcome_a"), trader.publicKey)
+          ).address,
+          userOutcomeNo: (
+            await createOutcomeAccount(pda("outcome_b"), trader.publicKey)
+          ).address,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
+          referrer: null,
+          referrerStats: null,
+        };
+
+        // Creates the trader's UserStats, the maker also gets YES to sell
+        await program.methods
+          .splitTokens(marketId, new BN(quantity))
+          .accounts(accountsOf[trader.publicKey.toBase58()])
+          .signers([trader])
+          .rpc();
+      }
+    });
+
+    it("Charges the taker and the maker on a fill", async () => {
+      await program.methods
+        .placeOrder(
+          marketId,
+          { sell: {} },
+          { yes: {} },
+          new BN(quantity),
+          new BN(price),
+          new BN(10)
+        )
+        .accounts(accountsOf[maker.publicKey.toBase58()])
+        .signers([maker])
+        .rpc();
+
+      const takerCollateral = accountsOf[taker.publicKey.toBase58()].userCollateral;
+      const before = await getAccount(provider.connection, takerCollateral);
+
+      await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(quantity),
+          new BN(price),
+          new BN(10)
+        )
+        .accounts(accountsOf[taker.publicKey.toBase58()])
+        .remainingAccounts(makerAccounts())
+        .signers([taker])
+        .rpc();
+
+      const fill = quantity * price;
+      const takerFee = fill / 100;
+      const makerFee = fill / 200;
+
+      // The taker pays the fill plus the taker fee
+      const after = await getAccount(provider.connection, takerCollateral);
+      assert.equal(Number(before.amount) - Number(after.amount), fill + takerFee);
+
+      const makerStats = await program.account.userStats.fetch(
+        userStats(maker.publicKey)
+      );
+      assert.equal(makerStats.claimableCollateral.toNumber(), fill - makerFee);
+
+      const feeVault = await getAccount(provider.connection, pda("fee_vault"));
+      assert.equal(Number(feeVault.amount), takerFee + makerFee);
+
+      // Creator share is 0, everything accrues to the protocol
+      const market = await program.account.market.fetch(pda("market"));
+      assert.equal(market.protocolFeesAccrued.toNumber(), takerFee + makerFee);
+      assert.equal(market.creatorFeesAccrued.toNumber(), 0);
+    });
+
+    it("Tracks traded volume across markets", async () => {
+      for (const trader of [maker, taker]) {
+        const volume = await program.account.traderVolume.fetch(
+          traderVolume(trader.publicKey)
+        );
+        assert.equal(volume.currentVolume.toNumber(), quantity * price);
+      }
+    });
+
+    it("Top tier makers earn a rebate out of the taker fee", async () => {
+      // Both traders are past 1 unit of volume after the fill above
+      await program.methods
+        .setFeeTiers(
+          [{ minVolume: new BN(1), takerFeeBps: 100, makerFeeBps: -20 }],
+          new BN(30 * 24 * 60 * 60)
+        )
+        .accounts({ admin: authority.publicKey })
+        .rpc();
+
+      const makerKey = maker.publicKey.toBase58();
+      await program.methods
+        .splitTokens(marketId, new BN(quantity))
+        .accounts(accountsOf[makerKey])
+        .signers([maker])
+        .rpc();
+      await program.methods
+        .placeOrder(
+          marketId,
+          { sell: {} },
+          { yes: {} },
+          new BN(quantity),
+          new BN(price),
+          new BN(10)
+        )
+        .accounts(accountsOf[makerKey])
+        .signers([maker])
+        .rpc();
+
+      const statsBefore = await program.account.userStats.fetch(
+        userStats(maker.publicKey)
+      );
+      const vaultBefore = await getAccount(provider.connection, pda("fee_vault"));
+
+      try {
+        await program.methods
+          .placeOrder(
+            marketId,
+            { buy: {} },
+            { yes: {} },
+            new BN(quantity),
+            new BN(price),
+            new BN(10)
+          )
+          .accounts(accountsOf[taker.publicKey.toBase58()])
+          .remainingAccounts(makerAccounts())
+          .signers([taker])
+          .rpc();
+      } finally {
+        await program.methods
+          .setFeeTiers([], new BN(30 * 24 * 60 * 60))
+          .accounts({ admin: authority.publicKey })
+          .rpc();
+      }
+
+      const fill = quantity * price;
+      const takerFee = fill / 100;
+      const rebate = (fill * 20) / 10000;
+
+      const statsAfter = await program.account.userStats.fetch(
+        userStats(maker.publicKey)
+      );
+      assert.equal(
+        statsAfter.claimableCollateral.sub(statsBefore.claimableCollateral).toNumber(),
+        fill + rebate
+      );
+      const vaultAfter = await getAccount(provider.connection, pda("fee_vault"));
+      assert.equal(
+        Number(vaultAfter.amount) - Number(vaultBefore.amount),
+        takerFee - rebate
+      );
+    });
+
+    it("Only the market authority can withdraw creator fees", async () => {
+      const accounts = accountsOf[taker.publicKey.toBase58()];
+      try {
+        await program.methods
+          .withdrawCreatorFees(marketId)
+          .accounts({
+            recipient: taker.publicKey,
+            market: pda("market"),
+            collateralMint,
+            feeVault: pda("fee_vault"),
+            recipientCollateral: accounts.userCollateral,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([taker])
+          .rpc();
+
+        assert.fail("NotAuthorized");
+      } catch (err) {
+        expect(err.toString()).to.include("NotAuthorized");
+      }
+    });
+
+    it("Fee recipient withdraws the protocol's share", async () => {
+      const recipientCollateral = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          authority.publicKey
+        )
+      ).address;
+      const before = await getAccount(provider.connection, recipientCollateral);
+      const { protocolFeesAccrued } = await program.account.market.fetch(
+        pda("market")
+      );
+
+      await program.methods
+        .withdrawProtocolFees(marketId)
+        .accounts({
+          recipient: authority.publicKey,
+          market: pda("market"),
+          collateralMint,
+          feeVault: pda("fee_vault"),
+          recipientCollateral,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const after = await getAccount(provider.connection, recipientCollateral);
+      assert.equal(
+        Number(after.amount) - Number(before.amount),
+        protocolFeesAccrued.toNumber()
+      );
+      const market = await program.account.market.fetch(pda("market"));
+      assert.equal(market.protocolFeesAccrued.toNumber(), 0);
+      const feeVault = await getAccount(provider.connection, pda("fee_vault"));
+      assert.equal(Number(feeVault.amount), 0);
+    });
+
+    it("Referrers earn a share of the taker fees they bring in", async () => {
+      const referrer = Keypair.generate();
+      const referrerStats = userStats(referrer.publicKey);
+      await program.methods
+        .setReferralFeeShare(5000)
+        .accounts({ admin: authority.publicKey })
+        .rpc();
+
+      const makerKey = maker.publicKey.toBase58();
+      await program.methods
+        .splitTokens(marketId, new BN(quantity))
+        .accounts(accountsOf[makerKey])
+        .signers([maker])
+        .rpc();
+      await program.methods
+        .placeOrder(
+          marketId,
+          { sell: {} },
+          { yes: {} },
+          new BN(quantity),
+          new BN(price),
+          new BN(10)
+        )
+        .accounts(accountsOf[makerKey])
+        .signers([maker])
+        .rpc();
+
+      await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(quantity),
+          new BN(price),
+          new BN(10)
+        )
+        .accounts({
+          ...accountsOf[taker.publicKey.toBase58()],
+          referrer: referrer.publicKey,
+          referrerStats,
+        })
+        .remainingAccounts(makerAccounts())
+        .signers([taker])
+        .rpc();
+
+      // Half of the 1% taker fee
+      const referralFee = (quantity * price) / 100 / 2;
+      const takerStats = await program.account.userStats.fetch(
+        userStats(taker.publicKey)
+      );
+      assert.isTrue(takerStats.referrer.equals(referrer.publicKey));
+      let stats = await program.account.userStats.fetch(referrerStats);
+      assert.equal(stats.referralFeesClaimable.toNumber(), referralFee);
+
+      const recipientCollateral = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          referrer.publicKey
+        )
+      ).address;
+      await program.methods
+        .withdrawReferralFees(marketId)
+        .accounts({
+          referrer: referrer.publicKey,
+          market: pda("market"),
+          collateralMint,
+          feeVault: pda("fee_vault"),
+          recipientCollateral,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([referrer])
+        .rpc();
+
+      const collateral = await getAccount(provider.connection, recipientCollateral);
+      assert.equal(Number(collateral.amount), referralFee);
+      stats = await program.account.userStats.fetch(referrerStats);
+      assert.equal(stats.referralFeesClaimable.toNumber(), 0);
+
+      await program.methods
+        .setReferralFeeShare(0)
+        .accounts({ admin: authority.publicKey })
+        .rpc();
+    });
+
+    // The maker rests a buy of 300 @ 67, reserving 0.5% of 20100 = 100 (100.5 rounded down),
+    // and the taker sells into it in three fills of 100
+    const restBuyAndFillInThirds = async (beforeFills?: () => Promise<unknown>) => {
+      const makerKey = maker.publicKey.toBase58();
+      await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(300),
+          new BN(67),
+          new BN(10)
+        )
+        .accounts(accountsOf[makerKey])
+        .signers([maker])
+        .rpc();
+      const placed = await program.account.userStats.fetch(
+        userStats(maker.publicKey)
+      );
+      const book = await program.account.orderBook.fetch(pda("orderbook"));
+      assert.equal(book.yesBuyOrders[0].feeReserved.toNumber(), 100);
+
+      if (beforeFills) {
+        await beforeFills();
+      }
+      for (let fill = 0; fill < 3; fill++) {
+        await program.methods
+          .placeOrder(
+            marketId,
+            { sell: {} },
+            { yes: {} },
+            new BN(100),
+            new BN(67),
+            new BN(10)
+          )
+          .accounts(accountsOf[taker.publicKey.toBase58()])
+          .remainingAccounts(makerAccounts())
+          .signers([taker])
+          .rpc();
+      }
+
+      const filled = await program.account.userStats.fetch(
+        userStats(maker.publicKey)
+      );
+      const { yesBuyOrders } = await program.account.orderBook.fetch(
+        pda("orderbook")
+      );
+      assert.equal(yesBuyOrders.length, 0);
+      // The whole reserve is out of locked collateral once the order completes
+      assert.equal(
+        placed.lockedCollateral.sub(filled.lockedCollateral).toNumber(),
+        100
+      );
+      return filled.claimableCollateral.sub(placed.claimableCollateral).toNumber();
+    };
+
+    it("A completed buy gets the rounding dust of its fee reserve back", async () => {
+      const vaultBefore = await getAccount(provider.connection, pda("fee_vault"));
+
+      const refunded = await restBuyAndFillInThirds();
+
+      // Each fill charges 33 (33.5 rounded down), 1 of the 100 reserved is left over
+      assert.equal(refunded, 1);
+      const vaultAfter = await getAccount(provider.connection, pda("fee_vault"));
+      const takerFees = 3 * 67;
+      assert.equal(
+        Number(vaultAfter.amount) - Number(vaultBefore.amount),
+        takerFees + 3 * 33
+      );
+    });
+
+    it("A maker who reaches a rebate tier while resting gets the whole reserve back", async () => {
+      try {
+        const refunded = await restBuyAndFillInThirds(() =>
+          program.methods
+            .setFeeTiers(
+              [{ minVolume: new BN(1), takerFeeBps: 100, makerFeeBps: -20 }],
+              new BN(30 * 24 * 60 * 60)
+            )
+            .accounts({ admin: authority.publicKey })
+            .rpc()
+        );
+
+        // Nothing is charged against the reserve, and each fill of 6700 earns a 13 rebate
+        assert.equal(refunded, 100 + 3 * 13);
+      } finally {
+        await program.methods
+          .setFeeTiers([], new BN(30 * 24 * 60 * 60))
+          .accounts({ admin: authority.publicKey })
+          .rpc();
+      }
+    });
+  });
+
+  describe("AMM Pool", () => {
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let reserve: (mint: PublicKey) => PublicKey;
+    let lp: Keypair;
+    let trader: Keypair;
+    const accountsOf: Record<string, any> = {};
+    const feeBps = 200;
+
+    // Same math as AmmPool::buy_amount / sell_amount
+    const ceilDiv = (a: number, b: number) => Math.floor((a + b - 1) / b);
+    const buyAmount = (amount: number, buyReserve: number, otherReserve: number) => {
+      const net = amount - Math.floor((amount * feeBps) / 10000);
+      return (
+        buyReserve + net - ceilDiv(buyReserve * otherReserve, otherReserve + net)
+      );
+    };
+    const sellAmount = (returnAmount: number, sellReserve: number, otherReserve: number) => {
+      const gross = ceilDiv(returnAmount * 10000, 10000 - feeBps);
+      return (
+        ceilDiv(sellReserve * otherReserve, otherReserve - gross) + gross - sellReserve
+      );
+    };
+    const reserves = async () => [
+      Number((await getOutcomeAccount(reserve(pda("outcome_a")))).amount),
+      Number((await getOutcomeAccount(reserve(pda("outcome_b")))).amount),
+    ];
+    const lpSupply = async () =>
+      Number(
+        (
+          await getMint(
+            provider.connection,
+            pda("amm_lp_mint"),
+            undefined,
+            TOKEN_2022_PROGRAM_ID
+          )
+        ).supply
+      );
+
+    before(async () => {
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+      reserve = (mint: PublicKey) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("amm_reserve"), idLE, mint.toBuffer()],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("AMM")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      await program.methods
+        .initializeAmmPool(marketId, feeBps)
+        .accounts({
+          authority: authority.publicKey,
+          market: pda("market"),
+          pool: pda("amm_pool"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          lpMint: pda("amm_lp_mint"),
+          yesReserve: reserve(pda("outcome_a")),
+          noReserve: reserve(pda("outcome_b")),
+        })
+        .rpc();
+
+      lp = Keypair.generate();
+      trader = Keypair.generate();
+      for (const owner of [lp, trader]) {
+        await provider.connection.confirmTransaction(
+          await provider.connection.requestAirdrop(
+            owner.publicKey,
+            LAMPORTS_PER_SOL
+          )
+        );
+        const userCollateral = (
+          await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            authority.payer,
+            collateralMint,
+            owner.publicKey
+          )
+        ).address;
+        await mintTo(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          userCollateral,
+          authority.publicKey,
+          initialCollateralAmount
+        );
+
+        accountsOf[owner.publicKey.toBase58()] = {
+          user: owner.publicKey,
+          market: pda("market"),
+          pool: pda("amm_pool"),
+          userCollateral,
+          collateralMint,
+          collateralVault: pda("vault"),
+          nativeUnwrap: null,
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          yesReserve: reserve(pda("outcome_a")),
+          noReserve: reserve(pda("outcome_b")),
+          userOutcomeYes: (
+            await createOutcomeAccount(pda("outcome_a"), owner.publicKey)
+          ).address,
+          userOutcomeNo: (
+            await createOutcomeAccount(pda("outcome_b"), owner.publicKey)
+          ).address,
+          lpMint: pda("amm_lp_mint"),
+          userLp: (await createOutcomeAccount(pda("amm_lp_mint"), owner.publicKey))
+            .address,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        };
+      }
+    });
+
+    it("Opens the pool with the market's reserves", async () => {
+      const pool = await program.account.ammPool.fetch(pda("amm_pool"));
+      assert.equal(pool.marketId, marketId);
+      assert.equal(pool.feeBps, feeBps);
+      assert.isTrue(pool.lpMint.equals(pda("amm_lp_mint")));
+      assert.isTrue(pool.yesReserve.equals(reserve(pda("outcome_a"))));
+      assert.isTrue(pool.noReserve.equals(reserve(pda("outcome_b"))));
+    });
+
+    it("The first deposit seeds the pool at even odds", async () => {
+      const accounts = accountsOf[lp.publicKey.toBase58()];
+      await program.methods
+        .addLiquidity(marketId, new BN(10000))
+        .accounts(accounts)
+        .signers([lp])
+        .rpc();
+
+      assert.deepEqual(await reserves(), [10000, 10000]);
+      const shares = await getOutcomeAccount(accounts.userLp);
+      assert.equal(Number(shares.amount), 10000);
+    });
+
+    it("Buys from the pool and leaves the fee in the reserves", async () => {
+      const accounts = accountsOf[trader.publicKey.toBase58()];
+      const [yesBefore, noBefore] = await reserves();
+      const expected = buyAmount(1000, yesBefore, noBefore);
+
+      await program.methods
+        .ammBuy(marketId, { yes: {} }, new BN(1000), new BN(expected))
+        .accounts(accounts)
+        .signers([trader])
+        .rpc();
+
+      const yes = await getOutcomeAccount(accounts.userOutcomeYes);
+      assert.equal(Number(yes.amount), expected);
+      const [yesAfter, noAfter] = await reserves();
+      assert.equal(yesAfter, yesBefore + 1000 - expected);
+      assert.equal(noAfter, noBefore + 1000);
+      // The fee grows the invariant
+      assert.isAbove(yesAfter * noAfter, yesBefore * noBefore);
+    });
+
+    it("Rejects trades past the slippage limit", async () => {
+      const accounts = accountsOf[trader.publicKey.toBase58()];
+      try {
+        await program.methods
+          .ammBuy(marketId, { yes: {} }, new BN(1000), new BN(1000000))
+          .accounts(accounts)
+          .signers([trader])
+          .rpc();
+
+        assert.fail("SlippageExceeded");
+      } catch (err) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+    });
+
+    it("Sells back to the pool for an exact collateral amount", async () => {
+      const accounts = accountsOf[trader.publicKey.toBase58()];
+      const [yesBefore, noBefore] = await reserves();
+      const tokensIn = sellAmount(500, yesBefore, noBefore);
+      const yesHeld = Number(
+        (await getOutcomeAccount(accounts.userOutcomeYes)).amount
+      );
+      const collateralBefore = Number(
+        (await getAccount(provider.connection, accounts.userCollateral)).amount
+      );
+
+      await program.methods
+        .ammSell(marketId, { yes: {} }, new BN(500), new BN(tokensIn))
+        .accounts(accounts)
+        .signers([trader])
+        .rpc();
+
+      const collateral = await getAccount(
+        provider.connection,
+        accounts.userCollateral
+      );
+      assert.equal(Number(collateral.amount), collateralBefore + 500);
+      const yes = await getOutcomeAccount(accounts.userOutcomeYes);
+      assert.equal(Number(yes.amount), yesHeld - tokensIn);
+      assert.deepEqual(await reserves(), [
+        yesBefore + tokensIn - 500,
+        noBefore - 500,
+      ]);
+    });
+
+    it("Later deposits get the surplus side back and keep the price", async () => {
+      const accounts = accountsOf[trader.publicKey.toBase58()];
+      const [yesBefore, noBefore] = await reserves();
+      const supply = await lpSupply();
+      const weight = Math.max(yesBefore, noBefore);
+      const yesHeld = Number(
+        (await getOutcomeAccount(accounts.userOutcomeYes)).amount
+      );
+
+      await program.methods
+        .addLiquidity(marketId, new BN(1000))
+        .accounts(accounts)
+        .signers([trader])
+        .rpc();
+
+      const shares = await getOutcomeAccount(accounts.userLp);
+      assert.equal(Number(shares.amount), Math.floor((1000 * supply) / weight));
+      const [yesAfter, noAfter] = await reserves();
+      assert.equal(yesAfter, yesBefore + Math.floor((1000 * yesBefore) / weight));
+      assert.equal(noAfter, noBefore + Math.floor((1000 * noBefore) / weight));
+      // The pool is short on YES after the buy, the YES it doesn't keep goes back
+      assert.equal(noAfter, noBefore + 1000);
+      const yes = await getOutcomeAccount(accounts.userOutcomeYes);
+      assert.equal(Number(yes.amount), yesHeld + 1000 - (yesAfter - yesBefore));
+    });
+
+    it("LPs withdraw their share of both reserves, fees included", async () => {
+      const accounts = accountsOf[lp.publicKey.toBase58()];
+      const [yesBefore, noBefore] = await reserves();
+      const supply = await lpSupply();
+
+      await program.methods
+        .removeLiquidity(marketId, new BN(10000))
+        .accounts(accounts)
+        .signers([lp])
+        .rpc();
+
+      const yes = await getOutcomeAccount(accounts.userOutcomeYes);
+      const no = await getOutcomeAccount(accounts.userOutcomeNo);
+      assert.equal(Number(yes.amount), Math.floor((yesBefore * 10000) / supply));
+      assert.equal(Number(no.amount), Math.floor((noBefore * 10000) / supply));
+      assert.equal(Number((await getOutcomeAccount(accounts.userLp)).amount), 0);
+      assert.equal(await lpSupply(), supply - 10000);
+    });
+  });
+
+  describe("LMSR", () => {
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let trader: Keypair;
+    let accounts: Record<string, any>;
+    let authorityCollateral: PublicKey;
+    const liquidity = 1000000;
+
+    // b * ln(e^(q_yes / b) + e^(q_no / b)), the on-chain fixed point agrees to far below 1 unit
+    const cost = (qYes: number, qNo: number) =>
+      liquidity *
+      Math.log(Math.exp(qYes / liquidity) + Math.exp(qNo / liquidity));
+    const balance = async (address: PublicKey) =>
+      Number((await getAccount(provider.connection, address)).amount);
+
+    before(async () => {
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("LMSR")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      authorityCollateral = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          authority.publicKey
+        )
+      ).address;
+      await mintTo(
+        provider.connection,
+        authority.payer,
+        collateralMint,
+        authorityCollateral,
+        authority.publicKey,
+        initialCollateralAmount
+      );
+
+      trader = Keypair.generate();
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(trader.publicKey, LAMPORTS_PER_SOL)
+      );
+      const userCollateral = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          trader.publicKey
+        )
+      ).address;
+      await mintTo(
+        provider.connection,
+        authority.payer,
+        collateralMint,
+        userCollateral,
+        authority.publicKey,
+        initialCollateralAmount
+      );
+      accounts = {
+        user: trader.publicKey,
+        market: pda("market"),
+        lmsr: pda("lmsr"),
+        userCollateral,
+        collateralMint,
+        collateralVault: pda("vault"),
+        nativeUnwrap: null,
+        outcomeYesMint: pda("outcome_a"),
+        outcomeNoMint: pda("outcome_b"),
+        userOutcomeYes: (
+          await createOutcomeAccount(pda("outcome_a"), trader.publicKey)
+        ).address,
+        userOutcomeNo: (
+          await createOutcomeAccount(pda("outcome_b"), trader.publicKey)
+        ).address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+    });
+
+    it("The creator funds b * ln(2) of subsidy", async () => {
+      const before = await balance(authorityCollateral);
+      await program.methods
+        .initializeLmsr(marketId, new BN(liquidity))
+        .accounts({
+          authority: authority.publicKey,
+          market: pda("market"),
+          lmsr: pda("lmsr"),
+          authorityCollateral,
+          collateralMint,
+          collateralVault: pda("vault"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const subsidy = Math.ceil(liquidity * Math.LN2);
+      assert.equal(before - (await balance(authorityCollateral)), subsidy);
+      const lmsr = await program.account.lmsrMaker.fetch(pda("lmsr"));
+      assert.equal(lmsr.subsidy.toNumber(), subsidy);
+      assert.equal(lmsr.collateral.toNumber(), subsidy);
+      const market = await program.account.market.fetch(pda("market"));
+      assert.deepEqual(market.amm, { lmsr: {} });
+    });
+
+    it("A market runs only one AMM", async () => {
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      const reserve = (mint: PublicKey) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("amm_reserve"), idLE, mint.toBuffer()],
+          program.programId
+        )[0];
+      try {
+        await program.methods
+          .initializeAmmPool(marketId, 0)
+          .accounts({
+            authority: authority.publicKey,
+            market: pda("market"),
+            pool: pda("amm_pool"),
+            outcomeYesMint: pda("outcome_a"),
+            outcomeNoMint: pda("outcome_b"),
+            lpMint: pda("amm_lp_mint"),
+            yesReserve: reserve(pda("outcome_a")),
+            noReserve: reserve(pda("outcome_b")),
+          })
+          .rpc();
+
+        assert.fail("AmmAlreadyEnabled");
+      } catch (err) {
+        expect(err.toString()).to.include("AmmAlreadyEnabled");
+      }
+    });
+
+    it("Buys at the cost function difference", async () => {
+      const expected = Math.ceil(cost(500000, 0) - cost(0, 0));
+      try {
+        await program.methods
+          .lmsrBuy(marketId, { yes: {} }, new BN(500000), new BN(expected - 10))
+          .accounts(accounts)
+          .signers([trader])
+          .rpc();
+
+        assert.fail("SlippageExceeded");
+      } catch (err) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+
+      const before = await balance(accounts.userCollateral);
+      await program.methods
+        .lmsrBuy(marketId, { yes: {} }, new BN(500000), new BN(expected + 1))
+        .accounts(accounts)
+        .signers([trader])
+        .rpc();
+
+      const paid = before - (await balance(accounts.userCollateral));
+      assert.closeTo(paid, expected, 1);
+      const yes = await getOutcomeAccount(accounts.userOutcomeYes);
+      assert.equal(Number(yes.amount), 500000);
+      const lmsr = await program.account.lmsrMaker.fetch(pda("lmsr"));
+      assert.equal(lmsr.yesShares.toNumber(), 500000);
+      assert.equal(lmsr.collateral.toNumber(), lmsr.subsidy.toNumber() + paid);
+    });
+
+    it("Sells back along the same curve", async () => {
+      const expected = Math.floor(cost(500000, 0) - cost(300000, 0));
+      const before = await balance(accounts.userCollateral);
+      await program.methods
+        .lmsrSell(marketId, { yes: {} }, new BN(200000), new BN(expected - 1))
+        .accounts(accounts)
+        .signers([trader])
+        .rpc();
+
+      assert.closeTo(
+        (await balance(accounts.userCollateral)) - before,
+        expected,
+        1
+      );
+      const yes = await getOutcomeAccount(accounts.userOutcomeYes);
+      assert.equal(Number(yes.amount), 300000);
+    });
+
+    it("Returns the leftover subsidy to the creator after settlement", async () => {
+      await program.methods
+        .setWinningSide(marketId, { outcomeA: {} })
+        .accounts({
+          authority: authority.publicKey,
+          market: pda("market"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+        })
+        .rpc();
+
+      const lmsr = await program.account.lmsrMaker.fetch(pda("lmsr"));
+      const before = await balance(authorityCollateral);
+      const withdraw = () =>
+        program.methods
+          .withdrawLmsrSubsidy(marketId)
+          .accounts({
+            creator: authority.publicKey,
+            market: pda("market"),
+            lmsr: pda("lmsr"),
+            creatorCollateral: authorityCollateral,
+            collateralMint,
+            collateralVault: pda("vault"),
+            nativeUnwrap: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+      await withdraw();
+
+      // The 300000 winning YES stay in the vault for their claims
+      const leftover = lmsr.collateral.toNumber() - 300000;
+      assert.equal((await balance(authorityCollateral)) - before, leftover);
+      assert.equal(await balance(pda("vault")), 300000);
+
+      try {
+        await withdraw();
+        assert.fail("SubsidyAlreadyWithdrawn");
+      } catch (err) {
+        expect(err.toString()).to.include("SubsidyAlreadyWithdrawn");
+      }
+    });
+
+    it("A voided market returns the subsidy minus the refunds it owes", async () => {
+      const { nextMarketId: voidedId } =
+        await program.account.marketCounter.fetch(marketCounter);
+      const idLE = new BN(voidedId).toArrayLike(Buffer, "le", 4);
+      const voidedPda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("LMSR voided")
+        )
+        .accounts({
+          market: voidedPda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: voidedPda("vault"),
+          feeVault: voidedPda("fee_vault"),
+          outcomeYesMint: voidedPda("outcome_a"),
+          outcomeNoMint: voidedPda("outcome_b"),
+          orderbook: voidedPda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      await program.methods
+        .initializeLmsr(voidedId, new BN(liquidity))
+        .accounts({
+          authority: authority.publicKey,
+          market: voidedPda("market"),
+          lmsr: voidedPda("lmsr"),
+          authorityCollateral,
+          collateralMint,
+          collateralVault: voidedPda("vault"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      await program.methods
+        .lmsrBuy(voidedId, { yes: {} }, new BN(300001), new BN(liquidity))
+        .accounts({
+          ...accounts,
+          market: voidedPda("market"),
+          lmsr: voidedPda("lmsr"),
+          collateralVault: voidedPda("vault"),
+          outcomeYesMint: voidedPda("outcome_a"),
+          outcomeNoMint: voidedPda("outcome_b"),
+          userOutcomeYes: (
+            await createOutcomeAccount(voidedPda("outcome_a"), trader.publicKey)
+          ).address,
+          userOutcomeNo: (
+            await createOutcomeAccount(voidedPda("outcome_b"), trader.publicKey)
+          ).address,
+        })
+        .signers([trader])
+        .rpc();
+      await program.methods
+        .setMarketStatus(voidedId, { voided: {} })
+        .accounts({ authority: authority.publicKey, market: voidedPda("market") })
+        .rpc();
+
+      const lmsr = await program.account.lmsrMaker.fetch(voidedPda("lmsr"));
+      const before = await balance(authorityCollateral);
+      await program.methods
+        .withdrawLmsrSubsidy(voidedId)
+        .accounts({
+          creator: authority.publicKey,
+          market: voidedPda("market"),
+          lmsr: voidedPda("lmsr"),
+          creatorCollateral: authorityCollateral,
+          collateralMint,
+          collateralVault: voidedPda("vault"),
+          nativeUnwrap: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // Half a unit per YES the maker sold stays behind for the refunds, rounded up
+      const owed = Math.ceil(300001 / 2);
+      assert.equal(
+        (await balance(authorityCollateral)) - before,
+        lmsr.collateral.toNumber() - owed
+      );
+      assert.equal(await balance(voidedPda("vault")), owed);
+    });
+  });
+
+  describe("Market Order Routing", () => {
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let reserve: (mint: PublicKey) => PublicKey;
+    let maker: Keypair;
+    let taker: Keypair;
+    const accountsOf: Record<string, any> = {};
+
+    const userStats = (owner: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_stats"),
+          owner.toBuffer(),
+          new BN(marketId).toArrayLike(Buffer, "le", 4),
+        ],
+        program.programId
+      )[0];
+    const traderVolume = (owner: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("trader_volume"), owner.toBuffer()],
+        program.programId
+      )[0];
+    const makerAccounts = () =>
+      [userStats(maker.publicKey), traderVolume(maker.publicKey)].map(
+        (pubkey) => ({ pubkey, isSigner: false, isWritable: true })
+      );
+    const reserves = async () => [
+      Number((await getOutcomeAccount(reserve(pda("outcome_a")))).amount),
+      Number((await getOutcomeAccount(reserve(pda("outcome_b")))).amount),
+    ];
+    const balance = async (address: PublicKey) =>
+      Number((await getAccount(provider.connection, address)).amount);
+    const marketOrder = (
+      side: object,
+      quantity: number,
+      limitPrice: number,
+      slippageLimit: BN
+    ) =>
+      program.methods.marketOrder(marketId, {
+        side,
+        tokenType: { yes: {} },
+        quantity: new BN(quantity),
+        limitPrice: new BN(limitPrice),
+        slippageLimit,
+        maxIteration: new BN(10),
+      });
+
+    before(async () => {
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+      reserve = (mint: PublicKey) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("amm_reserve"), idLE, mint.toBuffer()],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Routing")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // No fees on either venue keeps the expected amounts exact
+      await program.methods
+        .setMarketFees(marketId, 0, 0)
+        .accounts({ admin: authority.publicKey, market: pda("market") })
+        .rpc();
+      await program.methods
+        .initializeAmmPool(marketId, 0)
+        .accounts({
+          authority: authority.publicKey,
+          market: pda("market"),
+          pool: pda("amm_pool"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          lpMint: pda("amm_lp_mint"),
+          yesReserve: reserve(pda("outcome_a")),
+          noReserve: reserve(pda("outcome_b")),
+        })
+        .rpc();
+
+      maker = Keypair.generate();
+      taker = Keypair.generate();
+      for (const trader of [maker, taker]) {
+        await provider.connection.confirmTransaction(
+          await provider.connection.requestAirdrop(
+            trader.publicKey,
+            LAMPORTS_PER_SOL
+          )
+        );
+        const userCollateral = (
+          await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            authority.payer,
+            collateralMint,
+            trader.publicKey
+          )
+        ).address;
+        await mintTo(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          userCollateral,
+          authority.publicKey,
+          initialCollateralAmount
+        );
+
+        accountsOf[trader.publicKey.toBase58()] = {
+          market: pda("market"),
+          user: trader.publicKey,
+          userCollateral,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          userOutcomeYes: (
+            await createOutcomeAccount(pda("outcome_a"), trader.publicKey)
+          ).address,
+          userOutcomeNo: (
+            await createOutcomeAccount(pda("outcome_b"), trader.publicKey)
+          ).address,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          pool: pda("amm_pool"),
+          yesReserve: reserve(pda("outcome_a")),
+          noReserve: reserve(pda("outcome_b")),
+          lmsr: null,
+          nativeUnwrap: null,
+          referrer: null,
+          referrerStats: null,
+        };
+
+        await program.methods
+          .splitTokens(marketId, new BN(1000))
+          .accounts(accountsOf[trader.publicKey.toBase58()])
+          .signers([trader])
+          .rpc();
+      }
+
+      // The maker seeds the pool at even odds, YES trades around 0.5 collateral there
+      await program.methods
+        .addLiquidity(marketId, new BN(100000))
+        .accounts({
+          ...accountsOf[maker.publicKey.toBase58()],
+          lpMint: pda("amm_lp_mint"),
+          userLp: (await createOutcomeAccount(pda("amm_lp_mint"), maker.publicKey))
+            .address,
+        })
+        .signers([maker])
+        .rpc();
+    });
+
+    it("Needs the AMM accounts when the market has an AMM", async () => {
+      try {
+        await marketOrder({ buy: {} }, 100, 1, new BN(1000000))
+          .accounts({
+            ...accountsOf[taker.publicKey.toBase58()],
+            pool: null,
+            yesReserve: null,
+            noReserve: null,
+          })
+          .signers([taker])
+          .rpc();
+
+        assert.fail("AmmAccountNotProvided");
+      } catch (err) {
+        expect(err.toString()).to.include("AmmAccountNotProvided");
+      }
+    });
+
+    it("Sells to the better book bid first and the rest to the pool", async () => {
+      // A bid at 2 per token beats the pool's price of about 0.5
+      await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(400),
+          new BN(2),
+          new BN(10)
+        )
+        .accounts(accountsOf[maker.publicKey.toBase58()])
+        .signers([maker])
+        .rpc();
+
+      const accounts = accountsOf[taker.publicKey.toBase58()];
+      const [yesBefore, noBefore] = await reserves();
+      const collateralBefore = await balance(accounts.userCollateral);
+
+      // Limit 0 takes any price, so the pool fills what the bid can't
+      await marketOrder({ sell: {} }, 1000, 0, new BN(0))
+        .accounts(accounts)
+        .remainingAccounts(makerAccounts())
+        .signers([taker])
+        .rpc();
+
+      const takerStats = await program.account.userStats.fetch(
+        userStats(taker.publicKey)
+      );
+      assert.equal(takerStats.claimableCollateral.toNumber(), 400 * 2);
+      const book = await program.account.orderBook.fetch(pda("orderbook"));
+      assert.equal(book.yesBuyOrders.length, 0);
+
+      // The pool took 600 YES and merged pairs to pay the rest
+      const [yesAfter, noAfter] = await reserves();
+      const paid = (await balance(accounts.userCollateral)) - collateralBefore;
+      assert.isAbove(paid, 0);
+      assert.equal(noAfter, noBefore - paid);
+      assert.equal(yesAfter, yesBefore + 600 - paid);
+      assert.isAtLeast(yesAfter * noAfter, yesBefore * noBefore);
+      assert.equal(Number((await getOutcomeAccount(accounts.userOutcomeYes)).amount), 0);
+    });
+
+    it("Buys from the pool while it's cheaper than the resting asks", async () => {
+      await program.methods
+        .placeOrder(
+          marketId,
+          { sell: {} },
+          { yes: {} },
+          new BN(300),
+          new BN(1),
+          new BN(10)
+        )
+        .accounts(accountsOf[maker.publicKey.toBase58()])
+        .signers([maker])
+        .rpc();
+
+      const accounts = accountsOf[taker.publicKey.toBase58()];
+      const [yesBefore, noBefore] = await reserves();
+      const collateralBefore = await balance(accounts.userCollateral);
+      const volumeBefore = (
+        await program.account.traderVolume.fetch(traderVolume(taker.publicKey))
+      ).currentVolume.toNumber();
+
+      // The pool stays below 1 per token, so it fills the whole order
+      await marketOrder({ buy: {} }, 500, 1, new BN(1000))
+        .accounts(accounts)
+        .remainingAccounts(makerAccounts())
+        .signers([taker])
+        .rpc();
+
+      const cost = collateralBefore - (await balance(accounts.userCollateral));
+      assert.isBelow(cost, 500);
+      // The routed AMM fill counts toward the taker's fee tier
+      const volume = await program.account.traderVolume.fetch(
+        traderVolume(taker.publicKey)
+      );
+      assert.equal(volume.currentVolume.toNumber(), volumeBefore + cost);
+      assert.equal(Number((await getOutcomeAccount(accounts.userOutcomeYes)).amount), 500);
+      assert.deepEqual(await reserves(), [yesBefore + cost - 500, noBefore + cost]);
+      const book = await program.account.orderBook.fetch(pda("orderbook"));
+      assert.equal(book.yesSellOrders[0].filledquantity.toNumber(), 0);
+    });
+
+    it("Fails as a whole past the slippage limit", async () => {
+      const accounts = accountsOf[taker.publicKey.toBase58()];
+      try {
+        await marketOrder({ buy: {} }, 500, 1, new BN(10))
+          .accounts(accounts)
+          .remainingAccounts(makerAccounts())
+          .signers([taker])
+          .rpc();
+
+        assert.fail("SlippageExceeded");
+      } catch (err) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+    });
+  });
+
+  describe("Split and Sell / Buy and Merge", () => {
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let escrow: (mint: PublicKey) => PublicKey;
+    let maker: Keypair;
+    let taker: Keypair;
+    const accountsOf: Record<string, any> = {};
+
+    const userStats = (owner: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_stats"),
+          owner.toBuffer(),
+          new BN(marketId).toArrayLike(Buffer, "le", 4),
+        ],
+        program.programId
+      )[0];
+    const traderVolume = (owner: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("trader_volume"), owner.toBuffer()],
+        program.programId
+      )[0];
+    const makerAccounts = () =>
+      [userStats(maker.publicKey), traderVolume(maker.publicKey)].map(
+        (pubkey) => ({ pubkey, isSigner: false, isWritable: true })
+      );
+    const balance = async (address: PublicKey) =>
+      Number((await getAccount(provider.connection, address)).amount);
+    const outcomeBalance = async (address: PublicKey) =>
+      Number((await getOutcomeAccount(address)).amount);
+    const params = (quantity: number, limitPrice: number) => ({
+      tokenType: { yes: {} },
+      quantity: new BN(quantity),
+      limitPrice: new BN(limitPrice),
+      maxIteration: new BN(10),
+    });
+
+    before(async () => {
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+      escrow = (mint: PublicKey) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("escrow"), idLE, mint.toBuffer()],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Shorting")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // No fees keeps the netted amounts exact
+      await program.methods
+        .setMarketFees(marketId, 0, 0)
+        .accounts({ admin: authority.publicKey, market: pda("market") })
+        .rpc();
+
+      maker = Keypair.generate();
+      taker = Keypair.generate();
+      for (const trader of [maker, taker]) {
+        await provider.connection.confirmTransaction(
+          await provider.connection.requestAirdrop(
+            trader.publicKey,
+            LAMPORTS_PER_SOL
+          )
+        );
+        const userCollateral = (
+          await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            authority.payer,
+            collateralMint,
+            trader.publicKey
+          )
+        ).address;
+        await mintTo(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          userCollateral,
+          authority.publicKey,
+          initialCollateralAmount
+        );
+
+        accountsOf[trader.publicKey.toBase58()] = {
+          market: pda("market"),
+          user: trader.publicKey,
+          userCollateral,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          userOutcomeYes: (
+            await createOutcomeAccount(pda("outcome_a"), trader.publicKey)
+          ).address,
+          userOutcomeNo: (
+            await createOutcomeAccount(pda("outcome_b"), trader.publicKey)
+          ).address,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
+          referrer: null,
+          referrerStats: null,
+        };
+      }
+
+      // Only the maker holds outcome tokens, the taker starts with collateral alone
+      await program.methods
+        .splitTokens(marketId, new BN(1000))
+        .accounts(accountsOf[maker.publicKey.toBase58()])
+        .signers([maker])
+        .rpc();
+    });
+
+    it("Shorts YES by splitting and selling into the bids", async () => {
+      await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(300),
+          new BN(2),
+          new BN(10)
+        )
+        .accounts(accountsOf[maker.publicKey.toBase58()])
+        .signers([maker])
+        .rpc();
+
+      const accounts = accountsOf[taker.publicKey.toBase58()];
+      const collateralBefore = await balance(accounts.userCollateral);
+      const escrowBefore = await outcomeBalance(escrow(pda("outcome_a")));
+
+      // Only the 300 the bid takes is split, the sale pays 600 against the 300 it costs
+      await program.methods
+        .splitAndSell(marketId, params(500, 2))
+        .accounts(accounts)
+        .remainingAccounts(makerAccounts())
+        .signers([taker])
+        .rpc();
+
+      assert.equal(
+        (await balance(accounts.userCollateral)) - collateralBefore,
+        300 * 2 - 300
+      );
+      assert.equal(await outcomeBalance(accounts.userOutcomeNo), 300);
+      assert.equal(await outcomeBalance(accounts.userOutcomeYes), 0);
+      assert.equal(
+        await outcomeBalance(escrow(pda("outcome_a"))),
+        escrowBefore + 300
+      );
+
+      const makerStats = await program.account.userStats.fetch(
+        userStats(maker.publicKey)
+      );
+      assert.equal(makerStats.claimableYes.toNumber(), 300);
+      const book = await program.account.orderBook.fetch(pda("orderbook"));
+      assert.equal(book.yesBuyOrders.length, 0);
+    });
+
+    it("Closes the short by buying YES and merging it with the NO", async () => {
+      await program.methods
+        .placeOrder(
+          marketId,
+          { sell: {} },
+          { yes: {} },
+          new BN(200),
+          new BN(2),
+          new BN(10)
+        )
+        .accounts(accountsOf[maker.publicKey.toBase58()])
+        .signers([maker])
+        .rpc();
+
+      const accounts = accountsOf[taker.publicKey.toBase58()];
+      const collateralBefore = await balance(accounts.userCollateral);
+      const escrowBefore = await outcomeBalance(escrow(pda("outcome_a")));
+
+      // The asks cost 400 and the merge returns 200, so the taker pays the difference
+      await program.methods
+        .buyAndMerge(marketId, params(200, 2))
+        .accounts(accounts)
+        .remainingAccounts(makerAccounts())
+        .signers([taker])
+        .rpc();
+
+      assert.equal(
+        collateralBefore - (await balance(accounts.userCollateral)),
+        200 * 2 - 200
+      );
+      assert.equal(await outcomeBalance(accounts.userOutcomeNo), 100);
+      assert.equal(await outcomeBalance(accounts.userOutcomeYes), 0);
+      assert.equal(
+        await outcomeBalance(escrow(pda("outcome_a"))),
+        escrowBefore - 200
+      );
+
+      const makerStats = await program.account.userStats.fetch(
+        userStats(maker.publicKey)
+      );
+      assert.equal(makerStats.claimableCollateral.toNumber(), 200 * 2);
+    });
+
+    it("Merging needs the other outcome for the whole quantity", async () => {
+      try {
+        await program.methods
+          .buyAndMerge(marketId, params(500, 2))
+          .accounts(accountsOf[taker.publicKey.toBase58()])
+          .signers([taker])
+          .rpc();
+
+        assert.fail("NotEnoughBalance");
+      } catch (err) {
+        expect(err.toString()).to.include("NotEnoughBalance");
+      }
+    });
+  });
+
+  describe("Liquidity Rewards", () => {
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let rewards: PublicKey;
+    let rewardVault: PublicKey;
+    let sponsor: Keypair;
+    let maker: Keypair;
+    let wideMaker: Keypair;
+    const accountsOf: Record<string, any> = {};
+
+    const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+    const balance = async (address: PublicKey) =>
+      Number((await getAccount(provider.connection, address)).amount);
+    const crank = () =>
+      program.methods
+        .crankLiquidityRewards(marketId)
+        .accounts({ market: pda("market"), orderbook: pda("orderbook"), rewards })
+        .rpc();
+    const rewardParams = (sampleInterval: number) => ({
+      rewardsPerEpoch: new BN(1000),
+      epochDuration: new BN(6),
+      epochs: 1,
+      maxSpreadBps: 5000,
+      sampleInterval: new BN(sampleInterval),
+      minSize: new BN(1),
+    });
+
+    before(async () => {
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Rewards")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      sponsor = Keypair.generate();
+      maker = Keypair.generate();
+      wideMaker = Keypair.generate();
+      for (const user of [sponsor, maker, wideMaker]) {
+        await provider.connection.confirmTransaction(
+          await provider.connection.requestAirdrop(user.publicKey, LAMPORTS_PER_SOL)
+        );
+        const userCollateral = (
+          await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            authority.payer,
+            collateralMint,
+            user.publicKey
+          )
+        ).address;
+        await mintTo(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          userCollateral,
+          authority.publicKey,
+          initialCollateralAmount
+        );
+
+        accountsOf[user.publicKey.toBase58()] = {
+          market: pda("market"),
+          user: user.publicKey,
+          userCollateral,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          userOutcomeYes: (
+            await createOutcomeAccount(pda("outcome_a"), user.publicKey)
+          ).address,
+          userOutcomeNo: (
+            await createOutcomeAccount(pda("outcome_b"), user.publicKey)
+          ).address,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
+          referrer: null,
+          referrerStats: null,
+        };
+      }
+
+      // Midpoint 3: the maker quotes 2 / 4, a third away, the wide maker bids 1, two thirds away
+      const makerAccounts = accountsOf[maker.publicKey.toBase58()];
+      await program.methods
+        .splitTokens(marketId, new BN(1000))
+        .accounts(makerAccounts)
+        .signers([maker])
+        .rpc();
+      await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(100),
+          new BN(2),
+          new BN(10)
+        )
+        .accounts(makerAccounts)
+        .signers([maker])
+        .rpc();
+      await program.methods
+        .placeOrder(
+          marketId,
+          { sell: {} },
+          { yes: {} },
+          new BN(100),
+          new BN(4),
+          new BN(10)
+        )
+        .accounts(makerAccounts)
+        .signers([maker])
+        .rpc();
+      await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(100),
+          new BN(1),
+          new BN(10)
+        )
+        .accounts(accountsOf[wideMaker.publicKey.toBase58()])
+        .signers([wideMaker])
+        .rpc();
+
+      rewards = PublicKey.findProgramAddressSync(
+        [Buffer.from("liquidity_rewards"), idLE, sponsor.publicKey.toBuffer()],
+        program.programId
+      )[0];
+      rewardVault = PublicKey.findProgramAddressSync(
+        [Buffer.from("reward_vault"), rewards.toBuffer()],
+        program.programId
+      )[0];
+    });
+
+    const createAccounts = () => ({
+      sponsor: sponsor.publicKey,
+      market: pda("market"),
+      rewards,
+      rewardVault,
+      sponsorCollateral: accountsOf[sponsor.publicKey.toBase58()].userCollateral,
+      collateralMint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    it("Sampling can't be slower than an epoch", async () => {
+      try {
+        await program.methods
+          .createLiquidityRewards(marketId, rewardParams(7))
+          .accounts(createAccounts())
+          .signers([sponsor])
+          .rpc();
+
+        assert.fail("InvalidRewardParams");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidRewardParams");
+      }
+    });
+
+    it("The sponsor funds every epoch up front", async () => {
+      await program.methods
+        .createLiquidityRewards(marketId, rewardParams(2))
+        .accounts(createAccounts())
+        .signers([sponsor])
+        .rpc();
+
+      assert.equal(await balance(rewardVault), 1000);
+    });
+
+    it("The crank waits a sample interval", async () => {
+      try {
+        await crank();
+
+        assert.fail("RewardCrankNotDue");
+      } catch (err) {
+        expect(err.toString()).to.include("RewardCrankNotDue");
+      }
+    });
+
+    it("Only orders inside the max spread score", async () => {
+      await sleep(2500);
+      await crank();
+
+      const state = await program.account.liquidityRewards.fetch(rewards);
+      assert.equal(state.makers.length, 1);
+      assert.isTrue(state.makers[0].maker.equals(maker.publicKey));
+      assert.isAbove(state.makers[0].score.toNumber(), 0);
+    });
+
+    it("Closing the epoch pays it to the scored makers", async () => {
+      await sleep(4000);
+      await crank();
+
+      const state = await program.account.liquidityRewards.fetch(rewards);
+      assert.equal(state.epoch, 1);
+      assert.equal(state.totalClaimable.toNumber(), 1000);
+
+      const makerCollateral = accountsOf[maker.publicKey.toBase58()].userCollateral;
+      const before = await balance(makerCollateral);
+      await program.methods
+        .claimLiquidityRewards(marketId)
+        .accounts({
+          maker: maker.publicKey,
+          market: pda("market"),
+          rewards,
+          rewardVault,
+          makerCollateral,
+          collateralMint,
+          nativeUnwrap: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([maker])
+        .rpc();
+      assert.equal((await balance(makerCollateral)) - before, 1000);
+    });
+
+    it("Nothing is left for the sponsor once every epoch paid out", async () => {
+      try {
+        await program.methods
+          .withdrawLiquidityRewards(marketId)
+          .accounts({ ...createAccounts(), nativeUnwrap: null })
+          .signers([sponsor])
+          .rpc();
+
+        assert.fail("InvalidAmount");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidAmount");
+      }
+    });
+  });
+
+  describe("Metadata Freeze", () => {
+    it("A resting bid freezes the metadata before any split", async () => {
+      const { nextMarketId: marketId } =
+        await program.account.marketCounter.fetch(marketCounter);
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      const pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Bid first")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(100),
+          new BN(2),
+          new BN(10)
+        )
+        .accounts({
+          market: pda("market"),
+          user: user.publicKey,
+          userCollateral: userCollateralAccount,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          userOutcomeYes: (
+            await createOutcomeAccount(pda("outcome_a"), user.publicKey)
+          ).address,
+          userOutcomeNo: (
+            await createOutcomeAccount(pda("outcome_b"), user.publicKey)
+          ).address,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
+          referrer: null,
+          referrerStats: null,
+        })
+        .signers([user])
+        .rpc();
+
+      try {
+        await program.methods
+          .updateMarketMetadata(marketId, marketMetadata("Bid first, renamed"))
+          .accounts({
+            authority: authority.publicKey,
+            market: pda("market"),
+            outcomeYesMint: pda("outcome_a"),
+            outcomeNoMint: pda("outcome_b"),
+          })
+          .rpc();
+
+        assert.fail("MetadataFrozen");
+      } catch (err) {
+        expect(err.toString()).to.include("MetadataFrozen");
+      }
+    });
+  });
+
+  describe("Voided Markets", () => {
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let accounts: Record<string, any>;
+    const amount = 1000;
+
+    before(async () => {
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Voided")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      accounts = {
+        market: pda("market"),
+        user: user.publicKey,
+        userCollateral: userCollateralAccount,
+        collateralVault: pda("vault"),
+        outcomeYesMint: pda("outcome_a"),
+        outcomeNoMint: pda("outcome_b"),
+        userOutcomeYes: (
+          await createOutcomeAccount(pda("outcome_a"), user.publicKey)
+        ).address,
+        userOutcomeNo: (
+          await createOutcomeAccount(pda("outcome_b"), user.publicKey)
+        ).address,
+        collateralMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        nativeUnwrap: null,
+      };
+      await program.methods
+        .splitTokens(marketId, new BN(amount))
+        .accounts(accounts)
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .setMarketStatus(marketId, { voided: {} })
+        .accounts({ authority: authority.publicKey, market: pda("market") })
+        .rpc();
+    });
+
+    it("Refunds half a unit per token to a one-sided holder", async () => {
+      // Give the NO side away, the user is left holding only YES
+      const other = await createOutcomeAccount(pda("outcome_b"), authority.publicKey);
+      await transferChecked(
+        provider.connection,
+        authority.payer,
+        accounts.userOutcomeNo,
+        pda("outcome_b"),
+        other.address,
+        user,
+        amount,
+        6,
+        [],
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+
+      const before = await getAccount(provider.connection, userCollateralAccount);
+
+      await program.methods
+        .claimRewards(marketId)
+        .accounts(accounts)
+        .signers([user])
+        .rpc();
+
+      const after = await getAccount(provider.connection, userCollateralAccount);
+      assert.equal(Number(after.amount) - Number(before.amount), amount / 2);
+      assert.equal(
+        Number((await getOutcomeAccount(accounts.userOutcomeYes)).amount),
+        0
+      );
+
+      const market = await program.account.market.fetch(pda("market"));
+      assert.equal(market.totalCollateralLocked.toNumber(), amount / 2);
+    });
+
+    it("A voided market can't be reopened", async () => {
+      try {
+        await program.methods
+          .setMarketStatus(marketId, { active: {} })
+          .accounts({ authority: authority.publicKey, market: pda("market") })
+          .rpc();
+
+        assert.fail("MarketVoided");
+      } catch (err) {
+        expect(err.toString()).to.include("MarketVoided");
+      }
+    });
+
+    it("Resting orders can still be cancelled past the deadline", async () => {
+      const { nextMarketId: shortId } =
+        await program.account.marketCounter.fetch(marketCounter);
+      const idLE = new BN(shortId).toArrayLike(Buffer, "le", 4);
+      const shortPda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 3),
+          marketMetadata("Voided, short deadline")
+        )
+        .accounts({
+          market: shortPda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: shortPda("vault"),
+          feeVault: shortPda("fee_vault"),
+          outcomeYesMint: shortPda("outcome_a"),
+          outcomeNoMint: shortPda("outcome_b"),
+          orderbook: shortPda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const shortAccounts = {
+        market: shortPda("market"),
+        user: user.publicKey,
+        userCollateral: userCollateralAccount,
+        collateralVault: shortPda("vault"),
+        feeVault: shortPda("fee_vault"),
+        outcomeYesMint: shortPda("outcome_a"),
+        outcomeNoMint: shortPda("outcome_b"),
+        userOutcomeYes: (
+          await createOutcomeAccount(shortPda("outcome_a"), user.publicKey)
+        ).address,
+        userOutcomeNo: (
+          await createOutcomeAccount(shortPda("outcome_b"), user.publicKey)
+        ).address,
+        collateralMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        nativeUnwrap: null,
+        referrer: null,
+        referrerStats: null,
+      };
+      await program.methods
+        .splitTokens(shortId, new BN(amount))
+        .accounts(shortAccounts)
+        .signers([user])
+        .rpc();
+      await program.methods
+        .placeOrder(
+          shortId,
+          { sell: {} },
+          { yes: {} },
+          new BN(amount),
+          new BN(2),
+          new BN(10)
+        )
+        .accounts(shortAccounts)
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .setMarketStatus(shortId, { voided: {} })
+        .accounts({ authority: authority.publicKey, market: shortPda("market") })
+        .rpc();
+      await new Promise((resolve) => setTimeout(resolve, 5000));
+
+      const { yesSellOrders } = await program.account.orderBook.fetch(
+        shortPda("orderbook")
+      );
+      await program.methods
+        .cancelOrder(shortId, yesSellOrders[0].id)
+        .accounts(shortAccounts)
+        .signers([user])
+        .rpc();
+
+      assert.equal(
+        Number((await getOutcomeAccount(shortAccounts.userOutcomeYes)).amount),
+        amount
+      );
+      const book = await program.account.orderBook.fetch(shortPda("orderbook"));
+      assert.equal(book.yesSellOrders.length, 0);
+    });
+  });
+
+  describe("Claiming Rewards & Set Winning Side", () => {
+    //Defining accounts of user & then funding them
+    let winningUser: Keypair;
+    let winnerCollateralAcount: PublicKey;
+    let winnerOutcomeYesAccount: PublicKey;
+    let winnerOutcomeNoAccount: PublicKey;
+
+    before(async () => {
+      winningUser = Keypair.generate();
+      let requestAirdropSig = await provider.connection.requestAirdrop(
+        winningUser.publicKey,
+        anchor.web3.LAMPORTS_PER_SOL * 5
+      );
+      await provider.connection.confirmTransaction(requestAirdropSig);
       // Now we will fund the User Collateral Account
       const winnerCollateralAcountInfo =
         await getOrCreateAssociatedTokenAccount(
@@ -369,16 +3921,12 @@ describe("prediction_market", () => {
         5000000
       );
 
-      const winnerOutcomeYesAccountInfo = await getOrCreateAssociatedTokenAccount(
-        provider.connection,
-        authority.payer,
+      const winnerOutcomeYesAccountInfo = await createOutcomeAccount(
         outcomeYesMint,
         winningUser.publicKey
       );
       winnerOutcomeYesAccount = winnerOutcomeYesAccountInfo.address;
-      const winnerOutcomeNoAccountInfo = await getOrCreateAssociatedTokenAccount(
-        provider.connection,
-        authority.payer,
+      const winnerOutcomeNoAccountInfo = await createOutcomeAccount(
         outcomeNoMint,
         winningUser.publicKey
       );
@@ -394,11 +3942,33 @@ describe("prediction_market", () => {
           outcomeNoMint,
           userOutcomeYes: winnerOutcomeYesAccount,
           userOutcomeNo: winnerOutcomeNoAccount,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
         })
         .signers([winningUser])
         .rpc();
     });
 
+    it("Only the market authority can set the winning side", async () => {
+      try {
+        await program.methods
+          .setWinningSide(marketId, { outcomeB: {} })
+          .accounts({
+            authority: winningUser.publicKey,
+            market: marketPda,
+            outcomeYesMint,
+            outcomeNoMint,
+          })
+          .signers([winningUser])
+          .rpc();
+
+        assert.fail("NotAuthorized");
+      } catch (err) {
+        expect(err.toString()).to.include("NotAuthorized");
+      }
+    });
+
     it("Setting the Winning Side to A", async () => {
       await program.methods
         .setWinningSide(marketId, { outcomeA: {} })
@@ -439,8 +4009,7 @@ describe("prediction_market", () => {
         provider.connection,
         winnerCollateralAcount
       );
-      let winnerOutcomeABefore = await getAccount(
-        provider.connection,
+      let winnerOutcomeABefore = await getOutcomeAccount(
         winnerOutcomeYesAccount
       );
 
@@ -457,6 +4026,9 @@ describe("prediction_market", () => {
           outcomeNoMint,
           userOutcomeYes: winnerOutcomeYesAccount,
           userOutcomeNo: winnerOutcomeNoAccount,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
         })
         .signers([winningUser])
         .rpc();
@@ -465,8 +4037,7 @@ describe("prediction_market", () => {
         provider.connection,
         winnerCollateralAcount
       );
-      let winnerOutcomeAAfterClaiming = await getAccount(
-        provider.connection,
+      let winnerOutcomeAAfterClaiming = await getOutcomeAccount(
         winnerOutcomeYesAccount
       );
 
@@ -495,6 +4066,9 @@ describe("prediction_market", () => {
             outcomeNoMint,
             userOutcomeYes: winnerOutcomeYesAccount,
             userOutcomeNo: winnerOutcomeNoAccount,
+            collateralMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            nativeUnwrap: null,
           })
           .signers([winningUser])
           .rpc();
@@ -521,6 +4095,9 @@ describe("prediction_market", () => {
             outcomeNoMint,
             userOutcomeYes: userOutcomeYesAccount,
             userOutcomeNo: userOutcomeNoAccount,
+            collateralMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            nativeUnwrap: null,
           })
           .signers([user])
           .rpc();
@@ -543,6 +4120,9 @@ describe("prediction_market", () => {
           outcomeNoMint,
           userOutcomeYes: userOutcomeYesAccount,
           userOutcomeNo: userOutcomeNoAccount,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
         });
         assert.fail("Failing with this error MarketAlreadySettled");
       } catch (error) {
@@ -550,4 +4130,600 @@ describe("prediction_market", () => {
       }
     });
   });
+
+  describe("Maker Accounts Out of Match Order", () => {
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let makerA: Keypair;
+    let makerB: Keypair;
+    let taker: Keypair;
+    const accountsOf: Record<string, any> = {};
+    const quantity = 100;
+
+    const userStats = (owner: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_stats"),
+          owner.toBuffer(),
+          new BN(marketId).toArrayLike(Buffer, "le", 4),
+        ],
+        program.programId
+      )[0];
+    const traderVolume = (owner: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("trader_volume"), owner.toBuffer()],
+        program.programId
+      )[0];
+    // UserStats followed by TraderVolume of every maker, in the order given
+    const makerAccounts = (...makers: Keypair[]) =>
+      makers
+        .flatMap((maker) => [
+          userStats(maker.publicKey),
+          traderVolume(maker.publicKey),
+        ])
+        .map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }));
+    const rest = (maker: Keypair, side: object, token: object, price: number) =>
+      program.methods
+        .placeOrder(
+          marketId,
+          side,
+          token,
+          new BN(quantity),
+          new BN(price),
+          new BN(10)
+        )
+        .accounts(accountsOf[maker.publicKey.toBase58()])
+        .signers([maker])
+        .rpc();
+
+    before(async () => {
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Maker accounts")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      makerA = Keypair.generate();
+      makerB = Keypair.generate();
+      taker = Keypair.generate();
+      for (const trader of [makerA, makerB, taker]) {
+        await provider.connection.confirmTransaction(
+          await provider.connection.requestAirdrop(
+            trader.publicKey,
+            LAMPORTS_PER_SOL
+          )
+        );
+        const userCollateral = (
+          await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            authority.payer,
+            collateralMint,
+            trader.publicKey
+          )
+        ).address;
+        await mintTo(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          userCollateral,
+          authority.publicKey,
+          initialCollateralAmount
+        );
+
+        accountsOf[trader.publicKey.toBase58()] = {
+          market: pda("market"),
+          user: trader.publicKey,
+          userCollateral,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          userOutcomeYes: (
+            await createOutcomeAccount(pda("outcome_a"), trader.publicKey)
+          ).address,
+          userOutcomeNo: (
+            await createOutcomeAccount(pda("outcome_b"), trader.publicKey)
+          ).address,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
+          referrer: null,
+          referrerStats: null,
+        };
+
+        // Both sides of every outcome, so anyone can sell either
+        await program.methods
+          .splitTokens(marketId, new BN(2 * quantity))
+          .accounts(accountsOf[trader.publicKey.toBase58()])
+          .signers([trader])
+          .rpc();
+      }
+    });
+
+    it("A buy rejects sellers passed out of match order", async () => {
+      // Asks match cheapest first: maker A at 1, then maker B at 2
+      await rest(makerA, { sell: {} }, { yes: {} }, 1);
+      await rest(makerB, { sell: {} }, { yes: {} }, 2);
+
+      try {
+        await program.methods
+          .placeOrder(
+            marketId,
+            { buy: {} },
+            { yes: {} },
+            new BN(2 * quantity),
+            new BN(2),
+            new BN(10)
+          )
+          .accounts(accountsOf[taker.publicKey.toBase58()])
+          .remainingAccounts(makerAccounts(makerB, makerA))
+          .signers([taker])
+          .rpc();
+
+        assert.fail("SellerStatsAccountNotProvided");
+      } catch (err) {
+        expect(err.toString()).to.include("SellerStatsAccountNotProvided");
+      }
+
+      // The same order goes through with the makers in match order
+      await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(2 * quantity),
+          new BN(2),
+          new BN(10)
+        )
+        .accounts(accountsOf[taker.publicKey.toBase58()])
+        .remainingAccounts(makerAccounts(makerA, makerB))
+        .signers([taker])
+        .rpc();
+      const book = await program.account.orderBook.fetch(pda("orderbook"));
+      assert.equal(book.yesSellOrders.length, 0);
+    });
+
+    it("A sell rejects buyers passed out of match order", async () => {
+      // Bids match highest first: maker B at 2, then maker A at 1
+      await rest(makerA, { buy: {} }, { no: {} }, 1);
+      await rest(makerB, { buy: {} }, { no: {} }, 2);
+
+      try {
+        await program.methods
+          .placeOrder(
+            marketId,
+            { sell: {} },
+            { no: {} },
+            new BN(2 * quantity),
+            new BN(1),
+            new BN(10)
+          )
+          .accounts(accountsOf[taker.publicKey.toBase58()])
+          .remainingAccounts(makerAccounts(makerA, makerB))
+          .signers([taker])
+          .rpc();
+
+        assert.fail("BuyerStatsAccountNotProvided");
+      } catch (err) {
+        expect(err.toString()).to.include("BuyerStatsAccountNotProvided");
+      }
+
+      // Nothing was matched, both bids are still resting
+      const book = await program.account.orderBook.fetch(pda("orderbook"));
+      assert.equal(book.noBuyOrders.length, 2);
+    });
+  });
+
+  describe("Order Book Views Against Resting Orders", () => {
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let makerA: Keypair;
+    let makerB: Keypair;
+    let taker: Keypair;
+    const accountsOf: Record<string, any> = {};
+
+    const view = () => ({ market: pda("market"), orderbook: pda("orderbook") });
+    const sell = (maker: Keypair, quantity: number, price: number) =>
+      program.methods
+        .placeOrder(
+          marketId,
+          { sell: {} },
+          { yes: {} },
+          new BN(quantity),
+          new BN(price),
+          new BN(10)
+        )
+        .accounts(accountsOf[maker.publicKey.toBase58()])
+        .signers([maker])
+        .rpc();
+
+    before(async () => {
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Views")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      makerA = Keypair.generate();
+      makerB = Keypair.generate();
+      taker = Keypair.generate();
+      for (const trader of [makerA, makerB, taker]) {
+        await provider.connection.confirmTransaction(
+          await provider.connection.requestAirdrop(
+            trader.publicKey,
+            LAMPORTS_PER_SOL
+          )
+        );
+        const userCollateral = (
+          await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            authority.payer,
+            collateralMint,
+            trader.publicKey
+          )
+        ).address;
+        await mintTo(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          userCollateral,
+          authority.publicKey,
+          initialCollateralAmount
+        );
+
+        accountsOf[trader.publicKey.toBase58()] = {
+          market: pda("market"),
+          user: trader.publicKey,
+          userCollateral,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          userOutcomeYes: (
+            await createOutcomeAccount(pda("outcome_a"), trader.publicKey)
+          ).address,
+          userOutcomeNo: (
+            await createOutcomeAccount(pda("outcome_b"), trader.publicKey)
+          ).address,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
+          referrer: null,
+          referrerStats: null,
+        };
+
+        await program.methods
+          .splitTokens(marketId, new BN(1000))
+          .accounts(accountsOf[trader.publicKey.toBase58()])
+          .signers([trader])
+          .rpc();
+      }
+
+      // Asks: A 100 @ 3, B 50 @ 3, A 70 @ 5
+      await sell(makerA, 100, 3);
+      await sell(makerB, 50, 3);
+      await sell(makerA, 70, 5);
+
+      // Takes 30 out of A's first ask, 70 of it stay on the book
+      const makerStats = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_stats"),
+          makerA.publicKey.toBuffer(),
+          new BN(marketId).toArrayLike(Buffer, "le", 4),
+        ],
+        program.programId
+      )[0];
+      const makerVolume = PublicKey.findProgramAddressSync(
+        [Buffer.from("trader_volume"), makerA.publicKey.toBuffer()],
+        program.programId
+      )[0];
+      await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(30),
+          new BN(3),
+          new BN(10)
+        )
+        .accounts(accountsOf[taker.publicKey.toBase58()])
+        .remainingAccounts(
+          [makerStats, makerVolume].map((pubkey) => ({
+            pubkey,
+            isSigner: false,
+            isWritable: true,
+          }))
+        )
+        .signers([taker])
+        .rpc();
+    });
+
+    it("Depth sums the unfilled size of every order at a price", async () => {
+      const levels = await program.methods
+        .getOrderBookDepth(marketId, { yes: {} }, { sell: {} }, 10)
+        .accounts(view())
+        .view();
+
+      assert.deepEqual(
+        levels.map((level) => [
+          Number(level.price),
+          Number(level.totalSize),
+          level.orderCount,
+        ]),
+        [
+          [3, 70 + 50, 2],
+          [5, 70, 1],
+        ]
+      );
+
+      // Levels past max_levels are left out
+      const best = await program.methods
+        .getOrderBookDepth(marketId, { yes: {} }, { sell: {} }, 1)
+        .accounts(view())
+        .view();
+      assert.equal(best.length, 1);
+      assert.equal(Number(best[0].price), 3);
+    });
+
+    it("Quotes a buy across levels, partly filling the last order", async () => {
+      // 70 @ 3 left of A's first ask, 50 @ 3 from B, then 30 of A's 70 @ 5
+      const quote = await program.methods
+        .quote(marketId, { yes: {} }, { buy: {} }, new BN(150))
+        .accounts(view())
+        .view();
+
+      assert.equal(Number(quote.fillableQuantity), 150);
+      assert.equal(Number(quote.totalCost), 70 * 3 + 50 * 3 + 30 * 5);
+      assert.equal(Number(quote.averagePrice), Math.floor(510 / 150));
+      assert.equal(Number(quote.worstPrice), 5);
+    });
+
+    it("Quotes only what the book holds for a larger buy", async () => {
+      const quote = await program.methods
+        .quote(marketId, { yes: {} }, { buy: {} }, new BN(1000))
+        .accounts(view())
+        .view();
+
+      assert.equal(Number(quote.fillableQuantity), 190);
+      assert.equal(Number(quote.totalCost), 70 * 3 + 50 * 3 + 70 * 5);
+      assert.equal(Number(quote.worstPrice), 5);
+
+      // Nothing rests on the bid side for a sell to hit
+      const sellQuote = await program.methods
+        .quote(marketId, { yes: {} }, { sell: {} }, new BN(10))
+        .accounts(view())
+        .view();
+      assert.equal(Number(sellQuote.fillableQuantity), 0);
+    });
+  });
+
+  describe("Order and Settlement Events", () => {
+    let marketId: number;
+    let pda: (seed: string) => PublicKey;
+    let maker: Keypair;
+    let taker: Keypair;
+    const accountsOf: Record<string, any> = {};
+    const quantity = 100;
+    const price = 3;
+
+    before(async () => {
+      ({ nextMarketId: marketId } = await program.account.marketCounter.fetch(
+        marketCounter
+      ));
+      const idLE = new BN(marketId).toArrayLike(Buffer, "le", 4);
+      pda = (seed: string) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), idLE],
+          program.programId
+        )[0];
+
+      await program.methods
+        .initializeMarket(
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          marketMetadata("Events")
+        )
+        .accounts({
+          market: pda("market"),
+          authority: authority.publicKey,
+          collateralMint,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          orderbook: pda("orderbook"),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      maker = Keypair.generate();
+      taker = Keypair.generate();
+      for (const trader of [maker, taker]) {
+        await provider.connection.confirmTransaction(
+          await provider.connection.requestAirdrop(
+            trader.publicKey,
+            LAMPORTS_PER_SOL
+          )
+        );
+        const userCollateral = (
+          await getOrCreateAssociatedTokenAccount(
+            provider.connection,
+            authority.payer,
+            collateralMint,
+            trader.publicKey
+          )
+        ).address;
+        await mintTo(
+          provider.connection,
+          authority.payer,
+          collateralMint,
+          userCollateral,
+          authority.publicKey,
+          initialCollateralAmount
+        );
+
+        accountsOf[trader.publicKey.toBase58()] = {
+          market: pda("market"),
+          user: trader.publicKey,
+          userCollateral,
+          collateralVault: pda("vault"),
+          feeVault: pda("fee_vault"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+          userOutcomeYes: (
+            await createOutcomeAccount(pda("outcome_a"), trader.publicKey)
+          ).address,
+          userOutcomeNo: (
+            await createOutcomeAccount(pda("outcome_b"), trader.publicKey)
+          ).address,
+          collateralMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          nativeUnwrap: null,
+          referrer: null,
+          referrerStats: null,
+        };
+
+        await program.methods
+          .splitTokens(marketId, new BN(quantity))
+          .accounts(accountsOf[trader.publicKey.toBase58()])
+          .signers([trader])
+          .rpc();
+      }
+    });
+
+    it("A resting order emits OrderPlaced", async () => {
+      const signature = await program.methods
+        .placeOrder(
+          marketId,
+          { sell: {} },
+          { yes: {} },
+          new BN(quantity),
+          new BN(price),
+          new BN(10)
+        )
+        .accounts(accountsOf[maker.publicKey.toBase58()])
+        .signers([maker])
+        .rpc({ commitment: "confirmed" });
+
+      const events = await cpiEvents(signature);
+      const placed = events.find((e) => e?.name === "orderPlaced");
+      assert.isDefined(placed);
+      assert.equal(placed.data.marketId, marketId);
+      assert.isTrue(placed.data.user.equals(maker.publicKey));
+      assert.deepEqual(placed.data.side, { sell: {} });
+      assert.deepEqual(placed.data.tokenType, { yes: {} });
+      assert.equal(Number(placed.data.price), price);
+      assert.equal(Number(placed.data.quantity), quantity);
+      // Nothing crossed, so nothing filled
+      assert.isUndefined(events.find((e) => e?.name === "orderFilled"));
+    });
+
+    it("A crossing order emits OrderFilled for the match", async () => {
+      const makerAccounts = [
+        PublicKey.findProgramAddressSync(
+          [
+            Buffer.from("user_stats"),
+            maker.publicKey.toBuffer(),
+            new BN(marketId).toArrayLike(Buffer, "le", 4),
+          ],
+          program.programId
+        )[0],
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("trader_volume"), maker.publicKey.toBuffer()],
+          program.programId
+        )[0],
+      ].map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }));
+
+      const signature = await program.methods
+        .placeOrder(
+          marketId,
+          { buy: {} },
+          { yes: {} },
+          new BN(quantity),
+          new BN(price),
+          new BN(10)
+        )
+        .accounts(accountsOf[taker.publicKey.toBase58()])
+        .remainingAccounts(makerAccounts)
+        .signers([taker])
+        .rpc({ commitment: "confirmed" });
+
+      const events = await cpiEvents(signature);
+      const placed = events.find((e) => e?.name === "orderPlaced");
+      const filled = events.filter((e) => e?.name === "orderFilled");
+      assert.isDefined(placed);
+      assert.equal(filled.length, 1);
+
+      const fill = filled[0].data;
+      assert.equal(fill.marketId, marketId);
+      assert.equal(Number(fill.takerOrderId), Number(placed.data.orderId));
+      assert.isTrue(fill.taker.equals(taker.publicKey));
+      assert.isTrue(fill.maker.equals(maker.publicKey));
+      assert.deepEqual(fill.takerSide, { buy: {} });
+      assert.equal(Number(fill.price), price);
+      assert.equal(Number(fill.quantity), quantity);
+      assert.equal(Number(fill.collateralAmount), quantity * price);
+    });
+
+    it("Settlement emits MarketSettled", async () => {
+      const signature = await program.methods
+        .setWinningSide(marketId, { outcomeB: {} })
+        .accounts({
+          authority: authority.publicKey,
+          market: pda("market"),
+          outcomeYesMint: pda("outcome_a"),
+          outcomeNoMint: pda("outcome_b"),
+        })
+        .rpc({ commitment: "confirmed" });
+
+      const events = await cpiEvents(signature);
+      const settled = events.find((e) => e?.name === "marketSettled");
+      assert.isDefined(settled);
+      assert.equal(settled.data.marketId, marketId);
+      assert.deepEqual(settled.data.winningOutcome, { outcomeB: {} });
+    });
+  });
 });